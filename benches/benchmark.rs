@@ -1,6 +1,12 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+mod support;
 
-use std::fs;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use std::{
+    fs,
+    io::{Read, Write},
+};
 
 use gzip::gzip::GzipFile;
 
@@ -48,5 +54,79 @@ fn benchmark(c: &mut Criterion) {
     c.bench_function("Type 2", |b| b.iter(|| test_block_type_2()));
 }
 
-criterion_group!(benches, benchmark);
+/// Builds the corpora decompression throughput is measured over, pairing
+/// each name with its raw bytes. Everything but the picture is generated so
+/// nothing large needs to be committed.
+fn corpora() -> Vec<(&'static str, Vec<u8>)> {
+    let mut random = support::XorShift::new(0x2545f4914f6cdd1d);
+    let mut next = move || random.next_u64();
+
+    let words = [
+        "the", "of", "and", "to", "a", "in", "is", "that", "for", "it", "as", "was", "with", "be",
+        "by", "on", "not", "deflate", "huffman", "window", "\n",
+    ];
+    let mut text = Vec::new();
+    while text.len() < 1 << 20 {
+        text.extend_from_slice(words[next() as usize % words.len()].as_bytes());
+        text.push(b' ');
+    }
+
+    let random = (0..1 << 20).map(|_| next() as u8).collect::<Vec<_>>();
+
+    let mut runs = Vec::new();
+    while runs.len() < 1 << 20 {
+        let byte = next() as u8;
+        runs.extend(std::iter::repeat_n(byte, next() as usize % 300));
+    }
+
+    vec![
+        ("text", text),
+        ("random", random),
+        ("runs", runs),
+        ("picture", fs::read("./tests/raw/picture.png").unwrap()),
+    ]
+}
+
+fn throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Throughput");
+
+    for (name, raw) in corpora() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("gzip", name),
+            &compressed,
+            |b, compressed| {
+                b.iter(|| {
+                    GzipFile::from_bytes(compressed)
+                        .unwrap()
+                        .decompress()
+                        .unwrap()
+                })
+            },
+        );
+        // The same data through flate2, as a baseline.
+        group.bench_with_input(
+            BenchmarkId::new("flate2", name),
+            &compressed,
+            |b, compressed| {
+                b.iter(|| {
+                    let mut decompressed = Vec::with_capacity(raw.len());
+                    GzDecoder::new(&compressed[..])
+                        .read_to_end(&mut decompressed)
+                        .unwrap();
+                    decompressed
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark, throughput);
 criterion_main!(benches);
@@ -0,0 +1,273 @@
+mod support;
+
+use criterion::{
+    criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    BenchmarkId, Criterion, Throughput,
+};
+use flate2::{write::GzEncoder, Compression};
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{self, BufRead, Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use gzip::{bufread, gzip::GzipFile};
+
+/// The number of allocations made so far, and the bytes they asked for,
+/// counted by CountingAllocator.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting every allocation and reallocation it
+/// makes, and their sizes, so benchmarks can report them. A reallocation
+/// counts the whole of its new size, as a copy into a fresh block would.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size, Ordering::Relaxed);
+}
+
+/// A criterion measurement that reads one of the allocator's counters
+/// instead of timing, so a change that allocates more shows up as a
+/// regression like one that's slower would.
+///
+/// # Fields
+///
+/// * 'counter' - The counter to read.
+/// * 'unit' - What it counts, shown after each value.
+/// * 'per_mb' - The unit of the value per MB of throughput.
+struct Counter {
+    counter: &'static AtomicUsize,
+    unit: &'static str,
+    per_mb: &'static str,
+}
+
+/// Counts allocations.
+const ALLOCATION_COUNT: Counter = Counter {
+    counter: &ALLOCATIONS,
+    unit: "allocs",
+    per_mb: "allocs/MB",
+};
+
+/// Counts the bytes allocated, however long they were held.
+const BYTE_COUNT: Counter = Counter {
+    counter: &ALLOCATED_BYTES,
+    unit: "bytes",
+    per_mb: "bytes/MB",
+};
+
+impl Measurement for Counter {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        self.counter.load(Ordering::Relaxed)
+    }
+    fn end(&self, start: usize) -> usize {
+        self.counter.load(Ordering::Relaxed) - start
+    }
+    fn add(&self, a: &usize, b: &usize) -> usize {
+        a + b
+    }
+    fn zero(&self) -> usize {
+        0
+    }
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+/// Shows counts as they are, there's no unit to scale.
+impl ValueFormatter for Counter {
+    fn scale_values(&self, _typical: f64, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        // A count per byte is too small to read, per MB isn't.
+        let (Throughput::Bytes(bytes) | Throughput::BytesDecimal(bytes)) = *throughput else {
+            return self.unit;
+        };
+        for value in values {
+            *value /= bytes as f64 / 1e6;
+        }
+        self.per_mb
+    }
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+}
+
+/// Returns len bytes of text made of a few words picked by a fixed
+/// xorshift generator, so runs are comparable.
+fn text(len: usize) -> Vec<u8> {
+    let mut random = support::XorShift::new(0x2545f4914f6cdd1d);
+    let words = [
+        "the", "of", "and", "to", "a", "in", "is", "that", "for", "it", "deflate", "huffman",
+        "window", "\n",
+    ];
+    let mut text = Vec::with_capacity(len + 16);
+
+    while text.len() < len {
+        text.extend_from_slice(words[random.next_u64() as usize % words.len()].as_bytes());
+        text.push(b' ');
+    }
+    text.truncate(len);
+    text
+}
+
+/// Compresses data as members of at most member_size bytes each, back to
+/// back in one file.
+fn members(data: &[u8], member_size: usize) -> Vec<u8> {
+    let mut compressed = Vec::new();
+
+    for chunk in data.chunks(member_size) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(chunk).unwrap();
+        compressed.extend(encoder.finish().unwrap());
+    }
+    compressed
+}
+
+/// Decompresses every member with one decoder, the way gzip-rs cat does.
+fn read_members(compressed: &[u8]) -> u64 {
+    let mut decoder = bufread::GzDecoder::new(compressed);
+    let mut total = 0;
+
+    loop {
+        total += io::copy(&mut decoder, &mut io::sink()).unwrap();
+        if decoder.get_mut().fill_buf().unwrap().is_empty() {
+            return total;
+        }
+        decoder.reset();
+    }
+}
+
+/// Counts allocations made decompressing the same data through each API.
+fn allocations(c: &mut Criterion<Counter>) {
+    decoders(c, "Allocations");
+}
+
+/// Counts the bytes allocated decompressing the same data through each API.
+fn allocated_bytes(c: &mut Criterion<Counter>) {
+    decoders(c, "Bytes allocated");
+}
+
+/// Runs each API on the same data, as a single member and as many small
+/// ones, under whichever counter c measures with.
+fn decoders(c: &mut Criterion<Counter>, group_name: &str) {
+    let mut group = c.benchmark_group(group_name);
+    let raw = text(1 << 20);
+
+    for (name, compressed) in [
+        ("single", members(&raw, raw.len())),
+        ("members", members(&raw, 1000)),
+    ] {
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+
+        if name == "single" {
+            group.bench_with_input(BenchmarkId::new("decompress", name), &compressed, {
+                |b, compressed| b.iter(|| GzipFile::from_bytes(compressed).unwrap().decompress())
+            });
+            let mut output = Vec::new();
+            group.bench_with_input(BenchmarkId::new("decompress_to_vec", name), &compressed, {
+                |b, compressed| {
+                    b.iter(|| {
+                        GzipFile::from_bytes(compressed)
+                            .unwrap()
+                            .decompress_to_vec(&mut output)
+                    })
+                }
+            });
+        }
+        group.bench_with_input(BenchmarkId::new("bufread", name), &compressed, {
+            |b, compressed| b.iter(|| read_members(compressed))
+        });
+    }
+
+    group.finish();
+}
+
+/// Times inputs far larger than the window and the decoders' buffers, and
+/// files of many tiny members where per member setup dominates.
+fn large_inputs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Large");
+    group.sample_size(10);
+
+    let raw = text(100 << 20);
+    let compressed = members(&raw, raw.len());
+    group.throughput(Throughput::Bytes(raw.len() as u64));
+    group.bench_with_input(BenchmarkId::new("sink", "100MB"), &compressed, {
+        |b, compressed| {
+            b.iter(|| {
+                GzipFile::from_bytes(compressed)
+                    .unwrap()
+                    .decompress_to_sink(|_| {})
+            })
+        }
+    });
+    group.bench_with_input(BenchmarkId::new("read", "100MB"), &compressed, {
+        |b, compressed| {
+            b.iter(|| {
+                let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+                let mut chunk = vec![0; 1 << 16];
+                while decoder.read(&mut chunk).unwrap() > 0 {}
+            })
+        }
+    });
+    drop(compressed);
+
+    let raw = &raw[..10 << 20];
+    let compressed = members(raw, 100);
+    group.throughput(Throughput::Bytes(raw.len() as u64));
+    group.bench_with_input(BenchmarkId::new("members", "100B"), &compressed, {
+        |b, compressed| b.iter(|| read_members(compressed))
+    });
+
+    group.finish();
+}
+
+// Allocation counts don't vary between runs, which leaves nothing to plot.
+criterion_group! {
+    name = memory;
+    config = Criterion::default().with_measurement(ALLOCATION_COUNT).without_plots();
+    targets = allocations
+}
+criterion_group! {
+    name = bytes;
+    config = Criterion::default().with_measurement(BYTE_COUNT).without_plots();
+    targets = allocated_bytes
+}
+criterion_group!(large, large_inputs);
+criterion_main!(memory, bytes, large);
@@ -0,0 +1,19 @@
+//! What the benchmarks share.
+
+/// A small xorshift generator, so generated corpora are the same between
+/// runs and the numbers stay comparable.
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Starts the generator from seed, which can be anything but 0.
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+    /// Returns the next number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
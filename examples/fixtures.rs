@@ -0,0 +1,34 @@
+//! Writes every fixture tests/common generates to a directory, so they can
+//! be looked at with other tools or checked in as files. The same build
+//! always writes the same bytes.
+//!
+//!     cargo run --example fixtures [DIR]
+//!
+//! DIR defaults to target/fixtures. Each fixture is written as NAME.gz,
+//! with what it decompresses to as raw/NAME, the layout of tests/compressed
+//! and tests/raw.
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{env, error::Error, fs, path::PathBuf};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let dir = PathBuf::from(
+        env::args()
+            .nth(1)
+            .unwrap_or_else(|| String::from("target/fixtures")),
+    );
+    fs::create_dir_all(dir.join("raw"))?;
+
+    let fixtures = common::fixtures();
+    for fixture in &fixtures {
+        fs::write(
+            dir.join(format!("{}.gz", fixture.name)),
+            &fixture.compressed,
+        )?;
+        fs::write(dir.join("raw").join(fixture.name), &fixture.raw)?;
+    }
+
+    println!("wrote {} fixtures to {}", fixtures.len(), dir.display());
+    Ok(())
+}
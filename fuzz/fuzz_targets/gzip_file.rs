@@ -0,0 +1,16 @@
+//! Decompresses arbitrary bytes as a whole gzip file, with limits set so a
+//! small input can't run the fuzzer out of memory.
+#![no_main]
+
+use gzip::gzip::GzipFile;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut file) = GzipFile::from_bytes(data) else {
+        return;
+    };
+
+    file.deflate.set_max_output_size(Some(1 << 24));
+    file.deflate.set_memory_limit(Some(1 << 25));
+    let _ = file.decompress();
+});
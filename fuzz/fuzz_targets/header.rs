@@ -0,0 +1,17 @@
+//! Parses arbitrary bytes as a gzip header, both from a slice and from a
+//! reader, which should only ever fail with a GzipError.
+#![no_main]
+
+use gzip::gzip::GzipHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let from_slice = GzipHeader::build(data);
+    let from_reader = GzipHeader::from_reader(&mut &data[..]);
+
+    // A header read from a reader takes only its own bytes, so it ends in
+    // the same place as one built from the slice.
+    if let (Ok(a), Ok(b)) = (from_slice, from_reader) {
+        assert_eq!(a.end_idx, b.end_idx);
+    }
+});
@@ -0,0 +1,53 @@
+//! Feeds arbitrary bytes to the Inflater as a raw DEFLATE stream. The first
+//! byte picks how the input and output are split up, so suspending and
+//! resuming partway through a token gets covered too, and the output has to
+//! match decoding the same stream all at once.
+#![no_main]
+
+use gzip::inflate::{DeflateData, Inflater, Status};
+use libfuzzer_sys::fuzz_target;
+
+const LIMIT: u64 = 1 << 22;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, stream)) = data.split_first() else {
+        return;
+    };
+    let input_size = (split & 0x0f) as usize + 1;
+    let output_size = (split >> 4) as usize + 1;
+
+    let mut inflater = Inflater::new();
+    inflater.set_max_output_size(Some(LIMIT));
+
+    let mut pieces = Vec::new();
+    let mut output = vec![0; output_size];
+    let mut input = stream;
+    let result = loop {
+        let end = input.len().min(input_size);
+        match inflater.inflate(&input[..end], &mut output) {
+            Ok((consumed, produced, status)) => {
+                input = &input[consumed..];
+                pieces.extend_from_slice(&output[..produced]);
+
+                match status {
+                    Status::StreamEnd => break Ok(()),
+                    Status::NeedsInput if input.is_empty() => break Err(()),
+                    _ => {}
+                }
+            }
+            Err(_) => break Err(()),
+        }
+    };
+
+    let mut whole = DeflateData::build(stream);
+    whole.set_max_output_size(Some(LIMIT));
+    match (result, whole.decompress()) {
+        (Ok(()), Ok(decompressed)) => assert_eq!(pieces, decompressed),
+        (Err(()), Err(_)) => {}
+        (a, b) => panic!(
+            "Decoding in pieces gave {:?}, all at once gave {:?}",
+            a,
+            b.map(|d| d.len())
+        ),
+    }
+});
@@ -0,0 +1,38 @@
+//! Adler-32, the checksum zlib streams end with.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+
+/// The largest prime below 2^16, both sums are kept modulo it.
+const MOD_ADLER: u32 = 65521;
+
+/// The most bytes that can be summed before the second sum could overflow a
+/// u32, so the modulo only has to be taken once per this many.
+const NMAX: usize = 5552;
+
+/// Returns the Adler-32 of buf.
+pub fn hash(buf: &[u8]) -> u32 {
+    update(1, buf)
+}
+/// Continues an Adler-32 over more bytes, so data that arrives in pieces can
+/// be hashed as it goes. Starting from 1, update(update(1, a), b) is the
+/// same as hash of a followed by b.
+pub fn update(adler: u32, buf: &[u8]) -> u32 {
+    let (mut a, mut b) = ((adler & 0xffff) % MOD_ADLER, (adler >> 16) % MOD_ADLER);
+    for chunk in buf.chunks(NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
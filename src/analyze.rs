@@ -0,0 +1,378 @@
+//! Per-block symbol statistics, for seeing why a stream compresses as well
+//! or as badly as it does.
+//!
+//! For every block the literal/length and distance symbols are counted, and
+//! the empirical entropy of those counts gives how many bits an ideal
+//! entropy coder would have needed for them. Comparing that to the bits the
+//! block's data actually took shows how much the Huffman codes cost, and
+//! comparing the ideal bits of neighbouring blocks to those of the blocks
+//! merged shows whether splitting them was worth it.
+//!
+//! A Breakdown goes the other way, splitting up the bits a stream actually
+//! took between its literals, match lengths, distances, block headers, and
+//! the gzip framing around it.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{
+    gzip::{GzipError, GzipHeader},
+    inflate::{BlockType, DeflateError, Inflater, Status, TraceEvent},
+    prefix::{
+        distance_code, length_code, DISTANCE_EXTRA_BITS, FIXED_CODE_LENGTHS,
+        FIXED_DISTANCE_LENGTHS, LENGTH_EXTRA_BITS,
+    },
+};
+
+/// The size of the scratch buffer decompressed bytes are thrown away into.
+const OUTPUT_CHUNK: usize = 32768;
+
+/// The symbol that ends every compressed block.
+const END_OF_BLOCK: usize = 256;
+
+/// The symbol counts of a single block, or of several merged together.
+///
+/// # Fields
+///
+/// * 'block_type' - How the block was coded.
+/// * 'bits' - The bit offsets of the block, from its BFINAL bit to just past
+///         its end.
+/// * 'data_start' - The bit offset the block's data starts at, after the
+///         header and any code lengths.
+/// * 'literal_lengths' - How many times each literal/length symbol was
+///         used, including the end of block.
+/// * 'distances' - How many times each distance symbol was used.
+/// * 'extra_bits' - How many extra bits the lengths and distances took.
+/// * 'stored_bytes' - How many bytes a stored block held.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStats {
+    pub block_type: BlockType,
+    pub bits: Range<u64>,
+    pub data_start: u64,
+    pub literal_lengths: [u64; 286],
+    pub distances: [u64; 30],
+    pub extra_bits: u64,
+    pub stored_bytes: u64,
+}
+
+impl BlockStats {
+    /// Creates empty stats for a block starting at bit_offset.
+    fn new(block_type: BlockType, bit_offset: u64) -> Self {
+        Self {
+            block_type,
+            bits: bit_offset..bit_offset,
+            // The data of a fixed block follows its 3 bit header, the other
+            // types move this on once their header has been read.
+            data_start: bit_offset + 3,
+            literal_lengths: [0; 286],
+            distances: [0; 30],
+            extra_bits: 0,
+            stored_bytes: 0,
+        }
+    }
+    /// Returns the number of bits the block's header and code lengths took.
+    pub fn header_bits(&self) -> u64 {
+        self.data_start - self.bits.start
+    }
+    /// Returns the number of bits the block's data took.
+    pub fn data_bits(&self) -> u64 {
+        self.bits.end - self.data_start
+    }
+    /// Returns the entropy of the literal/length symbols in bits per symbol.
+    pub fn literal_entropy(&self) -> f64 {
+        entropy(&self.literal_lengths)
+    }
+    /// Returns the entropy of the distance symbols in bits per symbol.
+    pub fn distance_entropy(&self) -> f64 {
+        entropy(&self.distances)
+    }
+    /// Returns the fewest bits the block's data could take if its symbols
+    /// were coded at exactly their entropy, extra bits included. A stored
+    /// block's bytes count at 8 bits each.
+    pub fn ideal_bits(&self) -> f64 {
+        let literal_lengths = self.literal_lengths.iter().sum::<u64>() as f64;
+        let distances = self.distances.iter().sum::<u64>() as f64;
+
+        literal_lengths * self.literal_entropy()
+            + distances * self.distance_entropy()
+            + (self.extra_bits + self.stored_bytes * 8) as f64
+    }
+    /// Adds another block's counts to these, as if both blocks had been
+    /// coded as one. The bit range grows to cover both.
+    ///
+    /// # Arguments
+    ///
+    /// * 'other' - The stats to add.
+    pub fn merge(&mut self, other: &BlockStats) {
+        for (count, other) in self.literal_lengths.iter_mut().zip(other.literal_lengths) {
+            *count += other;
+        }
+        for (count, other) in self.distances.iter_mut().zip(other.distances) {
+            *count += other;
+        }
+        self.extra_bits += other.extra_bits;
+        self.stored_bytes += other.stored_bytes;
+        self.bits = self.bits.start.min(other.bits.start)..self.bits.end.max(other.bits.end);
+    }
+    /// Counts a single event belonging to the block.
+    fn count(&mut self, event: &TraceEvent<'_>) {
+        match *event {
+            TraceEvent::Stored { bit_offset, len } => {
+                self.data_start = bit_offset;
+                self.stored_bytes = len as u64;
+            }
+            TraceEvent::DynamicTables { bit_offset, .. } => self.data_start = bit_offset,
+            TraceEvent::Literal { byte, .. } => add(&mut self.literal_lengths, byte as usize),
+            TraceEvent::Match {
+                length, distance, ..
+            } => {
+                let code = length_code(length);
+                add(&mut self.literal_lengths, END_OF_BLOCK + 1 + code);
+                self.extra_bits += bits(&LENGTH_EXTRA_BITS, code);
+
+                let code = distance_code(distance);
+                add(&mut self.distances, code);
+                self.extra_bits += bits(&DISTANCE_EXTRA_BITS, code);
+            }
+            TraceEvent::BlockEnd { bit_offset } => {
+                self.bits.end = bit_offset;
+                if self.block_type != BlockType::Stored {
+                    add(&mut self.literal_lengths, END_OF_BLOCK);
+                }
+            }
+            TraceEvent::Block { .. } => {}
+        }
+    }
+}
+
+/// Counts one more use of symbol. The Inflater only traces valid symbols,
+/// so it's always in counts.
+fn add(counts: &mut [u64], symbol: usize) {
+    if let Some(count) = counts.get_mut(symbol) {
+        *count += 1;
+    }
+}
+
+/// Returns the number of bits table gives symbol, code lengths or extra
+/// bits, which like in add is always there.
+fn bits(table: &[u8], symbol: usize) -> u64 {
+    table.get(symbol).copied().unwrap_or_default() as u64
+}
+
+/// Decodes a raw DEFLATE stream, counting the symbols of every block.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+///
+/// # Returns
+///
+/// The stats of each block in order, or a DeflateError if the stream is
+/// invalid.
+pub fn analyze(stream: &[u8]) -> Result<Vec<BlockStats>, DeflateError> {
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&blocks);
+
+    trace_stream(stream, move |event| {
+        let mut blocks = sink.lock().unwrap_or_else(PoisonError::into_inner);
+        match *event {
+            TraceEvent::Block {
+                bit_offset,
+                block_type,
+                ..
+            } => blocks.push(BlockStats::new(block_type, bit_offset)),
+            // Every other event comes after a block header.
+            _ => {
+                if let Some(block) = blocks.last_mut() {
+                    block.count(event);
+                }
+            }
+        }
+    })?;
+
+    let mut blocks = blocks.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(std::mem::take(&mut *blocks))
+}
+
+/// Where the bits of a stream went, see breakdown. Everything is counted
+/// in bits.
+///
+/// # Fields
+///
+/// * 'literals' - The codes of literals, and the bytes of stored blocks.
+/// * 'lengths' - The length codes of matches, and their extra bits.
+/// * 'distances' - The distance codes of matches, and their extra bits.
+/// * 'headers' - Everything else in the DEFLATE stream: block headers, code
+///         lengths, end of block codes, and padding.
+/// * 'container' - The gzip header and trailer around the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Breakdown {
+    pub literals: u64,
+    pub lengths: u64,
+    pub distances: u64,
+    pub headers: u64,
+    pub container: u64,
+}
+
+impl Breakdown {
+    /// Returns the total number of bits.
+    pub fn total(&self) -> u64 {
+        self.literals + self.lengths + self.distances + self.headers + self.container
+    }
+    /// Adds another breakdown to this one, such as to total up the members
+    /// of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * 'other' - The breakdown to add.
+    pub fn merge(&mut self, other: &Breakdown) {
+        self.literals += other.literals;
+        self.lengths += other.lengths;
+        self.distances += other.distances;
+        self.headers += other.headers;
+        self.container += other.container;
+    }
+}
+
+/// Works out where the bits of a raw DEFLATE stream went.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+///
+/// # Returns
+///
+/// A tuple containing the Breakdown, with no container bits, and the
+/// number of bytes of stream the DEFLATE data took up. Or, a DeflateError
+/// if the stream is invalid.
+pub fn breakdown(stream: &[u8]) -> Result<(Breakdown, usize), DeflateError> {
+    let totals = Arc::new(Mutex::new(Breakdown::default()));
+    let sink = Arc::clone(&totals);
+    // The code lengths of the current block, for the bits each token took.
+    let mut literal_lengths = Vec::new();
+    let mut distance_lengths = Vec::new();
+
+    let used = trace_stream(stream, move |event| {
+        let mut totals = sink.lock().unwrap_or_else(PoisonError::into_inner);
+        match *event {
+            TraceEvent::Block {
+                block_type: BlockType::Fixed,
+                ..
+            } => {
+                literal_lengths = FIXED_CODE_LENGTHS.to_vec();
+                distance_lengths = FIXED_DISTANCE_LENGTHS.to_vec();
+            }
+            TraceEvent::DynamicTables {
+                literal_lengths: literals,
+                distance_lengths: distances,
+                ..
+            } => {
+                literal_lengths = literals.to_vec();
+                distance_lengths = distances.to_vec();
+            }
+            TraceEvent::Stored { len, .. } => totals.literals += len as u64 * 8,
+            TraceEvent::Literal { byte, .. } => {
+                totals.literals += bits(&literal_lengths, byte as usize);
+            }
+            TraceEvent::Match {
+                length, distance, ..
+            } => {
+                let code = length_code(length);
+                totals.lengths += bits(&literal_lengths, END_OF_BLOCK + 1 + code)
+                    + bits(&LENGTH_EXTRA_BITS, code);
+
+                let code = distance_code(distance);
+                totals.distances +=
+                    bits(&distance_lengths, code) + bits(&DISTANCE_EXTRA_BITS, code);
+            }
+            _ => {}
+        }
+    })?;
+
+    let mut totals = *totals.lock().unwrap_or_else(PoisonError::into_inner);
+    totals.headers = used as u64 * 8 - totals.literals - totals.lengths - totals.distances;
+
+    Ok((totals, used))
+}
+
+/// Works out where the bits of each member of a gzip file went.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+///
+/// # Returns
+///
+/// The Breakdown of each member in order, or a GzipError if the file is
+/// invalid.
+pub fn breakdown_gzip(bytes: &[u8]) -> Result<Vec<Breakdown>, GzipError> {
+    let mut members = Vec::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let header = GzipHeader::build(rest)?;
+        let stream = rest.get(header.end_idx..).unwrap_or_default();
+        let (mut member, used) = breakdown(stream)?;
+
+        // Whatever's left of a cut off trailer still counts.
+        let trailer = (stream.len() - used).min(8);
+        member.container = (header.end_idx + trailer) as u64 * 8;
+        members.push(member);
+
+        rest = stream.get(used + trailer..).unwrap_or_default();
+    }
+
+    Ok(members)
+}
+
+/// Decodes a raw DEFLATE stream, handing every event to trace.
+///
+/// # Returns
+///
+/// The number of bytes of stream the DEFLATE data took up, or a
+/// DeflateError if the stream is invalid.
+fn trace_stream<F>(stream: &[u8], trace: F) -> Result<usize, DeflateError>
+where
+    F: FnMut(&TraceEvent<'_>) + Send + 'static,
+{
+    let mut inflater = Inflater::new();
+    inflater.set_trace(Some(Box::new(trace)));
+
+    let mut output = vec![0; OUTPUT_CHUNK];
+    let mut input = stream;
+    loop {
+        let (consumed, _, status) = inflater.inflate(input, &mut output)?;
+        input = input.get(consumed..).unwrap_or_default();
+
+        match status {
+            Status::StreamEnd => return Ok(stream.len() - input.len()),
+            Status::NeedsInput => return Err(inflater.unexpected_eof()),
+            Status::NeedsOutput | Status::Pending => {}
+        }
+    }
+}
+
+/// Returns the empirical entropy of a set of symbol counts in bits per
+/// symbol, or 0 if there aren't any.
+fn entropy(counts: &[u64]) -> f64 {
+    let total = counts.iter().sum::<u64>() as f64;
+
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .fold(0.0, |entropy, &count| {
+            let p = count as f64 / total;
+            entropy - p * p.log2()
+        })
+}
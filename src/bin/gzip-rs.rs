@@ -0,0 +1,688 @@
+//! A command line front end to the library.
+//!
+//! Usage:
+//!
+//!     gzip-rs [-n | -N] [-o PATH] FILE...       Compress each FILE to FILE.gz.
+//!     gzip-rs -d [-n | -N] [-o PATH] FILE...    Decompress each FILE.
+//!     gzip-rs cat [FILE...]                     Write each FILE decompressed to stdout.
+//!     gzip-rs dump FILE                         Print an infgen style disassembly of FILE.
+//!     gzip-rs hexdump FILE                      Print the bytes of FILE with what each field means.
+//!     gzip-rs stats FILE                        Print the symbol statistics of each block.
+//!     gzip-rs tables FILE                       Print the code lengths of each dynamic block.
+//!     gzip-rs bits FILE                         Print where the compressed bits of FILE went.
+//!     gzip-rs identify FILE                     Guess which program wrote FILE.
+//!     gzip-rs verify FILE                       Check FILE survives a round trip through our encoder.
+//!     gzip-rs json FILE                         Print the metadata of FILE as JSON (json feature).
+//!
+//! Run as zcat, through a link or a copy, it acts like gzip-rs cat.
+//!
+//! While stderr is a terminal, how far through each file is shown on it.
+use std::{
+    env,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant, SystemTime},
+};
+
+use gzip::{
+    analyze, bufread::GzDecoder, deflate::CompressOptions, disasm, gzip::GzipHeader, identify,
+    verify, write::GzEncoder,
+};
+
+const USAGE: &str = "Usage: gzip-rs [-n | -N] [-o PATH] FILE...
+       gzip-rs -d [-n | -N] [-o PATH] FILE...
+       gzip-rs cat [FILE...]
+       gzip-rs dump FILE
+       gzip-rs hexdump FILE
+       gzip-rs stats FILE
+       gzip-rs tables FILE
+       gzip-rs bits FILE
+       gzip-rs identify FILE
+       gzip-rs verify FILE
+       gzip-rs json FILE
+
+Without -d each FILE is compressed to FILE.gz, leaving FILE in place.
+
+  -d, --decompress  Decompress each FILE, leaving FILE in place.
+  -n, --no-name     When compressing, store no name or timestamp. When
+                    decompressing, name the output after FILE without its
+                    suffix, and leave its timestamp alone. This is the
+                    default.
+  -N, --name        When compressing, store FILE's name and timestamp.
+                    When decompressing, name the output after the name
+                    stored in FILE, and give it the stored timestamp.
+  -o, --output PATH Write the output to PATH instead of next to FILE. If
+                    PATH is a directory, or ends in a separator, each
+                    output is written into it, and it's created if need
+                    be. Otherwise PATH is the output, and only one FILE can
+                    be given.
+
+cat writes every member of each FILE to stdout, reading stdin when FILE is -
+or when there are none.
+
+hexdump prints every field of FILE, down to each code and extra bits, with
+the bytes and bits it was read from and what it means.
+
+stats prints the bits each block's header and data took, the bits its data
+would take coded at the entropy of its symbols, and that entropy in bits per
+literal/length and distance symbol.
+
+tables prints the HLIT, HDIST, and HCLEN of each dynamic block and every one
+of its code lengths, in symbol order.
+
+bits prints how many bits of each member, and of the whole file, went on
+literals, match lengths, match distances, block headers, and the gzip header
+and trailer.
+
+identify prints a guess at which program wrote FILE, how each candidate
+scored, and the traits of the header and blocks the scores came from.
+
+verify decompresses FILE, compresses the result again with the default
+options, and checks that decompresses to the same bytes.
+
+json prints the header fields, sizes, trailer, and blocks of every member of
+FILE as JSON, when built with the json feature.";
+
+/// The suffixes stripped from a file's name to name its output.
+const SUFFIXES: [&str; 3] = [".gz", ".tgz", ".z"];
+
+/// The size of the pieces a file is read in to compress it.
+const COMPRESS_CHUNK: usize = 65536;
+
+/// How long to wait between redrawing the progress line.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What the command line asked for.
+///
+/// # Fields
+///
+/// * 'decompress' - Whether -d was given.
+/// * 'restore_name' - Whether -N was given, and not overridden by a later -n.
+/// * 'output' - Where -o said to write to, if it was given.
+/// * 'files' - The files to work on.
+struct Options {
+    decompress: bool,
+    restore_name: bool,
+    output: Option<Output>,
+    files: Vec<String>,
+}
+
+/// Compresses or decompresses a single file, as process_all hands them
+/// out.
+type Job = fn(&Path, &Options) -> Result<(), Box<dyn Error>>;
+
+/// Where -o said to write to.
+enum Output {
+    /// Each output goes into this directory.
+    Directory(PathBuf),
+    /// The only output goes to this file.
+    File(PathBuf),
+}
+
+impl Options {
+    /// Parses the arguments after the program name.
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            decompress: false,
+            restore_name: false,
+            output: None,
+            files: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-d" | "--decompress" => options.decompress = true,
+                "-n" | "--no-name" => options.restore_name = false,
+                "-N" | "--name" => options.restore_name = true,
+                "-o" | "--output" => {
+                    let path = args.next().ok_or("-o needs a path")?;
+                    let is_directory = path.ends_with(std::path::MAIN_SEPARATOR)
+                        || path.ends_with('/')
+                        || Path::new(path).is_dir();
+
+                    options.output = Some(if is_directory {
+                        Output::Directory(PathBuf::from(path))
+                    } else {
+                        Output::File(PathBuf::from(path))
+                    });
+                }
+                "--" => options.files.extend(args.by_ref().cloned()),
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option '{}'", flag));
+                }
+                file => options.files.push(file.to_string()),
+            }
+        }
+
+        if matches!(options.output, Some(Output::File(_))) && options.files.len() > 1 {
+            return Err(String::from(
+                "-o can only name a file when there's one FILE, give a directory instead",
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args();
+    let zcat = args
+        .next()
+        .is_some_and(|program| Path::new(&program).file_stem() == Some("zcat".as_ref()));
+    let args = args.collect::<Vec<_>>();
+
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ref files if zcat => cat(files),
+        ["cat", ref files @ ..] => cat(files),
+        ["dump", path] => dump(path),
+        ["hexdump", path] => hexdump(path),
+        ["stats", path] => stats(path),
+        ["tables", path] => tables(path),
+        ["bits", path] => bits(path),
+        ["identify", path] => identify(path),
+        ["verify", path] => verify(path),
+        #[cfg(feature = "json")]
+        ["json", path] => json(path),
+        _ => match Options::parse(&args) {
+            Ok(options) if options.decompress && !options.files.is_empty() => {
+                process_all(&options, decompress)
+            }
+            Ok(options) if !options.files.is_empty() => process_all(&options, compress),
+            Ok(_) => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+            Err(err) => {
+                eprintln!("gzip-rs: {}\n{}", err, USAGE);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("gzip-rs: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Writes every file given decompressed to stdout, one after another,
+/// carrying on past files that fail like decompress_all.
+fn cat(files: &[&str]) -> Result<(), Box<dyn Error>> {
+    let files = match files {
+        [] => &["-"],
+        files => files,
+    };
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let mut failed = 0;
+
+    for &file in files {
+        let result = match file {
+            "-" => cat_members(io::stdin().lock(), &mut stdout),
+            path => open(Path::new(path))
+                .and_then(|mut decoder| copy_members(&mut decoder, &mut stdout))
+                .map_err(Box::from),
+        };
+
+        if let Err(err) = result {
+            eprintln!("gzip-rs: {}: {}", file, err);
+            failed += 1;
+        }
+    }
+
+    stdout.flush()?;
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} files failed", failed, files.len()).into()),
+    }
+}
+
+/// Decompresses every member of a gzip file into writer, a file being any
+/// number of members back to back.
+fn cat_members<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<(), Box<dyn Error>> {
+    copy_members(&mut GzDecoder::new(reader), writer)?;
+    Ok(())
+}
+
+/// Decompresses the member decoder is on, and every member after it, into
+/// writer.
+fn copy_members<R: BufRead, W: Write>(
+    decoder: &mut GzDecoder<R>,
+    writer: &mut W,
+) -> io::Result<()> {
+    loop {
+        decoder.copy_to(writer)?;
+
+        if decoder.get_mut().fill_buf()?.is_empty() {
+            return Ok(());
+        }
+        decoder.reset();
+    }
+}
+
+/// Prints the disassembly of a gzip file to stdout.
+fn dump(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    disasm::disassemble(&bytes, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Prints the annotated hexdump of a gzip file to stdout.
+fn hexdump(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    disasm::hexdump(&bytes, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Prints where the bits of each member of a gzip file went to stdout.
+fn bits(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let members = analyze::breakdown_gzip(&bytes)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    let mut total = analyze::Breakdown::default();
+    for member in &members {
+        total.merge(member);
+    }
+
+    let names = ["literals", "lengths", "distances", "headers", "container"];
+    write!(stdout, "{:<8}", "member")?;
+    for name in names {
+        write!(stdout, " {:>17}", name)?;
+    }
+    writeln!(stdout, " {:>10}", "total")?;
+
+    let rows = members
+        .iter()
+        .enumerate()
+        .map(|(index, member)| (index.to_string(), member));
+    for (name, member) in rows.chain([(String::from("all"), &total)]) {
+        let bits = [
+            member.literals,
+            member.lengths,
+            member.distances,
+            member.headers,
+            member.container,
+        ];
+
+        write!(stdout, "{:<8}", name)?;
+        for bits in bits {
+            let share = bits as f64 * 100.0 / member.total().max(1) as f64;
+            write!(stdout, " {:>10} {:>5.1}%", bits, share)?;
+        }
+        writeln!(stdout, " {:>10}", member.total())?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Prints a guess at which program wrote a gzip file to stdout, with the
+/// evidence for it.
+fn identify(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let identification = identify::identify(&bytes)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    match identification.best() {
+        Some(producer) => writeln!(stdout, "probably {}", producer)?,
+        None => writeln!(stdout, "unknown")?,
+    }
+    writeln!(stdout)?;
+    for (producer, score) in &identification.scores {
+        writeln!(stdout, "{:<12} {:>3}", producer.to_string(), score)?;
+    }
+    writeln!(stdout)?;
+    for evidence in &identification.evidence {
+        writeln!(stdout, "{}", evidence)?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Round trips a gzip file through our encoder, printing the sizes at each
+/// step to stdout.
+fn verify(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let verification = verify::verify(&bytes, &CompressOptions::default())?;
+
+    println!(
+        "{}: ok, {} bytes, {} compressed, {} recompressed",
+        path, verification.plaintext, verification.compressed, verification.recompressed
+    );
+    Ok(())
+}
+
+/// Prints the metadata of a gzip file to stdout as JSON.
+#[cfg(feature = "json")]
+fn json(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    println!("{}", gzip::report::to_json(&bytes)?);
+    Ok(())
+}
+
+/// Prints the code lengths of every dynamic block in a gzip file to stdout.
+fn tables(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let header = GzipHeader::build(&bytes)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    disasm::dump_tables(&bytes[header.end_idx..], &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Prints the symbol statistics of every block in a gzip file to stdout,
+/// and how they'd compare if the whole stream were one block.
+fn stats(path: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let header = GzipHeader::build(&bytes)?;
+    let blocks = analyze::analyze(&bytes[header.end_idx..])?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    writeln!(
+        stdout,
+        "{:>5} {:<7} {:>8} {:>10} {:>12} {:>7} {:>7}",
+        "block", "type", "header", "data", "ideal", "litlen", "dist"
+    )?;
+    for (index, block) in blocks.iter().enumerate() {
+        writeln!(
+            stdout,
+            "{:>5} {:<7} {:>8} {:>10} {:>12.0} {:>7.3} {:>7.3}",
+            index,
+            format!("{:?}", block.block_type).to_lowercase(),
+            block.header_bits(),
+            block.data_bits(),
+            block.ideal_bits(),
+            block.literal_entropy(),
+            block.distance_entropy(),
+        )?;
+    }
+
+    if let Some((first, rest)) = blocks.split_first() {
+        let mut merged = first.clone();
+        for block in rest {
+            merged.merge(block);
+        }
+        let split = blocks.iter().map(|block| block.ideal_bits()).sum::<f64>();
+
+        writeln!(
+            stdout,
+            "ideal bits as {} blocks {:.0}, as one block {:.0}",
+            blocks.len(),
+            split,
+            merged.ideal_bits()
+        )?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Compresses or decompresses every file given, carrying on past files that
+/// fail so one bad file doesn't stop the rest.
+fn process_all(options: &Options, process: Job) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+
+    if let Some(Output::Directory(directory)) = &options.output {
+        fs::create_dir_all(directory)?;
+    }
+
+    for file in &options.files {
+        if let Err(err) = process(Path::new(file), options) {
+            eprintln!("gzip-rs: {}: {}", file, err);
+            failed += 1;
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} files failed", failed, options.files.len()).into()),
+    }
+}
+
+/// Decompresses a single file, next to itself unless -o says otherwise.
+fn decompress(path: &Path, options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut decoder = open(path)?;
+    let header = decoder.read_header()?;
+
+    let output_path = match &options.output {
+        Some(Output::File(file)) => file.clone(),
+        output => {
+            let name = match header.original_name() {
+                Some(name) if options.restore_name => PathBuf::from(name),
+                _ => strip_suffix(path)?,
+            };
+            match output {
+                Some(Output::Directory(directory)) => directory.join(name),
+                _ => path.with_file_name(name),
+            }
+        }
+    };
+    let modified = header.modified().filter(|_| options.restore_name);
+
+    let mut output = BufWriter::new(create(&output_path)?);
+    let result = copy_members(&mut decoder, &mut output).and_then(|_| output.flush());
+    if let Err(err) = result {
+        // Don't leave half a file behind.
+        drop(output);
+        let _ = fs::remove_file(&output_path);
+        return Err(err.into());
+    }
+
+    if let Some(modified) = modified {
+        output.get_ref().set_modified(modified)?;
+    }
+
+    Ok(())
+}
+
+/// Compresses a single file to FILE.gz, next to itself unless -o says
+/// otherwise.
+fn compress(path: &Path, options: &Options) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("can't tell the output name from the input name")?;
+    if let Some(suffix) = SUFFIXES.iter().find(|&suffix| name.ends_with(suffix)) {
+        return Err(format!("already has a {} suffix", suffix).into());
+    }
+
+    let mut input = File::open(path)?;
+    let metadata = input.metadata()?;
+    let header = match options.restore_name {
+        true => GzipHeader {
+            // Times that don't fit are left out, 0 meaning there's none.
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .and_then(|since| u32::try_from(since.as_secs()).ok())
+                .unwrap_or(0),
+            fname: Some(name.to_string()),
+            ..GzipHeader::default()
+        },
+        false => GzipHeader::default(),
+    };
+
+    let output_path = match &options.output {
+        Some(Output::File(file)) => file.clone(),
+        Some(Output::Directory(directory)) => directory.join(format!("{}.gz", name)),
+        None => path.with_file_name(format!("{}.gz", name)),
+    };
+    let output = BufWriter::new(create(&output_path)?);
+    let mut encoder = GzEncoder::with_header(output, CompressOptions::default(), header);
+
+    let mut progress = progress_line(path, &metadata);
+    let mut chunk = vec![0; COMPRESS_CHUNK];
+    let mut read = 0;
+    let result = loop {
+        match input.read(&mut chunk) {
+            Ok(0) => break encoder.finish().map(drop),
+            Ok(n) => {
+                if let Err(err) = encoder.write_all(&chunk[..n]) {
+                    break Err(err);
+                }
+                read += n as u64;
+                if let Some(line) = &mut progress {
+                    line.update(read);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => break Err(err),
+        }
+    };
+
+    if let Err(err) = result {
+        // Don't leave half a file behind.
+        let _ = fs::remove_file(&output_path);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Creates the file an output goes to, refusing to overwrite one that's
+/// already there. It's made with create_new, so a file that appears after
+/// the name is picked isn't overwritten either.
+fn create(path: &Path) -> Result<File, Box<dyn Error>> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            Err(format!("{} already exists", path.display()).into())
+        }
+        file => Ok(file?),
+    }
+}
+
+/// Returns the name of a compressed file without its suffix.
+fn strip_suffix(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("can't tell the output name from the input name")?;
+
+    for suffix in SUFFIXES {
+        if let Some(stem) = name.strip_suffix(suffix).filter(|stem| !stem.is_empty()) {
+            // A .tgz is a tar file.
+            return Ok(match suffix {
+                ".tgz" => PathBuf::from(format!("{}.tar", stem)),
+                _ => PathBuf::from(stem),
+            });
+        }
+    }
+
+    Err(format!("unknown suffix, expected one of {}", SUFFIXES.join(", ")).into())
+}
+
+/// Opens a gzip file to decompress, showing progress from the decoder's
+/// progress hook when progress_line gives a line for it.
+fn open(path: &Path) -> io::Result<GzDecoder<BufReader<File>>> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let mut decoder = GzDecoder::new(BufReader::new(file));
+
+    if let Some(mut line) = progress_line(path, &metadata) {
+        decoder.set_progress(Some(Box::new(move |progress| {
+            line.update(progress.total_in)
+        })));
+    }
+
+    Ok(decoder)
+}
+
+/// Returns a progress line for a file being compressed or decompressed, or
+/// None if stderr isn't a terminal or the file's size isn't known.
+fn progress_line(path: &Path, metadata: &fs::Metadata) -> Option<ProgressLine> {
+    (io::stderr().is_terminal() && metadata.is_file()).then(|| ProgressLine {
+        name: path.display().to_string(),
+        total: metadata.len(),
+        started: Instant::now(),
+        drawn: None,
+    })
+}
+
+/// A line on stderr showing how far through a file a job is, the
+/// throughput and how long is left, redrawn as the file is read: from the
+/// decoder's progress hook when decompressing, and after each chunk when
+/// compressing. It's cleared when it's dropped.
+///
+/// # Fields
+///
+/// * 'name' - The file's name, to start the line with.
+/// * 'total' - The file's size.
+/// * 'started' - When the file was opened.
+/// * 'drawn' - When the line was last drawn, or None if it hasn't been.
+struct ProgressLine {
+    name: String,
+    total: u64,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl ProgressLine {
+    /// Redraws the line for read bytes of the file, if it's been long
+    /// enough since it last was.
+    fn update(&mut self, read: u64) {
+        if self
+            .drawn
+            .is_some_and(|drawn| drawn.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = read as f64 / elapsed.max(f64::EPSILON);
+        let left = self.total.saturating_sub(read) as f64 / rate.max(1.0);
+
+        eprint!(
+            "\r\x1b[K{}: {:>3}% {} of {}, {}/s, {}:{:02} left",
+            self.name,
+            read * 100 / self.total.max(1),
+            human_size(read as f64),
+            human_size(self.total as f64),
+            human_size(rate),
+            left as u64 / 60,
+            left as u64 % 60,
+        );
+        self.drawn = Some(Instant::now());
+    }
+}
+
+impl Drop for ProgressLine {
+    /// Clears the line, so whatever's printed next starts on a blank line.
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+/// Formats a number of bytes with a binary unit, like "1.5 MiB".
+fn human_size(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} {}", size as u64, units[0]),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
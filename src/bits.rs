@@ -1,5 +1,7 @@
 use std::{error::Error, fmt::Display};
 
+use crate::prefix::Code;
+
 #[derive(Debug)]
 pub enum BitVecError {
     OutOfBounds(usize),
@@ -67,12 +69,14 @@ impl BitVector64 {
         let buf_idx = self.buffer.len() - 1;
         self.len += len;
 
-        let shifted_buffer = buffer << (64 - len);
+        // Shifting a u64 by 64 overflows, which pushing no bits, or a whole
+        // word at a word boundary, would otherwise do.
+        let shifted_buffer = buffer.checked_shl(64 - len as u32).unwrap_or(0);
 
         if (bit_idx + len) >= 64 {
             self.buffer.push(0);
             self.buffer[buf_idx] |= shifted_buffer >> bit_idx;
-            self.buffer[buf_idx + 1] = shifted_buffer << (64 - bit_idx);
+            self.buffer[buf_idx + 1] = shifted_buffer.checked_shl(64 - bit_idx as u32).unwrap_or(0);
         } else {
             self.buffer[buf_idx] |= shifted_buffer >> bit_idx;
         }
@@ -176,6 +180,76 @@ impl BitVector64 {
             self.pop_front()
         }
     }
+    /// Returns the next n bits without advancing the index, with the next
+    /// bit in the stream as the most significant bit. Meant to be paired
+    /// with consume, so a lookup table decoder can peek the longest code
+    /// length, find the symbol, and then consume only the bits its code used.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to peek, at most 64.
+    ///
+    /// # Returns
+    ///
+    /// A u64 holding the next n bits in its n least significant bits. Bits
+    /// past the end of the vector are read as zeroes, use remaining to tell
+    /// how many of them are real.
+    pub fn peek_bits(&self, n: u8) -> u64 {
+        let n = n.min(64);
+        if n == 0 {
+            return 0;
+        }
+
+        let buf_idx = self.idx / 64;
+        let bit_idx = self.idx % 64;
+
+        let mut bits = self.buffer.get(buf_idx).copied().unwrap_or(0) << bit_idx;
+        if bit_idx > 0 {
+            bits |= self.buffer.get(buf_idx + 1).copied().unwrap_or(0) >> (64 - bit_idx);
+        }
+
+        bits >> (64 - n as u32)
+    }
+    /// Reads the next n bits in the order DEFLATE packs integers, where the
+    /// first bit in the stream is the least significant bit of the value.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to read, at most 64.
+    ///
+    /// # Returns
+    ///
+    /// An option containing the value read, or None if fewer than n bits
+    /// remain, in which case nothing is consumed.
+    pub fn read_bits(&mut self, n: u8) -> Option<u64> {
+        if n as usize > self.remaining() || n > 64 {
+            return None;
+        }
+        if n == 0 {
+            return Some(0);
+        }
+
+        let value = self.peek_bits(n).reverse_bits() >> (64 - n as u32);
+        self.consume(n as usize);
+
+        Some(value)
+    }
+    /// Advances the index by n bits, stopping at the end of the vector.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to skip over.
+    pub fn consume(&mut self, n: usize) {
+        self.idx = self.len.min(self.idx + n);
+    }
+    /// Returns the number of bits left to be read.
+    ///
+    /// # Returns
+    ///
+    /// A usize containing the number of bits after the current index.
+    pub fn remaining(&self) -> usize {
+        self.len.saturating_sub(self.idx)
+    }
 }
 
 impl Default for BitVector64 {
@@ -208,3 +282,274 @@ impl Display for BitVector64 {
         Ok(())
     }
 }
+
+/// A small bit reservoir for reading a DEFLATE stream that arrives in pieces.
+/// Bytes are pulled in from the input only as space allows, so the reservoir
+/// can be carried across calls while the input slices come and go. Unlike
+/// BitVector64, bits are kept in the order DEFLATE packs them, the next bit
+/// in the stream as the least significant bit of the buffer, so input bytes
+/// go in as they are and integer fields come out without reversing.
+///
+/// # Fields
+///
+/// * 'buffer' - A u64 holding the buffered bits, right aligned. Bits past
+///         len are always zero.
+/// * 'len' - The number of bits in the buffer that are part of the stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BitBuffer {
+    pub buffer: u64,
+    pub len: u8,
+}
+
+/// Returns a mask of the n least significant bits, n being at most 64.
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+#[inline]
+fn low_bits(n: u32) -> u64 {
+    u64::MAX.checked_shr(64 - n).unwrap_or(0)
+}
+
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+impl BitBuffer {
+    /// Creates a new empty BitBuffer.
+    ///
+    /// # Returns
+    ///
+    /// A BitBuffer with no bits in it.
+    pub fn new() -> Self {
+        Self { buffer: 0, len: 0 }
+    }
+    /// Appends the bits of a byte to the end of the buffer, least
+    /// significant bit first. Does nothing if there is no room for 8 more
+    /// bits.
+    ///
+    /// # Arguments
+    ///
+    /// * 'byte' - The byte to append.
+    ///
+    /// # Returns
+    ///
+    /// A bool, true if the byte was added.
+    #[inline]
+    pub fn push_byte(&mut self, byte: u8) -> bool {
+        if self.len > 56 {
+            return false;
+        }
+        self.buffer |= (byte as u64) << self.len;
+        self.len += 8;
+        true
+    }
+    /// Pulls bytes from the front of the input until the buffer is full or
+    /// the input runs out, up to 8 at once with a single load.
+    ///
+    /// # Arguments
+    ///
+    /// * 'input' - The bytes to take from.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes taken from the input.
+    #[inline]
+    pub fn refill(&mut self, input: &[u8]) -> usize {
+        // With 8 bytes to hand, as many whole bytes as fit are loaded at
+        // once, a little endian word already being in stream order.
+        if let Some(&word) = input.first_chunk::<8>() {
+            let taken = (64 - self.len as usize) / 8;
+            let bits = taken as u32 * 8;
+            let word = u64::from_le_bytes(word) & low_bits(bits);
+
+            self.buffer |= word.checked_shl(self.len as u32).unwrap_or(0);
+            self.len += bits as u8;
+            return taken;
+        }
+
+        let mut taken = 0;
+        while input.get(taken).is_some_and(|&byte| self.push_byte(byte)) {
+            taken += 1;
+        }
+        taken
+    }
+    /// Returns the next n bits without consuming them, in stream order with
+    /// the next bit as the least significant, the order a DecodeTable is
+    /// indexed by. Bits past the end of the buffer are read as zeroes.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to peek, at most 64.
+    ///
+    /// # Returns
+    ///
+    /// A u64 holding the next n bits in its n least significant bits.
+    #[inline]
+    pub fn peek_bits(&self, n: u8) -> u64 {
+        self.buffer & low_bits(n.min(64) as u32)
+    }
+    /// Drops the next n bits, or every bit if there are fewer than n.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to drop.
+    #[inline]
+    pub fn consume(&mut self, n: u8) {
+        let n = n.min(self.len);
+        self.buffer = self.buffer.checked_shr(n as u32).unwrap_or(0);
+        self.len -= n;
+    }
+    /// Reads the next n bits in the order DEFLATE packs integers, where the
+    /// first bit in the stream is the least significant bit of the value.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The number of bits to read.
+    ///
+    /// # Returns
+    ///
+    /// An option containing the value read, or None if fewer than n bits
+    /// are buffered, in which case nothing is consumed.
+    #[inline]
+    pub fn read_bits(&mut self, n: u8) -> Option<u64> {
+        if n > self.len {
+            return None;
+        }
+
+        let value = self.buffer & low_bits(n as u32);
+        self.consume(n);
+
+        Some(value)
+    }
+    /// Skips ahead to the next byte boundary of the stream, dropping whatever
+    /// is left of the current byte. The buffer only ever takes in whole
+    /// bytes, so that is the bits past the last multiple of 8.
+    ///
+    /// # Returns
+    ///
+    /// The number of bits skipped.
+    pub fn align_to_byte(&mut self) -> u8 {
+        let skipped = self.len % 8;
+        self.consume(skipped);
+        skipped
+    }
+    /// Removes up to n whole bytes from the end of the buffer, which are the
+    /// most recently pushed, so they can be handed back to whoever supplied
+    /// the input.
+    ///
+    /// # Arguments
+    ///
+    /// * 'n' - The most bytes to remove.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes removed.
+    pub fn unpush_bytes(&mut self, n: usize) -> usize {
+        let removed = n.min(self.len as usize / 8);
+        self.len -= removed as u8 * 8;
+        self.buffer &= low_bits(self.len as u32);
+        removed
+    }
+}
+
+/// Packs bits into bytes the way a DEFLATE stream is written, the first bit
+/// written going in the least significant bit of the first byte.
+///
+/// # Fields
+///
+/// * 'bytes' - The bytes that have been filled so far.
+/// * 'buffer' - A u64 holding bits not yet moved to bytes, the oldest in
+///         its least significant bits.
+/// * 'len' - The number of bits in the buffer.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    len: u8,
+}
+
+impl BitWriter {
+    /// Creates a new empty BitWriter.
+    ///
+    /// # Returns
+    ///
+    /// A BitWriter with nothing written to it.
+    pub fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            buffer: 0,
+            len: 0,
+        }
+    }
+    /// Writes the n least significant bits of value in the order DEFLATE
+    /// packs integers, least significant bit first.
+    ///
+    /// # Arguments
+    ///
+    /// * 'value' - The bits to write.
+    /// * 'n' - The number of bits to write, at most 32.
+    #[inline]
+    pub fn write_bits(&mut self, value: u32, n: u8) {
+        let value = value as u64 & ((1u64 << n) - 1);
+        self.buffer |= value << self.len;
+        self.len += n;
+
+        while self.len >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.len -= 8;
+        }
+    }
+    /// Writes a prefix code, which unlike an integer goes most significant
+    /// bit first.
+    ///
+    /// # Arguments
+    ///
+    /// * 'code' - The code to write.
+    #[inline]
+    pub fn write_code(&mut self, code: Code) {
+        let reversed = code.buffer.reverse_bits() >> (32 - code.length as u32);
+        self.write_bits(reversed, code.length);
+    }
+    /// Pads the stream with zeroes up to the next byte boundary.
+    pub fn align_to_byte(&mut self) {
+        if self.len > 0 {
+            self.write_bits(0, 8 - self.len);
+        }
+    }
+    /// Writes whole bytes, the stream has to be at a byte boundary.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - The bytes to write.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        debug_assert_eq!(self.len, 0);
+        self.bytes.extend_from_slice(bytes);
+    }
+    /// Returns the number of bits written so far.
+    pub fn bit_len(&self) -> u64 {
+        self.bytes.len() as u64 * 8 + self.len as u64
+    }
+    /// Takes the bytes that have been filled so far, leaving any bits of a
+    /// partly filled byte to be written later.
+    pub fn take_bytes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+    /// Returns how many bytes the writer has allocated for the bytes it's
+    /// filled.
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+    /// Pads the stream to a byte boundary and returns everything written.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.bytes
+    }
+}
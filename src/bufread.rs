@@ -0,0 +1,547 @@
+//! Decompressing readers over buffered readers.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::{
+    crc,
+    gzip::{verify_trailer, GzipError, GzipHeader, HeaderOptions, WRITE_BATCH},
+    inflate::{copy_prefix, BlockInfo, Inflater, MemoryUsage, StateReader, Status},
+};
+
+/// The most bytes a header read by a decoder made with
+/// GzDecoder::with_fixed_memory can take up, plenty for a file name and
+/// comment.
+pub const FIXED_HEADER_SIZE: usize = 1024;
+
+/// How far through a file a GzDecoder has got, handed to its progress hook.
+///
+/// # Fields
+///
+/// * 'member' - The index of the member being decompressed, counting from 0
+///         and going up each time the decoder is reset.
+/// * 'total_in' - The compressed bytes read so far, headers and trailers
+///         included, across every member.
+/// * 'total_out' - The bytes decompressed so far, across every member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub member: u64,
+    pub total_in: u64,
+    pub total_out: u64,
+}
+
+/// A hook called with a GzDecoder's Progress, see GzDecoder::set_progress.
+pub type ProgressHook = Box<dyn FnMut(&Progress) + Send>;
+
+/// A reader that decompresses a gzip file as it is read from, handing the
+/// inflater whatever compressed bytes the inner reader has buffered, so only
+/// the buffer and the inflater's window are ever held in memory.
+///
+/// # Fields
+///
+/// * 'reader' - The inner buffered reader.
+/// * 'header' - The file's header, read on the first call to read.
+/// * 'header_options' - The HeaderOptions to read the header with.
+/// * 'inflater' - The inflater decoding the DEFLATE data.
+/// * 'crc' - The CRC-32 of the bytes decompressed so far.
+/// * 'finished' - Whether the trailer has been read and checked.
+/// * 'progress' - How far through the file decompression is.
+/// * 'progress_hook' - A hook called with progress after every read.
+pub struct GzDecoder<R: BufRead> {
+    reader: R,
+    header: Option<GzipHeader>,
+    header_options: HeaderOptions,
+    inflater: Inflater,
+    crc: u32,
+    finished: bool,
+    progress: Progress,
+    progress_hook: Option<ProgressHook>,
+}
+
+impl GzDecoder<BufReader<File>> {
+    /// Opens a gzip file to be decompressed as it's read, rather than reading
+    /// the whole file in first like GzipFile::from_path.
+    ///
+    /// # Arguments
+    ///
+    /// * 'path' - A path in the form of any type that can be coerced into a
+    ///         Path.
+    ///
+    /// # Returns
+    ///
+    /// The decoder, or a GzipError if the file couldn't be opened.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, GzipError> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    /// Wraps a buffered reader holding a gzip file. Nothing is read until the
+    /// first call to read.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            header: None,
+            header_options: HeaderOptions::default(),
+            inflater: Inflater::new(),
+            crc: 0,
+            finished: false,
+            progress: Progress {
+                member: 0,
+                total_in: 0,
+                total_out: 0,
+            },
+            progress_hook: None,
+        }
+    }
+    /// Like new, but the decoder allocates no more than
+    /// inflate::FIXED_MEMORY_USAGE bytes for the inflater and
+    /// FIXED_HEADER_SIZE for the header, so with a reader that buffers into
+    /// memory of its own the whole file can be decompressed in a known
+    /// amount of memory, see Inflater::with_fixed_memory. Headers longer than
+    /// FIXED_HEADER_SIZE fail with GzipError::InvalidHeader.
+    pub fn with_fixed_memory(reader: R) -> Self {
+        Self {
+            header_options: HeaderOptions {
+                max_size: Some(FIXED_HEADER_SIZE),
+                ..HeaderOptions::default()
+            },
+            inflater: Inflater::with_fixed_memory(),
+            ..Self::new(reader)
+        }
+    }
+    /// Returns the file's header, or None if it hasn't been read yet.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.header.as_ref()
+    }
+    /// Reads the header if it hasn't been already, for when it's needed
+    /// before any of the file is decompressed.
+    ///
+    /// # Returns
+    ///
+    /// The file's header, or a GzipError if it couldn't be read.
+    pub fn read_header(&mut self) -> Result<&GzipHeader, GzipError> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => {
+                let header =
+                    GzipHeader::from_reader_with_options(&mut self.reader, &self.header_options)?;
+                self.progress.total_in += header.end_idx as u64;
+                header
+            }
+        };
+
+        Ok(self.header.insert(header))
+    }
+    /// Sets the HeaderOptions the header is read with, which only has an
+    /// effect before it's been read.
+    ///
+    /// # Arguments
+    ///
+    /// * 'options' - The HeaderOptions to read the header with.
+    pub fn set_header_options(&mut self, options: HeaderOptions) {
+        self.header_options = options;
+    }
+    /// Limits how large the file can decompress to, reading past the limit
+    /// fails with an io::Error wrapping DeflateError::OutputLimitError.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to decompress, or None for no limit.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.inflater.set_max_output_size(limit);
+    }
+    /// Limits how many times larger than the compressed data the output can
+    /// be, reading past the limit fails with an io::Error wrapping
+    /// DeflateError::ExpansionRatioError.
+    ///
+    /// # Arguments
+    ///
+    /// * 'ratio' - The most bytes of output per byte of input, or None for
+    ///         no limit.
+    pub fn set_max_expansion_ratio(&mut self, ratio: Option<u64>) {
+        self.inflater.set_max_expansion_ratio(ratio);
+    }
+    /// Limits how much memory the inflater can allocate, see
+    /// Inflater::set_memory_limit.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to allocate, or None for no limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.inflater.set_memory_limit(limit);
+    }
+    /// Keeps the last few events decoded and attaches them to any error,
+    /// see Inflater::set_history.
+    ///
+    /// # Arguments
+    ///
+    /// * 'len' - How many events to keep, or None to keep none.
+    pub fn set_history(&mut self, len: Option<usize>) {
+        self.inflater.set_history(len);
+    }
+    /// Sets whether a BlockInfo is kept for every block, see
+    /// Inflater::set_record_blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * 'record' - Whether to record blocks.
+    pub fn set_record_blocks(&mut self, record: bool) {
+        self.inflater.set_record_blocks(record);
+    }
+    /// Returns every block that has been read so far.
+    pub fn blocks(&self) -> &[BlockInfo] {
+        self.inflater.blocks()
+    }
+    /// Returns how much heap memory the decoder has allocated, the
+    /// inflater's and the header's, see Inflater::memory_usage. The inner
+    /// reader's buffer isn't the decoder's, so it isn't counted.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.inflater.memory_usage();
+        usage.buffers += self.header.as_ref().map_or(0, GzipHeader::memory_usage);
+        usage
+    }
+    /// Sets a flag that cancels decompressing, reading once it's set fails
+    /// with an io::Error wrapping GzipError::Cancelled, see
+    /// Inflater::set_cancel.
+    ///
+    /// # Arguments
+    ///
+    /// * 'cancel' - The flag to check, or None to never be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.inflater.set_cancel(cancel);
+    }
+    /// Sets a hook to be called with the decoder's Progress at the end of
+    /// every read, for showing how far through a large file decompression
+    /// is. Against the size of the file, total_in gives the fraction done.
+    /// The hook is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'hook' - The hook to call, or None to stop reporting progress.
+    pub fn set_progress(&mut self, hook: Option<ProgressHook>) {
+        self.progress_hook = hook;
+    }
+    /// Returns how far through the file decompression is.
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+    /// Saves where the decoder is in the file, so decompressing can be
+    /// picked back up later by restore_state, in another process or on
+    /// another machine. That covers the header, the checksum of what's been
+    /// decompressed, progress, and the inflater's state, see
+    /// Inflater::save_state. Settings and hooks aren't saved.
+    ///
+    /// # Returns
+    ///
+    /// The saved state. The reader has to carry on from progress().total_in
+    /// bytes into the file when it's restored.
+    pub fn save_state(&self) -> Vec<u8> {
+        let header = self.header.as_ref().map(GzipHeader::to_bytes);
+
+        let mut bytes = vec![header.is_some() as u8];
+        let header = header.unwrap_or_default();
+        bytes.extend((header.len() as u64).to_le_bytes());
+        bytes.extend(header);
+        bytes.extend(self.crc.to_le_bytes());
+        bytes.push(self.finished as u8);
+        for value in [
+            self.progress.member,
+            self.progress.total_in,
+            self.progress.total_out,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(self.inflater.save_state());
+
+        bytes
+    }
+    /// Creates a decoder from a state saved by save_state, ready to carry on
+    /// decompressing from the same point.
+    ///
+    /// # Arguments
+    ///
+    /// * 'reader' - The file, positioned progress().total_in bytes in, as it
+    ///         was when the state was saved.
+    /// * 'state' - The saved state.
+    ///
+    /// # Returns
+    ///
+    /// The restored decoder, with the default settings. Or, a GzipError
+    /// wrapping DeflateError::InvalidStateError if state wasn't written by
+    /// save_state.
+    pub fn restore_state(reader: R, state: &[u8]) -> Result<Self, GzipError> {
+        let mut decoder = Self::new(reader);
+        let mut state = StateReader::new(state);
+
+        let has_header = state.bool()?;
+        let header = state.bytes(u32::MAX as usize)?;
+        if has_header {
+            decoder.header = Some(GzipHeader::build(header)?);
+        }
+
+        decoder.crc = state.u32()?;
+        decoder.finished = state.bool()?;
+        decoder.progress = Progress {
+            member: state.u64()?,
+            total_in: state.u64()?,
+            total_out: state.u64()?,
+        };
+        decoder.inflater = Inflater::restore_state(state.rest())?;
+
+        Ok(decoder)
+    }
+    /// Decompresses the rest of the member into a writer, like io::copy but
+    /// gathering WRITE_BATCH bytes of output before each write rather than
+    /// 8KB, so sinks such as sockets and files see far fewer writes. The
+    /// batch is allocated on each call.
+    ///
+    /// # Arguments
+    ///
+    /// * 'writer' - The writer to write the decompressed data to. Whatever
+    ///         was decompressed before an error is still written.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written, or the io::Error from reading or
+    /// writing.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<u64> {
+        let mut batch = vec![0; WRITE_BATCH];
+        let mut total = 0;
+
+        loop {
+            let mut filled = 0;
+            let mut result = Ok(());
+            while filled < batch.len() {
+                match self.read(batch.get_mut(filled..).unwrap_or_default()) {
+                    Ok(0) => break,
+                    Ok(read) => filled += read,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+
+            writer.write_all(batch.get(..filled).unwrap_or_default())?;
+            total += filled as u64;
+            result?;
+
+            if filled < batch.len() {
+                return Ok(total);
+            }
+        }
+    }
+    /// Gets ready to decompress the member after this one from the same
+    /// reader, keeping the inflater's buffers and every setting, so a file
+    /// of many members doesn't need a new decoder for each.
+    pub fn reset(&mut self) {
+        self.header = None;
+        self.inflater.reset();
+        self.crc = 0;
+        self.finished = false;
+        self.progress.member += 1;
+    }
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+    /// Returns a mutable reference to the inner reader, for checking whether
+    /// another member follows. Reading from it partway through a member
+    /// will corrupt the output.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+    /// Returns the inner reader, positioned after the trailer if the whole
+    /// file has been read.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+    /// Reads the 8 byte trailer after the DEFLATE data and checks it against
+    /// what was decompressed.
+    fn read_trailer(&mut self) -> Result<(), GzipError> {
+        let mut trailer = [0u8; 8];
+        self.reader.read_exact(&mut trailer)?;
+
+        let crc32 = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let isize = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        self.progress.total_in += 8;
+        verify_trailer(crc32, isize, self.crc, self.inflater.total_out())?;
+
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.read_member(buf);
+        if let Some(hook) = &mut self.progress_hook {
+            hook(&self.progress);
+        }
+        result
+    }
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    /// Does the work of read, decompressing from the current member.
+    fn read_member(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_header()?;
+
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let input = self.reader.fill_buf()?;
+            let eof = input.is_empty();
+
+            let (consumed, produced, status) =
+                self.inflater.inflate(input, buf).map_err(GzipError::from)?;
+
+            self.reader.consume(consumed);
+            self.crc = crc::update(self.crc, buf.get(..produced).unwrap_or_default());
+            self.progress.total_in += consumed as u64;
+            self.progress.total_out += produced as u64;
+
+            match status {
+                Status::StreamEnd => {
+                    self.read_trailer()?;
+                    return Ok(produced);
+                }
+                Status::NeedsInput if eof => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        self.inflater.unexpected_eof(),
+                    ));
+                }
+                _ if produced > 0 => return Ok(produced),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The size of the buffer MultiGzDecoder decompresses into for BufRead.
+const MULTI_BUFFER: usize = 32768;
+
+/// A reader that decompresses every member of a gzip file one after the
+/// other, as gzip -d does, so a file made by concatenating gzip files, or
+/// written by a tool that starts a new member every so often, reads as one
+/// stream. This is what .tar.gz extraction wants, the tar reader sees the
+/// whole archive however many members it was compressed into.
+///
+/// # Fields
+///
+/// * 'decoder' - The decoder for the current member, reset for each one.
+/// * 'buffer' - Decompressed bytes for BufRead, allocated on the first call
+///         to fill_buf.
+/// * 'pos' - The index of the next byte of buffer to hand out.
+/// * 'filled' - How many bytes at the start of buffer are decompressed.
+pub struct MultiGzDecoder<R: BufRead> {
+    decoder: GzDecoder<R>,
+    buffer: Vec<u8>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: BufRead> MultiGzDecoder<R> {
+    /// Wraps a buffered reader holding a gzip file of any number of
+    /// members. Nothing is read until the first call to read.
+    pub fn new(reader: R) -> Self {
+        Self {
+            decoder: GzDecoder::new(reader),
+            buffer: Vec::new(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+    /// Returns the header of the member being decompressed, or None if it
+    /// hasn't been read yet.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.decoder.header()
+    }
+    /// Returns how far through the file decompression is, with member
+    /// counting the members started before this one.
+    pub fn progress(&self) -> Progress {
+        self.decoder.progress()
+    }
+    /// Returns how much heap memory the decoder has allocated, the
+    /// member's decoder's, see GzDecoder::memory_usage, and the BufRead
+    /// buffer.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.decoder.memory_usage();
+        usage.buffers += self.buffer.capacity();
+        usage
+    }
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        self.decoder.get_ref()
+    }
+    /// Returns the inner reader, positioned after the last member if the
+    /// whole file has been read.
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+    /// Reads from the current member, moving on to the next when it ends,
+    /// until there's output or the inner reader has nothing left.
+    fn read_members(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.decoder.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            if self.decoder.get_mut().fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            self.decoder.reset();
+        }
+    }
+}
+
+impl<R: BufRead> Read for MultiGzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Bytes already in the BufRead buffer come first.
+        if self.pos < self.filled {
+            let len = copy_prefix(buf, self.fill_buf()?);
+            self.consume(len);
+            return Ok(len);
+        }
+        self.read_members(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for MultiGzDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.filled {
+            if self.buffer.is_empty() {
+                self.buffer = vec![0; MULTI_BUFFER];
+            }
+            let mut buffer = std::mem::take(&mut self.buffer);
+            let result = self.read_members(&mut buffer);
+            self.buffer = buffer;
+
+            self.filled = result?;
+            self.pos = 0;
+        }
+        Ok(self.buffer.get(self.pos..self.filled).unwrap_or_default())
+    }
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
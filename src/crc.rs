@@ -1,7 +1,25 @@
+// Runs on untrusted input, so nothing here may be able to panic, see Panics
+// in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+
 pub fn hash(buf: &[u8]) -> u32 {
-    let mut crc = !0;
+    update(0, buf)
+}
+/// Continues a CRC-32 over more bytes, so data that arrives in pieces can be
+/// hashed as it goes. Starting from 0, update(update(0, a), b) is the same
+/// as hash of a followed by b.
+pub fn update(crc: u32, buf: &[u8]) -> u32 {
+    let mut crc = !crc;
     for &byte in buf.iter() {
-        crc = CRC32_TABLE[((crc as u8) ^ byte) as usize] ^ (crc >> 8);
+        // A u8 can't index past the table, so this never falls back to 0.
+        let entry = CRC32_TABLE.get(((crc as u8) ^ byte) as usize);
+        crc = entry.copied().unwrap_or_default() ^ (crc >> 8);
     }
     !crc
 }
@@ -0,0 +1,811 @@
+//! DEFLATE encoding of LZ77 tokens.
+//!
+//! write_block takes a block's literals and matches and writes them as a
+//! stored, fixed, or dynamic block, whichever is asked for or comes out
+//! smallest. Finding the matches is left to the caller, so the tokens can
+//! come from anywhere, such as a stream that's already been compressed.
+//!
+//! compress does the whole job for a buffer, with a simple greedy match
+//! finder. It's there for round trips and tests rather than to compete with
+//! zlib, so it favours being easy to follow over squeezing out every byte.
+use crate::{
+    adler,
+    bits::BitWriter,
+    crc,
+    gzip::GzipHeader,
+    prefix::{
+        canonical_codes, cl_code_count, distance_code, huffman_lengths, length_code, Code,
+        CL_CODES, CL_CODE_ORDER, DISTANCE_BASE, DISTANCE_EXTRA_BITS, FIXED_CODE_LENGTHS,
+        FIXED_DISTANCE_CODES, FIXED_DISTANCE_LENGTHS, FIXED_LITERAL_CODES, HCLEN_BASE, HDIST_BASE,
+        HLIT_BASE, LENGTH_BASE, LENGTH_EXTRA_BITS, MAX_CL_CODE_LENGTH, MAX_CODE_LENGTH,
+    },
+    stream::{BlockType, MemoryUsage, WINDOW_SIZE},
+    zlib,
+};
+
+/// The most bytes a single stored block can hold.
+const MAX_STORED: usize = 65535;
+
+/// How many bytes StreamCompressor gathers before compressing them, if it
+/// isn't flushed first.
+const INPUT_CHUNK: usize = 1 << 16;
+
+/// The symbol that ends every compressed block.
+const END_OF_BLOCK: usize = 256;
+
+/// The shortest and longest matches DEFLATE can code.
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+/// The furthest back a match can reach.
+const MAX_DISTANCE: usize = 32768;
+
+/// The number of bits match finding hashes the next 3 bytes to.
+const HASH_BITS: u32 = 15;
+
+/// How compress finds matches and splits them into blocks.
+///
+/// # Fields
+///
+/// * 'block_type' - The type every block is written as, or None for
+///         whichever is smallest for each block.
+/// * 'block_size' - The most tokens to put in each block. Defaults to 16383,
+///         the same as zlib.
+/// * 'max_chain' - How many earlier positions with the same hash to try for
+///         a match at each position. 0 turns match finding off, so every
+///         byte is a literal. Defaults to 128.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressOptions {
+    pub block_type: Option<BlockType>,
+    pub block_size: usize,
+    pub max_chain: usize,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self {
+            block_type: None,
+            block_size: 16383,
+            max_chain: 128,
+        }
+    }
+}
+
+/// A single item of LZ77 compressed data.
+///
+/// # Variants
+///
+/// * 'Literal' - A byte to output as it is.
+/// * 'Match' - A copy of length bytes, from 3 to 258, from distance bytes
+///         back, from 1 to 32768.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+/// What went into a DEFLATE stream, counted as it's compressed.
+///
+/// # Fields
+///
+/// * 'stored_blocks' - The number of stored blocks written. Data too long
+///         for one stored block counts once for each it's split over.
+/// * 'fixed_blocks' - The number of blocks written with the fixed codes.
+/// * 'dynamic_blocks' - The number of blocks written with their own codes.
+/// * 'literals' - The number of literals coded, stored bytes not included.
+/// * 'matches' - The number of matches coded.
+/// * 'match_length' - The lengths of every match added up.
+/// * 'match_distance' - The distances of every match added up.
+/// * 'header_bits' - The bits spent on block headers: BFINAL and BTYPE, a
+///         dynamic block's codes, and a stored block's padding, LEN and NLEN.
+/// * 'data_bits' - The bits spent on everything else, the codes and extra
+///         bits of literals, matches and ends of block, and stored bytes.
+/// * 'bytes_in' - The number of bytes compressed.
+/// * 'bytes_out' - The number of bytes of stream written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressStats {
+    pub stored_blocks: u64,
+    pub fixed_blocks: u64,
+    pub dynamic_blocks: u64,
+    pub literals: u64,
+    pub matches: u64,
+    pub match_length: u64,
+    pub match_distance: u64,
+    pub header_bits: u64,
+    pub data_bits: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl CompressStats {
+    /// Returns the average length of a match, or 0 if there weren't any.
+    pub fn average_match_length(&self) -> f64 {
+        self.match_length as f64 / self.matches.max(1) as f64
+    }
+    /// Returns the average distance of a match, or 0 if there weren't any.
+    pub fn average_match_distance(&self) -> f64 {
+        self.match_distance as f64 / self.matches.max(1) as f64
+    }
+    /// Counts a block that's just been written.
+    fn add_block(&mut self, block: &WrittenBlock, tokens: &[Token]) {
+        match block.block_type {
+            BlockType::Stored => self.stored_blocks += block.blocks,
+            BlockType::Fixed => self.fixed_blocks += 1,
+            BlockType::Dynamic => self.dynamic_blocks += 1,
+        }
+        if block.block_type != BlockType::Stored {
+            for token in tokens {
+                match *token {
+                    Token::Literal(_) => self.literals += 1,
+                    Token::Match { length, distance } => {
+                        self.matches += 1;
+                        self.match_length += length as u64;
+                        self.match_distance += distance as u64;
+                    }
+                }
+            }
+        }
+        self.header_bits += block.header_bits;
+        self.data_bits += block.bits - block.header_bits;
+    }
+}
+
+/// Compresses a buffer into a raw DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'data' - The bytes to compress.
+/// * 'options' - How to find matches and split blocks.
+///
+/// # Returns
+///
+/// The DEFLATE stream.
+pub fn compress(data: &[u8], options: &CompressOptions) -> Vec<u8> {
+    compress_with_stats(data, options).0
+}
+
+/// Like compress, but also counts the blocks, literals and matches written
+/// and the bits they took.
+///
+/// # Arguments
+///
+/// * 'data' - The bytes to compress.
+/// * 'options' - How to find matches and split blocks.
+///
+/// # Returns
+///
+/// The DEFLATE stream, and its CompressStats.
+pub fn compress_with_stats(data: &[u8], options: &CompressOptions) -> (Vec<u8>, CompressStats) {
+    let mut writer = BitWriter::new();
+    let mut stats = CompressStats::default();
+    compress_blocks(&mut writer, data, 0, options, true, &mut stats);
+
+    let output = writer.finish();
+    stats.bytes_out = output.len() as u64;
+    (output, stats)
+}
+
+/// Compresses the bytes of data from start on as blocks, letting matches
+/// reach back into the bytes before start, so a stream can be compressed a
+/// piece at a time with the end of the last piece as its window.
+///
+/// # Arguments
+///
+/// * 'writer' - Where to write the blocks.
+/// * 'data' - The bytes already compressed to match against, then the
+///         bytes to compress.
+/// * 'start' - The index of the first byte to compress.
+/// * 'options' - How to find matches and split blocks.
+/// * 'final_block' - Whether to set BFINAL on the last block. If there's
+///         nothing to compress, an empty block is only written when it is.
+/// * 'stats' - Where to count the blocks written, and the bytes compressed.
+pub(crate) fn compress_blocks(
+    writer: &mut BitWriter,
+    data: &[u8],
+    start: usize,
+    options: &CompressOptions,
+    final_block: bool,
+    stats: &mut CompressStats,
+) {
+    let tokens = find_matches(data, start, options.max_chain);
+    let data = &data[start..];
+    stats.bytes_in += data.len() as u64;
+
+    let chunks = tokens.chunks(options.block_size.max(1));
+    let blocks = chunks.len();
+    let mut start = 0;
+    for (index, chunk) in chunks.enumerate() {
+        let len = chunk
+            .iter()
+            .map(|token| match *token {
+                Token::Literal(_) => 1,
+                Token::Match { length, .. } => length,
+            })
+            .sum::<usize>();
+
+        let block = write_counted_block(
+            writer,
+            chunk,
+            &data[start..start + len],
+            options.block_type,
+            final_block && index == blocks - 1,
+        );
+        stats.add_block(&block, chunk);
+        start += len;
+    }
+    // Empty data still needs a block to end the stream.
+    if blocks == 0 && final_block {
+        let block = write_counted_block(writer, &[], &[], options.block_type, true);
+        stats.add_block(&block, &[]);
+    }
+}
+
+/// Compresses a stream a piece at a time, keeping the end of what it's
+/// already compressed as the window for the next piece.
+///
+/// # Fields
+///
+/// * 'options' - How to find matches and split blocks.
+/// * 'bits' - The stream, less the whole bytes already taken from it.
+/// * 'input' - Up to WINDOW_SIZE bytes already compressed, then the bytes
+///         waiting to be.
+/// * 'compressed' - The index in input of the first waiting byte.
+/// * 'stats' - What's been written so far.
+pub(crate) struct StreamCompressor {
+    options: CompressOptions,
+    bits: BitWriter,
+    input: Vec<u8>,
+    compressed: usize,
+    stats: CompressStats,
+}
+
+impl StreamCompressor {
+    pub(crate) fn new(options: CompressOptions) -> Self {
+        Self {
+            options,
+            bits: BitWriter::new(),
+            input: Vec::new(),
+            compressed: 0,
+            stats: CompressStats::default(),
+        }
+    }
+    /// Returns what's been compressed so far. Bytes still waiting to be
+    /// compressed aren't counted.
+    pub(crate) fn stats(&self) -> &CompressStats {
+        &self.stats
+    }
+    /// Returns how much heap memory the compressor is holding on to, its
+    /// input as the window and the stream's bytes not yet taken as buffers.
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            window: self.input.capacity(),
+            tables: 0,
+            buffers: self.bits.capacity(),
+        }
+    }
+    /// Adds bytes to compress, compressing once INPUT_CHUNK are waiting.
+    /// Returns whatever whole bytes of the stream that made.
+    pub(crate) fn write(&mut self, data: &[u8]) -> Vec<u8> {
+        self.input.extend_from_slice(data);
+
+        if self.input.len() - self.compressed >= INPUT_CHUNK {
+            self.compress(false)
+        } else {
+            Vec::new()
+        }
+    }
+    /// Compresses the waiting bytes, returning every whole byte of the
+    /// stream so far. The final block is padded out to a byte.
+    pub(crate) fn compress(&mut self, final_block: bool) -> Vec<u8> {
+        compress_blocks(
+            &mut self.bits,
+            &self.input,
+            self.compressed,
+            &self.options,
+            final_block,
+            &mut self.stats,
+        );
+        if final_block {
+            self.bits.align_to_byte();
+        }
+
+        self.input
+            .drain(..self.input.len().saturating_sub(WINDOW_SIZE));
+        self.compressed = self.input.len();
+        self.take_bytes()
+    }
+    /// Compresses the waiting bytes and ends them with an empty stored
+    /// block, so everything so far can be decoded from the bytes returned.
+    pub(crate) fn sync_flush(&mut self) -> Vec<u8> {
+        let mut output = self.compress(false);
+        let block = write_counted_block(&mut self.bits, &[], &[], Some(BlockType::Stored), false);
+        self.stats.add_block(&block, &[]);
+        output.extend(self.take_bytes());
+        output
+    }
+    /// Takes the whole bytes of the stream written so far, counting them.
+    fn take_bytes(&mut self) -> Vec<u8> {
+        let bytes = self.bits.take_bytes();
+        self.stats.bytes_out += bytes.len() as u64;
+        bytes
+    }
+}
+
+/// Compresses a buffer into a single member gzip file, with no name,
+/// timestamp, or other optional fields.
+///
+/// # Arguments
+///
+/// * 'data' - The bytes to compress.
+/// * 'options' - How to find matches and split blocks.
+///
+/// # Returns
+///
+/// The gzip file.
+pub fn compress_gzip(data: &[u8], options: &CompressOptions) -> Vec<u8> {
+    let mut output = GzipHeader::default().to_bytes();
+    output.extend(compress(data, options));
+    output.extend(crc::hash(data).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+
+    output
+}
+
+/// Compresses a buffer into a zlib stream, with no preset dictionary.
+///
+/// # Arguments
+///
+/// * 'data' - The bytes to compress.
+/// * 'options' - How to find matches and split blocks.
+///
+/// # Returns
+///
+/// The zlib stream.
+pub fn compress_zlib(data: &[u8], options: &CompressOptions) -> Vec<u8> {
+    let mut output = zlib::HEADER.to_vec();
+    output.extend(compress(data, options));
+    output.extend(adler::hash(data).to_be_bytes());
+
+    output
+}
+
+/// Returns the most heap memory compress can allocate at once, for
+/// budgeting before compressing. That's the match finder's hash chains, the
+/// tokens, and the output, each Vec allowed to have grown to twice what it
+/// holds. Each block also briefly allocates a few KB to build its codes,
+/// which isn't counted.
+///
+/// # Arguments
+///
+/// * 'data_len' - The number of bytes to compress.
+/// * 'options' - The options they'll be compressed with.
+///
+/// # Returns
+///
+/// The upper bound in bytes.
+pub fn memory_usage(data_len: usize, options: &CompressOptions) -> usize {
+    let chains = ((1 << HASH_BITS) + data_len) * std::mem::size_of::<usize>();
+    let tokens = 2 * data_len.max(2) * std::mem::size_of::<Token>();
+
+    // No code is longer than 15 bits, so no byte takes more than 2 to write,
+    // and a dynamic block's header is never more than 320.
+    let blocks = data_len / options.block_size.max(1) + data_len / MAX_STORED + 2;
+    let output = 2 * (2 * data_len + 320 * blocks);
+
+    chains + tokens + output
+}
+
+/// Writes a block holding the given tokens.
+///
+/// # Arguments
+///
+/// * 'writer' - Where to write the block.
+/// * 'tokens' - The block's literals and matches, without the end of block.
+/// * 'data' - The bytes the tokens decompress to, for if the block is
+///         stored.
+/// * 'block_type' - The type of block to write, or None for whichever is
+///         smallest. Stored data too long for one block is split over
+///         several.
+/// * 'final_block' - Whether to set BFINAL, on the last block if the data
+///         is split.
+///
+/// # Returns
+///
+/// The type of block written.
+pub fn write_block(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    data: &[u8],
+    block_type: Option<BlockType>,
+    final_block: bool,
+) -> BlockType {
+    write_counted_block(writer, tokens, data, block_type, final_block).block_type
+}
+
+/// What write_counted_block wrote.
+///
+/// # Fields
+///
+/// * 'block_type' - The type of block written.
+/// * 'blocks' - How many blocks that took, only more than 1 for stored data
+///         too long for one.
+/// * 'bits' - The bits written.
+/// * 'header_bits' - How many of those went on block headers.
+struct WrittenBlock {
+    block_type: BlockType,
+    blocks: u64,
+    bits: u64,
+    header_bits: u64,
+}
+
+/// Does the work of write_block, also measuring what it wrote for
+/// CompressStats.
+fn write_counted_block(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    data: &[u8],
+    block_type: Option<BlockType>,
+    final_block: bool,
+) -> WrittenBlock {
+    let start = writer.bit_len();
+    let (literal_lengths, distances, extra_bits) = frequencies(tokens);
+    let dynamic = DynamicHeader::build(&literal_lengths, &distances);
+
+    let block_type = block_type.unwrap_or_else(|| {
+        let fixed = 3
+            + extra_bits
+            + cost(&literal_lengths, &FIXED_CODE_LENGTHS)
+            + cost(&distances, &FIXED_DISTANCE_LENGTHS);
+        let dynamic = 3
+            + extra_bits
+            + dynamic.bits()
+            + cost(&literal_lengths, &dynamic.literal_lengths)
+            + cost(&distances, &dynamic.distance_lengths);
+        let stored = stored_bits(writer.bit_len(), data.len());
+
+        if stored < fixed.min(dynamic) {
+            BlockType::Stored
+        } else if dynamic < fixed {
+            BlockType::Dynamic
+        } else {
+            BlockType::Fixed
+        }
+    });
+
+    let header_bits = match block_type {
+        BlockType::Stored => stored_bits(start, data.len()) - data.len() as u64 * 8,
+        BlockType::Fixed => 3,
+        BlockType::Dynamic => 3 + dynamic.bits(),
+    };
+    match block_type {
+        BlockType::Stored => write_stored(writer, data, final_block),
+        BlockType::Fixed => {
+            writer.write_bits(final_block as u32, 1);
+            writer.write_bits(1, 2);
+            write_tokens(writer, tokens, &FIXED_LITERAL_CODES, &FIXED_DISTANCE_CODES);
+        }
+        BlockType::Dynamic => {
+            writer.write_bits(final_block as u32, 1);
+            writer.write_bits(2, 2);
+            dynamic.write(writer);
+
+            let literal_codes = codes(&dynamic.literal_lengths);
+            let distance_codes = codes(&dynamic.distance_lengths);
+            write_tokens(writer, tokens, &literal_codes, &distance_codes);
+        }
+    }
+
+    WrittenBlock {
+        block_type,
+        blocks: match block_type {
+            BlockType::Stored => data.len().div_ceil(MAX_STORED).max(1) as u64,
+            _ => 1,
+        },
+        bits: writer.bit_len() - start,
+        header_bits,
+    }
+}
+
+/// The code lengths of a dynamic block, and the code length code that
+/// describes them.
+///
+/// # Fields
+///
+/// * 'literal_lengths' - The literal/length code lengths, HLIT of them.
+/// * 'distance_lengths' - The distance code lengths, HDIST of them.
+/// * 'code_length_lengths' - The code length code lengths, by symbol.
+/// * 'hclen' - How many code length code lengths are sent.
+/// * 'code_lengths' - The code length symbols describing both sets of
+///         lengths, with the value of each one's extra bits.
+struct DynamicHeader {
+    literal_lengths: Vec<u8>,
+    distance_lengths: Vec<u8>,
+    code_length_lengths: Vec<u8>,
+    hclen: usize,
+    code_lengths: Vec<(usize, u32)>,
+}
+
+impl DynamicHeader {
+    /// Builds the codes for a block's symbol frequencies.
+    fn build(literal_lengths: &[u64], distances: &[u64]) -> Self {
+        let mut literal_lengths = huffman_lengths(literal_lengths, MAX_CODE_LENGTH);
+        let mut distance_lengths = huffman_lengths(distances, MAX_CODE_LENGTH);
+
+        // Unused codes at the end don't need sending.
+        let hlit = HLIT_BASE.max(literal_lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+        let hdist = HDIST_BASE.max(distance_lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+        literal_lengths.truncate(hlit);
+        distance_lengths.truncate(hdist);
+
+        let code_lengths = run_lengths(&[&literal_lengths[..], &distance_lengths[..]].concat());
+
+        let mut frequencies = [0; CL_CODES];
+        for &(symbol, _) in &code_lengths {
+            frequencies[symbol] += 1;
+        }
+        let code_length_lengths = huffman_lengths(&frequencies, MAX_CL_CODE_LENGTH);
+        let hclen = cl_code_count(&code_length_lengths);
+
+        Self {
+            literal_lengths,
+            distance_lengths,
+            code_length_lengths,
+            hclen,
+            code_lengths,
+        }
+    }
+    /// Returns the number of bits the header takes after BTYPE.
+    fn bits(&self) -> u64 {
+        let code_lengths = self
+            .code_lengths
+            .iter()
+            .map(|&(symbol, _)| (self.code_length_lengths[symbol] + extra_bits(symbol)) as u64)
+            .sum::<u64>();
+
+        5 + 5 + 4 + 3 * self.hclen as u64 + code_lengths
+    }
+    /// Writes the header after BTYPE.
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write_bits((self.literal_lengths.len() - HLIT_BASE) as u32, 5);
+        writer.write_bits((self.distance_lengths.len() - HDIST_BASE) as u32, 5);
+        writer.write_bits((self.hclen - HCLEN_BASE) as u32, 4);
+        for &symbol in &CL_CODE_ORDER[..self.hclen] {
+            writer.write_bits(self.code_length_lengths[symbol] as u32, 3);
+        }
+
+        let codes = codes(&self.code_length_lengths);
+        for &(symbol, extra) in &self.code_lengths {
+            writer.write_code(codes[symbol]);
+            writer.write_bits(extra, extra_bits(symbol));
+        }
+    }
+}
+
+/// Splits the bytes of data from start on into literals and matches,
+/// greedily taking the longest match at each position out of the last
+/// max_chain with the same hash. The bytes before start are only matched
+/// against.
+fn find_matches(data: &[u8], start: usize, max_chain: usize) -> Vec<Token> {
+    let hash = |position: usize| {
+        let bytes = u32::from_le_bytes([data[position], data[position + 1], data[position + 2], 0]);
+        (bytes.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    // The latest position with each hash, and the one before each position
+    // with the same hash, both as position + 1 so 0 can mean none.
+    let mut head = vec![0; 1 << HASH_BITS];
+    let mut previous = vec![0; data.len()];
+    // Positions from here on don't have 3 bytes left to hash.
+    let hashable = data.len().saturating_sub(MIN_MATCH - 1);
+
+    if max_chain > 0 {
+        let window = start.min(hashable);
+        for (position, previous) in previous[..window].iter_mut().enumerate() {
+            let hash = hash(position);
+            *previous = head[hash];
+            head[hash] = position + 1;
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut position = start;
+    while position < data.len() {
+        let (mut length, mut distance) = (0, 0);
+
+        if max_chain > 0 && position < hashable {
+            let longest = (data.len() - position).min(MAX_MATCH);
+            let mut candidate = head[hash(position)];
+            for _ in 0..max_chain {
+                if candidate == 0 || position - (candidate - 1) > MAX_DISTANCE {
+                    break;
+                }
+                let start = candidate - 1;
+                let matched = data[start..]
+                    .iter()
+                    .zip(&data[position..position + longest])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if matched > length {
+                    (length, distance) = (matched, position - start);
+                    if matched == longest {
+                        break;
+                    }
+                }
+                candidate = previous[start];
+            }
+        }
+
+        if length >= MIN_MATCH {
+            tokens.push(Token::Match { length, distance });
+        } else {
+            tokens.push(Token::Literal(data[position]));
+            length = 1;
+        }
+
+        // Every position covered goes in the chains, for later matches.
+        let end = (position + length).min(hashable);
+        let covered = position.min(end)..end;
+        for (covered, previous) in covered.clone().zip(&mut previous[covered]) {
+            let hash = hash(covered);
+            *previous = head[hash];
+            head[hash] = covered + 1;
+        }
+        position += length;
+    }
+
+    tokens
+}
+
+/// Counts how often each literal/length and distance symbol is used by a
+/// block's tokens, and how many extra bits they need.
+fn frequencies(tokens: &[Token]) -> ([u64; 286], [u64; 30], u64) {
+    let mut literal_lengths = [0; 286];
+    let mut distances = [0; 30];
+    let mut extra_bits = 0;
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literal_lengths[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let length = length_code(length);
+                let distance = distance_code(distance);
+                literal_lengths[END_OF_BLOCK + 1 + length] += 1;
+                distances[distance] += 1;
+                extra_bits += (LENGTH_EXTRA_BITS[length] + DISTANCE_EXTRA_BITS[distance]) as u64;
+            }
+        }
+    }
+    literal_lengths[END_OF_BLOCK] += 1;
+
+    (literal_lengths, distances, extra_bits)
+}
+
+/// Returns the bits a set of symbols takes with the given code lengths.
+fn cost(frequencies: &[u64], lengths: &[u8]) -> u64 {
+    frequencies
+        .iter()
+        .zip(lengths)
+        .map(|(&frequency, &length)| frequency * length as u64)
+        .sum()
+}
+
+/// Returns the bits data would take as stored blocks written from bit
+/// offset start, with the padding to the first byte boundary.
+fn stored_bits(start: u64, len: usize) -> u64 {
+    let blocks = len.div_ceil(MAX_STORED).max(1) as u64;
+    let padding = (8 - (start + 3) % 8) % 8;
+
+    // Blocks after the first start on a byte boundary, so always have 5
+    // bits of padding.
+    3 + padding + 32 + (blocks - 1) * 40 + len as u64 * 8
+}
+
+/// Writes data as stored blocks, as many as it takes.
+fn write_stored(writer: &mut BitWriter, data: &[u8], final_block: bool) {
+    let blocks = data.len().div_ceil(MAX_STORED).max(1);
+
+    for index in 0..blocks {
+        let chunk = &data[(index * MAX_STORED).min(data.len())..];
+        let chunk = &chunk[..chunk.len().min(MAX_STORED)];
+
+        writer.write_bits((final_block && index == blocks - 1) as u32, 1);
+        writer.write_bits(0, 2);
+        writer.align_to_byte();
+        writer.write_bits(chunk.len() as u32, 16);
+        writer.write_bits(!chunk.len() as u32, 16);
+        writer.write_bytes(chunk);
+    }
+}
+
+/// Writes tokens and the end of block with the given codes.
+fn write_tokens(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    literal_codes: &[Code],
+    distance_codes: &[Code],
+) {
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => writer.write_code(literal_codes[byte as usize]),
+            Token::Match { length, distance } => {
+                let code = length_code(length);
+                writer.write_code(literal_codes[END_OF_BLOCK + 1 + code]);
+                writer.write_bits(
+                    (length - LENGTH_BASE[code] as usize) as u32,
+                    LENGTH_EXTRA_BITS[code],
+                );
+
+                let code = distance_code(distance);
+                writer.write_code(distance_codes[code]);
+                writer.write_bits(
+                    (distance - DISTANCE_BASE[code] as usize) as u32,
+                    DISTANCE_EXTRA_BITS[code],
+                );
+            }
+        }
+    }
+    writer.write_code(literal_codes[END_OF_BLOCK]);
+}
+
+/// Returns the canonical codes for a set of code lengths, with empty codes
+/// for unused symbols.
+fn codes(lengths: &[u8]) -> Vec<Code> {
+    canonical_codes(lengths)
+        .into_iter()
+        .map(Option::unwrap_or_default)
+        .collect()
+}
+
+/// Returns how many extra bits follow a code length symbol.
+fn extra_bits(symbol: usize) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Run length codes a sequence of code lengths into code length symbols,
+/// 16 repeating the previous length 3 to 6 times, 17 and 18 repeating zero
+/// 3 to 10 and 11 to 138 times.
+///
+/// # Returns
+///
+/// A vector of each symbol with the value of its extra bits.
+fn run_lengths(lengths: &[u8]) -> Vec<(usize, u32)> {
+    let mut symbols = Vec::new();
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let length = lengths[index];
+        let run = lengths[index..]
+            .iter()
+            .take_while(|&&next| next == length)
+            .count();
+        index += run;
+
+        let mut left = run;
+        if length == 0 {
+            while left >= 11 {
+                let repeat = left.min(138);
+                symbols.push((18, (repeat - 11) as u32));
+                left -= repeat;
+            }
+            if left >= 3 {
+                symbols.push((17, (left - 3) as u32));
+                left = 0;
+            }
+        } else {
+            symbols.push((length as usize, 0));
+            left -= 1;
+            while left >= 3 {
+                let repeat = left.min(6);
+                symbols.push((16, (repeat - 3) as u32));
+                left -= repeat;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((length as usize, 0), left));
+    }
+
+    symbols
+}
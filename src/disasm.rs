@@ -0,0 +1,680 @@
+//! An infgen style disassembler, printing how a DEFLATE stream was coded.
+//!
+//! Each line is one part of the stream: block headers, the code lengths of
+//! dynamic blocks, then every literal and match, with the bit offset it was
+//! found at in the left column. It's built on the Inflater's trace hook, so
+//! the stream is decoded exactly as it would be to decompress it, and a
+//! corrupt stream prints everything up to where it goes wrong.
+//!
+//! dump_tables prints only the code lengths of each dynamic block, in full,
+//! for comparing how this crate and another decoder read a block's tables.
+//!
+//! hexdump goes down to the bits, printing every field of the file with the
+//! bytes and bits it was read from and what they meant: the header fields,
+//! each bit of a block header, every code length, and the code and extra
+//! bits of every literal and match.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    fmt::Write as _,
+    io::Write,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{
+    gzip::{GzipError, GzipHeader},
+    inflate::{BlockType, Inflater, Status, TraceEvent},
+    prefix::{
+        canonical_codes, distance_code, length_code, CL_CODES, CL_CODE_ORDER, DISTANCE_BASE,
+        DISTANCE_EXTRA_BITS, FIXED_CODE_LENGTHS, FIXED_DISTANCE_LENGTHS, HCLEN_BASE, HDIST_BASE,
+        HLIT_BASE, LENGTH_BASE, LENGTH_EXTRA_BITS, MAX_CL_CODE_LENGTH,
+    },
+};
+
+/// The size of the scratch buffer decompressed bytes are thrown away into.
+const OUTPUT_CHUNK: usize = 32768;
+
+/// The column headings of a hexdump.
+const HEXDUMP_HEADING: &str =
+    "      bit   byte  hex            bits                         meaning\n";
+
+/// The most bytes and bits of a field a hexdump line shows.
+const HEXDUMP_BYTES: usize = 4;
+
+/// Disassembles a gzip file, printing its header, every part of its DEFLATE
+/// stream, and its trailer.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+/// * 'writer' - Where to print the disassembly.
+///
+/// # Returns
+///
+/// A unit value, or a GzipError if the file is invalid or writing failed.
+pub fn disassemble<W: Write>(bytes: &[u8], writer: &mut W) -> Result<(), GzipError> {
+    let header = GzipHeader::build(bytes)?;
+
+    writeln!(writer, "! gzip")?;
+    if let Some(name) = &header.fname {
+        writeln!(writer, "! name {:?}", name)?;
+    }
+    if let Some(comment) = &header.fcomment {
+        writeln!(writer, "! comment {:?}", comment)?;
+    }
+    writeln!(writer, "! mtime {}", header.mtime)?;
+
+    let stream = bytes.get(header.end_idx..).unwrap_or_default();
+    let used = disassemble_deflate(stream, writer)?;
+
+    if let Some(&[c0, c1, c2, c3, i0, i1, i2, i3]) = stream
+        .get(used..)
+        .and_then(|trailer| trailer.first_chunk::<8>())
+    {
+        let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+        let isize = u32::from_le_bytes([i0, i1, i2, i3]);
+        writeln!(writer, "! crc {:#010x}", crc32)?;
+        writeln!(writer, "! length {}", isize)?;
+    }
+
+    Ok(())
+}
+
+/// Disassembles a raw DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+/// * 'writer' - Where to print the disassembly.
+///
+/// # Returns
+///
+/// The number of bytes of stream the DEFLATE data took up, or a GzipError
+/// if the stream is invalid or writing failed.
+pub fn disassemble_deflate<W: Write>(stream: &[u8], writer: &mut W) -> Result<usize, GzipError> {
+    print_events(stream, writer, format_event)
+}
+
+/// Prints the HLIT, HDIST, and HCLEN of every dynamic block in a raw
+/// DEFLATE stream, followed by all of its code lengths, zeros included.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+/// * 'writer' - Where to print the tables.
+///
+/// # Returns
+///
+/// The number of bytes of stream the DEFLATE data took up, or a GzipError
+/// if the stream is invalid or writing failed.
+pub fn dump_tables<W: Write>(stream: &[u8], writer: &mut W) -> Result<usize, GzipError> {
+    let mut block = 0;
+    let mut block_offset = 0;
+
+    print_events(stream, writer, move |lines, event| match *event {
+        TraceEvent::Block { bit_offset, .. } => {
+            block_offset = bit_offset;
+            Ok(())
+        }
+        TraceEvent::BlockEnd { .. } => {
+            block += 1;
+            Ok(())
+        }
+        TraceEvent::DynamicTables {
+            hclen,
+            code_length_lengths,
+            literal_lengths,
+            distance_lengths,
+            ..
+        } => {
+            writeln!(lines, "block {} at bit {}", block, block_offset)?;
+            writeln!(
+                lines,
+                "hlit {} hdist {} hclen {}",
+                literal_lengths.len() - HLIT_BASE,
+                distance_lengths.len() - HDIST_BASE,
+                hclen - HCLEN_BASE
+            )?;
+
+            let tables = [
+                ("code", &code_length_lengths[..]),
+                ("litlen", literal_lengths),
+                ("dist", distance_lengths),
+            ];
+            for (name, lengths) in tables {
+                for (row, lengths) in lengths.chunks(16).enumerate() {
+                    write!(lines, "{:<6} {:>3}:", name, row * 16)?;
+                    for length in lengths {
+                        write!(lines, " {:>2}", length)?;
+                    }
+                    writeln!(lines)?;
+                }
+            }
+            writeln!(lines)
+        }
+        _ => Ok(()),
+    })
+}
+
+/// Prints an annotated hexdump of a gzip file: every field of its header,
+/// DEFLATE stream, and trailer on its own line, with its offset in bits and
+/// bytes from the start of the file, the bytes and bits it takes up, and
+/// what it means.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+/// * 'writer' - Where to print the hexdump.
+///
+/// # Returns
+///
+/// A unit value, or a GzipError if the file is invalid or writing failed.
+pub fn hexdump<W: Write>(bytes: &[u8], writer: &mut W) -> Result<(), GzipError> {
+    let header = GzipHeader::build(bytes)?;
+    let mut lines = String::new();
+    let mut fields = Annotator {
+        lines: &mut lines,
+        bytes,
+        base: 0,
+    };
+    // Writing to a String can't fail.
+    let mut field = |start: usize, len: usize, meaning: String| {
+        let _ = fields.field(start as u64 * 8, len as u64 * 8, &meaning);
+    };
+
+    field(0, 2, String::from("ID"));
+    field(2, 1, format!("CM {}", header.cm));
+    field(
+        3,
+        1,
+        format!("FLG {:#010b}", bytes.get(3).copied().unwrap_or_default()),
+    );
+    field(4, 4, format!("MTIME {}", header.mtime));
+    field(8, 1, format!("XFL {}", header.xfl));
+    field(9, 1, format!("OS {}", header.os));
+
+    let mut offset = 10;
+    if let Some(extra) = &header.fextra {
+        field(offset, 2, format!("XLEN {}", extra.len()));
+        field(offset + 2, extra.len(), String::from("extra field"));
+        offset += 2 + extra.len();
+    }
+    for (name, text) in [("FNAME", &header.fname), ("FCOMMENT", &header.fcomment)] {
+        if let Some(text) = text {
+            // The text is stored one byte per character, then a zero.
+            let len = text.chars().count() + 1;
+            field(offset, len, format!("{} {:?}", name, text));
+            offset += len;
+        }
+    }
+    if let Some(crc) = header.crc {
+        field(offset, 2, format!("CRC16 {:#06x}", crc));
+    }
+
+    writer.write_all(HEXDUMP_HEADING.as_bytes())?;
+    writer.write_all(lines.as_bytes())?;
+
+    let stream = bytes.get(header.end_idx..).unwrap_or_default();
+    let used = print_hexdump(stream, header.end_idx as u64 * 8, writer)?;
+
+    let trailer = header.end_idx + used;
+    let mut lines = String::new();
+    let mut fields = Annotator {
+        lines: &mut lines,
+        bytes,
+        base: 0,
+    };
+    if let Some(&[a, b, c, d, e, f, g, h]) = bytes.get(trailer..trailer + 8) {
+        let crc32 = u32::from_le_bytes([a, b, c, d]);
+        let isize = u32::from_le_bytes([e, f, g, h]);
+        let start = trailer as u64 * 8;
+        let _ = fields.field(start, 32, &format!("CRC32 {:#010x}", crc32));
+        let _ = fields.field(start + 32, 32, &format!("ISIZE {}", isize));
+    }
+    writer.write_all(lines.as_bytes())?;
+
+    Ok(())
+}
+
+/// Prints an annotated hexdump of a raw DEFLATE stream, see hexdump.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+/// * 'writer' - Where to print the hexdump.
+///
+/// # Returns
+///
+/// The number of bytes of stream the DEFLATE data took up, or a GzipError
+/// if the stream is invalid or writing failed.
+pub fn hexdump_deflate<W: Write>(stream: &[u8], writer: &mut W) -> Result<usize, GzipError> {
+    writer.write_all(HEXDUMP_HEADING.as_bytes())?;
+    print_hexdump(stream, 0, writer)
+}
+
+const HEXDUMP_BITS: u64 = 24;
+
+/// Prints the hexdump lines of a DEFLATE stream, with offsets moved on by
+/// base bits so they count from the start of the file.
+fn print_hexdump<W: Write>(stream: &[u8], base: u64, writer: &mut W) -> Result<usize, GzipError> {
+    // The hook outlives this call, so it needs its own copy to read from.
+    let owned = stream.to_vec();
+    // The block being read, and the code lengths its tokens are coded with.
+    let mut block_start = 0;
+    let mut block_type = BlockType::Stored;
+    let mut literal_lengths = Vec::new();
+    let mut distance_lengths = Vec::new();
+
+    print_events(stream, writer, move |lines, event| {
+        let mut fields = Annotator {
+            lines,
+            bytes: &owned,
+            base,
+        };
+
+        match *event {
+            TraceEvent::Block {
+                bit_offset,
+                final_block,
+                block_type: kind,
+            } => {
+                block_start = bit_offset;
+                block_type = kind;
+                if kind == BlockType::Fixed {
+                    literal_lengths = FIXED_CODE_LENGTHS.to_vec();
+                    distance_lengths = FIXED_DISTANCE_LENGTHS.to_vec();
+                }
+                let (btype, name) = match kind {
+                    BlockType::Stored => (0, "stored"),
+                    BlockType::Fixed => (1, "fixed"),
+                    BlockType::Dynamic => (2, "dynamic"),
+                };
+
+                fields.field(bit_offset, 1, &format!("BFINAL {}", final_block as u8))?;
+                fields.field(bit_offset + 1, 2, &format!("BTYPE {} ({})", btype, name))
+            }
+            TraceEvent::Stored { bit_offset, len } => {
+                let padding = bit_offset - 32 - (block_start + 3);
+                if padding > 0 {
+                    fields.field(block_start + 3, padding, "padding")?;
+                }
+                fields.field(bit_offset - 32, 16, &format!("LEN {}", len))?;
+                fields.field(bit_offset - 16, 16, &format!("NLEN {}", !len))?;
+                if len > 0 {
+                    let meaning = format!("{} bytes of stored data", len);
+                    fields.field(bit_offset, len as u64 * 8, &meaning)?;
+                }
+                Ok(())
+            }
+            TraceEvent::DynamicTables {
+                hclen,
+                code_length_lengths,
+                literal_lengths: literals,
+                distance_lengths: distances,
+                ..
+            } => {
+                let start = block_start + 3;
+                let (hlit, hdist) = (literals.len(), distances.len());
+                fields.field(
+                    start,
+                    5,
+                    &format!("HLIT {} ({} lengths)", hlit - HLIT_BASE, hlit),
+                )?;
+                fields.field(
+                    start + 5,
+                    5,
+                    &format!("HDIST {} ({} lengths)", hdist - HDIST_BASE, hdist),
+                )?;
+                fields.field(
+                    start + 10,
+                    4,
+                    &format!("HCLEN {} ({} lengths)", hclen - HCLEN_BASE, hclen),
+                )?;
+                for (index, &symbol) in CL_CODE_ORDER.iter().take(hclen).enumerate() {
+                    let length = code_length_lengths.get(symbol).copied().unwrap_or_default();
+                    fields.field(
+                        start + 14 + index as u64 * 3,
+                        3,
+                        &format!("code length code {}: {}", symbol, length),
+                    )?;
+                }
+                fields.code_lengths(
+                    start + 14 + hclen as u64 * 3,
+                    code_length_lengths,
+                    hlit,
+                    hlit + hdist,
+                )?;
+
+                literal_lengths = literals.to_vec();
+                distance_lengths = distances.to_vec();
+                Ok(())
+            }
+            TraceEvent::Literal { bit_offset, byte } => {
+                let meaning = if byte.is_ascii_graphic() || byte == b' ' {
+                    format!("literal '{}'", byte as char)
+                } else {
+                    format!("literal {}", byte)
+                };
+                let bits = literal_lengths
+                    .get(byte as usize)
+                    .copied()
+                    .unwrap_or_default();
+                fields.field(bit_offset, bits as u64, &meaning)
+            }
+            TraceEvent::Match {
+                bit_offset,
+                length,
+                distance,
+            } => {
+                let code = length_code(length);
+                // The Inflater only traces valid symbols, so every lookup
+                // here finds one.
+                let bits = literal_lengths.get(257 + code).copied().unwrap_or_default() as u64;
+                fields.field(bit_offset, bits, &format!("length symbol {}", 257 + code))?;
+                let mut offset = bit_offset + bits;
+
+                let extra = LENGTH_EXTRA_BITS.get(code).copied().unwrap_or_default() as u64;
+                let value = length - LENGTH_BASE.get(code).copied().unwrap_or_default() as usize;
+                if extra > 0 {
+                    fields.field(offset, extra, &format!("length extra {}", value))?;
+                    offset += extra;
+                }
+
+                let code = distance_code(distance);
+                let bits = distance_lengths.get(code).copied().unwrap_or_default() as u64;
+                fields.field(offset, bits, &format!("distance symbol {}", code))?;
+                offset += bits;
+
+                let extra = DISTANCE_EXTRA_BITS.get(code).copied().unwrap_or_default() as u64;
+                let value =
+                    distance - DISTANCE_BASE.get(code).copied().unwrap_or_default() as usize;
+                if extra > 0 {
+                    fields.field(offset, extra, &format!("distance extra {}", value))?;
+                }
+                fields.sum(&format!("match {} {}", length, distance))
+            }
+            TraceEvent::BlockEnd { bit_offset } if block_type != BlockType::Stored => {
+                let bits = literal_lengths.get(256).copied().unwrap_or_default() as u64;
+                fields.field(bit_offset - bits, bits, "end of block")
+            }
+            TraceEvent::BlockEnd { .. } => Ok(()),
+        }
+    })
+}
+
+/// Appends the lines of a hexdump.
+///
+/// # Fields
+///
+/// * 'lines' - Where to append the lines.
+/// * 'bytes' - The bytes the fields are read from.
+/// * 'base' - How many bits before the start of bytes the file starts, for
+///         the offsets printed.
+struct Annotator<'a> {
+    lines: &'a mut String,
+    bytes: &'a [u8],
+    base: u64,
+}
+
+impl Annotator<'_> {
+    /// Appends the line of a field len bits long, start bits into bytes.
+    fn field(&mut self, start: u64, len: u64, meaning: &str) -> std::fmt::Result {
+        let first = (start / 8) as usize;
+        let last = ((start + len.max(1) - 1) / 8) as usize;
+        let shown = self
+            .bytes
+            .get(first.min(self.bytes.len())..(last + 1).min(self.bytes.len()))
+            .unwrap_or_default();
+
+        let mut hex = shown
+            .iter()
+            .take(HEXDUMP_BYTES)
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if shown.len() > HEXDUMP_BYTES {
+            hex.push_str(" ..");
+        }
+
+        // Bits in the order they're read, least significant first in each
+        // byte, so a Huffman code reads from left to right.
+        let mut bits = (start..start + len.min(HEXDUMP_BITS))
+            .map(|offset| match read_bits(self.bytes, offset, 1) {
+                Some(1) => '1',
+                _ => '0',
+            })
+            .collect::<String>();
+        if len > HEXDUMP_BITS {
+            bits.push_str("...");
+        }
+
+        let start = start + self.base;
+        writeln!(
+            self.lines,
+            "{:>9} {:>6}  {:<14} {:<28} {}",
+            start,
+            start / 8,
+            hex,
+            bits,
+            meaning
+        )
+    }
+    /// Appends a line with only a meaning, summing up the fields above it.
+    fn sum(&mut self, meaning: &str) -> std::fmt::Result {
+        writeln!(
+            self.lines,
+            "{:>9} {:>6}  {:<14} {:<28} = {}",
+            "", "", "", "", meaning
+        )
+    }
+    /// Decodes the run length coded code lengths of a dynamic block a
+    /// second time, appending a line for each symbol and its extra bits.
+    ///
+    /// # Arguments
+    ///
+    /// * 'start' - The bit offset the code lengths start at.
+    /// * 'code_length_lengths' - The code length code lengths, by symbol.
+    /// * 'hlit' - How many of the lengths are literal/length code lengths,
+    ///         the rest are distance code lengths.
+    /// * 'count' - How many lengths there are in all.
+    fn code_lengths(
+        &mut self,
+        start: u64,
+        code_length_lengths: &[u8; CL_CODES],
+        hlit: usize,
+        count: usize,
+    ) -> std::fmt::Result {
+        let codes = canonical_codes(code_length_lengths);
+        let name = |index: usize| match index < hlit {
+            true => format!("litlen {}", index),
+            false => format!("dist {}", index - hlit),
+        };
+
+        let mut offset = start;
+        let mut index = 0;
+        while index < count {
+            // Huffman codes are packed from their most significant bit, so
+            // reading one a bit at a time builds up its value in order.
+            let mut code = 0;
+            let mut length = 0;
+            let symbol = loop {
+                let Some(bit) = read_bits(self.bytes, offset + length as u64, 1) else {
+                    return Ok(());
+                };
+                code = code << 1 | bit;
+                length += 1;
+
+                let found = codes.iter().position(|candidate| {
+                    candidate.is_some_and(|candidate| {
+                        candidate.length == length && candidate.buffer == code
+                    })
+                });
+                match found {
+                    Some(symbol) => break symbol,
+                    // The inflater already read these, so this can't happen.
+                    None if length == MAX_CL_CODE_LENGTH => return Ok(()),
+                    None => {}
+                }
+            };
+
+            let (extra, repeat) = match symbol {
+                16 => (2, 3),
+                17 => (3, 3),
+                18 => (7, 11),
+                _ => (0, 1),
+            };
+            let Some(value) = read_bits(self.bytes, offset + length as u64, extra) else {
+                return Ok(());
+            };
+            let times = repeat + value as usize;
+
+            let meaning = match symbol {
+                16 => format!(
+                    "{} to {}: previous, {} times",
+                    name(index),
+                    name(index + times - 1),
+                    times
+                ),
+                17 | 18 => format!(
+                    "{} to {}: 0, {} times",
+                    name(index),
+                    name(index + times - 1),
+                    times
+                ),
+                length => format!("{}: {}", name(index), length),
+            };
+            let bits = length as u64 + extra as u64;
+            self.field(offset, bits, &meaning)?;
+
+            offset += bits;
+            index += times;
+        }
+        Ok(())
+    }
+}
+
+/// Reads count bits at a bit offset, least significant bit first, or None
+/// if they run past the end of bytes.
+fn read_bits(bytes: &[u8], offset: u64, count: u8) -> Option<u32> {
+    let mut value = 0;
+    for bit in 0..count as u64 {
+        let position = offset + bit;
+        let byte = *bytes.get((position / 8) as usize)?;
+        value |= ((byte >> (position % 8)) as u32 & 1) << bit;
+    }
+    Some(value)
+}
+
+/// Decodes a raw DEFLATE stream, printing the lines format appends for
+/// each event.
+fn print_events<W, F>(stream: &[u8], writer: &mut W, mut format: F) -> Result<usize, GzipError>
+where
+    W: Write,
+    F: FnMut(&mut String, &TraceEvent<'_>) -> std::fmt::Result + Send + 'static,
+{
+    // The hook has to own what it writes to, so lines are gathered here and
+    // handed to the writer after every call to inflate.
+    let lines = Arc::new(Mutex::new(String::new()));
+    let sink = Arc::clone(&lines);
+
+    let mut inflater = Inflater::new();
+    inflater.set_trace(Some(Box::new(move |event| {
+        let mut lines = sink.lock().unwrap_or_else(PoisonError::into_inner);
+        // Writing to a String can't fail.
+        let _ = format(&mut lines, event);
+    })));
+
+    let mut output = vec![0; OUTPUT_CHUNK];
+    let mut input = stream;
+    loop {
+        let result = inflater.inflate(input, &mut output);
+
+        let mut lines = lines.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.write_all(lines.as_bytes())?;
+        lines.clear();
+
+        let (consumed, _, status) = result?;
+        input = input.get(consumed..).unwrap_or_default();
+
+        match status {
+            Status::StreamEnd => return Ok(stream.len() - input.len()),
+            Status::NeedsInput => return Err(inflater.unexpected_eof().into()),
+            Status::NeedsOutput | Status::Pending => {}
+        }
+    }
+}
+
+/// Appends the lines describing a single event.
+fn format_event(lines: &mut String, event: &TraceEvent<'_>) -> std::fmt::Result {
+    match *event {
+        TraceEvent::Block {
+            bit_offset,
+            final_block,
+            block_type,
+        } => {
+            let block_type = match block_type {
+                BlockType::Stored => "stored",
+                BlockType::Fixed => "fixed",
+                BlockType::Dynamic => "dynamic",
+            };
+            if final_block {
+                writeln!(lines, "{:>9} last", bit_offset)?;
+                writeln!(lines, "{:>9} {}", "", block_type)
+            } else {
+                writeln!(lines, "{:>9} {}", bit_offset, block_type)
+            }
+        }
+        TraceEvent::Stored { bit_offset, len } => writeln!(lines, "{:>9} len {}", bit_offset, len),
+        TraceEvent::DynamicTables {
+            hclen,
+            code_length_lengths,
+            literal_lengths,
+            distance_lengths,
+            ..
+        } => {
+            writeln!(
+                lines,
+                "{:>9} count {} {} {}",
+                "",
+                literal_lengths.len(),
+                distance_lengths.len(),
+                hclen
+            )?;
+
+            let tables = [
+                ("code", &code_length_lengths[..]),
+                ("litlen", literal_lengths),
+                ("dist", distance_lengths),
+            ];
+            for (name, lengths) in tables {
+                for (symbol, &length) in lengths.iter().enumerate() {
+                    if length != 0 {
+                        writeln!(lines, "{:>9} {} {} {}", "", name, symbol, length)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        TraceEvent::Literal { bit_offset, byte } => {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                writeln!(lines, "{:>9} literal '{}", bit_offset, byte as char)
+            } else {
+                writeln!(lines, "{:>9} literal {}", bit_offset, byte)
+            }
+        }
+        TraceEvent::Match {
+            bit_offset,
+            length,
+            distance,
+        } => writeln!(lines, "{:>9} match {} {}", bit_offset, length, distance),
+        TraceEvent::BlockEnd { bit_offset } => writeln!(lines, "{:>9} end", bit_offset),
+    }
+}
@@ -0,0 +1,177 @@
+//! Git loose objects, the zlib streams stored under .git/objects.
+//!
+//! Inflated, a loose object is a header of its type and size in ASCII
+//! decimal, a NUL, and then the object's contents, so a blob holding
+//! "hello" is "blob 5\0hello".
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{error::Error, fmt::Display};
+
+use crate::zlib::{self, ZlibError};
+
+/// The longest header git writes, "commit " and a u64 in decimal, and the
+/// NUL. Anything longer isn't a loose object.
+const MAX_HEADER: usize = 7 + 20 + 1;
+
+/// The kind of a git object.
+///
+/// # Variants
+///
+/// * 'Blob' - A file's contents.
+/// * 'Tree' - A directory listing.
+/// * 'Commit' - A commit.
+/// * 'Tag' - An annotated tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectType {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl ObjectType {
+    /// Returns the name git uses for the type in headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Tree => "tree",
+            ObjectType::Commit => "commit",
+            ObjectType::Tag => "tag",
+        }
+    }
+    /// Returns the type with the given name, or None if there isn't one.
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"blob" => Some(ObjectType::Blob),
+            b"tree" => Some(ObjectType::Tree),
+            b"commit" => Some(ObjectType::Commit),
+            b"tag" => Some(ObjectType::Tag),
+            _ => None,
+        }
+    }
+}
+
+/// An inflated loose object.
+///
+/// # Fields
+///
+/// * 'object_type' - The type from the header.
+/// * 'size' - The size the header declares, which the payload has been
+///         checked to match.
+/// * 'payload' - The object's contents, after the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LooseObject {
+    pub object_type: ObjectType,
+    pub size: u64,
+    pub payload: Vec<u8>,
+}
+
+/// A custom error type for loose object errors.
+///
+/// # Members
+///
+/// * 'ZlibError' - Wrapper for a ZlibError raised while inflating.
+/// * 'InvalidHeader' - Used when the inflated data doesn't start with a
+///             type, a space, a decimal size, and a NUL. Contains the bytes
+///             the header should have been in.
+/// * 'UnknownType' - Used when the header's type isn't one of git's.
+///             Contains the type's bytes.
+/// * 'SizeMismatch' - Used when the payload isn't the size the header
+///             declares.
+#[derive(Debug)]
+pub enum ObjectError {
+    ZlibError(ZlibError),
+    InvalidHeader(Vec<u8>),
+    UnknownType(Vec<u8>),
+    SizeMismatch { expected: u64, actual: u64 },
+}
+
+impl Display for ObjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectError::ZlibError(err) => {
+                write!(f, "Error: Inflating the object raised the {}", err)
+            }
+            ObjectError::InvalidHeader(header) => {
+                write!(f, "Error: Failed to parse object header bytes {:?}", header)
+            }
+            ObjectError::UnknownType(name) => {
+                write!(
+                    f,
+                    "Error: Object type '{}' is not one of blob, tree, commit, or tag",
+                    String::from_utf8_lossy(name)
+                )
+            }
+            ObjectError::SizeMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Object header declares {} bytes, but the payload is {}",
+                    expected, actual
+                )
+            }
+        }
+    }
+}
+
+impl Error for ObjectError {}
+
+impl From<ZlibError> for ObjectError {
+    fn from(err: ZlibError) -> Self {
+        ObjectError::ZlibError(err)
+    }
+}
+
+/// Inflates a loose object and splits it into its header and payload.
+///
+/// # Arguments
+///
+/// * 'bytes' - The object file's bytes, a zlib stream.
+///
+/// # Returns
+///
+/// The object's type, declared size, and payload, or an ObjectError.
+pub fn inflate_loose_object(bytes: &[u8]) -> Result<LooseObject, ObjectError> {
+    let mut inflated = zlib::decompress(bytes)?;
+
+    let searched = inflated.get(..MAX_HEADER).unwrap_or(&inflated);
+    let invalid = || ObjectError::InvalidHeader(searched.to_vec());
+    let nul = searched.iter().position(|&b| b == 0).ok_or_else(invalid)?;
+    let (header, _) = searched.split_at(nul);
+    let space = header.iter().position(|&b| b == b' ').ok_or_else(invalid)?;
+    let (name, size) = header.split_at(space);
+    let size = size.get(1..).unwrap_or_default();
+
+    let object_type =
+        ObjectType::from_name(name).ok_or_else(|| ObjectError::UnknownType(name.to_vec()))?;
+    // Git writes sizes without a sign or leading zeros.
+    if size.first() == Some(&b'0') && size.len() > 1 {
+        return Err(invalid());
+    }
+    let size = std::str::from_utf8(size)
+        .ok()
+        .filter(|size| size.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|size| size.parse::<u64>().ok())
+        .ok_or_else(invalid)?;
+
+    let payload = inflated.split_off(nul + 1);
+    if payload.len() as u64 != size {
+        return Err(ObjectError::SizeMismatch {
+            expected: size,
+            actual: payload.len() as u64,
+        });
+    }
+
+    Ok(LooseObject {
+        object_type,
+        size,
+        payload,
+    })
+}
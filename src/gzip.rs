@@ -1,11 +1,36 @@
 //! Gzip encoding and decoding.
-use std::{error::Error, fmt::Display, fs, path::Path};
-
-use crate::{
-    crc,
-    inflate::{DeflateData, DeflateError},
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+#[cfg(feature = "inflate")]
+use std::{
+    borrow::Cow,
+    fs,
+    io::{IoSlice, Write},
+    path::Path,
+};
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, Read},
+    time::{Duration, SystemTime},
 };
 
+use crate::{crc, stream::DeflateError};
+#[cfg(feature = "inflate")]
+use crate::{inflate::DeflateData, write::write_all_vectored};
+
+/// The most decompressed bytes handed to a writer at once.
+#[cfg(feature = "inflate")]
+pub(crate) const WRITE_BATCH: usize = 1 << 18;
+
 /// A custom error type for GZIP related errors.
 ///
 /// # Members
@@ -16,11 +41,30 @@ use crate::{
 /// * 'NotGzipFile' - Used when a file is read that does not contain the GZIP
 ///             magic bytes (0x1f, 0x8b).
 /// * 'IoError' - Wrapper for std::io::Error.
+/// * 'DeflateError' - Wrapper for a DeflateError raised while decompressing.
+/// * 'HeaderChecksumMismatch' - Used when FHCRC is set and the CRC16 stored
+///             in the header doesn't match the header's bytes.
+/// * 'ReservedFlag' - Used when strict_flags is set and one of the reserved
+///             FLG bits 5 through 7 is set. Contains the number of the
+///             lowest bit that is.
+/// * 'UnsupportedCompressionMethod' - Used when CM isn't 8, DEFLATE, the
+///             only method gzip defines. Contains the CM byte.
+/// * 'OutOfMemory' - Used when a buffer couldn't be allocated, rather than
+///             aborting the process. Contains the size of the allocation
+///             that failed.
+/// * 'Cancelled' - Used when decompressing was stopped by its cancel flag,
+///             see Inflater::set_cancel.
 #[derive(Debug)]
 pub enum GzipError {
     InvalidHeader(Vec<u8>),
     NotGzipFile(Vec<u8>),
     IoError(std::io::Error),
+    DeflateError(DeflateError),
+    HeaderChecksumMismatch { expected: u16, actual: u16 },
+    ReservedFlag(u8),
+    UnsupportedCompressionMethod(u8),
+    OutOfMemory(usize),
+    Cancelled,
 }
 
 // Define how GzipErrors are displayed.
@@ -31,15 +75,42 @@ impl Display for GzipError {
                 write!(f, "Error: Failed to parse header bytes {:?}", header)
             }
             GzipError::NotGzipFile(magic_bytes) => {
+                let [id1, id2] = [0, 1].map(|i| magic_bytes.get(i).copied().unwrap_or_default());
                 write!(
                     f,
                     "Error: File missing GZIP ID bytes '{}, {}' does not equal '0x1f, 0x8b",
-                    magic_bytes[0], magic_bytes[1]
+                    id1, id2
                 )
             }
             GzipError::IoError(err) => {
                 write!(f, "Error: Operation raised the io::Error: {}", err)
             }
+            GzipError::DeflateError(err) => {
+                write!(f, "Error: Decompression raised the {}", err)
+            }
+            GzipError::HeaderChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Header CRC16 {:#06x} does not match the header's {:#06x}",
+                    expected, actual
+                )
+            }
+            GzipError::ReservedFlag(bit) => {
+                write!(f, "Error: Reserved FLG bit {} is set", bit)
+            }
+            GzipError::UnsupportedCompressionMethod(cm) => {
+                write!(
+                    f,
+                    "Error: Compression method {} is not supported, only 8 (DEFLATE) is",
+                    cm
+                )
+            }
+            GzipError::OutOfMemory(size) => {
+                write!(f, "Error: Failed to allocate {} bytes", size)
+            }
+            GzipError::Cancelled => {
+                write!(f, "Error: Decompression was cancelled")
+            }
         }
     }
 }
@@ -52,14 +123,106 @@ impl From<std::io::Error> for GzipError {
     }
 }
 
+// Running out of memory or being cancelled isn't a problem with the data, so
+// those are pulled out of the DeflateError to be handled on their own.
+impl From<DeflateError> for GzipError {
+    fn from(err: DeflateError) -> Self {
+        match err.inner() {
+            DeflateError::OutOfMemoryError(size) => GzipError::OutOfMemory(*size),
+            DeflateError::CancelledError => GzipError::Cancelled,
+            _ => GzipError::DeflateError(err),
+        }
+    }
+}
+
+// Lets GzipErrors be returned from io::Read and io::Write implementations,
+// io::Errors are unwrapped and anything else but running out of memory or
+// being cancelled is invalid data. Cancelled isn't Interrupted, which readers
+// retry.
+impl From<GzipError> for io::Error {
+    fn from(err: GzipError) -> Self {
+        match err {
+            GzipError::IoError(err) => err,
+            err @ GzipError::OutOfMemory(_) => io::Error::new(io::ErrorKind::OutOfMemory, err),
+            err @ GzipError::Cancelled => io::Error::other(err),
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Checks what was decompressed against a file's trailer. The size is
+/// checked first, a truncated or corrupt file usually fails it and it says
+/// more about what went wrong than the checksum does.
+///
+/// # Arguments
+///
+/// * 'crc32' - The CRC-32 stored in the trailer.
+/// * 'isize' - The size stored in the trailer, mod 2^32.
+/// * 'crc' - The CRC-32 of the decompressed data.
+/// * 'len' - The number of bytes decompressed.
+#[cfg(feature = "inflate")]
+pub(crate) fn verify_trailer(
+    crc32: u32,
+    isize: u32,
+    crc: u32,
+    len: u64,
+) -> Result<(), DeflateError> {
+    if isize != len as u32 {
+        return Err(DeflateError::SizeMismatchError {
+            expected: isize,
+            actual: len as u32,
+        });
+    }
+
+    if crc32 != crc {
+        return Err(DeflateError::ChecksumMismatchError {
+            expected: crc32,
+            actual: crc,
+        });
+    }
+
+    Ok(())
+}
+
+/// Options controlling how strictly a header is checked while it's parsed.
+///
+/// # Fields
+///
+/// * 'verify_crc' - Whether to check the CRC16 stored when FHCRC is set.
+///         Some old versions of gzip wrote it incorrectly, so it can be
+///         turned off to read their files. Defaults to true.
+/// * 'strict_flags' - Whether to reject headers with any of the FLG bits
+///         RFC 1952 reserves set. They're ignored otherwise, as gzip itself
+///         does. Defaults to false.
+/// * 'max_size' - The most bytes the header can take up, optional fields
+///         and all, or None for no limit. Reading a longer header fails with
+///         GzipError::InvalidHeader before any more of it is held in memory.
+///         Defaults to None.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderOptions {
+    pub verify_crc: bool,
+    pub strict_flags: bool,
+    pub max_size: Option<usize>,
+}
+
+impl Default for HeaderOptions {
+    fn default() -> Self {
+        Self {
+            verify_crc: true,
+            strict_flags: false,
+            max_size: None,
+        }
+    }
+}
+
 /// A struct containing the individual parts of a GZIP header.
 ///
 /// # Fields
 ///
 /// * 'cm' - A single u8 value representing the CM section of the header.
-///         Because GZIP only ever uses 8/DEFLATE, this is unneccessary,
-///         but is present in the rare case where GZIP updates to contain
-///         more compression methods.
+///         GZIP only defines 8/DEFLATE, and headers with any other method
+///         are rejected, but it's kept in the rare case where GZIP updates
+///         to contain more compression methods.
 /// * 'flg' - A bool array with 5 elements containing the 5 flags the FLG
 ///         section in the header. Takes the format [FTEXT, FHCRC, FEXTRA
 ///         FNAME, FCOMMENT].
@@ -70,9 +233,13 @@ impl From<std::io::Error> for GzipError {
 /// * 'os' - A single u8 value defining the operating system. Also mostly
 ///         useless nowadays.
 /// * 'crc' - An optional u16 containing the CRC16 checksum if provided.
-/// * 'fextra' - An optional Vec<u8> containing the extra flags if provided.
+/// * 'fextra' - An optional Vec<u8> containing the XLEN bytes of the extra
+///         field if provided, extra_fields splits it into subfields.
 /// * 'fname' - An optional String containing the name of the original file.
-/// * 'fcomment' - An optional String containing the files comment if provided.
+///         RFC 1952 has it in ISO-8859-1, so each byte is decoded as the
+///         character with the same code point.
+/// * 'fcomment' - An optional String containing the files comment if provided,
+///         decoded from ISO-8859-1 the same as fname.
 #[derive(Debug)]
 pub struct GzipHeader {
     pub cm: u8,
@@ -101,20 +268,48 @@ impl GzipHeader {
     /// failing to parse the header, or the bytes lacking the GZIP file
     /// identification bytes.
     pub fn build(bytes: &[u8]) -> Result<Self, GzipError> {
-        // Extract the core 10 byte header.
-        let header = bytes[0..10].to_vec();
+        Self::build_with_options(bytes, &HeaderOptions::default())
+    }
+    /// Accepts the raw bytes from a GZIP file and parses out the header
+    /// elements, checking them as strictly as the options say to.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - A reference to the byte array containing the header.
+    /// * 'options' - The HeaderOptions to parse with.
+    ///
+    /// # Returns
+    ///
+    /// Either the successfully built header, or a GzipError.
+    pub fn build_with_options(bytes: &[u8], options: &HeaderOptions) -> Result<Self, GzipError> {
+        // A header running past max_size is treated like one that's cut
+        // short at it.
+        let bytes = bytes
+            .get(..options.max_size.unwrap_or(usize::MAX))
+            .unwrap_or(bytes);
 
-        // Split the header into each part.
-        let id = [header[0], header[1]];
-        let cm = header[2];
-        let flg = header[3];
-        let mtime = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
-        let xfl = header[8];
-        let os = header[9];
+        // Extract the core 10 byte header, and split it into each part.
+        let Some(&[id1, id2, cm, flg, m0, m1, m2, m3, xfl, os]) = bytes.first_chunk::<10>() else {
+            return Err(GzipError::InvalidHeader(bytes.to_vec()));
+        };
+        let header = bytes.get(..10).unwrap_or_default().to_vec();
+        let mtime = u32::from_le_bytes([m0, m1, m2, m3]);
 
         // Check for the ID bytes.
-        if id != [0x1f, 0x8b] {
-            return Err(GzipError::NotGzipFile([header[0], header[1]].to_vec()));
+        if [id1, id2] != [0x1f, 0x8b] {
+            return Err(GzipError::NotGzipFile(vec![id1, id2]));
+        }
+
+        // Anything but DEFLATE would be misread by the inflater.
+        if cm != 8 {
+            return Err(GzipError::UnsupportedCompressionMethod(cm));
+        }
+
+        // Bits 5 through 7 are reserved and should be zero.
+        if options.strict_flags && flg >> 5 != 0 {
+            return Err(GzipError::ReservedFlag(
+                (flg >> 5).trailing_zeros() as u8 + 5,
+            ));
         }
 
         // Initialize the option values as none.
@@ -140,52 +335,60 @@ impl GzipHeader {
 
         // If FEXTRA is set, collect the two bytes that dictate its size,
         // and then take that amount of bytes from the data stream.
+        // Every field is checked against the end of bytes, a header that's
+        // cut short is invalid rather than read past.
         if flags[2] {
-            let xlen = u16::from_le_bytes([bytes[10], bytes[11]]);
-            _fextra = Some(bytes[12..xlen as usize].to_vec());
-            _idx += xlen as usize + 2;
+            let Some(&[lo, hi]) = bytes.get(10..12) else {
+                return Err(GzipError::InvalidHeader(header));
+            };
+            let xlen = u16::from_le_bytes([lo, hi]) as usize;
+            let Some(extra) = bytes.get(12..12 + xlen) else {
+                return Err(GzipError::InvalidHeader(header));
+            };
+            _fextra = Some(extra.to_vec());
+            _idx += xlen + 2;
         }
 
         if flags[3] {
-            let after_header = bytes[_idx..]
-                .iter()
-                .cloned()
-                .take_while(|byte| byte != &0u8)
-                .collect::<Vec<_>>();
-
-            _idx += after_header.len() + 1;
-
-            _fname = match String::from_utf8(after_header) {
-                Ok(name) => Some(name),
-                Err(_) => {
-                    return Err(GzipError::InvalidHeader(header));
-                }
-            }
+            let rest = bytes.get(_idx..).unwrap_or_default();
+            let Some(end) = rest.iter().position(|&byte| byte == 0) else {
+                return Err(GzipError::InvalidHeader(header));
+            };
+            let (name, _) = rest.split_at(end);
+            _fname = Some(latin1(name));
+            _idx += name.len() + 1;
         }
 
         if flags[4] {
-            let after_header = bytes[_idx..]
-                .iter()
-                .cloned()
-                .take_while(|byte| byte != &0u8)
-                .collect::<Vec<_>>();
-
-            _idx += after_header.len() + 1;
-
-            _fcomment = match String::from_utf8(after_header) {
-                Ok(comment) => Some(comment),
-                Err(_) => {
-                    return Err(GzipError::InvalidHeader(header));
-                }
-            }
+            let rest = bytes.get(_idx..).unwrap_or_default();
+            let Some(end) = rest.iter().position(|&byte| byte == 0) else {
+                return Err(GzipError::InvalidHeader(header));
+            };
+            let (comment, _) = rest.split_at(end);
+            _fcomment = Some(latin1(comment));
+            _idx += comment.len() + 1;
         }
 
         // Now check for FHCRC because it occurs at the end of the header
         // right before the DEFLATE data, so, _idx needs to be incremented as
         // much as it will be before grabbing the crc.
+        // The CRC16 is the low 16 bits of the CRC-32 of every header byte
+        // before it, stored least significant byte first.
         if flags[1] {
-            _crc = Some(((bytes[_idx] as u16) << 8) | bytes[_idx + 1] as u16);
+            let Some(&[lo, hi]) = bytes.get(_idx.._idx + 2) else {
+                return Err(GzipError::InvalidHeader(header));
+            };
+            let crc = u16::from_le_bytes([lo, hi]);
+            let actual = crc::hash(bytes.get(.._idx).unwrap_or_default()) as u16;
+
+            if options.verify_crc && crc != actual {
+                return Err(GzipError::HeaderChecksumMismatch {
+                    expected: crc,
+                    actual,
+                });
+            }
 
+            _crc = Some(crc);
             _idx += 2;
         }
 
@@ -202,6 +405,281 @@ impl GzipHeader {
             end_idx: _idx,
         })
     }
+    /// Reads a header from the start of a reader, taking only the header's
+    /// bytes so the reader is left at the start of the DEFLATE data.
+    ///
+    /// # Arguments
+    ///
+    /// * 'reader' - The reader to take the header from. Optional fields are
+    ///         read a byte at a time, so this should be buffered.
+    ///
+    /// # Returns
+    ///
+    /// The header, or a GzipError if reading failed or build did.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, GzipError> {
+        Self::from_reader_with_options(reader, &HeaderOptions::default())
+    }
+    /// Like from_reader, but parses the header with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * 'reader' - The reader to take the header from.
+    /// * 'options' - The HeaderOptions to parse the header with.
+    ///
+    /// # Returns
+    ///
+    /// The header, or a GzipError if reading failed or build_with_options
+    /// did.
+    pub fn from_reader_with_options<R: Read>(
+        reader: &mut R,
+        options: &HeaderOptions,
+    ) -> Result<Self, GzipError> {
+        let mut fixed = [0; 10];
+        reader.read_exact(&mut fixed)?;
+
+        // Check for the ID bytes before reading anything else.
+        let [id1, id2, _, flg, ..] = fixed;
+        if [id1, id2] != [0x1f, 0x8b] {
+            return Err(GzipError::NotGzipFile(vec![id1, id2]));
+        }
+        let mut bytes = fixed.to_vec();
+
+        let mut byte = [0u8; 1];
+        let max_size = options.max_size.unwrap_or(usize::MAX);
+
+        // FEXTRA, a two byte length and then that many bytes.
+        if flg & 0b100 != 0 {
+            let mut xlen = [0u8; 2];
+            reader.read_exact(&mut xlen)?;
+            bytes.extend_from_slice(&xlen);
+
+            let start = bytes.len();
+            let end = start + u16::from_le_bytes(xlen) as usize;
+            if end > max_size {
+                return Err(GzipError::InvalidHeader(bytes));
+            }
+            bytes.resize(end, 0);
+            reader.read_exact(bytes.get_mut(start..).unwrap_or_default())?;
+        }
+
+        // FNAME and FCOMMENT, each zero terminated.
+        for flag in [0b1000, 0b10000] {
+            if flg & flag != 0 {
+                loop {
+                    if bytes.len() == max_size {
+                        return Err(GzipError::InvalidHeader(bytes));
+                    }
+                    reader.read_exact(&mut byte)?;
+                    bytes.push(byte[0]);
+                    if byte[0] == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // FHCRC, two bytes.
+        if flg & 0b10 != 0 {
+            if bytes.len() + 2 > max_size {
+                return Err(GzipError::InvalidHeader(bytes));
+            }
+            let mut crc = [0u8; 2];
+            reader.read_exact(&mut crc)?;
+            bytes.extend_from_slice(&crc);
+        }
+
+        Self::build_with_options(&bytes, options)
+    }
+    /// Returns the name to restore the decompressed file to, the final part
+    /// of FNAME. Any directories in it are dropped so a file can't be
+    /// written outside of where it's being decompressed.
+    ///
+    /// # Returns
+    ///
+    /// The name, or None if there's no FNAME or it doesn't hold a usable
+    /// file name.
+    pub fn original_name(&self) -> Option<&str> {
+        let name = self.fname.as_deref()?;
+        // Names written on Windows can use either separator.
+        let name = name.rsplit(['/', '\\']).next()?;
+
+        match name {
+            "" | "." | ".." => None,
+            name => Some(name),
+        }
+    }
+    /// Returns MTIME as a SystemTime, or None if it's 0, which means no
+    /// time was stored.
+    pub fn modified(&self) -> Option<SystemTime> {
+        match self.mtime {
+            0 => None,
+            mtime => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64)),
+        }
+    }
+    /// Returns the number of bytes the header's optional fields have
+    /// allocated on the heap.
+    pub fn memory_usage(&self) -> usize {
+        self.fextra.as_ref().map_or(0, Vec::capacity)
+            + self.fname.as_ref().map_or(0, String::capacity)
+            + self.fcomment.as_ref().map_or(0, String::capacity)
+    }
+    /// Returns an iterator over the subfields of FEXTRA, which is empty if
+    /// the header has no extra field.
+    pub fn extra_fields(&self) -> ExtraFields<'_> {
+        ExtraFields::new(self.fextra.as_deref().unwrap_or_default())
+    }
+    /// Lays the header out as it's stored at the start of a member. FEXTRA,
+    /// FNAME, and FCOMMENT are set by whether their fields are there, FTEXT
+    /// and FHCRC by flg, and the CRC16 is worked out afresh rather than
+    /// taken from crc.
+    ///
+    /// # Returns
+    ///
+    /// The header's bytes. Characters of fname and fcomment outside of
+    /// ISO-8859-1 are written as '?', and an extra field longer than 65535
+    /// bytes is cut short.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let flg = self.flg[0] as u8
+            | (self.flg[1] as u8) << 1
+            | (self.fextra.is_some() as u8) << 2
+            | (self.fname.is_some() as u8) << 3
+            | (self.fcomment.is_some() as u8) << 4;
+
+        let mut bytes = vec![0x1f, 0x8b, self.cm, flg];
+        bytes.extend_from_slice(&self.mtime.to_le_bytes());
+        bytes.extend_from_slice(&[self.xfl, self.os]);
+
+        if let Some(extra) = &self.fextra {
+            let extra = extra.get(..u16::MAX as usize).unwrap_or(extra);
+            bytes.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(extra);
+        }
+        for text in [&self.fname, &self.fcomment].into_iter().flatten() {
+            bytes.extend(text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')));
+            bytes.push(0);
+        }
+        if self.flg[1] {
+            let crc = crc::hash(&bytes) as u16;
+            bytes.extend_from_slice(&crc.to_le_bytes());
+        }
+
+        bytes
+    }
+}
+
+// An empty header, OS 255 (unknown) and every optional field left out.
+impl Default for GzipHeader {
+    fn default() -> Self {
+        Self {
+            cm: 8,
+            flg: [false; 5],
+            mtime: 0,
+            xfl: 0,
+            os: 255,
+            crc: None,
+            fextra: None,
+            fname: None,
+            fcomment: None,
+            end_idx: 10,
+        }
+    }
+}
+
+/// A subfield of a gzip header's extra field, as laid out in section 2.3.1.1
+/// of RFC 1952.
+///
+/// # Fields
+///
+/// * 'si1' - The first byte of the subfield ID.
+/// * 'si2' - The second byte of the subfield ID.
+/// * 'data' - The subfield's data, at most 65535 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraField {
+    pub si1: u8,
+    pub si2: u8,
+    pub data: Vec<u8>,
+}
+
+impl ExtraField {
+    /// Creates a subfield with the given ID and data.
+    ///
+    /// # Arguments
+    ///
+    /// * 'si1' - The first byte of the subfield ID.
+    /// * 'si2' - The second byte of the subfield ID.
+    /// * 'data' - The subfield's data.
+    pub fn new(si1: u8, si2: u8, data: Vec<u8>) -> Self {
+        Self { si1, si2, data }
+    }
+    /// Lays the subfield out as it's stored in a header, SI1, SI2, the two
+    /// byte LEN, then the data.
+    ///
+    /// # Returns
+    ///
+    /// The subfield's bytes, or None if the data is too long for LEN.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let len = u16::try_from(self.data.len()).ok()?;
+
+        let mut bytes = vec![self.si1, self.si2];
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        Some(bytes)
+    }
+}
+
+impl From<(u8, u8, &[u8])> for ExtraField {
+    fn from((si1, si2, data): (u8, u8, &[u8])) -> Self {
+        Self::new(si1, si2, data.to_vec())
+    }
+}
+
+/// An iterator over the subfields of an extra field, yielding the two ID
+/// bytes and the data of each. Bytes that don't make up a whole subfield
+/// end the iteration.
+///
+/// # Fields
+///
+/// * 'rest' - The extra field bytes that haven't been iterated over yet.
+#[derive(Debug, Clone)]
+pub struct ExtraFields<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> ExtraFields<'a> {
+    /// Creates an iterator over the subfields in the XLEN bytes of an extra
+    /// field.
+    ///
+    /// # Arguments
+    ///
+    /// * 'extra' - The extra field, without XLEN.
+    pub fn new(extra: &'a [u8]) -> Self {
+        Self { rest: extra }
+    }
+    /// Returns the bytes left after the last whole subfield, empty if the
+    /// extra field is well formed.
+    pub fn remainder(&self) -> &'a [u8] {
+        self.rest
+    }
+}
+
+impl<'a> Iterator for ExtraFields<'a> {
+    type Item = (u8, u8, &'a [u8]);
+    fn next(&mut self) -> Option<Self::Item> {
+        let &[si1, si2, lo, hi, ..] = self.rest else {
+            return None;
+        };
+        let len = u16::from_le_bytes([lo, hi]) as usize;
+        let data = self.rest.get(4..4 + len)?;
+
+        self.rest = self.rest.get(4 + len..).unwrap_or_default();
+        Some((si1, si2, data))
+    }
+}
+
+/// Decodes ISO-8859-1 bytes, which map one to one onto the first 256
+/// Unicode code points, so every byte string is valid.
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
 }
 
 /// A struct containing the parts of a gzip file.
@@ -210,17 +688,22 @@ impl GzipHeader {
 ///
 /// * 'header' - A byte vector containing the header, has to be a vector due to
 ///         gzip optional header elements.
-/// * 'deflate' - A byte vector containing the DEFLATE compressed blocks.
-/// * 'footer' - A byte vector containing the footer
-pub struct GzipFile {
+/// * 'deflate' - The DEFLATE compressed blocks, borrowed from the bytes the
+///         file was built from when possible.
+/// * 'crc32' - The CRC-32 of the decompressed data, from the trailer.
+/// * 'isize' - The decompressed size mod 2^32, from the trailer.
+#[cfg(feature = "inflate")]
+pub struct GzipFile<'a> {
     pub header: GzipHeader,
-    pub deflate: DeflateData,
+    pub deflate: DeflateData<'a>,
     pub crc32: u32,
     pub isize: u32,
 }
 
-impl GzipFile {
-    /// Accepts a byte array and returns a GzipFile struct.
+#[cfg(feature = "inflate")]
+impl<'a> GzipFile<'a> {
+    /// Accepts a byte array and returns a GzipFile struct. The DEFLATE data
+    /// is borrowed from bytes rather than copied.
     ///
     /// # Arguments
     ///
@@ -229,18 +712,101 @@ impl GzipFile {
     /// # Returns
     ///
     /// The built GzipFile struct, or an error if building the header failed.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GzipError> {
-        let header = GzipHeader::build(bytes)?;
-        let footer = &bytes[bytes.len() - 8..bytes.len()];
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, GzipError> {
+        Self::from_cow(Cow::Borrowed(bytes), &HeaderOptions::default())
+    }
+    /// Like from_bytes, but parses the header with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - A reference to a byte array containing the gzip file.
+    /// * 'options' - The HeaderOptions to parse the header with.
+    ///
+    /// # Returns
+    ///
+    /// The built GzipFile struct, or an error if building the header failed.
+    pub fn from_bytes_with_options(
+        bytes: &'a [u8],
+        options: &HeaderOptions,
+    ) -> Result<Self, GzipError> {
+        Self::from_cow(Cow::Borrowed(bytes), options)
+    }
+    /// Accepts an owned byte vector and returns a GzipFile struct that owns
+    /// it, reusing the vector's allocation for the DEFLATE data.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - A byte vector containing the gzip file.
+    ///
+    /// # Returns
+    ///
+    /// The built GzipFile struct, or an error if building the header failed.
+    pub fn from_vec(bytes: Vec<u8>) -> Result<GzipFile<'static>, GzipError> {
+        GzipFile::from_cow(Cow::Owned(bytes), &HeaderOptions::default())
+    }
+    /// Maps a gzip file into memory and builds a GzipFile that decompresses
+    /// straight from the mapping, so the file is never read in as a whole.
+    ///
+    /// # Arguments
+    ///
+    /// * 'path' - A path in the form of any type that can be coerced into a
+    ///         Path. The file must not be changed while the GzipFile exists.
+    ///
+    /// # Returns
+    ///
+    /// Either the GzipFile struct, or a GzipError.
+    #[cfg(feature = "memmap")]
+    pub fn from_path_mmap<P: AsRef<Path>>(path: P) -> Result<GzipFile<'static>, GzipError> {
+        let file = fs::File::open(path)?;
+        // SAFETY: The mapping is only ever read, the caller is responsible
+        // for the file not being modified or truncated while it's mapped.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+
+        let (header, crc32, isize) = Self::parse_parts(&map, &HeaderOptions::default())?;
+        let (start, end) = (header.end_idx, map.len().saturating_sub(8));
+
+        Ok(GzipFile {
+            header,
+            deflate: DeflateData::build_mapped(map, start, end, isize as usize),
+            crc32,
+            isize,
+        })
+    }
+    /// Parses the header and trailer out of a whole gzip file.
+    fn parse_parts(
+        bytes: &[u8],
+        options: &HeaderOptions,
+    ) -> Result<(GzipHeader, u32, u32), GzipError> {
+        let header = GzipHeader::build_with_options(bytes, options)?;
+        // Without room for the trailer there's no DEFLATE data to be found.
+        if bytes.len() < header.end_idx + 8 {
+            return Err(DeflateError::UnexpectedEofError { bit_offset: 0 }.into());
+        }
+        let Some(&[c0, c1, c2, c3, i0, i1, i2, i3]) = bytes.last_chunk::<8>() else {
+            return Err(DeflateError::UnexpectedEofError { bit_offset: 0 }.into());
+        };
 
-        let crc32 = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
-        let isize = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+        let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+        let isize = u32::from_le_bytes([i0, i1, i2, i3]);
 
-        let deflate_raw = bytes[header.end_idx..bytes.len() - 8].to_vec();
+        Ok((header, crc32, isize))
+    }
+    fn from_cow(bytes: Cow<'a, [u8]>, options: &HeaderOptions) -> Result<Self, GzipError> {
+        let (header, crc32, isize) = Self::parse_parts(&bytes, options)?;
+
+        let (start, end) = (header.end_idx, bytes.len().saturating_sub(8));
+        let deflate_raw = match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(bytes.get(start..end).unwrap_or_default()),
+            Cow::Owned(mut bytes) => {
+                bytes.truncate(end);
+                bytes.drain(..start);
+                Cow::Owned(bytes)
+            }
+        };
 
         Ok(Self {
             header,
-            deflate: DeflateData::build(&deflate_raw),
+            deflate: DeflateData::build_with_size_hint(deflate_raw, isize as usize),
             crc32,
             isize,
         })
@@ -256,21 +822,166 @@ impl GzipFile {
     /// # Returns
     ///
     /// Either the GzipFile struct, or a GzipError.
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<GzipFile, GzipError> {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<GzipFile<'static>, GzipError> {
         let bytes = fs::read(path)?;
 
-        Self::from_bytes(&bytes)
+        GzipFile::from_vec(bytes)
     }
     #[inline]
     pub fn decompress(&mut self) -> Result<Vec<u8>, DeflateError> {
         let data = self.deflate.decompress()?;
 
-        let crc = crc::hash(&data);
+        verify_trailer(
+            self.crc32,
+            self.isize,
+            self.deflate.crc32(),
+            data.len() as u64,
+        )?;
+
+        Ok(data)
+    }
+    /// Decompresses the file into a Vec owned by the caller and confirms the
+    /// checksum, reusing the Vec's allocation, see
+    /// DeflateData::decompress_to_vec.
+    ///
+    /// # Arguments
+    ///
+    /// * 'output' - The Vec to decompress into, cleared first.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes decompressed, or a DeflateError if decoding
+    /// failed or the checksums don't match.
+    pub fn decompress_to_vec(&mut self, output: &mut Vec<u8>) -> Result<usize, DeflateError> {
+        let len = self.deflate.decompress_to_vec(output)?;
+
+        verify_trailer(self.crc32, self.isize, self.deflate.crc32(), len as u64)?;
 
-        if self.crc32 != crc {
-            return Err(DeflateError::DecompressionError("Checksums do not match."));
+        Ok(len)
+    }
+    /// Decompresses the file, handing the output to the sink one chunk at a
+    /// time instead of keeping it, and confirms the checksum at the end.
+    ///
+    /// # Arguments
+    ///
+    /// * 'sink' - A closure called with each chunk of decompressed bytes, in
+    ///         order. The checksum can only be confirmed once every chunk
+    ///         has been handed over, so a corrupt file is reported after the
+    ///         sink has seen its data.
+    ///
+    /// # Returns
+    ///
+    /// The total number of bytes handed to the sink, or a DeflateError if
+    /// decoding failed or the checksums don't match.
+    pub fn decompress_to_sink<F: FnMut(&[u8])>(
+        &mut self,
+        mut sink: F,
+    ) -> Result<u64, DeflateError> {
+        let mut crc = 0;
+        let len = self.deflate.decompress_to_sink(|chunk| {
+            crc = crc::update(crc, chunk);
+            sink(chunk);
+        })?;
+
+        verify_trailer(self.crc32, self.isize, crc, len)?;
+
+        Ok(len)
+    }
+    /// Decompresses the file straight into a writer, such as a file or a
+    /// socket, without keeping the output, and confirms the checksum at the
+    /// end.
+    ///
+    /// Output is written in batches of up to WRITE_BATCH bytes to keep the
+    /// number of writes down, and a file of only stored blocks is written
+    /// straight from its bytes with write_vectored.
+    ///
+    /// # Arguments
+    ///
+    /// * 'w' - The writer to write the decompressed data to. Like with
+    ///         decompress_to_sink, a corrupt file may only be reported after
+    ///         its data has been written.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written, or a GzipError wrapping either the
+    /// io::Error from the writer or the DeflateError from decoding.
+    pub fn decompress_to_writer<W: Write>(&mut self, w: &mut W) -> Result<u64, GzipError> {
+        if let Some(slices) = self.stored_slices()? {
+            let len = slices.iter().map(|slice| slice.len() as u64).sum();
+            let mut slices: Vec<IoSlice> = slices.into_iter().map(IoSlice::new).collect();
+            write_all_vectored(w, &mut slices)?;
+            return Ok(len);
         }
 
+        let mut crc = 0;
+        let len = self
+            .deflate
+            .try_decompress_in_chunks(WRITE_BATCH, |chunk| {
+                crc = crc::update(crc, chunk);
+                w.write_all(chunk).map_err(GzipError::from)
+            })?;
+
+        verify_trailer(self.crc32, self.isize, crc, len)?;
+
+        Ok(len)
+    }
+    /// Decompresses the file into a buffer supplied by the caller, without
+    /// allocating, and confirms the checksum.
+    ///
+    /// # Arguments
+    ///
+    /// * 'output' - The buffer to write the decompressed data into.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written to the start of output, or a
+    /// DeflateError if decoding failed, the data doesn't fit, or the
+    /// checksums don't match.
+    pub fn decompress_into(&mut self, output: &mut [u8]) -> Result<usize, DeflateError> {
+        let len = self.deflate.decompress_into(output)?;
+
+        verify_trailer(self.crc32, self.isize, self.deflate.crc32(), len as u64)?;
+
+        Ok(len)
+    }
+    /// Like decompress, but borrows the output from the bytes the file was
+    /// built from when the member is a single stored block, so files that
+    /// only use gzip for its checksum aren't copied, see
+    /// DeflateData::decompress_cow.
+    ///
+    /// # Returns
+    ///
+    /// The decompressed bytes, or a DeflateError if decoding failed or the
+    /// checksums don't match.
+    pub fn decompress_cow(&mut self) -> Result<Cow<'a, [u8]>, DeflateError> {
+        let data = self.deflate.decompress_cow()?;
+
+        verify_trailer(
+            self.crc32,
+            self.isize,
+            self.deflate.crc32(),
+            data.len() as u64,
+        )?;
+
         Ok(data)
     }
+    /// Returns the data of every stored block without copying it, once the
+    /// checksum has been confirmed, see DeflateData::stored_slices.
+    ///
+    /// # Returns
+    ///
+    /// The data of each block in order, None if the file was built from
+    /// owned bytes or holds blocks that aren't stored, or a DeflateError if
+    /// the checksums don't match.
+    pub fn stored_slices(&self) -> Result<Option<Vec<&'a [u8]>>, DeflateError> {
+        let Some(slices) = self.deflate.stored_slices() else {
+            return Ok(None);
+        };
+        let crc = slices.iter().fold(0, |crc, slice| crc::update(crc, slice));
+        let len = slices.iter().map(|slice| slice.len() as u64).sum();
+
+        verify_trailer(self.crc32, self.isize, crc, len)?;
+
+        Ok(Some(slices))
+    }
 }
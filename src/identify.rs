@@ -0,0 +1,330 @@
+//! Guessing which program wrote a gzip file.
+//!
+//! Encoders leave traces of themselves beyond what the format requires: the
+//! header fields they fill in, how many tokens they put in a block before
+//! starting a new one, and which matches their search never takes. None of
+//! these are certain, a program can be told to write any header and forks
+//! share their parents' habits, so identify scores every candidate and
+//! keeps the evidence for each point.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::fmt;
+
+use crate::{
+    gzip::{GzipError, GzipHeader},
+    inflate::BlockType,
+    tokens::{decode_events, Event},
+};
+
+/// The furthest back zlib and gzip take a match of length 3 from when
+/// lazy matching, as a short match from further away rarely pays for its
+/// distance.
+const TOO_FAR: usize = 4096;
+
+/// The tokens a zlib block holds when its symbol buffer fills at the
+/// default memLevel.
+const ZLIB_BLOCK_TOKENS: usize = 16383;
+
+/// The tokens a Go compress/flate block holds when it fills.
+const GO_BLOCK_TOKENS: usize = 16384;
+
+/// The most tokens a GNU gzip block holds.
+const GZIP_BLOCK_TOKENS: usize = 32767;
+
+/// A program or library that might have written a file.
+///
+/// # Variants
+///
+/// * 'Gzip' - GNU gzip.
+/// * 'Pigz' - pigz, the parallel gzip built on zlib.
+/// * 'Zlib' - zlib's own gzip writing, or deflate with a gzip wrapper.
+/// * 'Zopfli' - Zopfli.
+/// * 'Libdeflate' - libdeflate.
+/// * 'Miniz' - miniz, or miniz_oxide behind flate2.
+/// * 'Go' - Go's compress/gzip.
+/// * 'Python' - Python's gzip module.
+/// * 'Bgzf' - A BGZF writer, as used by samtools and htslib.
+/// * 'SevenZip' - 7-Zip, or another Windows archiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Producer {
+    Gzip,
+    Pigz,
+    Zlib,
+    Zopfli,
+    Libdeflate,
+    Miniz,
+    Go,
+    Python,
+    Bgzf,
+    SevenZip,
+}
+
+impl Producer {
+    /// Every producer, in the order they're scored.
+    pub const ALL: [Producer; 10] = [
+        Producer::Gzip,
+        Producer::Pigz,
+        Producer::Zlib,
+        Producer::Zopfli,
+        Producer::Libdeflate,
+        Producer::Miniz,
+        Producer::Go,
+        Producer::Python,
+        Producer::Bgzf,
+        Producer::SevenZip,
+    ];
+}
+
+impl fmt::Display for Producer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Producer::Gzip => "gzip",
+            Producer::Pigz => "pigz",
+            Producer::Zlib => "zlib",
+            Producer::Zopfli => "zopfli",
+            Producer::Libdeflate => "libdeflate",
+            Producer::Miniz => "miniz",
+            Producer::Go => "go",
+            Producer::Python => "python",
+            Producer::Bgzf => "bgzf",
+            Producer::SevenZip => "7-zip",
+        })
+    }
+}
+
+/// The result of identify.
+///
+/// # Fields
+///
+/// * 'scores' - Every producer with a non-zero score, highest first. The
+///         scores only mean anything relative to each other.
+/// * 'evidence' - What was seen in the file, one line per trait, in the
+///         order they were checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identification {
+    pub scores: Vec<(Producer, i32)>,
+    pub evidence: Vec<String>,
+}
+
+impl Identification {
+    /// Returns the most likely producer, or None if nothing scored above 0.
+    pub fn best(&self) -> Option<Producer> {
+        self.scores
+            .first()
+            .filter(|&&(_, score)| score > 0)
+            .map(|&(producer, _)| producer)
+    }
+}
+
+/// The scores being built up, and the evidence behind them.
+struct Scorer {
+    scores: [i32; Producer::ALL.len()],
+    evidence: Vec<String>,
+}
+
+impl Scorer {
+    /// Notes a trait of the file, adding points to each producer it's
+    /// typical of.
+    fn note(&mut self, evidence: String, points: &[(Producer, i32)]) {
+        for &(producer, score) in points {
+            if let Some(total) = self.scores.get_mut(producer as usize) {
+                *total += score;
+            }
+        }
+        self.evidence.push(evidence);
+    }
+}
+
+/// The shape of a single block, for what identify looks at.
+///
+/// # Fields
+///
+/// * 'block_type' - How the block was coded.
+/// * 'final_block' - Whether BFINAL was set.
+/// * 'tokens' - The literals and matches of a compressed block, or the bytes
+///         of a stored one.
+struct Block {
+    block_type: BlockType,
+    final_block: bool,
+    tokens: usize,
+}
+
+/// Guesses which program wrote a gzip file, from the first member's header
+/// and DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file. Only the first member is looked at.
+///
+/// # Returns
+///
+/// The scores and the evidence behind them, or a GzipError if the header
+/// or stream is invalid.
+pub fn identify(bytes: &[u8]) -> Result<Identification, GzipError> {
+    let header = GzipHeader::build(bytes)?;
+
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut far_short_matches = 0;
+    decode_events(
+        bytes.get(header.end_idx..).unwrap_or_default(),
+        &mut |event| match event {
+            Event::BlockStart {
+                block_type,
+                final_block,
+            } => blocks.push(Block {
+                block_type,
+                final_block,
+                tokens: 0,
+            }),
+            Event::Literal(_) | Event::Match { .. } => {
+                if let Event::Match {
+                    length: 3,
+                    distance,
+                } = event
+                {
+                    far_short_matches += (distance > TOO_FAR) as u64;
+                }
+                if let Some(block) = blocks.last_mut() {
+                    block.tokens += 1;
+                }
+            }
+            Event::EndOfBlock | Event::BlockEnd => {}
+        },
+    )?;
+
+    let mut scorer = Scorer {
+        scores: [0; Producer::ALL.len()],
+        evidence: Vec::new(),
+    };
+    score_header(&mut scorer, &header);
+    score_blocks(&mut scorer, &blocks, far_short_matches, header.xfl);
+
+    let mut scores: Vec<(Producer, i32)> = Producer::ALL
+        .into_iter()
+        .zip(scorer.scores)
+        .filter(|&(_, score)| score != 0)
+        .collect();
+    // Stable, so ties keep the order of Producer::ALL.
+    scores.sort_by_key(|&(_, score)| -score);
+
+    Ok(Identification {
+        scores,
+        evidence: scorer.evidence,
+    })
+}
+
+/// Scores the fields of a member's header.
+fn score_header(scorer: &mut Scorer, header: &GzipHeader) {
+    use Producer::*;
+
+    if header
+        .extra_fields()
+        .any(|(si1, si2, _)| (si1, si2) == (b'B', b'C'))
+    {
+        scorer.note("extra field has a BGZF BC subfield".into(), &[(Bgzf, 10)]);
+    }
+
+    match (&header.fname, header.mtime) {
+        (Some(_), 0) => scorer.note("has a name but no timestamp".into(), &[(Python, 1)]),
+        (Some(_), _) => scorer.note(
+            "has a name and timestamp, as when compressing a file".into(),
+            &[(Gzip, 2), (Pigz, 2), (Python, 1), (SevenZip, 1)],
+        ),
+        (None, 0) => scorer.note(
+            "has no name or timestamp, as when compressing a stream".into(),
+            &[(Zlib, 1), (Zopfli, 1), (Libdeflate, 1), (Miniz, 1), (Go, 1)],
+        ),
+        (None, _) => scorer.note("has a timestamp but no name".into(), &[(Zlib, 1)]),
+    }
+
+    match header.os {
+        3 => scorer.note(
+            "OS is 3 (Unix)".into(),
+            &[(Gzip, 1), (Pigz, 1), (Zlib, 1), (Zopfli, 1)],
+        ),
+        255 => scorer.note(
+            "OS is 255 (unknown)".into(),
+            &[(Libdeflate, 2), (Miniz, 2), (Go, 2), (Python, 2)],
+        ),
+        0 | 11 => scorer.note(format!("OS is {} (Windows)", header.os), &[(SevenZip, 2)]),
+        os => scorer.note(format!("OS is {os}"), &[]),
+    }
+
+    match header.xfl {
+        // Python's default level is 9, Zopfli always claims the best.
+        2 => scorer.note("XFL is 2 (slowest)".into(), &[(Zopfli, 1), (Python, 1)]),
+        4 => scorer.note("XFL is 4 (fastest)".into(), &[]),
+        xfl => scorer.note(format!("XFL is {xfl}"), &[]),
+    }
+}
+
+/// Scores the shape of a member's blocks.
+fn score_blocks(scorer: &mut Scorer, blocks: &[Block], far_short_matches: u64, xfl: u8) {
+    use Producer::*;
+
+    // A sync flush ends with an empty stored block, pigz does one at the
+    // end of every chunk it hands to a thread.
+    let is_flush = |block: &Block| block.block_type == BlockType::Stored && block.tokens == 0;
+
+    // The final block holds whatever was left, and a flush cuts short the
+    // block before it, so only full blocks say anything about where an
+    // encoder splits.
+    let full: Vec<usize> = blocks
+        .iter()
+        .zip(blocks.iter().skip(1))
+        .filter(|(block, next)| block.block_type != BlockType::Stored && !is_flush(next))
+        .map(|(block, _)| block.tokens)
+        .collect();
+
+    if full.is_empty() {
+        scorer.note(format!("{} block(s), none full", blocks.len()), &[]);
+    } else if full.iter().all(|&tokens| tokens == ZLIB_BLOCK_TOKENS) {
+        scorer.note(
+            format!("every full block has {ZLIB_BLOCK_TOKENS} tokens"),
+            &[(Zlib, 4), (Pigz, 3)],
+        );
+    } else if full.iter().all(|&tokens| tokens == GO_BLOCK_TOKENS) {
+        scorer.note(
+            format!("every full block has {GO_BLOCK_TOKENS} tokens"),
+            &[(Go, 4)],
+        );
+    } else if full.iter().all(|&tokens| tokens <= GZIP_BLOCK_TOKENS) {
+        scorer.note(
+            format!("full blocks vary in size, up to {GZIP_BLOCK_TOKENS} tokens"),
+            &[(Gzip, 3)],
+        );
+    } else {
+        scorer.note(
+            format!("some blocks have more than {GZIP_BLOCK_TOKENS} tokens"),
+            &[(Zopfli, 2), (Libdeflate, 2), (Miniz, 1)],
+        );
+    }
+
+    let flushes = blocks
+        .iter()
+        .filter(|block| !block.final_block && is_flush(block))
+        .count();
+    if flushes > 0 {
+        scorer.note(
+            format!("{flushes} empty stored block(s) from sync flushes"),
+            &[(Pigz, 3)],
+        );
+    }
+
+    // Only the lazy levels drop far away short matches, the fastest level
+    // of zlib and gzip keeps them.
+    if far_short_matches > 0 && xfl != 4 {
+        scorer.note(
+            format!("{far_short_matches} match(es) of length 3 from more than {TOO_FAR} back"),
+            &[(Gzip, -3), (Pigz, -3), (Zlib, -3)],
+        );
+    }
+}
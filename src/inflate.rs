@@ -1,349 +1,2168 @@
-use std::{error::Error, fmt::Display};
+// Everything here runs on untrusted input, so nothing may be able to panic,
+// see Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::VecDeque,
+    ops::{Deref, Range},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, OnceLock,
+    },
+};
 
+pub use crate::stream::{
+    BlockType, DeflateError, ErrorPosition, MemoryUsage, Phase, TraceRecord, WINDOW_SIZE,
+};
 use crate::{
-    bits::BitVector64,
+    bits::BitBuffer,
+    crc,
     prefix::{
-        PrefixTree, DISTANCE_BASE, DISTANCE_EXTRA_BITS, FIXED_CODE_LENGTHS, LENGTH_BASE,
-        LENGTH_EXTRA_BITS,
+        cl_code_symbol, kraft, DecodeTable, TableEntry, CL_CODES, DISTANCE_BASE,
+        DISTANCE_EXTRA_BITS, FIXED_CODE_LENGTHS, FIXED_DISTANCE_LENGTHS, HCLEN_BASE, HDIST_BASE,
+        HLIT_BASE, LENGTH_BASE, LENGTH_EXTRA_BITS, MAX_CODE_LENGTH, MAX_HDIST, MAX_HLIT,
     },
 };
 
-#[derive(Debug)]
-pub enum DeflateError {
-    InvalidBlockError(&'static str),
-    InvalidSymbolError(usize, &'static str),
-    DecompressionError(&'static str),
+/// Root table sizes for each of the lookup tables, codes longer than these
+/// fall through to a sub-table.
+const LITERAL_ROOT_BITS: u8 = 9;
+const DISTANCE_ROOT_BITS: u8 = 6;
+const CODE_LENGTH_ROOT_BITS: u8 = 7;
+
+/// The most entries each of the lookup tables can grow to. check_code only
+/// accepts complete codes, so a sub-table of 2^k entries takes at least
+/// k + 1 codes under its root prefix, and the root prefixes without one take
+/// a code for each bit set in how many of them there are. These are the most
+/// entries MAX_HLIT, MAX_HDIST, and CL_CODES codes can be spent on.
+const MAX_LITERAL_ENTRIES: usize = 3072;
+const MAX_DISTANCE_ENTRIES: usize = 1104;
+const MAX_CODE_LENGTH_ENTRIES: usize = 1 << CODE_LENGTH_ROOT_BITS;
+
+/// The bytes an Inflater made by Inflater::with_fixed_memory allocates, the
+/// window and the largest tables any stream can need.
+pub const FIXED_MEMORY_USAGE: usize = WINDOW_SIZE
+    + (MAX_LITERAL_ENTRIES + MAX_DISTANCE_ENTRIES + MAX_CODE_LENGTH_ENTRIES)
+        * std::mem::size_of::<TableEntry>();
+
+/// How many bytes DeflateData::decompress grows its output by at a time.
+const OUTPUT_CHUNK: usize = 32768;
+
+/// The most bytes a single byte of DEFLATE data can decompress to, a match of
+/// 258 bytes takes at least 2 bits with a dynamic code. Used to keep a size
+/// hint from a corrupt or malicious header from reserving absurd amounts of
+/// memory.
+const MAX_EXPANSION: usize = 1032;
+
+/// The version of the format Inflater::save_state writes, bumped whenever it
+/// changes so an old state is rejected rather than misread.
+const STATE_VERSION: u8 = 2;
+
+/// Allocates a zeroed buffer of len bytes, failing with
+/// DeflateError::OutOfMemoryError instead of aborting if there isn't the
+/// memory for it.
+pub(crate) fn try_zeroed(len: usize) -> Result<Vec<u8>, DeflateError> {
+    let mut buffer = Vec::new();
+    try_grow(&mut buffer, len)?;
+    buffer.resize(len, 0);
+    Ok(buffer)
 }
 
-impl Display for DeflateError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DeflateError::InvalidBlockError(s) => {
-                write!(f, "InvalidBlock Error: {}", s)
-            }
-            DeflateError::InvalidSymbolError(v, r) => {
-                write!(f, "InvalidSymbolError cause by symbol: {}, {}", v, r)
-            }
-            DeflateError::DecompressionError(s) => {
-                write!(f, "DecompressionError: {}", s)
-            }
+/// Reserves room for exactly additional more bytes in buffer, failing with
+/// DeflateError::OutOfMemoryError instead of aborting if there isn't the
+/// memory for it.
+fn try_grow(buffer: &mut Vec<u8>, additional: usize) -> Result<(), DeflateError> {
+    buffer
+        .try_reserve_exact(additional)
+        .map_err(|_| DeflateError::OutOfMemoryError(buffer.len().saturating_add(additional)))
+}
+
+/// Copies as much of src as fits into the start of dst.
+///
+/// # Returns
+///
+/// The number of bytes copied.
+#[inline]
+pub(crate) fn copy_prefix(dst: &mut [u8], src: &[u8]) -> usize {
+    let n = dst.len().min(src.len());
+    if let (Some(dst), Some(src)) = (dst.get_mut(..n), src.get(..n)) {
+        dst.copy_from_slice(src);
+    }
+    n
+}
+
+/// The literal/length and distance tables for BTYPE 1, built on first use
+/// and shared by every block of every file.
+static FIXED_TABLES: OnceLock<(DecodeTable, DecodeTable)> = OnceLock::new();
+
+/// Returns the fixed literal/length and distance tables, building them if
+/// this is the first call.
+fn fixed_tables() -> &'static (DecodeTable, DecodeTable) {
+    FIXED_TABLES.get_or_init(|| {
+        (
+            DecodeTable::from_lengths(&FIXED_CODE_LENGTHS, LITERAL_ROOT_BITS),
+            // The fixed distance code has 32 codes, 30 and 31 don't
+            // represent a distance but still need decoding to be rejected.
+            DecodeTable::from_lengths(&[FIXED_DISTANCE_LENGTHS[0]; 32], DISTANCE_ROOT_BITS),
+        )
+    })
+}
+
+/// Why a call to Inflater::inflate returned.
+///
+/// # Variants
+///
+/// * 'StreamEnd' - The final block has been decoded, any input that was not
+///         consumed comes after the DEFLATE stream.
+/// * 'NeedsInput' - Every byte of input was consumed and more is required to
+///         make progress.
+/// * 'NeedsOutput' - The output buffer is full and decoding can continue
+///         once there is room for more.
+/// * 'Pending' - Only returned by Inflater::inflate_step, the call decoded
+///         as many symbols as it was allowed to and decoding can continue on
+///         the next call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    StreamEnd,
+    NeedsInput,
+    NeedsOutput,
+    Pending,
+}
+
+/// Where the Inflater is within the stream, so decoding can stop at any
+/// point and pick back up on the next call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Reading BFINAL and BTYPE.
+    Header,
+    /// Reading LEN and NLEN of a stored block.
+    StoredLength,
+    /// Copying the bytes of a stored block, holds how many are left.
+    Stored(usize),
+    /// Reading HLIT, HDIST, and HCLEN of a dynamic block.
+    DynamicCounts,
+    /// Reading the code length code lengths, holds how many have been read.
+    CodeLengthLengths(usize),
+    /// Reading the literal/length and distance code lengths.
+    CodeLengths,
+    /// Decoding literals and matches.
+    Data,
+    /// Writing out a match that didn't fit in the output buffer.
+    Copy { length: usize, distance: usize },
+    /// The final block has ended.
+    Done,
+}
+
+/// Where a block sits in the stream and what it decompressed to, see
+/// Inflater::set_record_blocks.
+///
+/// # Fields
+///
+/// * 'block_type' - How the block was coded.
+/// * 'final_block' - Whether the block has BFINAL set.
+/// * 'bits' - The bit offsets of the block, from its BFINAL bit to just past
+///         its end, counting from the start of the stream.
+/// * 'bytes' - The offsets of the bytes the block decompressed to, counting
+///         from the start of the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub block_type: BlockType,
+    pub final_block: bool,
+    pub bits: Range<u64>,
+    pub bytes: Range<u64>,
+}
+
+/// Something the Inflater has decoded, handed to the trace hook as soon as
+/// it's found. Bit offsets count from the start of the stream.
+///
+/// # Variants
+///
+/// * 'Block' - A block header, at the offset of its BFINAL bit.
+/// * 'Stored' - The LEN of a stored block, at the offset of its first byte
+///         of data.
+/// * 'DynamicTables' - The code lengths of a dynamic block, at the offset
+///         just past them where the block's data starts. The code length
+///         code lengths are indexed by symbol, hclen is how many were sent.
+/// * 'Literal' - A literal byte, at the offset of its code.
+/// * 'Match' - A match, at the offset of its length code.
+/// * 'BlockEnd' - The end of a block, at the offset just past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent<'a> {
+    Block {
+        bit_offset: u64,
+        final_block: bool,
+        block_type: BlockType,
+    },
+    Stored {
+        bit_offset: u64,
+        len: u16,
+    },
+    DynamicTables {
+        bit_offset: u64,
+        hclen: usize,
+        code_length_lengths: &'a [u8; CL_CODES],
+        literal_lengths: &'a [u8],
+        distance_lengths: &'a [u8],
+    },
+    Literal {
+        bit_offset: u64,
+        byte: u8,
+    },
+    Match {
+        bit_offset: u64,
+        length: usize,
+        distance: usize,
+    },
+    BlockEnd {
+        bit_offset: u64,
+    },
+}
+
+/// A closure the Inflater calls with every TraceEvent, see
+/// Inflater::set_trace.
+pub type TraceHook = Box<dyn FnMut(&TraceEvent<'_>) + Send>;
+
+impl From<&TraceEvent<'_>> for TraceRecord {
+    fn from(event: &TraceEvent<'_>) -> Self {
+        match *event {
+            TraceEvent::Block {
+                bit_offset,
+                final_block,
+                block_type,
+            } => TraceRecord::Block {
+                bit_offset,
+                final_block,
+                block_type,
+            },
+            TraceEvent::Stored { bit_offset, len } => TraceRecord::Stored { bit_offset, len },
+            TraceEvent::DynamicTables {
+                bit_offset,
+                hclen,
+                literal_lengths,
+                distance_lengths,
+                ..
+            } => TraceRecord::DynamicTables {
+                bit_offset,
+                hlit: literal_lengths.len(),
+                hdist: distance_lengths.len(),
+                hclen,
+            },
+            TraceEvent::Literal { bit_offset, byte } => TraceRecord::Literal { bit_offset, byte },
+            TraceEvent::Match {
+                bit_offset,
+                length,
+                distance,
+            } => TraceRecord::Match {
+                bit_offset,
+                length,
+                distance,
+            },
+            TraceEvent::BlockEnd { bit_offset } => TraceRecord::BlockEnd { bit_offset },
         }
     }
 }
 
-impl Error for DeflateError {}
+/// The literal/length and distance code lengths of a dynamic block as
+/// they're read. Held inline rather than on the heap, as there are never
+/// more than MAX_HLIT + MAX_HDIST of them.
+///
+/// # Fields
+///
+/// * 'lengths' - Space for every length, only the first len are in use.
+/// * 'len' - How many lengths have been read.
+#[derive(Debug, Clone, Copy)]
+struct CodeLengths {
+    lengths: [u8; MAX_HLIT + MAX_HDIST],
+    len: usize,
+}
 
-pub struct DeflateData {
-    compressed: Vec<u8>,
-    decompressed: Vec<u8>,
-    bitstream: BitVector64,
-    finished: bool,
+impl CodeLengths {
+    /// Creates an empty CodeLengths.
+    fn new() -> Self {
+        Self {
+            lengths: [0; MAX_HLIT + MAX_HDIST],
+            len: 0,
+        }
+    }
+    /// Returns the lengths read so far.
+    fn as_slice(&self) -> &[u8] {
+        self.lengths.get(..self.len).unwrap_or_default()
+    }
+    /// Returns how many lengths have been read.
+    fn len(&self) -> usize {
+        self.len
+    }
+    /// Returns the last length read, if there is one.
+    fn last(&self) -> Option<&u8> {
+        self.as_slice().last()
+    }
+    /// Forgets every length read, ready for the next block.
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+    /// Repeats length until there are count lengths, or as many as there's
+    /// space for, MAX_HLIT + MAX_HDIST.
+    fn resize(&mut self, count: usize, length: u8) {
+        let count = count.min(self.lengths.len());
+        if let Some(lengths) = self.lengths.get_mut(self.len..count) {
+            lengths.fill(length);
+            self.len = count;
+        }
+    }
+}
+
+/// The last few events an Inflater decoded, see Inflater::set_history.
+///
+/// # Fields
+///
+/// * 'len' - How many records to keep, 0 to keep none.
+/// * 'records' - The records, oldest first.
+struct History {
+    len: usize,
+    records: VecDeque<TraceRecord>,
+}
+
+impl History {
+    /// Adds a record, dropping the oldest if there are already len.
+    fn push(&mut self, record: TraceRecord) {
+        if self.len == 0 {
+            return;
+        }
+        if self.records.len() == self.len {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// Reads the fields of a saved state back out in the order they were
+/// written, see Inflater::save_state.
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Starts reading from the beginning of bytes.
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+    /// Takes the next len bytes.
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], DeflateError> {
+        let Some((taken, rest)) = self.bytes.split_at_checked(len) else {
+            return Err(DeflateError::InvalidStateError(
+                "Saved state was cut short.",
+            ));
+        };
+        self.bytes = rest;
+        Ok(taken)
+    }
+    /// Takes the next byte.
+    pub(crate) fn u8(&mut self) -> Result<u8, DeflateError> {
+        Ok(self.take(1)?.first().copied().unwrap_or_default())
+    }
+    /// Takes the next byte as a bool, anything but 0 or 1 is invalid.
+    pub(crate) fn bool(&mut self) -> Result<bool, DeflateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeflateError::InvalidStateError(
+                "Saved state holds an invalid flag.",
+            )),
+        }
+    }
+    /// Takes the next 4 bytes as a little endian u32.
+    pub(crate) fn u32(&mut self) -> Result<u32, DeflateError> {
+        let mut word = [0; 4];
+        copy_prefix(&mut word, self.take(4)?);
+        Ok(u32::from_le_bytes(word))
+    }
+    /// Takes the next 8 bytes as a little endian u64.
+    pub(crate) fn u64(&mut self) -> Result<u64, DeflateError> {
+        let mut word = [0; 8];
+        copy_prefix(&mut word, self.take(8)?);
+        Ok(u64::from_le_bytes(word))
+    }
+    /// Takes the next u64 as a usize no larger than max.
+    pub(crate) fn usize(&mut self, max: usize) -> Result<usize, DeflateError> {
+        match usize::try_from(self.u64()?) {
+            Ok(value) if value <= max => Ok(value),
+            _ => Err(DeflateError::InvalidStateError(
+                "Saved state holds a value out of range.",
+            )),
+        }
+    }
+    /// Takes a length written as a u64, and then that many bytes, the length
+    /// being no more than max.
+    pub(crate) fn bytes(&mut self, max: usize) -> Result<&'a [u8], DeflateError> {
+        let len = self.usize(max)?;
+        self.take(len)
+    }
+    /// Takes whatever is left.
+    pub(crate) fn rest(self) -> &'a [u8] {
+        self.bytes
+    }
+    /// Checks every byte has been read.
+    pub(crate) fn finish(self) -> Result<(), DeflateError> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(DeflateError::InvalidStateError(
+                "Saved state has bytes left over.",
+            )),
+        }
+    }
 }
 
-impl DeflateData {
-    pub fn build(compressed: &[u8]) -> Self {
-        let bitstream = BitVector64::from_be_bytes(compressed);
+/// A single decoded item from a compressed block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    EndOfBlock,
+    Match { length: usize, distance: usize },
+}
+
+/// A streaming DEFLATE decoder. Input and output are supplied a piece at a
+/// time, and decoding suspends whenever either runs out, even partway
+/// through a block. The last 32KB of output are kept in a sliding window so
+/// back-references keep working after the output they point to has been
+/// handed back to the caller. The window is allocated by the first call to
+/// inflate, so a failed allocation is returned as an error like any other.
+///
+/// # Fields
+///
+/// * 'state' - The current position in the stream.
+/// * 'bits' - Bits taken from the input that haven't been decoded yet.
+/// * 'final_block' - Whether the current block has BFINAL set.
+/// * 'fixed_block' - Whether the current block uses the fixed codes.
+/// * 'window' - A ring buffer holding the most recent output.
+/// * 'window_pos' - The index in window the next byte will be written to.
+/// * 'block' - The index of the current block, counting from 0.
+/// * 'block_info' - The type and starting offsets of the current block.
+/// * 'blocks' - Every block that has ended, if they're being recorded.
+/// * 'total_in' - The number of bytes consumed so far.
+/// * 'total_out' - The number of bytes produced so far.
+/// * 'literal_table' - The literal/length table of the current dynamic block.
+/// * 'distance_table' - The distance table of the current dynamic block.
+/// * 'code_length_table' - The code length table of the current dynamic block.
+/// * 'hlit' - The number of literal/length codes in the current dynamic block.
+/// * 'hdist' - The number of distance codes in the current dynamic block.
+/// * 'hclen' - The number of code length codes in the current dynamic block.
+/// * 'code_length_lengths' - The code length code lengths read so far.
+/// * 'code_lengths' - The literal/length and distance code lengths read so far.
+/// * 'max_output' - The most bytes the stream is allowed to decompress to.
+/// * 'max_ratio' - The most bytes of output allowed per byte of input.
+/// * 'memory_limit' - The most bytes the inflater, and whatever owns it, is
+///         allowed to allocate.
+/// * 'trace' - A hook called with each part of the stream as it's decoded.
+/// * 'history' - The last few parts of the stream decoded, for errors.
+/// * 'budget' - How many more symbols the current call may decode, usize::MAX
+///         outside of inflate_step.
+/// * 'cancel' - A flag that stops decoding once it's set.
+/// * 'dictionary' - How many bytes of a preset dictionary are in the window
+///         ahead of the output, for distances to reach back into.
+pub struct Inflater {
+    state: State,
+    bits: BitBuffer,
+    final_block: bool,
+    fixed_block: bool,
+    window: Vec<u8>,
+    window_pos: usize,
+    block: u64,
+    block_info: BlockInfo,
+    blocks: Option<Vec<BlockInfo>>,
+    total_in: u64,
+    total_out: u64,
+    literal_table: DecodeTable,
+    distance_table: DecodeTable,
+    code_length_table: DecodeTable,
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+    code_length_lengths: [u8; CL_CODES],
+    code_lengths: CodeLengths,
+    max_output: Option<u64>,
+    max_ratio: Option<u64>,
+    memory_limit: Option<usize>,
+    trace: Option<TraceHook>,
+    history: History,
+    budget: usize,
+    cancel: Option<Arc<AtomicBool>>,
+    dictionary: usize,
+}
+
+impl Inflater {
+    /// Creates a new Inflater ready for the start of a DEFLATE stream.
+    ///
+    /// # Returns
+    ///
+    /// An Inflater with an empty window.
+    pub fn new() -> Self {
         Self {
-            compressed: compressed.to_vec(),
-            decompressed: Vec::new(),
-            bitstream,
-            finished: false,
+            state: State::Header,
+            bits: BitBuffer::new(),
+            final_block: false,
+            fixed_block: false,
+            window: Vec::new(),
+            window_pos: 0,
+            block: 0,
+            block_info: BlockInfo {
+                block_type: BlockType::Stored,
+                final_block: false,
+                bits: 0..0,
+                bytes: 0..0,
+            },
+            blocks: None,
+            total_in: 0,
+            total_out: 0,
+            literal_table: DecodeTable::from_lengths(&[], LITERAL_ROOT_BITS),
+            distance_table: DecodeTable::from_lengths(&[], DISTANCE_ROOT_BITS),
+            code_length_table: DecodeTable::from_lengths(&[], CODE_LENGTH_ROOT_BITS),
+            hlit: 0,
+            hdist: 0,
+            hclen: 0,
+            code_length_lengths: [0; CL_CODES],
+            code_lengths: CodeLengths::new(),
+            max_output: None,
+            max_ratio: None,
+            memory_limit: None,
+            trace: None,
+            history: History {
+                len: 0,
+                records: VecDeque::new(),
+            },
+            budget: usize::MAX,
+            cancel: None,
+            dictionary: 0,
         }
     }
-    pub fn decompress(&mut self) -> Result<Vec<u8>, DeflateError> {
-        while !self.finished {
-            // Initialize header.
-            let mut header: [u8; 3] = [0; 3];
-
-            // Iterate through header, popping the first 3 items from the
-            // bitstream and adding them to header.
-            for header_bit in header.iter_mut() {
-                if let Some(b) = self.bitstream.next() {
-                    *header_bit = b;
-                } else {
-                    return Err(DeflateError::InvalidBlockError(
-                        "Block ran out of bits before a header was specified.",
-                    ));
+    /// Creates a new Inflater that allocates everything it will ever need up
+    /// front, FIXED_MEMORY_USAGE bytes for the window and tables, and never
+    /// allocates again, for devices that have to know how much memory
+    /// decompressing takes before they start. Output only ever goes to the
+    /// buffer passed to inflate, so it can be streamed on to wherever it's
+    /// kept a piece at a time. The memory limit is set to FIXED_MEMORY_USAGE,
+    /// so turning on history or block recording fails with
+    /// DeflateError::MemoryLimitError rather than growing past it.
+    ///
+    /// # Returns
+    ///
+    /// An Inflater with its window and tables allocated.
+    pub fn with_fixed_memory() -> Self {
+        let mut inflater = Self::new();
+        inflater.window = vec![0; WINDOW_SIZE];
+
+        for (table, entries) in [
+            (&mut inflater.literal_table, MAX_LITERAL_ENTRIES),
+            (&mut inflater.distance_table, MAX_DISTANCE_ENTRIES),
+            (&mut inflater.code_length_table, MAX_CODE_LENGTH_ENTRIES),
+        ] {
+            table
+                .entries
+                .reserve_exact(entries.saturating_sub(table.entries.len()));
+        }
+        inflater.memory_limit = Some(FIXED_MEMORY_USAGE);
+
+        inflater
+    }
+    /// Limits how large the decompressed stream can be, so a small malicious
+    /// input can't decompress to an enormous output. Once the limit is
+    /// reached, inflate returns DeflateError::OutputLimitError instead of
+    /// producing more. The limit is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to decompress, or None for no limit.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.max_output = limit;
+    }
+    /// Limits how many times larger than the compressed stream the output
+    /// can be. The output is measured against the input consumed so far, so
+    /// a stream is stopped as soon as it goes over, rather than once it has
+    /// all been decoded. Once it does, inflate returns
+    /// DeflateError::ExpansionRatioError. The limit is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'ratio' - The most bytes of output per byte of input, or None for
+    ///         no limit. Ordinary data rarely goes past 10, DEFLATE can't go
+    ///         past 1032.
+    pub fn set_max_expansion_ratio(&mut self, ratio: Option<u64>) {
+        self.max_ratio = ratio;
+    }
+    /// Limits how much memory the inflater can allocate. The window takes
+    /// 32KB and the tables a few more, so this mostly matters to the
+    /// decoders built on the Inflater, which count their own buffers against
+    /// the same limit. Once it's exceeded, inflate returns
+    /// DeflateError::MemoryLimitError. The limit is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to allocate, or None for no limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+    /// Sets a hook to be called with every block header, table, literal,
+    /// and match as it's decoded, for seeing exactly how a stream was
+    /// compressed. Events are only ever reported once, even if decoding
+    /// suspends partway through a block. The hook is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'trace' - The hook to call, or None to stop tracing.
+    pub fn set_trace(&mut self, trace: Option<TraceHook>) {
+        self.trace = trace;
+    }
+    /// Sets a flag that cancels decoding, for stopping a long decode from
+    /// another thread, like a server giving up on a request past its
+    /// deadline. The flag is checked at the start of every call to inflate
+    /// and every block, once it's set inflate returns
+    /// DeflateError::CancelledError. Like any other error, that leaves the
+    /// stream where it is unfinished, the inflater has to be reset before
+    /// it's used again. The flag is kept by reset.
+    ///
+    /// # Arguments
+    ///
+    /// * 'cancel' - The flag to check, or None to never be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.cancel = cancel;
+    }
+    /// Primes the window with a preset dictionary, bytes the stream's first
+    /// matches can reach back into as if they'd been decompressed just
+    /// before it, as zlib's FDICT streams need. Only the last 32KB can be
+    /// reached, so only they are kept. Call it before the first inflate of
+    /// a stream, reset forgets the dictionary.
+    ///
+    /// # Arguments
+    ///
+    /// * 'dictionary' - The dictionary the stream was compressed with.
+    ///
+    /// # Returns
+    ///
+    /// A DeflateError if the window couldn't be allocated.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<(), DeflateError> {
+        if self.window.is_empty() {
+            self.window = try_zeroed(WINDOW_SIZE)?;
+        }
+        // The dictionary isn't output, so doesn't count towards total_out.
+        let total_out = self.total_out;
+        self.write_window(dictionary);
+        self.total_out = total_out;
+        self.dictionary = (self.dictionary + dictionary.len()).min(WINDOW_SIZE);
+
+        Ok(())
+    }
+    /// Returns an error if the cancel flag is set.
+    fn check_cancelled(&self) -> Result<(), DeflateError> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(atomic::Ordering::Relaxed) => {
+                Err(DeflateError::CancelledError)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Keeps the last len events decoded, and attaches them to any error
+    /// inflate returns as a DeflateError::TracedError, so a corrupt stream
+    /// shows what was decoded just before it went wrong. The history is
+    /// cleared by reset, but its length is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * 'len' - How many events to keep, or None to keep none.
+    pub fn set_history(&mut self, len: Option<usize>) {
+        self.history.len = len.unwrap_or(0);
+        self.history.records.clear();
+        self.history.records.shrink_to(self.history.len);
+    }
+    /// Returns the events kept so far, oldest first, or an empty iterator
+    /// if no history is being kept.
+    pub fn history(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.history.records.iter()
+    }
+    /// Sets whether a BlockInfo is kept for every block as it ends, for
+    /// tools that need to know where the block boundaries are, like an index
+    /// for seeking. Recorded blocks are cleared by reset, but whether to
+    /// record them is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * 'record' - Whether to record blocks.
+    pub fn set_record_blocks(&mut self, record: bool) {
+        self.blocks = match (record, self.blocks.take()) {
+            (true, blocks) => Some(blocks.unwrap_or_default()),
+            (false, _) => None,
+        };
+    }
+    /// Returns every block that has ended so far, in order, or an empty
+    /// slice if blocks aren't being recorded.
+    pub fn blocks(&self) -> &[BlockInfo] {
+        self.blocks.as_deref().unwrap_or_default()
+    }
+    /// Returns the number of bytes the inflater has allocated on the heap,
+    /// for the window, the decoding tables, and any recorded blocks and
+    /// history, which count as buffers.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let entries = self.literal_table.entries.capacity()
+            + self.distance_table.entries.capacity()
+            + self.code_length_table.entries.capacity();
+        let blocks = self.blocks.as_ref().map_or(0, Vec::capacity);
+
+        MemoryUsage {
+            window: self.window.capacity(),
+            tables: entries * std::mem::size_of::<TableEntry>(),
+            buffers: blocks * std::mem::size_of::<BlockInfo>()
+                + self.history.records.capacity() * std::mem::size_of::<TraceRecord>(),
+        }
+    }
+    /// Decodes as much of the input into the output as possible.
+    ///
+    /// # Arguments
+    ///
+    /// * 'input' - The next piece of the compressed stream.
+    /// * 'output' - The buffer to write decompressed bytes to.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the number of input bytes consumed, the number of
+    /// bytes written to output, and the Status explaining why decoding
+    /// stopped. Or, a DeflateError if the stream is invalid.
+    pub fn inflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, usize, Status), DeflateError> {
+        self.inflate_step(input, output, usize::MAX)
+    }
+    /// Like inflate, but decodes at most max_symbols symbols before
+    /// returning Status::Pending, so a single call can only ever take so
+    /// long. Each literal, match, end of block, block header, code length,
+    /// and byte of a stored block is a symbol. A cooperative scheduler or
+    /// event loop can call this between other work, with the same input
+    /// from where the last call left off, until it returns something other
+    /// than Status::Pending.
+    ///
+    /// # Arguments
+    ///
+    /// * 'input' - The next piece of the compressed stream.
+    /// * 'output' - The buffer to write decompressed bytes to.
+    /// * 'max_symbols' - The most symbols to decode, at least one is always
+    ///         decoded if there's input and room for it.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the number of input bytes consumed, the number of
+    /// bytes written to output, and the Status explaining why decoding
+    /// stopped. Or, a DeflateError if the stream is invalid.
+    pub fn inflate_step(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        max_symbols: usize,
+    ) -> Result<(usize, usize, Status), DeflateError> {
+        self.budget = max_symbols.max(1);
+        let result = self.inflate_budgeted(input, output);
+        self.budget = usize::MAX;
+        result
+    }
+    /// Does the work of inflate and inflate_step, within budget.
+    fn inflate_budgeted(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, usize, Status), DeflateError> {
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+
+        self.check_cancelled()
+            .map_err(|error| self.locate(error, 0))?;
+        if self.window.is_empty() {
+            self.window = try_zeroed(WINDOW_SIZE).map_err(|error| self.locate(error, 0))?;
+        }
+
+        // Past the output limit there's no room left, so the inflater stops
+        // for output there like it would at the end of the buffer. The
+        // expansion ratio is limited the same way, by how much output all of
+        // this input could be allowed.
+        let mut limit = None;
+        if let Some(max) = self.max_output {
+            let room = max.saturating_sub(self.total_out);
+            if room < output.len() as u64 {
+                limit = Some((room, DeflateError::OutputLimitError(max)));
+            }
+        }
+        if let Some(ratio) = self.max_ratio {
+            let room = ratio
+                .saturating_mul(self.total_in + input.len() as u64)
+                .saturating_sub(self.total_out);
+            if room < output.len() as u64 && limit.as_ref().is_none_or(|(max, _)| room < *max) {
+                limit = Some((room, DeflateError::ExpansionRatioError(ratio)));
+            }
+        }
+        let output = match &limit {
+            Some((room, _)) => output.get_mut(..*room as usize).unwrap_or_default(),
+            None => output,
+        };
+
+        let status = self
+            .decode(input, output, &mut in_pos, &mut out_pos)
+            .map_err(|error| self.locate(error, in_pos))?;
+
+        match status {
+            // Anything left in the bit buffer is either padding, or bytes
+            // that come after the stream, those go back to the caller.
+            Status::StreamEnd => {
+                in_pos -= self.bits.unpush_bytes(in_pos);
+                self.bits = BitBuffer::new();
+            }
+            // Whole bytes that haven't been looked at yet can be read again
+            // next call, which keeps the buffer from holding on to input past
+            // the end of the stream.
+            Status::NeedsOutput => {
+                if let Some((_, err)) = limit {
+                    return Err(self.locate(err, in_pos));
                 }
+                in_pos -= self.bits.unpush_bytes(in_pos);
+            }
+            Status::NeedsInput | Status::Pending => {}
+        }
+
+        self.total_in += in_pos as u64;
+        if let Some(limit) = self.memory_limit {
+            if self.memory_usage().total() > limit {
+                return Err(self.locate(DeflateError::MemoryLimitError(limit), 0));
+            }
+        }
+
+        Ok((in_pos, out_pos, status))
+    }
+    /// Runs through the stream until it ends or either the input or output
+    /// runs out, keeping in_pos and out_pos up to date so an error can be
+    /// placed within the stream.
+    fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        in_pos: &mut usize,
+        out_pos: &mut usize,
+    ) -> Result<Status, DeflateError> {
+        Ok(loop {
+            if self.budget == 0 && self.state != State::Done {
+                break Status::Pending;
             }
 
-            self.finished = matches!(header[0], 1);
+            match self.state {
+                State::Header => {
+                    self.check_cancelled()?;
+                    self.refill(input, in_pos);
+                    let bit_offset = self.bit_position(*in_pos);
+                    let Some(header) = self.bits.read_bits(3) else {
+                        break Status::NeedsInput;
+                    };
+                    self.budget -= 1;
 
-            // Main decompression loop.
-            match (header[1], header[2]) {
-                (0, 0) => {
-                    self.block_type_0()?;
+                    // The header is a single BFINAL bit followed by the 2
+                    // bit BTYPE.
+                    self.final_block = header & 1 == 1;
+                    let block_type = match header >> 1 {
+                        0 => BlockType::Stored,
+                        1 => BlockType::Fixed,
+                        2 => BlockType::Dynamic,
+                        _ => return Err(DeflateError::InvalidBlockError("Invalid BTYPE.")),
+                    };
+                    self.state = match block_type {
+                        BlockType::Stored => State::StoredLength,
+                        BlockType::Fixed => {
+                            self.fixed_block = true;
+                            State::Data
+                        }
+                        BlockType::Dynamic => State::DynamicCounts,
+                    };
+                    self.block_info = BlockInfo {
+                        block_type,
+                        final_block: self.final_block,
+                        bits: bit_offset..bit_offset,
+                        bytes: self.total_out..self.total_out,
+                    };
+                    self.trace(TraceEvent::Block {
+                        bit_offset,
+                        final_block: self.final_block,
+                        block_type,
+                    });
                 }
-                (1, 0) => {
-                    self.block_type_1()?;
+                State::StoredLength => {
+                    // Stored blocks start at the next byte boundary, whatever
+                    // is left of the current byte is padding.
+                    self.bits.align_to_byte();
+
+                    self.refill(input, in_pos);
+                    if self.bits.len < 32 {
+                        break Status::NeedsInput;
+                    }
+
+                    let len = self.bits.read_bits(16).unwrap_or_default() as u16;
+                    let nlen = self.bits.read_bits(16).unwrap_or_default() as u16;
+
+                    if len != !nlen {
+                        return Err(DeflateError::InvalidBlockError(
+                            "BTYPE is 0, but NLEN is not the bitwise complement to LEN.",
+                        ));
+                    }
+
+                    self.state = State::Stored(len as usize);
+                    self.trace(TraceEvent::Stored {
+                        bit_offset: self.bit_position(*in_pos),
+                        len,
+                    });
+                }
+                State::Stored(0) => {
+                    self.end_block(*in_pos);
                 }
-                (0, 1) => {
-                    self.block_type_2()?;
+                State::Stored(remaining) => {
+                    if *out_pos == output.len() {
+                        break Status::NeedsOutput;
+                    }
+
+                    // Bytes already pulled into the bit buffer come before
+                    // the rest of the input.
+                    if let Some(byte) = self.bits.read_bits(8) {
+                        self.write_byte(output, out_pos, byte as u8);
+                        self.budget -= 1;
+                        self.state = State::Stored(remaining - 1);
+                    } else if *in_pos < input.len() {
+                        let rest = input.get(*in_pos..).unwrap_or_default();
+                        let rest = rest.get(..remaining.min(self.budget)).unwrap_or(rest);
+                        let space = output.get_mut(*out_pos..).unwrap_or_default();
+                        let n = copy_prefix(space, rest);
+                        self.write_window(rest.get(..n).unwrap_or_default());
+
+                        *in_pos += n;
+                        *out_pos += n;
+                        self.budget -= n;
+                        self.state = State::Stored(remaining - n);
+                    } else {
+                        break Status::NeedsInput;
+                    }
                 }
-                _ => return Err(DeflateError::InvalidBlockError("Invalid BTYPE.")),
+                State::DynamicCounts => {
+                    self.refill(input, in_pos);
+                    if self.bits.len < 14 {
+                        break Status::NeedsInput;
+                    }
+
+                    // # of literal/length codes - 257 (257..286)
+                    self.hlit = self.bits.read_bits(5).unwrap_or_default() as usize + HLIT_BASE;
+                    // # of distance codes - 1 (1..32)
+                    self.hdist = self.bits.read_bits(5).unwrap_or_default() as usize + HDIST_BASE;
+                    // # of code length codes - 4 (4..19)
+                    self.hclen = self.bits.read_bits(4).unwrap_or_default() as usize + HCLEN_BASE;
+
+                    if self.hlit > MAX_HLIT || self.hdist > MAX_HDIST {
+                        return Err(DeflateError::InvalidBlockError(
+                            "HLIT or HDIST is larger than the number of codes in the alphabet.",
+                        ));
+                    }
+
+                    self.code_length_lengths = [0; CL_CODES];
+                    self.state = State::CodeLengthLengths(0);
+                }
+                State::CodeLengthLengths(read) if read < self.hclen => {
+                    self.refill(input, in_pos);
+                    let Some(length) = self.bits.read_bits(3) else {
+                        break Status::NeedsInput;
+                    };
+
+                    // Lengths are stored in CL_CODE_ORDER, not by symbol.
+                    if let Some(slot) = self.code_length_lengths.get_mut(cl_code_symbol(read)) {
+                        *slot = length as u8;
+                    }
+                    self.budget -= 1;
+                    self.state = State::CodeLengthLengths(read + 1);
+                }
+                State::CodeLengthLengths(_) => {
+                    check_code(
+                        &self.code_length_lengths,
+                        false,
+                        "Dynamic block's code length code is over-subscribed.",
+                        "Dynamic block's code length code is incomplete.",
+                    )?;
+
+                    // The tables are rebuilt in place, so a file with many
+                    // dynamic blocks reuses the same memory for each.
+                    self.code_length_table
+                        .rebuild(&self.code_length_lengths, CODE_LENGTH_ROOT_BITS);
+                    self.code_lengths.clear();
+                    self.state = State::CodeLengths;
+                }
+                State::CodeLengths if self.code_lengths.len() < self.hlit + self.hdist => {
+                    self.refill(input, in_pos);
+                    if !self.read_code_length()? {
+                        break Status::NeedsInput;
+                    }
+                    self.budget -= 1;
+                }
+                State::CodeLengths => {
+                    let Some((literal_lengths, distance_lengths)) =
+                        self.code_lengths.as_slice().split_at_checked(self.hlit)
+                    else {
+                        return Err(DeflateError::DecompressionError(
+                            "Dynamic block's code lengths were cut short.",
+                        ));
+                    };
+
+                    if literal_lengths.get(256).is_none_or(|&length| length == 0) {
+                        return Err(DeflateError::InvalidBlockError(
+                            "Dynamic block has no code for the end of block symbol.",
+                        ));
+                    }
+
+                    check_code(
+                        literal_lengths,
+                        true,
+                        "Dynamic block's literal/length code is over-subscribed.",
+                        "Dynamic block's literal/length code is incomplete.",
+                    )?;
+                    check_code(
+                        distance_lengths,
+                        true,
+                        "Dynamic block's distance code is over-subscribed.",
+                        "Dynamic block's distance code is incomplete.",
+                    )?;
+
+                    self.literal_table
+                        .rebuild(literal_lengths, LITERAL_ROOT_BITS);
+                    self.distance_table
+                        .rebuild(distance_lengths, DISTANCE_ROOT_BITS);
+                    self.fixed_block = false;
+                    self.state = State::Data;
+
+                    let event = TraceEvent::DynamicTables {
+                        bit_offset: self.bit_position(*in_pos),
+                        hclen: self.hclen,
+                        code_length_lengths: &self.code_length_lengths,
+                        literal_lengths,
+                        distance_lengths,
+                    };
+                    self.history.push(TraceRecord::from(&event));
+                    if let Some(trace) = &mut self.trace {
+                        trace(&event);
+                    }
+                }
+                State::Data => {
+                    let run_start = *out_pos;
+                    let run = self.decode_literals(input, output, in_pos, out_pos);
+                    // The run's literals go into the window together, rather
+                    // than a byte at a time as they're decoded.
+                    self.write_window(output.get(run_start..*out_pos).unwrap_or_default());
+
+                    let token = match run? {
+                        Ok(token) => token,
+                        Err(status) => break status,
+                    };
+                    match token {
+                        // decode_literals never stops on a literal.
+                        Token::Literal(_) => {}
+                        Token::EndOfBlock => self.end_block(*in_pos),
+                        Token::Match { length, distance } => {
+                            // DEFLATE distances never go past the window, a
+                            // stream that claims to is corrupt.
+                            let reach = self.total_out + self.dictionary as u64;
+                            if distance > WINDOW_SIZE || distance as u64 > reach {
+                                return Err(DeflateError::InvalidDistanceError {
+                                    distance,
+                                    output_len: self.total_out,
+                                });
+                            }
+                            self.state = State::Copy { length, distance };
+                        }
+                    }
+                }
+                State::Copy { length, distance } => {
+                    let space = output.get_mut(*out_pos..).unwrap_or_default();
+                    let n = length.min(space.len());
+                    if n == 0 {
+                        break Status::NeedsOutput;
+                    }
+
+                    let copy = space.get_mut(..n).unwrap_or_default();
+
+                    // Only the first distance bytes come from the window, past
+                    // that the match repeats what it just wrote. Each pass
+                    // doubles the bytes copied, which stays a multiple of the
+                    // distance, so the pattern lines up.
+                    let mut copied = distance.min(n);
+                    self.read_window(distance, copy.get_mut(..copied).unwrap_or_default());
+                    while let Some((done, rest)) = copy.split_at_mut_checked(copied) {
+                        if rest.is_empty() {
+                            break;
+                        }
+                        copied += copy_prefix(rest, done);
+                    }
+
+                    self.write_window(copy);
+                    *out_pos += n;
+
+                    self.state = match length - n {
+                        0 => State::Data,
+                        length => State::Copy { length, distance },
+                    };
+                }
+                State::Done => break Status::StreamEnd,
             }
+        })
+    }
+    /// Returns the Inflater to the start of a new DEFLATE stream, keeping the
+    /// window, tables, and code length buffers allocated so a single Inflater
+    /// can decode many streams without allocating again.
+    pub fn reset(&mut self) {
+        self.state = State::Header;
+        self.bits = BitBuffer::new();
+        self.final_block = false;
+        self.fixed_block = false;
+        // The window's old contents can stay, total_out being 0 keeps any
+        // distance from reaching them.
+        self.window_pos = 0;
+        self.block = 0;
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
         }
-        Ok(self.decompressed.clone())
+        self.history.records.clear();
+        self.total_in = 0;
+        self.total_out = 0;
+        self.hlit = 0;
+        self.hdist = 0;
+        self.hclen = 0;
+        self.code_length_lengths = [0; CL_CODES];
+        self.code_lengths.clear();
+        self.dictionary = 0;
     }
-    fn block_type_0(&mut self) -> Result<(), DeflateError> {
-        let len = self
-            .bitstream
-            .by_ref()
-            .skip(5)
-            .take(16)
-            .fold(0u16, |acc, bit| (acc << 1) | (bit as u16))
-            .reverse_bits();
-
-        // Take the subsequent 16 bits as a u16.
-        let nlen = self
-            .bitstream
-            .by_ref()
-            .take(16)
-            .fold(0u16, |acc, bit| (acc << 1) | (bit as u16))
-            .reverse_bits();
-
-        if len != !nlen {
-            return Err(DeflateError::InvalidBlockError(
-                "BTYPE is 0, but NLEN is not the bitwise complement to LEN.",
+    /// Returns whether the final block has been decoded.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Done
+    }
+    /// Returns the number of bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+    /// Returns the number of bits decoded so far, which is where decoding
+    /// picks up from on the next call.
+    pub fn bit_offset(&self) -> u64 {
+        self.bit_position(0)
+    }
+    /// Saves where the inflater is in the stream, so decoding can be picked
+    /// back up later by restore_state, in another process or on another
+    /// machine. That covers the position, the current block's code lengths,
+    /// and the window, a little over 32KB. Settings like the limits and
+    /// hooks aren't saved, nor are recorded blocks or history.
+    ///
+    /// # Returns
+    ///
+    /// The saved state. The input has to carry on from total_in bytes into
+    /// the stream when it's restored.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut bytes = vec![STATE_VERSION];
+
+        let (tag, a, b) = match self.state {
+            State::Header => (0, 0, 0),
+            State::StoredLength => (1, 0, 0),
+            State::Stored(remaining) => (2, remaining, 0),
+            State::DynamicCounts => (3, 0, 0),
+            State::CodeLengthLengths(read) => (4, read, 0),
+            State::CodeLengths => (5, 0, 0),
+            State::Data => (6, 0, 0),
+            State::Copy { length, distance } => (7, length, distance),
+            State::Done => (8, 0, 0),
+        };
+        bytes.push(tag);
+        for value in [a, b] {
+            bytes.extend((value as u64).to_le_bytes());
+        }
+
+        let block_type = match self.block_info.block_type {
+            BlockType::Stored => 0,
+            BlockType::Fixed => 1,
+            BlockType::Dynamic => 2,
+        };
+        bytes.extend(self.bits.buffer.to_le_bytes());
+        bytes.push(self.bits.len);
+        bytes.extend([self.final_block as u8, self.fixed_block as u8]);
+        bytes.extend([block_type, self.block_info.final_block as u8]);
+        for value in [
+            self.block_info.bits.start,
+            self.block_info.bytes.start,
+            self.block,
+            self.total_in,
+            self.total_out,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+
+        let values = [
+            self.hlit,
+            self.hdist,
+            self.hclen,
+            self.window_pos,
+            self.dictionary,
+        ];
+        for value in values {
+            bytes.extend((value as u64).to_le_bytes());
+        }
+        bytes.extend(self.code_length_lengths);
+        for part in [self.code_lengths.as_slice(), &self.window] {
+            bytes.extend((part.len() as u64).to_le_bytes());
+            bytes.extend(part);
+        }
+
+        bytes
+    }
+    /// Creates an Inflater from a state saved by save_state, ready to carry
+    /// on decoding from the same point.
+    ///
+    /// # Arguments
+    ///
+    /// * 'state' - The saved state.
+    ///
+    /// # Returns
+    ///
+    /// The restored Inflater, with the default settings. Or, a
+    /// DeflateError::InvalidStateError if state wasn't written by
+    /// save_state.
+    pub fn restore_state(state: &[u8]) -> Result<Self, DeflateError> {
+        let mut reader = StateReader::new(state);
+        if reader.u8()? != STATE_VERSION {
+            return Err(DeflateError::InvalidStateError(
+                "Saved state is from another version.",
             ));
         }
 
-        // Figure out what byte the current index is in.
-        let byte_idx = self.bitstream.idx / 8;
+        let tag = reader.u8()?;
+        let a = reader.usize(u16::MAX as usize)?;
+        let b = reader.usize(WINDOW_SIZE)?;
+        let state = match tag {
+            0 => State::Header,
+            1 => State::StoredLength,
+            2 => State::Stored(a),
+            3 => State::DynamicCounts,
+            4 if a <= CL_CODES => State::CodeLengthLengths(a),
+            5 => State::CodeLengths,
+            6 => State::Data,
+            7 if (1..=258).contains(&a) && b > 0 => State::Copy {
+                length: a,
+                distance: b,
+            },
+            8 => State::Done,
+            _ => return Err(DeflateError::InvalidStateError("Saved state is invalid.")),
+        };
 
-        self.compressed[byte_idx..len as usize + byte_idx]
-            .iter()
-            .for_each(|x| self.decompressed.push(*x));
+        let mut inflater = Self::new();
+        inflater.state = state;
 
-        Ok(())
+        let buffer = reader.u64()?;
+        let len = reader.u8()?;
+        if len > 64 || (len < 64 && buffer >> len != 0) {
+            return Err(DeflateError::InvalidStateError(
+                "Saved state's bit buffer is invalid.",
+            ));
+        }
+        inflater.bits = BitBuffer { buffer, len };
+        inflater.final_block = reader.bool()?;
+        inflater.fixed_block = reader.bool()?;
+
+        let block_type = match reader.u8()? {
+            0 => BlockType::Stored,
+            1 => BlockType::Fixed,
+            2 => BlockType::Dynamic,
+            _ => return Err(DeflateError::InvalidStateError("Saved state is invalid.")),
+        };
+        let final_block = reader.bool()?;
+        let (bit_start, byte_start) = (reader.u64()?, reader.u64()?);
+        inflater.block_info = BlockInfo {
+            block_type,
+            final_block,
+            bits: bit_start..bit_start,
+            bytes: byte_start..byte_start,
+        };
+        inflater.block = reader.u64()?;
+        inflater.total_in = reader.u64()?;
+        inflater.total_out = reader.u64()?;
+
+        inflater.hlit = reader.usize(MAX_HLIT)?;
+        inflater.hdist = reader.usize(MAX_HDIST)?;
+        inflater.hclen = reader.usize(CL_CODES)?;
+        inflater.window_pos = reader.usize(WINDOW_SIZE - 1)?;
+        inflater.dictionary = reader.usize(WINDOW_SIZE)?;
+        copy_prefix(&mut inflater.code_length_lengths, reader.take(CL_CODES)?);
+
+        let code_lengths = reader.bytes(inflater.hlit + inflater.hdist)?;
+        inflater.code_lengths.resize(code_lengths.len(), 0);
+        copy_prefix(&mut inflater.code_lengths.lengths, code_lengths);
+
+        let window = reader.bytes(WINDOW_SIZE)?;
+        if !window.is_empty() {
+            if window.len() != WINDOW_SIZE {
+                return Err(DeflateError::InvalidStateError(
+                    "Saved state's window is the wrong size.",
+                ));
+            }
+            inflater.window = try_zeroed(WINDOW_SIZE)?;
+            copy_prefix(&mut inflater.window, window);
+        }
+        reader.finish()?;
+
+        // The tables aren't saved, they're rebuilt from the lengths they
+        // were built from.
+        inflater
+            .code_length_table
+            .rebuild(&inflater.code_length_lengths, CODE_LENGTH_ROOT_BITS);
+        if let Some((literal_lengths, distance_lengths)) = inflater
+            .code_lengths
+            .as_slice()
+            .split_at_checked(inflater.hlit)
+        {
+            inflater
+                .literal_table
+                .rebuild(literal_lengths, LITERAL_ROOT_BITS);
+            inflater
+                .distance_table
+                .rebuild(distance_lengths, DISTANCE_ROOT_BITS);
+        }
+
+        Ok(inflater)
     }
-    fn block_type_1(&mut self) -> Result<(), DeflateError> {
-        let mut prefix_tree = PrefixTree::from_lengths(&FIXED_CODE_LENGTHS);
-
-        //let mut output = Vec::new();
-
-        // Iterate through the bitstream.
-        while let Some(bit) = self.bitstream.by_ref().next() {
-            // Walk the tree and if there is a value, take it and continue.
-            if let Some(value) = prefix_tree.walk(bit) {
-                // If the value less than 256, it is a literal and should be
-                // pushed unaltered to the output stream.
-                if value < 256 {
-                    self.decompressed.push(value as u8);
-                // If it is in the range from 257..285 it is a length code.
-                } else if let 257..=285 = value {
-                    // Get the base and number of extra bits.
-                    let mut length = LENGTH_BASE[value - 257];
-                    let len_extra = LENGTH_EXTRA_BITS[value - 257];
-                    // If length has extra bits, iterate through them, and add
-                    // the value to the base length.
-                    if len_extra > 0 {
-                        let additional_length = self
-                            .bitstream
-                            .by_ref()
-                            .take(len_extra as usize)
-                            .fold(0u16, |acc, bit| (acc << 1) | bit as u16)
-                            .reverse_bits()
-                            >> (16 - len_extra);
-                        length += additional_length;
-                    }
+    /// Returns an UnexpectedEofError at the current position, for when the
+    /// input runs out before the stream has ended.
+    pub(crate) fn unexpected_eof(&self) -> DeflateError {
+        let error = DeflateError::UnexpectedEofError {
+            bit_offset: self.bit_offset(),
+        };
+        self.locate(error, 0)
+    }
+    /// Returns the number of bytes produced so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+    /// Moves on from the end of the current block, to the next block's
+    /// header or the end of the stream.
+    fn end_block(&mut self, in_pos: usize) {
+        let bit_offset = self.bit_position(in_pos);
 
-                    // After every length code is a 5 bit distance code.
-                    let mut distance: usize = self
-                        .bitstream
-                        .by_ref()
-                        .take(5)
-                        .fold(0usize, |acc, bit| (acc << 1) | bit as usize);
-
-                    let dist_extra = DISTANCE_EXTRA_BITS[distance];
-                    let dist_base = DISTANCE_BASE[distance];
-
-                    if dist_extra > 0 {
-                        let additional_distance = self
-                            .bitstream
-                            .by_ref()
-                            .take(dist_extra as usize)
-                            .fold(0u16, |acc, bit| (acc << 1) | bit as u16)
-                            .reverse_bits()
-                            >> (16 - dist_extra);
-                        distance = (dist_base + additional_distance) as usize;
-                    } else {
-                        distance = dist_base as usize;
-                    }
+        self.block += 1;
+        self.state = if self.final_block {
+            State::Done
+        } else {
+            State::Header
+        };
+        if let Some(blocks) = &mut self.blocks {
+            let mut info = self.block_info.clone();
+            info.bits.end = bit_offset;
+            info.bytes.end = self.total_out;
+            blocks.push(info);
+        }
+        self.trace(TraceEvent::BlockEnd { bit_offset });
+    }
+    /// Returns the bit offset from the start of the stream that decoding is
+    /// at, given how much of the current call's input has been pulled into
+    /// the bit buffer.
+    fn bit_position(&self, in_pos: usize) -> u64 {
+        ((self.total_in + in_pos as u64) * 8).saturating_sub(self.bits.len as u64)
+    }
+    /// Hands an event to the trace hook, if there is one, and keeps it in
+    /// the history.
+    #[inline]
+    fn trace(&mut self, event: TraceEvent<'_>) {
+        self.history.push(TraceRecord::from(&event));
+        if let Some(trace) = &mut self.trace {
+            trace(&event);
+        }
+    }
+    /// Traces a literal or match, called before its bits are consumed so
+    /// the offset is that of its first code.
+    fn trace_token(&mut self, token: Token, in_pos: usize) {
+        let bit_offset = self.bit_position(in_pos);
+        match token {
+            Token::Literal(byte) => self.trace(TraceEvent::Literal { bit_offset, byte }),
+            Token::Match { length, distance } => self.trace(TraceEvent::Match {
+                bit_offset,
+                length,
+                distance,
+            }),
+            // The end of the block is traced once the block has ended.
+            Token::EndOfBlock => {}
+        }
+    }
+    /// Wraps an error with where in the stream it was found, and the
+    /// history if one is being kept.
+    ///
+    /// # Arguments
+    ///
+    /// * 'error' - The error to wrap.
+    /// * 'in_pos' - How much of the current call's input has been pulled
+    ///         into the bit buffer.
+    fn locate(&self, error: DeflateError, in_pos: usize) -> DeflateError {
+        let phase = match self.state {
+            State::Header | State::StoredLength | State::DynamicCounts => Phase::Header,
+            State::CodeLengthLengths(_) | State::CodeLengths => Phase::CodeLengths,
+            State::Stored(_) | State::Data | State::Copy { .. } | State::Done => Phase::Data,
+        };
+
+        let error = DeflateError::PositionedError {
+            error: Box::new(error),
+            position: ErrorPosition {
+                bit_offset: self.bit_position(in_pos),
+                block: self.block,
+                phase,
+            },
+        };
+
+        match self.history.len {
+            0 => error,
+            _ => DeflateError::TracedError {
+                error: Box::new(error),
+                history: self.history.records.iter().copied().collect(),
+            },
+        }
+    }
+    /// Decodes tokens, writing literals straight to the output, until one
+    /// comes along that isn't a literal. The window is left for the caller
+    /// to update with the whole run at once.
+    ///
+    /// # Returns
+    ///
+    /// The token that ended the run, either the end of the block or a match,
+    /// or the Status to stop with if the input or output ran out first. Or,
+    /// a DeflateError if a symbol is invalid.
+    fn decode_literals(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        in_pos: &mut usize,
+        out_pos: &mut usize,
+    ) -> Result<Result<Token, Status>, DeflateError> {
+        let tracing = self.trace.is_some() || self.history.len > 0;
 
-                    let start_idx = self.decompressed.len() - distance;
-                    let end_idx = start_idx + length as usize;
+        loop {
+            if self.budget == 0 {
+                return Ok(Err(Status::Pending));
+            }
+            self.refill(input, in_pos);
 
-                    for idx in start_idx..end_idx {
-                        self.decompressed.push(self.decompressed[idx]);
+            // Decode from a copy so a token cut off by the end of the input
+            // leaves the buffer untouched for the next call.
+            let mut bits = self.bits;
+            let Some(token) = self.next_token(&mut bits)? else {
+                return Ok(Err(Status::NeedsInput));
+            };
+
+            // A full output only stops literals, the end of the block can
+            // still be reached, and matches wait in State::Copy.
+            if token != Token::EndOfBlock && *out_pos == output.len() {
+                return Ok(Err(Status::NeedsOutput));
+            }
+            if tracing {
+                self.trace_token(token, *in_pos);
+            }
+            self.bits = bits;
+            self.budget -= 1;
+
+            match token {
+                Token::Literal(byte) => {
+                    if let Some(slot) = output.get_mut(*out_pos) {
+                        *slot = byte;
                     }
-                } else if value == 256 {
-                    break;
+                    *out_pos += 1;
                 }
+                token => return Ok(Ok(token)),
             }
         }
-        /*
-        output
-            .iter()
-            .map(|x| *x as u8)
-            .for_each(|byte| self.decompressed.push(byte));
-        */
-        Ok(())
     }
-    fn block_type_2(&mut self) -> Result<(), DeflateError> {
-        // # of literal/length codes - 257 (257..286)
-        let hlit = self
-            .bitstream
-            .by_ref()
-            .take(5)
-            .fold(0u16, |acc, bit| (acc << 1) | bit as u16)
-            .reverse_bits()
-            >> (16 - 5);
-
-        // # of distance codes - 1 (1..32)
-        let hdist = self
-            .bitstream
-            .by_ref()
-            .take(5)
-            .fold(0u8, |acc, bit| (acc << 1) | bit)
-            .reverse_bits()
-            >> (8 - 5);
-
-        // # of code length codes - 4 (4..19)
-        let hclen = self
-            .bitstream
-            .by_ref()
-            .take(4)
-            .fold(0u8, |acc, bit| (acc << 1) | bit)
-            .reverse_bits()
-            >> (8 - 4);
-
-        // Code lengths for the code lengths.
-        let cl_len_vec = self
-            .bitstream
-            .by_ref()
-            .take(((hclen + 4) * 3) as usize)
-            .collect::<Vec<_>>();
-
-        //
-        let mut cl_lengths_sorted = [0; 19];
-
-        const LENGTH_ORDER: [usize; 19] = [
-            16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
-        ];
+    /// Takes whatever bytes the bit buffer has room for from the input,
+    /// starting at in_pos.
+    #[inline]
+    fn refill(&mut self, input: &[u8], in_pos: &mut usize) {
+        *in_pos += self.bits.refill(input.get(*in_pos..).unwrap_or_default());
+    }
+    /// Writes a byte to the output and the window.
+    #[inline]
+    fn write_byte(&mut self, output: &mut [u8], out_pos: &mut usize, byte: u8) {
+        if let Some(slot) = output.get_mut(*out_pos) {
+            *slot = byte;
+        }
+        *out_pos += 1;
+        self.write_window(&[byte]);
+    }
+    /// Copies bytes that have already been written to the output into the
+    /// window.
+    #[inline]
+    fn write_window(&mut self, bytes: &[u8]) {
+        self.total_out += bytes.len() as u64;
 
-        // Put code lengths into cl_lengths in the order:
-        // 16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15
-        for (i, len) in cl_len_vec.chunks(3).enumerate() {
-            let value = len.iter().rev().fold(0u8, |acc, bit| (acc << 1) | *bit);
-
-            cl_lengths_sorted[LENGTH_ORDER[i]] = value;
-        }
-
-        // Generate the code length prefix tree.
-        let mut code_length_tree = PrefixTree::from_lengths(&cl_lengths_sorted);
-
-        let mut code_lengths: Vec<u8> = Vec::new();
-
-        while code_lengths.len() < (hlit as usize + 257 + hdist as usize + 1) {
-            if let Some(bit) = self.bitstream.by_ref().next() {
-                if let Some(symbol) = code_length_tree.walk(bit) {
-                    match symbol {
-                        0..16 => code_lengths.push(symbol as u8),
-                        16..=18 => {
-                            let (number_of_extra, base) = match symbol {
-                                16 => (2, 3usize),
-                                17 => (3, 3usize),
-                                _ => (7, 11usize),
-                            };
-                            let _extra_bits: usize = (self
-                                .bitstream
-                                .by_ref()
-                                .take(number_of_extra)
-                                .fold(0u8, |acc, bit| (acc << 1) | bit)
-                                .reverse_bits()
-                                >> (8 - number_of_extra))
-                                as usize;
-
-                            if symbol == 16 {
-                                for _ in 0..(base + _extra_bits) {
-                                    code_lengths.push(*code_lengths.last().unwrap());
-                                }
-                            } else {
-                                code_lengths.resize(code_lengths.len() + base + _extra_bits, 0);
-                            }
-                        }
-                        _ => {}
-                    }
+        // Only the last WINDOW_SIZE bytes can ever be referenced. They go in
+        // from window_pos on, and whatever doesn't fit before the end wraps
+        // around to the start.
+        let bytes = bytes
+            .get(bytes.len().saturating_sub(WINDOW_SIZE)..)
+            .unwrap_or_default();
+        let pos = self.window_pos.min(self.window.len());
+        let (before, after) = self.window.split_at_mut(pos);
+        let first = copy_prefix(after, bytes);
+        copy_prefix(before, bytes.get(first..).unwrap_or_default());
+
+        self.window_pos = (self.window_pos + bytes.len()) % WINDOW_SIZE;
+    }
+    /// Fills bytes with the window's contents starting distance bytes back.
+    /// bytes can't be longer than distance, which can't be more than
+    /// WINDOW_SIZE.
+    fn read_window(&self, distance: usize, bytes: &mut [u8]) {
+        let start = (self.window_pos + WINDOW_SIZE - distance.min(WINDOW_SIZE)) % WINDOW_SIZE;
+        let (before, after) = self.window.split_at(start.min(self.window.len()));
+
+        let first = copy_prefix(bytes, after);
+        copy_prefix(bytes.get_mut(first..).unwrap_or_default(), before);
+    }
+    /// Reads a single code length symbol, and any repeat that goes along
+    /// with it, from the bit buffer.
+    ///
+    /// # Returns
+    ///
+    /// True if a symbol was read, false if the buffer ran out of bits first,
+    /// in which case nothing is consumed. Or, a DeflateError if the symbol
+    /// is invalid.
+    fn read_code_length(&mut self) -> Result<bool, DeflateError> {
+        let mut bits = self.bits;
+        let Some(symbol) = decode_symbol(&mut bits, &self.code_length_table)? else {
+            return Ok(false);
+        };
+
+        let (number_of_extra, base, repeated) = match symbol {
+            0..16 => (0, 1, symbol as u8),
+            16 => match self.code_lengths.last() {
+                Some(&previous) => (2, 3, previous),
+                None => {
+                    return Err(DeflateError::InvalidBlockError(
+                        "Code length 16 repeats the previous length, but there isn't one.",
+                    ))
                 }
+            },
+            17 => (3, 3, 0),
+            _ => (7, 11, 0),
+        };
+
+        let Some(extra) = bits.read_bits(number_of_extra) else {
+            return Ok(false);
+        };
+
+        let count = self.code_lengths.len() + base + extra as usize;
+        if count > self.hlit + self.hdist {
+            return Err(DeflateError::InvalidBlockError(
+                "Code length repeats past the number of codes.",
+            ));
+        }
+
+        self.code_lengths.resize(count, repeated);
+        self.bits = bits;
+
+        Ok(true)
+    }
+    /// Decodes the next literal, match, or end of block from the given bits
+    /// with the tables for the current block.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bits' - The bits to decode from.
+    ///
+    /// # Returns
+    ///
+    /// An option containing the token, or None if the bits ran out before
+    /// the whole token was read. Or, a DeflateError if a symbol is invalid.
+    fn next_token(&self, bits: &mut BitBuffer) -> Result<Option<Token>, DeflateError> {
+        let (literal_table, distance_table) = if self.fixed_block {
+            let tables = fixed_tables();
+            (&tables.0, &tables.1)
+        } else {
+            (&self.literal_table, &self.distance_table)
+        };
+
+        let Some(symbol) = decode_symbol(bits, literal_table)? else {
+            return Ok(None);
+        };
+
+        match symbol {
+            // If the value less than 256, it is a literal and should be
+            // pushed unaltered to the output stream.
+            0..256 => Ok(Some(Token::Literal(symbol as u8))),
+            256 => Ok(Some(Token::EndOfBlock)),
+            // If it is in the range from 257..285 it is a length code, made
+            // up of a base length plus some number of extra bits.
+            _ => {
+                let (Some(&base), Some(&extra_bits)) = (
+                    LENGTH_BASE.get(symbol - 257),
+                    LENGTH_EXTRA_BITS.get(symbol - 257),
+                ) else {
+                    return Err(DeflateError::InvalidSymbolError(
+                        symbol,
+                        "Literal/length codes 286 and 287 don't represent a length.",
+                    ));
+                };
+                let Some(extra) = bits.read_bits(extra_bits) else {
+                    return Ok(None);
+                };
+                let length = base as usize + extra as usize;
+
+                // Every length is followed by a distance, which is also a base
+                // plus extra bits.
+                let Some(distance_symbol) = decode_symbol(bits, distance_table)? else {
+                    return Ok(None);
+                };
+                let (Some(&base), Some(&extra_bits)) = (
+                    DISTANCE_BASE.get(distance_symbol),
+                    DISTANCE_EXTRA_BITS.get(distance_symbol),
+                ) else {
+                    return Err(DeflateError::InvalidSymbolError(
+                        distance_symbol,
+                        "Distance codes 30 and 31 don't represent a distance.",
+                    ));
+                };
+                let Some(extra) = bits.read_bits(extra_bits) else {
+                    return Ok(None);
+                };
+
+                Ok(Some(Token::Match {
+                    length,
+                    distance: base as usize + extra as usize,
+                }))
             }
         }
+    }
+}
 
-        let mut ll_tree = PrefixTree::from_lengths(&code_lengths[0..(hlit as usize + 257)]);
-        let mut dist_tree = PrefixTree::from_lengths(&code_lengths[(hlit as usize + 257)..]);
-
-        // Testing pushing data straight to the decompressed stream.
-        //let mut output: Vec<usize> = Vec::new();
-
-        // Nearly identical logic to block type 1.
-        while let Some(bit) = self.bitstream.by_ref().next() {
-            if let Some(sym) = ll_tree.walk(bit) {
-                if sym < 256 {
-                    self.decompressed.push(sym as u8);
-                } else if let 257..285 = sym {
-                    let mut length = LENGTH_BASE[sym - 257];
-                    let len_extra = LENGTH_EXTRA_BITS[sym - 257];
-
-                    if len_extra > 0 {
-                        let additional_length = self
-                            .bitstream
-                            .by_ref()
-                            .take(len_extra as usize)
-                            .fold(0u16, |acc, bit| (acc << 1) | bit as u16)
-                            .reverse_bits()
-                            >> (16 - len_extra);
-                        length += additional_length;
-                    }
+impl Default for Inflater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-                    // Distance codes are encoded.
-                    let mut distance: usize;
-                    loop {
-                        if let Some(bit) = self.bitstream.by_ref().next() {
-                            if let Some(dist) = dist_tree.walk(bit) {
-                                distance = dist;
-                                break;
-                            }
-                        }
-                    }
+/// Checks that a dynamic block's code lengths make a usable prefix code.
+/// Over-subscribed codes can't be decoded, and incomplete codes leave bit
+/// sequences that don't decode to anything. The one exception, allowed when
+/// allow_single is set, is the literal/length and distance codes having a
+/// single one bit code (or, for distances, none at all), which is how one
+/// symbol gets encoded.
+///
+/// # Arguments
+///
+/// * 'lengths' - The code lengths to check.
+/// * 'allow_single' - Whether a lone one bit code, or no codes, is allowed.
+/// * 'over_subscribed' - The error message for an over-subscribed code.
+/// * 'incomplete' - The error message for an incomplete code.
+///
+/// # Returns
+///
+/// Nothing, or a DeflateError::InvalidBlockError with the matching message.
+fn check_code(
+    lengths: &[u8],
+    allow_single: bool,
+    over_subscribed: &'static str,
+    incomplete: &'static str,
+) -> Result<(), DeflateError> {
+    match kraft(lengths) {
+        Ordering::Greater => Err(DeflateError::InvalidBlockError(over_subscribed)),
+        Ordering::Less if !(allow_single && lengths.iter().all(|&length| length <= 1)) => {
+            Err(DeflateError::InvalidBlockError(incomplete))
+        }
+        _ => Ok(()),
+    }
+}
 
-                    let dist_extra = DISTANCE_EXTRA_BITS[distance];
-                    let dist_base = DISTANCE_BASE[distance];
-
-                    if dist_extra > 0 {
-                        let additional_distance = self
-                            .bitstream
-                            .by_ref()
-                            .take(dist_extra as usize)
-                            .fold(0u16, |acc, bit| (acc << 1) | bit as u16)
-                            .reverse_bits()
-                            >> (16 - dist_extra);
-                        distance = (dist_base + additional_distance) as usize;
-                    } else {
-                        distance = dist_base as usize;
-                    }
+/// Decodes a single symbol from the bit buffer using a lookup table.
+///
+/// # Arguments
+///
+/// * 'bits' - The bits to decode from.
+/// * 'table' - The DecodeTable for the alphabet being read.
+///
+/// # Returns
+///
+/// An option containing the decoded symbol, or None if more bits are
+/// needed. Or, a DeflateError if the bits can't be a valid code.
+#[inline]
+fn decode_symbol(bits: &mut BitBuffer, table: &DecodeTable) -> Result<Option<usize>, DeflateError> {
+    match table.lookup(bits.peek_bits(MAX_CODE_LENGTH)) {
+        TableEntry::Symbol { symbol, length } if length <= bits.len => {
+            bits.consume(length);
+            Ok(Some(symbol as usize))
+        }
+        _ if bits.len >= MAX_CODE_LENGTH => Err(DeflateError::DecompressionError(
+            "Bitstream held an invalid prefix code.",
+        )),
+        _ => Ok(None),
+    }
+}
 
-                    let start_idx = self.decompressed.len() - distance;
-                    let end_idx = start_idx + length as usize;
+/// Where the compressed bytes DeflateData decompresses are held.
+enum Input<'a> {
+    Bytes(Cow<'a, [u8]>),
+    #[cfg(feature = "memmap")]
+    Mapped {
+        map: memmap2::Mmap,
+        start: usize,
+        end: usize,
+    },
+}
 
-                    for idx in start_idx..end_idx {
-                        self.decompressed.push(self.decompressed[idx]);
-                    }
-                } else if sym == 256 {
-                    break;
+impl Deref for Input<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Bytes(bytes) => bytes,
+            #[cfg(feature = "memmap")]
+            Input::Mapped { map, start, end } => map.get(*start..*end).unwrap_or_default(),
+        }
+    }
+}
+
+/// DEFLATE compressed data along with the state needed to decompress it.
+///
+/// # Fields
+///
+/// * 'compressed' - The compressed bytes, borrowed from the caller or
+///         mapped from a file when possible so the input isn't copied.
+/// * 'decompressed' - The output of the last call to decompress.
+/// * 'inflater' - The inflater that does the decoding, reused between calls.
+/// * 'size_hint' - How many bytes to reserve for the output up front.
+/// * 'crc' - The CRC-32 of the output of the last call to decompress or
+///         decompress_into, updated as each piece is produced.
+/// * 'finished' - Whether decompressed holds the full output.
+/// * 'dictionary' - The preset dictionary the window is primed with before
+///         each decompression, empty for none.
+pub struct DeflateData<'a> {
+    compressed: Input<'a>,
+    decompressed: Vec<u8>,
+    inflater: Inflater,
+    size_hint: usize,
+    crc: u32,
+    finished: bool,
+    dictionary: Vec<u8>,
+}
+
+impl<'a> DeflateData<'a> {
+    /// Builds DeflateData over the compressed bytes. A borrowed slice is used
+    /// in place, an owned Vec is taken without copying.
+    ///
+    /// # Arguments
+    ///
+    /// * 'compressed' - The DEFLATE compressed bytes.
+    ///
+    /// # Returns
+    ///
+    /// The built DeflateData.
+    pub fn build<C: Into<Cow<'a, [u8]>>>(compressed: C) -> Self {
+        Self {
+            compressed: Input::Bytes(compressed.into()),
+            decompressed: Vec::new(),
+            inflater: Inflater::new(),
+            size_hint: 0,
+            crc: 0,
+            finished: false,
+            dictionary: Vec::new(),
+        }
+    }
+    /// Builds DeflateData that expects to decompress to a known size, so the
+    /// output can be allocated once up front instead of growing as it goes.
+    ///
+    /// # Arguments
+    ///
+    /// * 'compressed' - The DEFLATE compressed bytes.
+    /// * 'size_hint' - The expected decompressed size, such as the ISIZE
+    ///         from a gzip trailer. Capped to what the compressed data could
+    ///         possibly expand to.
+    ///
+    /// # Returns
+    ///
+    /// The built DeflateData.
+    pub fn build_with_size_hint<C: Into<Cow<'a, [u8]>>>(compressed: C, size_hint: usize) -> Self {
+        let mut deflate = Self::build(compressed);
+        deflate.size_hint = size_hint.min(deflate.compressed.len().saturating_mul(MAX_EXPANSION));
+        deflate
+    }
+    /// Builds DeflateData over part of a memory mapped file, so the file
+    /// never has to be read into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * 'map' - The mapped file.
+    /// * 'start' - The index of the first byte of DEFLATE data in map.
+    /// * 'end' - The index one past the last byte of DEFLATE data in map.
+    /// * 'size_hint' - The expected decompressed size, as in
+    ///         build_with_size_hint.
+    ///
+    /// # Returns
+    ///
+    /// The built DeflateData.
+    #[cfg(feature = "memmap")]
+    pub fn build_mapped(
+        map: memmap2::Mmap,
+        start: usize,
+        end: usize,
+        size_hint: usize,
+    ) -> DeflateData<'static> {
+        let mut deflate = DeflateData::build(Vec::new());
+        deflate.compressed = Input::Mapped { map, start, end };
+        deflate.size_hint = size_hint.min(end.saturating_sub(start).saturating_mul(MAX_EXPANSION));
+        deflate
+    }
+    pub fn decompress(&mut self) -> Result<Vec<u8>, DeflateError> {
+        if !self.finished {
+            // Decoding into the kept output rather than a new Vec means
+            // decompressing again after changing a limit reuses it.
+            let mut decompressed = std::mem::take(&mut self.decompressed);
+            let result = self.decompress_to_vec(&mut decompressed);
+            self.decompressed = decompressed;
+            result?;
+
+            self.finished = true;
+        }
+
+        Ok(self.decompressed.clone())
+    }
+    /// Decompresses the data into a Vec owned by the caller, clearing it
+    /// first but keeping its allocation, so one buffer can be reused for
+    /// every file or member decoded instead of allocating each time.
+    ///
+    /// # Arguments
+    ///
+    /// * 'output' - The Vec to decompress into. Its capacity counts against
+    ///         the memory limit, like the output of decompress.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes decompressed, or a DeflateError, in which case
+    /// output holds some of the data before the error.
+    pub fn decompress_to_vec(&mut self, output: &mut Vec<u8>) -> Result<usize, DeflateError> {
+        self.start()?;
+        let mut input = &self.compressed[..];
+        output.clear();
+        self.crc = 0;
+        let size_hint = self.size_hint.min(self.memory_room(output.capacity()));
+        try_grow(output, size_hint)?;
+
+        while !self.inflater.is_finished() {
+            // Grow the output and let the inflater write straight into the
+            // new space, using up any reserved capacity before growing past
+            // it.
+            let len = output.len();
+            let spare = output.capacity() - len;
+            let grow = if spare > 0 {
+                spare
+            } else if let Some(limit) = self.inflater.memory_limit {
+                // Growing exactly keeps the output from doubling past the
+                // limit.
+                let grow = OUTPUT_CHUNK.min(self.memory_room(output.capacity()));
+                if grow == 0 {
+                    return Err(DeflateError::MemoryLimitError(limit));
+                }
+                try_grow(output, grow)?;
+                grow
+            } else {
+                // Without a limit the output doubles, as it would growing
+                // on its own, so large outputs aren't copied over and over.
+                output
+                    .try_reserve(OUTPUT_CHUNK)
+                    .map_err(|_| DeflateError::OutOfMemoryError(len + OUTPUT_CHUNK))?;
+                OUTPUT_CHUNK
+            };
+            output.resize(len + grow, 0);
+
+            let result = self
+                .inflater
+                .inflate(input, output.get_mut(len..).unwrap_or_default());
+            let (consumed, produced, status) = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    output.truncate(len);
+                    return Err(err);
                 }
+            };
+
+            input = input.get(consumed..).unwrap_or_default();
+            output.truncate(len + produced);
+            // Hash each piece while it's still in cache, rather than going
+            // over the whole output again afterwards.
+            self.crc = crc::update(self.crc, output.get(len..).unwrap_or_default());
+
+            if status == Status::NeedsInput {
+                return Err(self.inflater.unexpected_eof());
             }
         }
 
+        Ok(output.len())
+    }
+    /// Limits how large the data can decompress to, see
+    /// Inflater::set_max_output_size.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to decompress, or None for no limit.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.inflater.set_max_output_size(limit);
+        self.finished = false;
+        if let Some(limit) = limit {
+            self.size_hint = self.size_hint.min(limit.try_into().unwrap_or(usize::MAX));
+        }
+    }
+    /// Sets whether a BlockInfo is kept for every block, see
+    /// Inflater::set_record_blocks. The blocks can be read with blocks once
+    /// the data has been decompressed.
+    ///
+    /// # Arguments
+    ///
+    /// * 'record' - Whether to record blocks.
+    pub fn set_record_blocks(&mut self, record: bool) {
+        self.inflater.set_record_blocks(record);
+        self.finished = false;
+    }
+    /// Returns the blocks recorded the last time the data was
+    /// decompressed.
+    pub fn blocks(&self) -> &[BlockInfo] {
+        self.inflater.blocks()
+    }
+    /// Limits how many times larger than the compressed data the output can
+    /// be, see Inflater::set_max_expansion_ratio.
+    ///
+    /// # Arguments
+    ///
+    /// * 'ratio' - The most bytes of output per byte of input, or None for
+    ///         no limit.
+    pub fn set_max_expansion_ratio(&mut self, ratio: Option<u64>) {
+        self.inflater.set_max_expansion_ratio(ratio);
+        self.finished = false;
+    }
+    /// Limits how much memory decompressing can allocate, counting the
+    /// output of decompress along with the inflater's own buffers, see
+    /// Inflater::set_memory_limit.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to allocate, or None for no limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.inflater.set_memory_limit(limit);
+        self.finished = false;
+    }
+    /// Keeps the last few events decoded and attaches them to any error,
+    /// see Inflater::set_history.
+    ///
+    /// # Arguments
+    ///
+    /// * 'len' - How many events to keep, or None to keep none.
+    pub fn set_history(&mut self, len: Option<usize>) {
+        self.inflater.set_history(len);
+        self.finished = false;
+    }
+    /// Sets a flag that cancels decompressing, see Inflater::set_cancel.
+    ///
+    /// # Arguments
+    ///
+    /// * 'cancel' - The flag to check, or None to never be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.inflater.set_cancel(cancel);
+        self.finished = false;
+    }
+    /// Sets the preset dictionary the data was compressed with, see
+    /// Inflater::set_dictionary.
+    ///
+    /// # Arguments
+    ///
+    /// * 'dictionary' - The dictionary, only the last 32KB of which is kept.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let start = dictionary.len().saturating_sub(WINDOW_SIZE);
+        self.dictionary = dictionary.get(start..).unwrap_or_default().to_vec();
+        self.finished = false;
+    }
+    /// Gets the inflater ready to decode the data from the start.
+    fn start(&mut self) -> Result<(), DeflateError> {
+        self.inflater.reset();
+        if !self.dictionary.is_empty() {
+            self.inflater.set_dictionary(&self.dictionary)?;
+        }
         Ok(())
     }
+    /// Returns how many more bytes the output can allocate before going over
+    /// the memory limit, given how much it already has.
+    fn memory_room(&self, allocated: usize) -> usize {
+        self.inflater.memory_limit.map_or(usize::MAX, |limit| {
+            limit.saturating_sub(self.inflater.memory_usage().total() + allocated)
+        })
+    }
+    /// Returns the CRC-32 of the output of the last call to decompress or
+    /// decompress_into, or 0 if neither has been called.
+    pub fn crc32(&self) -> u32 {
+        self.crc
+    }
+    /// Decompresses the data straight into a buffer supplied by the caller,
+    /// without allocating.
+    ///
+    /// # Arguments
+    ///
+    /// * 'output' - The buffer to write the decompressed data into.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written to the start of output, or a DeflateError.
+    /// If the data doesn't fit, DeflateError::OutputTooSmallError is returned
+    /// holding the length of output.
+    pub fn decompress_into(&mut self, output: &mut [u8]) -> Result<usize, DeflateError> {
+        self.start()?;
+
+        let (_, produced, status) = self.inflater.inflate(&self.compressed, output)?;
+        self.crc = crc::hash(output.get(..produced).unwrap_or_default());
+
+        match status {
+            Status::StreamEnd => Ok(produced),
+            // inflate never returns Pending, it has no budget to run out of.
+            Status::NeedsOutput | Status::Pending => {
+                Err(DeflateError::OutputTooSmallError(output.len()))
+            }
+            Status::NeedsInput => Err(self.inflater.unexpected_eof()),
+        }
+    }
+    /// Decompresses the data without keeping the output, instead handing it
+    /// to the sink one chunk at a time. Back-references are served from the
+    /// inflater's 32KB window, so memory use stays the same no matter how
+    /// large the decompressed data is.
+    ///
+    /// # Arguments
+    ///
+    /// * 'sink' - A closure called with each chunk of decompressed bytes, in
+    ///         order. Chunks are never larger than 32KB.
+    ///
+    /// # Returns
+    ///
+    /// The total number of bytes handed to the sink, or a DeflateError.
+    pub fn decompress_to_sink<F: FnMut(&[u8])>(
+        &mut self,
+        mut sink: F,
+    ) -> Result<u64, DeflateError> {
+        self.try_decompress_to_sink(|chunk| {
+            sink(chunk);
+            Ok(())
+        })
+    }
+    /// Like decompress_to_sink, but the sink can fail, which stops decoding
+    /// and returns its error.
+    ///
+    /// # Arguments
+    ///
+    /// * 'sink' - A closure called with each chunk of decompressed bytes, in
+    ///         order, returning an error to stop.
+    ///
+    /// # Returns
+    ///
+    /// The total number of bytes handed to the sink, or the sink's error, or
+    /// a DeflateError converted into the sink's error type.
+    pub fn try_decompress_to_sink<E, F>(&mut self, sink: F) -> Result<u64, E>
+    where
+        E: From<DeflateError>,
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        self.try_decompress_in_chunks(OUTPUT_CHUNK, sink)
+    }
+    /// Does try_decompress_to_sink with chunks of up to chunk_size bytes, so
+    /// writers can be handed larger batches than OUTPUT_CHUNK.
+    pub(crate) fn try_decompress_in_chunks<E, F>(
+        &mut self,
+        chunk_size: usize,
+        mut sink: F,
+    ) -> Result<u64, E>
+    where
+        E: From<DeflateError>,
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        let chunk_size = chunk_size.min(self.memory_room(0));
+        if let (0, Some(limit)) = (chunk_size, self.inflater.memory_limit) {
+            return Err(DeflateError::MemoryLimitError(limit).into());
+        }
+        let mut chunk = try_zeroed(chunk_size)?;
+        self.start()?;
+        let mut input = &self.compressed[..];
+
+        while !self.inflater.is_finished() {
+            let (consumed, produced, status) = self.inflater.inflate(input, &mut chunk)?;
+
+            input = input.get(consumed..).unwrap_or_default();
+            sink(chunk.get(..produced).unwrap_or_default())?;
+
+            if status == Status::NeedsInput {
+                return Err(self.inflater.unexpected_eof().into());
+            }
+        }
+
+        Ok(self.inflater.total_out())
+    }
+    /// Returns the data of every block without copying it, if the data is
+    /// borrowed and made up only of stored blocks, as level 0 compressors
+    /// write when gzip is only wanted for its framing and checksum. The
+    /// slices point into the borrowed bytes, so they outlive the
+    /// DeflateData.
+    ///
+    /// # Returns
+    ///
+    /// The data of each block in order, or None if the data is owned,
+    /// mapped, holds a block that isn't stored or is invalid, or is larger
+    /// than the output limit. Decompressing is the way to find what's wrong
+    /// with invalid data.
+    pub fn stored_slices(&self) -> Option<Vec<&'a [u8]>> {
+        let Input::Bytes(Cow::Borrowed(mut bytes)) = self.compressed else {
+            return None;
+        };
+        let mut slices = Vec::new();
+        let mut total = 0u64;
+
+        loop {
+            // Each block starts on a byte boundary, as the one before it was
+            // stored, with the padding after BTYPE ignored.
+            let &[header, len_lo, len_hi, nlen_lo, nlen_hi, ..] = bytes else {
+                return None;
+            };
+            let len = u16::from_le_bytes([len_lo, len_hi]);
+            if header & 0b110 != 0 || len != !u16::from_le_bytes([nlen_lo, nlen_hi]) {
+                return None;
+            }
+
+            let data = bytes.get(5..5 + len as usize)?;
+            total += len as u64;
+            if self.inflater.max_output.is_some_and(|max| total > max) {
+                return None;
+            }
+            slices.push(data);
+            bytes = bytes.get(5 + len as usize..).unwrap_or_default();
+
+            if header & 1 == 1 {
+                return Some(slices);
+            }
+        }
+    }
+    /// Like decompress, but borrows the output from the compressed bytes
+    /// instead of copying it when it's a single stored block, see
+    /// stored_slices. Several stored blocks are joined without being
+    /// decoded, anything else is decompressed as usual.
+    ///
+    /// # Returns
+    ///
+    /// The decompressed bytes, or a DeflateError.
+    pub fn decompress_cow(&mut self) -> Result<Cow<'a, [u8]>, DeflateError> {
+        let Some(slices) = self.stored_slices() else {
+            return self.decompress().map(Cow::Owned);
+        };
+
+        self.crc = slices.iter().fold(0, |crc, slice| crc::update(crc, slice));
+        match slices[..] {
+            [] => Ok(Cow::Borrowed(&[])),
+            [slice] => Ok(Cow::Borrowed(slice)),
+            _ => Ok(Cow::Owned(slices.concat())),
+        }
+    }
 }
@@ -1,5 +1,49 @@
+// The doc comments in this crate indent continuation lines of argument and
+// field lists to line up, which newer versions of clippy flag.
+#![allow(clippy::doc_overindented_list_items)]
+
+// Decoding and encoding can each be left out with the inflate and deflate
+// features. The modules both need, stream for errors and block types, the
+// headers, and the checksums, are always built.
+pub mod adler;
+#[cfg(feature = "inflate")]
+pub mod analyze;
 pub mod bits;
+#[cfg(feature = "inflate")]
+pub mod bufread;
 pub mod crc;
+#[cfg(feature = "deflate")]
+pub mod deflate;
+#[cfg(feature = "inflate")]
+pub mod disasm;
+#[cfg(feature = "inflate")]
+pub mod git;
 pub mod gzip;
+#[cfg(feature = "inflate")]
+pub mod identify;
+#[cfg(feature = "inflate")]
 pub mod inflate;
+#[cfg(feature = "inflate")]
+pub mod members;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod png;
 pub mod prefix;
+#[cfg(feature = "inflate")]
+pub mod read;
+#[cfg(all(feature = "inflate", feature = "deflate"))]
+pub mod recompress;
+#[cfg(feature = "test-utils")]
+pub mod reference;
+#[cfg(feature = "json")]
+pub mod report;
+pub mod stream;
+#[cfg(feature = "inflate")]
+pub mod tokens;
+#[cfg(all(feature = "inflate", feature = "deflate"))]
+pub mod transcode;
+#[cfg(all(feature = "inflate", feature = "deflate"))]
+pub mod verify;
+#[cfg(any(feature = "inflate", feature = "deflate"))]
+pub mod write;
+pub mod zlib;
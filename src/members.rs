@@ -0,0 +1,254 @@
+//! The members of a gzip file, found from their framing.
+//!
+//! A gzip file is any number of members back to back, each a header, a
+//! DEFLATE stream, and a trailer. Nothing says how long a stream is short
+//! of decoding it, so members are found by inflating into a scratch buffer
+//! that's thrown away. BGZF members, whose header gives their size, are
+//! inflated too, and their size checked against where the stream really
+//! ends. The compressed bytes can then be copied as they are, such as to
+//! concatenate files the way cat a.gz b.gz > c.gz does, or one member
+//! picked out by its index or name and decoded on its own.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use crate::{
+    gzip::{GzipError, GzipFile, GzipHeader},
+    inflate::{try_zeroed, DeflateError, Inflater, Status},
+};
+
+/// The size of the scratch buffer members are inflated into.
+const SCRATCH_SIZE: usize = 32768;
+
+/// One member of a gzip file.
+///
+/// # Fields
+///
+/// * 'header' - The member's header.
+/// * 'bytes' - The whole member, header to trailer, as it is in the file.
+/// * 'crc32' - The CRC-32 from the trailer, which isn't checked.
+/// * 'isize' - The size mod 2^32 from the trailer, checked against the
+///         inflated size.
+#[derive(Debug)]
+pub struct Member<'a> {
+    pub header: GzipHeader,
+    pub bytes: &'a [u8],
+    pub crc32: u32,
+    pub isize: u32,
+}
+
+impl Member<'_> {
+    /// Decompresses the member and checks its trailer.
+    ///
+    /// # Returns
+    ///
+    /// The decompressed data, or a GzipError.
+    pub fn decompress(&self) -> Result<Vec<u8>, GzipError> {
+        Ok(GzipFile::from_bytes(self.bytes)?.decompress()?)
+    }
+}
+
+/// Finds every member of a gzip file, checking each has a valid header, a
+/// complete DEFLATE stream, and a trailer. CRC-32s aren't computed, so a
+/// member with corrupt data but intact framing is still found.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file, any number of members.
+///
+/// # Returns
+///
+/// The members in order, or a GzipError for the first one whose framing
+/// is broken, or if there are bytes after the last that aren't a member.
+pub fn members(bytes: &[u8]) -> Result<Vec<Member<'_>>, GzipError> {
+    let mut finder = MemberFinder::new();
+    let mut members = Vec::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let member = finder.next(rest)?;
+        rest = rest.get(member.bytes.len()..).unwrap_or_default();
+        members.push(member);
+    }
+
+    Ok(members)
+}
+
+/// Finds the member at an index, skipping the ones before it by their
+/// framing alone.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file, any number of members.
+/// * 'index' - The member's position in the file, counting from 0.
+///
+/// # Returns
+///
+/// The member, None if the file has no more than index members, or a
+/// GzipError for the first member up to it whose framing is broken.
+pub fn nth(bytes: &[u8], index: usize) -> Result<Option<Member<'_>>, GzipError> {
+    let mut position = 0;
+    find(bytes, |_| {
+        position += 1;
+        position > index
+    })
+}
+
+/// Finds the first member whose header's FNAME is name, skipping the ones
+/// before it by their framing alone.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file, any number of members.
+/// * 'name' - The file name to look for, compared exactly.
+///
+/// # Returns
+///
+/// The member, None if no member has that name, or a GzipError for the
+/// first member up to it whose framing is broken.
+pub fn named<'a>(bytes: &'a [u8], name: &str) -> Result<Option<Member<'a>>, GzipError> {
+    find(bytes, |header| header.fname.as_deref() == Some(name))
+}
+
+/// Returns the first member whose header is_match accepts.
+fn find<F>(bytes: &[u8], mut is_match: F) -> Result<Option<Member<'_>>, GzipError>
+where
+    F: FnMut(&GzipHeader) -> bool,
+{
+    let mut finder = MemberFinder::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let member = finder.next(rest)?;
+        if is_match(&member.header) {
+            return Ok(Some(member));
+        }
+        rest = rest.get(member.bytes.len()..).unwrap_or_default();
+    }
+
+    Ok(None)
+}
+
+/// Joins gzip files into one multi-member file, copying every member as it
+/// is rather than recompressing. Each file's framing is checked with
+/// members first, so a broken file is caught instead of silently making
+/// the output undecodable from that point on.
+///
+/// # Arguments
+///
+/// * 'files' - The gzip files, in the order their data should come out.
+///
+/// # Returns
+///
+/// The joined file, or the GzipError of the first file that isn't valid.
+pub fn concatenate(files: &[&[u8]]) -> Result<Vec<u8>, GzipError> {
+    let mut finder = MemberFinder::new();
+    let mut output = Vec::new();
+
+    for file in files {
+        let mut rest = *file;
+        while !rest.is_empty() {
+            let member = finder.next(rest)?;
+            output.extend_from_slice(member.bytes);
+            rest = rest.get(member.bytes.len()..).unwrap_or_default();
+        }
+    }
+
+    Ok(output)
+}
+
+/// Finds where members end, keeping an inflater and scratch buffer to
+/// reuse between them.
+///
+/// # Fields
+///
+/// * 'inflater' - The inflater members are decoded with.
+/// * 'scratch' - Where their output goes, allocated when first needed.
+pub(crate) struct MemberFinder {
+    inflater: Inflater,
+    scratch: Vec<u8>,
+}
+
+impl MemberFinder {
+    pub(crate) fn new() -> Self {
+        Self {
+            inflater: Inflater::new(),
+            scratch: Vec::new(),
+        }
+    }
+    /// Finds the member at the start of bytes. A BGZF member's BSIZE has
+    /// to agree with where its stream ends, so a member whose BSIZE is
+    /// wrong isn't copied with the size it claims.
+    pub(crate) fn next<'a>(&mut self, bytes: &'a [u8]) -> Result<Member<'a>, GzipError> {
+        let header = GzipHeader::build(bytes)?;
+        let eof = || GzipError::from(DeflateError::UnexpectedEofError { bit_offset: 0 });
+
+        let stream = bytes.get(header.end_idx..).unwrap_or_default();
+        let (used, inflated) = self.inflate(stream)?;
+        let len = header.end_idx + used + 8;
+
+        if bgzf_size(&header).is_some_and(|size| size != len) {
+            let header_bytes = bytes.get(..header.end_idx).unwrap_or_default();
+            return Err(GzipError::InvalidHeader(header_bytes.to_vec()));
+        }
+
+        let member = bytes.get(..len).ok_or_else(eof)?;
+        let Some(&[c0, c1, c2, c3, i0, i1, i2, i3]) = member.last_chunk::<8>() else {
+            return Err(eof());
+        };
+        let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+        let isize = u32::from_le_bytes([i0, i1, i2, i3]);
+
+        if inflated as u32 != isize {
+            return Err(DeflateError::SizeMismatchError {
+                expected: isize,
+                actual: inflated as u32,
+            }
+            .into());
+        }
+
+        Ok(Member {
+            header,
+            bytes: member,
+            crc32,
+            isize,
+        })
+    }
+    /// Inflates a DEFLATE stream, returning how many bytes long it is and
+    /// how many it inflates to.
+    fn inflate(&mut self, stream: &[u8]) -> Result<(usize, u64), GzipError> {
+        if self.scratch.is_empty() {
+            self.scratch = try_zeroed(SCRATCH_SIZE)?;
+        }
+        self.inflater.reset();
+
+        let mut used = 0;
+        loop {
+            let input = stream.get(used..).unwrap_or_default();
+            let (consumed, _, status) = self.inflater.inflate(input, &mut self.scratch)?;
+            used += consumed;
+
+            match status {
+                Status::StreamEnd => return Ok((used, self.inflater.total_out())),
+                Status::NeedsInput => return Err(self.inflater.unexpected_eof().into()),
+                Status::NeedsOutput | Status::Pending => {}
+            }
+        }
+    }
+}
+
+/// Finds the BGZF 'BC' subfield in a header, returning the total size of
+/// the member it came from.
+pub(crate) fn bgzf_size(header: &GzipHeader) -> Option<usize> {
+    header.extra_fields().find_map(|field| match field {
+        // BSIZE is the member's size minus 1.
+        (b'B', b'C', &[lo, hi]) => Some(u16::from_le_bytes([lo, hi]) as usize + 1),
+        _ => None,
+    })
+}
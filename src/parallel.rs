@@ -0,0 +1,408 @@
+//! Multi-threaded decompression of gzip files made of many members.
+//!
+//! A gzip file can hold any number of members one after another, each with
+//! its own header and trailer, and decompressing to the concatenation of
+//! their contents. Members written by BGZF (and tools that borrow its
+//! layout) record their compressed size in a 'BC' FEXTRA subfield, so they
+//! can be found without decoding and handed out to threads. Members without
+//! it are decoded as they're found, since decoding is the only way to tell
+//! where they end.
+//!
+//! Files that can't be split still get a second thread from
+//! decompress_pipelined, which hashes and writes one chunk of output while
+//! the next is being decoded, or from ReadAheadDecoder, which decodes the
+//! next chunk while the caller reads the last.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    num::NonZeroUsize,
+    sync::mpsc::{self, Receiver, Sender, SyncSender},
+    thread,
+};
+
+use crate::{
+    bufread::GzDecoder,
+    crc,
+    gzip::{verify_trailer, GzipError, GzipFile, GzipHeader},
+    inflate::{DeflateError, Inflater, Status},
+    members::bgzf_size,
+};
+
+/// The size of each chunk of output decompress_pipelined hands over.
+const PIPELINE_CHUNK: usize = 65536;
+
+/// How many chunks can wait on the writing thread before decoding stops to
+/// let it catch up, bounding how much output is held at once.
+const PIPELINE_DEPTH: usize = 4;
+
+/// A member of a gzip file, either still compressed or already decoded.
+enum Member<'a> {
+    Pending(&'a [u8]),
+    Done(Vec<u8>),
+}
+
+/// Decompresses every member of a gzip file, decoding members on as many
+/// threads as the machine has available.
+///
+/// # Arguments
+///
+/// * 'bytes' - The whole gzip file.
+///
+/// # Returns
+///
+/// The decompressed contents of every member in order, or a GzipError.
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+    decompress_with_threads(bytes, threads)
+}
+
+/// Decompresses every member of a gzip file on the given number of threads.
+///
+/// # Arguments
+///
+/// * 'bytes' - The whole gzip file.
+/// * 'threads' - The most threads to decode members on, at least 1 is used.
+///
+/// # Returns
+///
+/// The decompressed contents of every member in order, or a GzipError.
+pub fn decompress_with_threads(bytes: &[u8], threads: usize) -> Result<Vec<u8>, GzipError> {
+    let mut members = split_members(bytes)?;
+
+    let mut pending = members
+        .iter_mut()
+        .filter(|member| matches!(member, Member::Pending(_)))
+        .collect::<Vec<_>>();
+
+    // BGZF members are all about the same size, so giving each thread an
+    // even run of them keeps the work balanced.
+    let per_thread = pending.len().div_ceil(threads.max(1)).max(1);
+
+    thread::scope(|scope| {
+        let handles = pending
+            .chunks_mut(per_thread)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    for member in chunk.iter_mut() {
+                        if let Member::Pending(compressed) = member {
+                            **member =
+                                Member::Done(GzipFile::from_bytes(compressed)?.decompress()?);
+                        }
+                    }
+                    Ok::<(), GzipError>(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap_or_else(|_| Err(thread_panicked())))
+    })?;
+
+    let mut decompressed = Vec::new();
+    for member in members {
+        if let Member::Done(data) = member {
+            decompressed.extend_from_slice(&data);
+        }
+    }
+
+    Ok(decompressed)
+}
+
+/// The error for a thread that panicked. Nothing run on one should be able
+/// to, but if it does the panic is reported rather than passed on.
+fn thread_panicked() -> GzipError {
+    GzipError::IoError(io::Error::other("Error: A decompression thread panicked"))
+}
+
+/// What the decoding thread of decompress_pipelined sends the writing
+/// thread.
+///
+/// # Variants
+///
+/// * 'Chunk' - The next piece of output.
+/// * 'Trailer' - The end of a member, with its trailer to check the chunks
+///         since the last one against.
+enum Piece {
+    Chunk(Vec<u8>),
+    Trailer { crc32: u32, isize: u32 },
+}
+
+/// Decompresses every member of a gzip file into a writer, decoding on this
+/// thread while another computes the CRC-32 and does the writing, so slow
+/// writes and hashing overlap with decoding instead of waiting on it.
+///
+/// # Arguments
+///
+/// * 'bytes' - The whole gzip file.
+/// * 'writer' - Where to write the decompressed data. As with
+///         GzipFile::decompress_to_writer, a corrupt member is only reported
+///         after its data has been written.
+///
+/// # Returns
+///
+/// The number of bytes written, or a GzipError from decoding, checking a
+/// trailer, or the writer.
+pub fn decompress_pipelined<W: Write + Send>(
+    bytes: &[u8],
+    writer: &mut W,
+) -> Result<u64, GzipError> {
+    let (send, receive) = mpsc::sync_channel(PIPELINE_DEPTH);
+    let (recycle, reuse) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let worker = scope.spawn(move || write_pieces(receive, recycle, writer));
+        let decoded = decode_pieces(bytes, send, reuse);
+        let written = worker.join().unwrap_or_else(|_| Err(thread_panicked()));
+
+        // A failed write stops decoding early, so its error comes first.
+        let written = written?;
+        decoded?;
+        Ok(written)
+    })
+}
+
+/// The decoding half of decompress_pipelined, reusing the chunks the
+/// writing thread hands back.
+fn decode_pieces(
+    mut bytes: &[u8],
+    send: SyncSender<Piece>,
+    reuse: Receiver<Vec<u8>>,
+) -> Result<(), GzipError> {
+    let mut inflater = Inflater::new();
+
+    while !bytes.is_empty() {
+        let header = GzipHeader::build(bytes)?;
+        bytes = bytes.get(header.end_idx..).unwrap_or_default();
+        inflater.reset();
+
+        loop {
+            let mut chunk = reuse.try_recv().unwrap_or_default();
+            chunk.resize(PIPELINE_CHUNK, 0);
+
+            let (consumed, produced, status) = inflater.inflate(bytes, &mut chunk)?;
+            bytes = bytes.get(consumed..).unwrap_or_default();
+            chunk.truncate(produced);
+
+            // The writing thread only hangs up after an error, which it
+            // reports itself.
+            if produced > 0 && send.send(Piece::Chunk(chunk)).is_err() {
+                return Ok(());
+            }
+            match status {
+                Status::StreamEnd => break,
+                Status::NeedsInput => return Err(inflater.unexpected_eof().into()),
+                Status::NeedsOutput | Status::Pending => {}
+            }
+        }
+
+        let Some((&[a, b, c, d, e, f, g, h], after)) = bytes.split_first_chunk::<8>() else {
+            return Err(DeflateError::UnexpectedEofError {
+                bit_offset: inflater.total_in() * 8,
+            }
+            .into());
+        };
+        let trailer = Piece::Trailer {
+            crc32: u32::from_le_bytes([a, b, c, d]),
+            isize: u32::from_le_bytes([e, f, g, h]),
+        };
+        if send.send(trailer).is_err() {
+            return Ok(());
+        }
+        bytes = after;
+    }
+
+    Ok(())
+}
+
+/// The writing half of decompress_pipelined, hashing and writing chunks
+/// until the decoding thread is done, and checking each member's trailer.
+fn write_pieces<W: Write>(
+    receive: Receiver<Piece>,
+    recycle: Sender<Vec<u8>>,
+    writer: &mut W,
+) -> Result<u64, GzipError> {
+    let (mut crc, mut len, mut total) = (0, 0, 0);
+
+    for piece in receive {
+        match piece {
+            Piece::Chunk(chunk) => {
+                crc = crc::update(crc, &chunk);
+                len += chunk.len() as u64;
+                writer.write_all(&chunk)?;
+                // Decoding may already be over, leaving the chunk unwanted.
+                let _ = recycle.send(chunk);
+            }
+            Piece::Trailer { crc32, isize } => {
+                verify_trailer(crc32, isize, crc, len)?;
+                total += len;
+                (crc, len) = (0, 0);
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(total)
+}
+
+/// Splits a gzip file into its members, decoding the ones that don't say
+/// how long they are.
+fn split_members(bytes: &[u8]) -> Result<Vec<Member<'_>>, GzipError> {
+    let mut members = Vec::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let header = GzipHeader::build(rest)?;
+
+        match bgzf_size(&header) {
+            Some(size) if size <= rest.len() => {
+                let (member, after) = rest.split_at(size);
+                members.push(Member::Pending(member));
+                rest = after;
+            }
+            _ => {
+                let mut decoder = GzDecoder::new(rest);
+                let mut data = Vec::new();
+                io::copy(&mut decoder, &mut data)?;
+
+                members.push(Member::Done(data));
+                rest = decoder.into_inner();
+            }
+        }
+    }
+
+    Ok(members)
+}
+
+/// A reader that decompresses a gzip file on a thread of its own, keeping up
+/// to PIPELINE_DEPTH chunks of output decoded ahead of what's been read, so
+/// decoding goes on while the caller works on the last chunk instead of
+/// waiting for the next read. The file is decompressed like
+/// read::GzDecoder would, one member.
+///
+/// Dropping the reader stops the thread once it next hands over a chunk, it
+/// isn't waited for.
+///
+/// # Fields
+///
+/// * 'chunks' - Decoded chunks from the thread, an empty one at the end.
+/// * 'recycle' - Sends chunks that have been read back to be reused.
+/// * 'chunk' - The chunk being read from.
+/// * 'pos' - How much of chunk has been read.
+/// * 'finished' - Whether the end of the file has been reached.
+pub struct ReadAheadDecoder {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    recycle: Sender<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl ReadAheadDecoder {
+    /// Starts decompressing the gzip file held by a reader on a new thread.
+    ///
+    /// # Arguments
+    ///
+    /// * 'reader' - The reader holding the gzip file, moved to the thread.
+    pub fn new<R: Read + Send + 'static>(reader: R) -> Self {
+        let (send, chunks) = mpsc::sync_channel(PIPELINE_DEPTH);
+        let (recycle, reuse) = mpsc::channel();
+
+        thread::spawn(move || read_ahead(GzDecoder::new(BufReader::new(reader)), send, reuse));
+
+        Self {
+            chunks,
+            recycle,
+            chunk: Vec::new(),
+            pos: 0,
+            finished: false,
+        }
+    }
+}
+
+impl Read for ReadAheadDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() && !self.finished {
+            let chunk = match self.chunks.recv() {
+                Ok(chunk) => chunk?,
+                // The thread only hangs up without an empty chunk after it
+                // has sent an error.
+                Err(_) => {
+                    return Err(io::Error::other(
+                        "Error: Decoding thread stopped after an earlier error",
+                    ))
+                }
+            };
+            self.finished = chunk.is_empty();
+
+            // The thread may already be done, leaving the chunk unwanted.
+            let _ = self.recycle.send(std::mem::replace(&mut self.chunk, chunk));
+            self.pos = 0;
+        }
+
+        let unread = self.chunk.get(self.pos..).unwrap_or_default();
+        let n = buf.len().min(unread.len());
+        buf.iter_mut()
+            .zip(unread)
+            .for_each(|(out, &byte)| *out = byte);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The decoding thread of a ReadAheadDecoder, sending chunks of output
+/// until the end of the file or an error, reusing the chunks handed back.
+fn read_ahead<R: BufRead>(
+    mut decoder: GzDecoder<R>,
+    send: SyncSender<io::Result<Vec<u8>>>,
+    reuse: Receiver<Vec<u8>>,
+) {
+    loop {
+        let mut chunk = reuse.try_recv().unwrap_or_default();
+        chunk.resize(PIPELINE_CHUNK, 0);
+
+        // Fill the whole chunk, a short read doesn't mean the end.
+        let mut filled = 0;
+        let result = loop {
+            match decoder.read(chunk.get_mut(filled..).unwrap_or_default()) {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    filled += n;
+                    if filled == chunk.len() {
+                        break Ok(());
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+        chunk.truncate(filled);
+
+        // Whatever was decoded before an error is still handed over first,
+        // and a hung up reader means there's no one left to decode for.
+        let end = filled < PIPELINE_CHUNK;
+        if (filled > 0 || result.is_ok()) && send.send(Ok(chunk)).is_err() {
+            return;
+        }
+        if let Err(err) = result {
+            let _ = send.send(Err(err));
+            return;
+        }
+        if end {
+            if filled > 0 {
+                let _ = send.send(Ok(Vec::new()));
+            }
+            return;
+        }
+    }
+}
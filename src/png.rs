@@ -0,0 +1,107 @@
+//! The glue between PNG image data and zlib.
+//!
+//! A PNG's pixels are filtered scanlines, each a filter type byte and then
+//! the row, compressed as one zlib stream that's split across any number of
+//! IDAT chunks. decode_idat takes the IDAT payloads joined back together
+//! and returns the scanlines, encode_idat makes the stream from scanlines,
+//! for the caller to split into chunks. Filtering and unfiltering the rows
+//! is left to the image code.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+#[cfg(feature = "deflate")]
+use crate::deflate::{compress_zlib, CompressOptions};
+#[cfg(feature = "inflate")]
+use crate::zlib::{self, ZlibError};
+
+/// The first column, first row, column step, and row step of each of the 7
+/// Adam7 interlacing passes.
+const ADAM7: [(u64, u64, u64, u64); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Returns the size of an image's filtered scanlines, what its IDAT data
+/// should decompress to.
+///
+/// # Arguments
+///
+/// * 'width' - The width in pixels, from IHDR.
+/// * 'height' - The height in pixels, from IHDR.
+/// * 'bits_per_pixel' - The bit depth times the number of channels of the
+///         colour type, such as 24 for 8 bit RGB.
+/// * 'interlaced' - Whether the interlace method is Adam7, which stores 7
+///         smaller images one after the other.
+///
+/// # Returns
+///
+/// The size in bytes, saturating rather than overflowing for impossibly
+/// large images.
+pub fn scanlines_len(width: u32, height: u32, bits_per_pixel: u8, interlaced: bool) -> u64 {
+    // Every row starts with its filter type byte.
+    let image_len = |width: u64, height: u64| {
+        let row = (width * bits_per_pixel as u64).div_ceil(8);
+        match width {
+            0 => 0,
+            _ => height.saturating_mul(row + 1),
+        }
+    };
+    let (width, height) = (width as u64, height as u64);
+
+    if !interlaced {
+        return image_len(width, height);
+    }
+    // A pass with no pixels has no rows at all, not even filter bytes.
+    ADAM7.iter().fold(0, |len, &(x, y, dx, dy)| {
+        let pass_width = width.saturating_sub(x).div_ceil(dx);
+        let pass_height = height.saturating_sub(y).div_ceil(dy);
+        len.saturating_add(image_len(pass_width, pass_height))
+    })
+}
+
+/// Decompresses an image's IDAT data into its filtered scanlines.
+///
+/// # Arguments
+///
+/// * 'idat' - The payloads of every IDAT chunk, in order, joined together.
+/// * 'expected_len' - The size of the scanlines, see scanlines_len, or None
+///         if it isn't known. Data that decompresses to more fails with
+///         DeflateError::OutputLimitError, so a small image can't claim
+///         gigabytes of memory.
+///
+/// # Returns
+///
+/// The scanlines, which may be fewer than expected if the data is, or a
+/// ZlibError.
+#[cfg(feature = "inflate")]
+pub fn decode_idat(idat: &[u8], expected_len: Option<u64>) -> Result<Vec<u8>, ZlibError> {
+    zlib::decompress_with_limit(idat, expected_len)
+}
+
+/// Compresses filtered scanlines into the zlib stream IDAT chunks hold.
+///
+/// # Arguments
+///
+/// * 'scanlines' - Every row of the image, each starting with its filter
+///         type byte.
+/// * 'options' - How to find matches and split blocks.
+///
+/// # Returns
+///
+/// The zlib stream, to be split into IDAT chunks of any size.
+#[cfg(feature = "deflate")]
+pub fn encode_idat(scanlines: &[u8], options: &CompressOptions) -> Vec<u8> {
+    compress_zlib(scanlines, options)
+}
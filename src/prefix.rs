@@ -1,4 +1,50 @@
-use std::{cell::RefCell, cmp::Ordering, fmt, fmt::Display, rc::Rc};
+use std::{cmp::Ordering, error::Error, fmt, fmt::Display};
+
+#[cfg(feature = "inflate")]
+use crate::bits::BitVector64;
+
+/// Errors from building or walking a PrefixTree.
+///
+/// # Members
+///
+/// * 'InvalidBit' - A bit other than 0 or 1 was given.
+/// * 'InvalidLength' - A code length is longer than MAX_CODE_LENGTH.
+/// * 'OverSubscribed' - The code lengths describe more codes than fit in
+///         their lengths, so some would have to share a prefix.
+/// * 'CodeConflict' - A code would pass through or land on a node that's
+///         already in use, holds the value being inserted.
+#[derive(Debug)]
+pub enum PrefixError {
+    InvalidBit(u8),
+    InvalidLength(u8),
+    OverSubscribed,
+    CodeConflict(usize),
+}
+
+impl Display for PrefixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrefixError::InvalidBit(bit) => {
+                write!(f, "Error: Bit is neither 0 nor 1: {}", bit)
+            }
+            PrefixError::InvalidLength(length) => {
+                write!(f, "Error: Code length is longer than 15 bits: {}", length)
+            }
+            PrefixError::OverSubscribed => {
+                write!(f, "Error: Code lengths are over-subscribed")
+            }
+            PrefixError::CodeConflict(value) => {
+                write!(
+                    f,
+                    "Error: Code for {} overlaps a code already in the tree",
+                    value
+                )
+            }
+        }
+    }
+}
+
+impl Error for PrefixError {}
 
 /// Code lengths from section 3.2.6 of RFC 1951.
 pub const FIXED_CODE_LENGTHS: [u8; 288] = [
@@ -13,6 +59,71 @@ pub const FIXED_CODE_LENGTHS: [u8; 288] = [
     7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 8, 8, 8, 8, 8, 8, 8, 8,
 ];
 
+/// Code lengths of the fixed distance codes from section 3.2.6 of RFC 1951.
+/// Distance codes 30 and 31 never occur in compressed data so are left out.
+pub const FIXED_DISTANCE_LENGTHS: [u8; 30] = [5; 30];
+
+/// The longest code length allowed in a DEFLATE stream.
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+/// The number of symbols in the code length alphabet a dynamic block's
+/// code lengths are written with.
+pub const CL_CODES: usize = 19;
+
+/// The order a dynamic block stores the code length code lengths in, from
+/// section 3.2.7 of RFC 1951. The lengths least likely to be used come
+/// last, so they can be left off the end.
+pub const CL_CODE_ORDER: [usize; CL_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// The longest code the code length code can use, its lengths are stored
+/// in 3 bits.
+pub const MAX_CL_CODE_LENGTH: u8 = 7;
+
+/// What the HLIT, HDIST, and HCLEN fields of a dynamic block header count
+/// up from, each field holds its count less these.
+pub const HLIT_BASE: usize = 257;
+pub const HDIST_BASE: usize = 1;
+pub const HCLEN_BASE: usize = 4;
+
+/// The most literal/length and distance codes a dynamic block can use,
+/// the fields can hold more but the alphabets end here.
+pub const MAX_HLIT: usize = 286;
+pub const MAX_HDIST: usize = 30;
+
+/// Returns the code length symbol a dynamic block header's index'th code
+/// length code length belongs to.
+///
+/// # Arguments
+///
+/// * 'index' - The position of the length in the header, below CL_CODES.
+///         Anything past that gives CL_CODES, which no symbol uses.
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+pub fn cl_code_symbol(index: usize) -> usize {
+    CL_CODE_ORDER.get(index).copied().unwrap_or(CL_CODES)
+}
+
+/// Returns how many code length code lengths a dynamic block header has to
+/// store so every used one is sent, HCLEN before HCLEN_BASE is taken off.
+///
+/// # Arguments
+///
+/// * 'lengths' - The code length code lengths, indexed by symbol.
+pub fn cl_code_count(lengths: &[u8]) -> usize {
+    let used = CL_CODE_ORDER
+        .iter()
+        .rposition(|&symbol| lengths.get(symbol).is_some_and(|&length| length > 0));
+
+    used.map_or(0, |index| index + 1).max(HCLEN_BASE)
+}
+
 /// The number of extra bits each length code has.
 pub const LENGTH_EXTRA_BITS: [u8; 29] = [
     0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
@@ -36,6 +147,57 @@ pub const DISTANCE_BASE: [u16; 30] = [
     2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
 ];
 
+/// The fixed literal/length codes from section 3.2.6 of RFC 1951, indexed by
+/// symbol. Built at compile time so encoding a BTYPE 1 block needs no setup.
+#[cfg(feature = "deflate")]
+pub const FIXED_LITERAL_CODES: [Code; 288] = fixed_literal_codes();
+
+/// The fixed distance codes, every distance symbol is its own 5 bit code.
+#[cfg(feature = "deflate")]
+pub const FIXED_DISTANCE_CODES: [Code; 30] = fixed_distance_codes();
+
+/// Generates the symbol to code table for the fixed literal/length alphabet.
+///
+/// # Returns
+///
+/// An array of Code structs where the index is the symbol being encoded.
+#[cfg(feature = "deflate")]
+const fn fixed_literal_codes() -> [Code; 288] {
+    let mut codes = [Code::from(0, 0); 288];
+    let mut symbol = 0;
+
+    while symbol < 288 {
+        let value = symbol as u32;
+        codes[symbol] = match symbol {
+            0..=143 => Code::from(0b0011_0000 + value, 8),
+            144..=255 => Code::from(0b1_1001_0000 + (value - 144), 9),
+            256..=279 => Code::from(value - 256, 7),
+            _ => Code::from(0b1100_0000 + (value - 280), 8),
+        };
+        symbol += 1;
+    }
+
+    codes
+}
+
+/// Generates the symbol to code table for the fixed distance alphabet.
+///
+/// # Returns
+///
+/// An array of Code structs where the index is the distance symbol.
+#[cfg(feature = "deflate")]
+const fn fixed_distance_codes() -> [Code; 30] {
+    let mut codes = [Code::from(0, 0); 30];
+    let mut symbol = 0;
+
+    while symbol < 30 {
+        codes[symbol] = Code::from(symbol as u32, 5);
+        symbol += 1;
+    }
+
+    codes
+}
+
 /// A struct for representing codes of differing bit lengths, codes are stored
 /// little endian, meant to be read from most significant bit to least
 /// significant bit.
@@ -101,7 +263,7 @@ impl Code {
     /// # Returns
     ///
     /// A Code struct with the given values.
-    pub fn from(buffer: u32, length: u8) -> Self {
+    pub const fn from(buffer: u32, length: u8) -> Self {
         Self {
             buffer,
             length,
@@ -170,6 +332,246 @@ impl Display for Code {
     }
 }
 
+/// Assigns the canonical prefix codes described in section 3.2.2 of RFC 1951
+/// to each symbol.
+///
+/// # Arguments
+///
+/// * 'code_lengths' - An array of u8 values representing the number of bits
+///         in the code for each symbol, the symbol is the index of the value.
+///
+/// # Returns
+///
+/// A vector indexed by symbol containing the Code for that symbol, or None
+/// if the symbol has a code length of 0 and is unused.
+pub fn canonical_codes(code_lengths: &[u8]) -> Vec<Option<Code>> {
+    // Define an array to hold the amount of times a code length appears.
+    // The index is the code length, and the value at the index is the
+    // number of occurances.
+    let mut occurances = [0u32; 256];
+
+    // Get the higest code length in the array.
+    let max_length = *code_lengths.iter().max().unwrap_or(&0) as usize;
+
+    // Iterates over code_lengths, taking occurances in as acc, and taking
+    // the current iterated value as idx. Then, acc is dereferenced to
+    // directly modify occurances, and it is indexed by idx (the code
+    // length) before being incremented while preventing overflow by
+    // saturating_add. acc is then returned, and the fold operation repeats
+    // until all members of code_lengths have been iterated over.
+    code_lengths.iter().fold(&mut occurances, |acc, &idx| {
+        (*acc)[idx as usize] = (*acc)[idx as usize].saturating_add(1);
+        acc
+    });
+
+    // Intialize next_code and code as zeroes.
+    let mut next_code = vec![0; max_length + 1];
+    let mut code = 0;
+    occurances[0] = 0;
+
+    for i in 1..=max_length {
+        code = (code + occurances[i - 1]) << 1;
+        next_code[i] = code;
+    }
+
+    let mut codes = vec![None; code_lengths.len()];
+
+    for j in 0..code_lengths.len() {
+        let len = code_lengths[j] as usize;
+        if len != 0 {
+            codes[j] = Some(Code::from(next_code[len], code_lengths[j]));
+            next_code[len] += 1;
+        }
+    }
+
+    codes
+}
+
+/// Compares the Kraft sum of a set of code lengths, the sum of 2^-length
+/// for every code, against 1. A prefix code with a sum of exactly 1 is
+/// complete, every sequence of bits starts with one of its codes.
+///
+/// # Arguments
+///
+/// * 'code_lengths' - An array of u8 values representing the number of
+///         bits in the code for each symbol, 0 meaning the symbol is unused.
+///
+/// # Returns
+///
+/// Ordering::Less if the code is incomplete, Ordering::Equal if it's
+/// complete, or Ordering::Greater if it's over-subscribed, meaning there
+/// are more codes than can fit in their lengths.
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+pub fn kraft(code_lengths: &[u8]) -> Ordering {
+    let mut occurances = [0i32; MAX_CODE_LENGTH as usize + 1];
+    for &length in code_lengths {
+        if length > MAX_CODE_LENGTH {
+            return Ordering::Greater;
+        }
+        if let Some(count) = occurances.get_mut(length as usize) {
+            *count += 1;
+        }
+    }
+
+    // Count how many codes of each length are still free, going down a
+    // level doubles them.
+    let mut left = 1i32;
+    for &count in occurances.iter().skip(1) {
+        left = (left << 1) - count;
+        if left < 0 {
+            return Ordering::Greater;
+        }
+    }
+
+    if left > 0 {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Returns the index of the length code covering a match length, which is
+/// its symbol less 257.
+///
+/// # Arguments
+///
+/// * 'length' - A match length from 3 to 258.
+pub fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+/// Returns the distance code covering a match distance.
+///
+/// # Arguments
+///
+/// * 'distance' - A match distance from 1 to 32768.
+pub fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+/// Builds the code lengths of a Huffman code for a set of symbol
+/// frequencies, with no code longer than max_length. Codes are only given
+/// to symbols that occur, but at least two are always given so the code is
+/// complete, which some decoders insist on.
+///
+/// # Arguments
+///
+/// * 'frequencies' - How many times each symbol occurs, indexed by symbol.
+/// * 'max_length' - The longest code allowed, there have to be few enough
+///         symbols to fit in codes of this length.
+///
+/// # Returns
+///
+/// A vector of code lengths indexed by symbol, 0 for unused symbols.
+#[cfg(feature = "deflate")]
+pub fn huffman_lengths(frequencies: &[u64], max_length: u8) -> Vec<u8> {
+    let mut symbols = (0..frequencies.len())
+        .filter(|&symbol| frequencies[symbol] > 0)
+        .collect::<Vec<_>>();
+    // Pad the code out to two symbols with ones that don't occur.
+    for symbol in 0..frequencies.len() {
+        if symbols.len() >= 2.min(frequencies.len()) {
+            break;
+        }
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    symbols.sort_by_key(|&symbol| (frequencies[symbol], symbol));
+
+    let mut lengths = vec![0; frequencies.len()];
+    if symbols.len() < 2 {
+        for &symbol in &symbols {
+            lengths[symbol] = 1;
+        }
+        return lengths;
+    }
+
+    // Build the tree by repeatedly joining the two least frequent nodes.
+    // Leaves are already sorted, and joined nodes are made in order of
+    // weight, so the next two are always at the front of one of the queues.
+    let mut weights = symbols
+        .iter()
+        .map(|&symbol| frequencies[symbol])
+        .collect::<Vec<_>>();
+    let mut parents = vec![0; symbols.len() * 2 - 1];
+    let (mut leaf, mut node) = (0, symbols.len());
+    for joined in symbols.len()..parents.len() {
+        let mut take = || {
+            let next = if leaf < symbols.len() && (node >= joined || weights[leaf] <= weights[node])
+            {
+                leaf += 1;
+                leaf - 1
+            } else {
+                node += 1;
+                node - 1
+            };
+            parents[next] = joined;
+            weights[next]
+        };
+        let weight = take() + take();
+        weights.push(weight);
+    }
+
+    // Count the leaves at each depth, any deeper than max_length are
+    // pulled up to it for now.
+    let root = parents.len() - 1;
+    let mut depths = vec![0usize; parents.len()];
+    let mut counts = vec![0u32; max_length as usize + 1];
+    for index in (0..root).rev() {
+        depths[index] = depths[parents[index]] + 1;
+        if index < symbols.len() {
+            counts[depths[index].min(max_length as usize)] += 1;
+        }
+    }
+
+    // Pulling leaves up over-subscribes the code, so push the deepest ones
+    // that can go deeper down a level until it fits. Then, if that left
+    // room, pull leaves back up until the code is complete. The sums are in
+    // units of a max_length code.
+    let unit = |length: usize| 1u64 << (max_length as usize - length);
+    let full = unit(0);
+    let mut sum = (1..=max_length as usize)
+        .map(|length| counts[length] as u64 * unit(length))
+        .sum::<u64>();
+    while sum > full {
+        let length = (1..max_length as usize)
+            .rev()
+            .find(|&length| counts[length] > 0)
+            .expect("too many symbols for max_length");
+        counts[length] -= 1;
+        counts[length + 1] += 1;
+        sum -= unit(length + 1);
+    }
+    while sum < full {
+        let length = (2..=max_length as usize)
+            .rev()
+            .find(|&length| counts[length] > 0 && sum + unit(length) <= full)
+            .expect("a code deeper than 1 fits whenever there's room");
+        counts[length] -= 1;
+        counts[length - 1] += 1;
+        sum += unit(length);
+    }
+
+    // The most frequent symbols get the shortest codes.
+    let mut length = 1;
+    for &symbol in symbols.iter().rev() {
+        while counts[length] == 0 {
+            length += 1;
+        }
+        lengths[symbol] = length as u8;
+        counts[length] -= 1;
+    }
+
+    lengths
+}
+
 /// Struct representing each node of a binary tree.
 ///
 /// # Fields
@@ -183,18 +585,19 @@ impl Display for Code {
 ///         generate prefix codes, significance represents the code.
 /// * 'code' - An instance of the Code struct which contains a u32 bit buffer
 ///         containing the code, and a length representing what quantity of bits
-///         in the buffer are part of the code.
-/// * 'left' - An option holding a Rc<RefCell<>> reference to the child node attached to
-///         the left.
-/// * 'right' - An option holding a Rc<RefCell<>> reference to the child node attached to
-///         the right.
+///         in the buffer are part of the code. Only set on leaves, a branch's
+///         code is the path to it.
+/// * 'left' - An option holding the index of the child node attached to the
+///         left within the PrefixTree's nodes.
+/// * 'right' - An option holding the index of the child node attached to the
+///         right within the PrefixTree's nodes.
 #[derive(Debug, Clone)]
 pub struct Node {
     pub value: Option<usize>,
     pub significance: u64,
     pub code: Code,
-    pub left: Option<Rc<RefCell<Node>>>,
-    pub right: Option<Rc<RefCell<Node>>>,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
 }
 
 impl Node {
@@ -248,20 +651,25 @@ impl Default for Node {
     }
 }
 
-/// A binary tree containing prefix codes.
+/// A binary tree containing prefix codes. Nodes are stored in a single vector
+/// and link to their children by index, so neither building nor walking the
+/// tree allocates per node or clones any references.
 ///
 /// # Fields
 ///
-/// * 'root' - The root node to which all others are connected.
-/// * 'leaves' - A vector containing all the leaf nodes/nodes with values.
-/// * 'current' - The most recent node to be traversed.
+/// * 'nodes' - A vector containing every node in the tree, the root node to
+///         which all others are connected is always at index 0.
+/// * 'current' - The index of the most recent node to be traversed.
 #[derive(Debug)]
 pub struct PrefixTree {
-    pub root: Rc<RefCell<Node>>,
-    pub current: Rc<RefCell<Node>>,
+    pub nodes: Vec<Node>,
+    pub current: usize,
 }
 
 impl PrefixTree {
+    /// The index of the root node.
+    pub const ROOT: usize = 0;
+
     /// Creates a new empty PrefixTree.
     ///
     /// # Returns
@@ -269,8 +677,8 @@ impl PrefixTree {
     /// A PrefixTree with default values.
     pub fn new() -> Self {
         Self {
-            root: Rc::new(RefCell::new(Node::new())),
-            current: Rc::new(RefCell::new(Node::new())),
+            nodes: vec![Node::new()],
+            current: Self::ROOT,
         }
     }
     /// Accepts a code as input and then creates the branches required to reach
@@ -283,6 +691,12 @@ impl PrefixTree {
     /// * 'value' - A usize value containing the value to be stored at the
     ///         new node.
     ///
+    /// # Returns
+    ///
+    /// A result containing either a unit value, or a PrefixError if the code
+    /// is too long, or overlaps a code already in the tree. The
+    /// tree is left unchanged on error.
+    ///
     /// # Examples
     ///
     /// '''
@@ -290,46 +704,63 @@ impl PrefixTree {
     ///
     /// let new_code = Code::from(0b011, 3);
     ///
-    /// tree.insert_code(new_code, 255);
+    /// tree.insert_code(new_code, 255)?;
     ///
     /// let mut value = 0;
     /// for bit in code {
-    ///     if let Some(v) = tree.walk(bit) {
+    ///     if let Some(v) = tree.walk(bit)? {
     ///         value = v;
     ///     }
     /// }
     ///
     /// assert_eq!(value, 255);
     /// '''
-    pub fn insert_code(&mut self, code: Code, value: usize) {
-        let mut current = Rc::clone(&self.root);
-        let mut current_code = Code::new();
-        for bit in code {
-            match bit {
-                0 => {
-                    if current.borrow().left.is_none() {
-                        current.borrow_mut().left = Some(Rc::new(RefCell::new(Node::new())));
-                    }
-                    let left = current.borrow().left.as_ref().unwrap().clone();
-                    current = left;
-                    current_code.push_bit(bit);
-                    current.borrow_mut().code = current_code;
-                }
-                1 => {
-                    if current.borrow().right.is_none() {
-                        current.borrow_mut().right = Some(Rc::new(RefCell::new(Node::new())));
-                    }
-                    let left = current.borrow().right.as_ref().unwrap().clone();
-                    current = left;
-                    current_code.push_bit(bit);
-                    current.borrow_mut().code = current_code;
-                }
-                _ => {}
+    pub fn insert_code(&mut self, code: Code, value: usize) -> Result<(), PrefixError> {
+        if code.length > MAX_CODE_LENGTH {
+            return Err(PrefixError::InvalidLength(code.length));
+        }
+
+        // Bits are taken straight from the buffer, most significant first,
+        // rather than through Code's iterator.
+        let bit = |depth: u8| code.buffer >> (code.length - 1 - depth) & 1 == 1;
+
+        // Follow the part of the path that already exists. Passing a leaf, or
+        // landing on a node that's already there, is a conflict, and nothing
+        // has been changed yet to undo.
+        let mut current = Self::ROOT;
+        let mut depth = 0;
+        while depth < code.length {
+            let node = &self.nodes[current];
+            if node.value.is_some() {
+                return Err(PrefixError::CodeConflict(value));
             }
+            match if bit(depth) { node.right } else { node.left } {
+                Some(child) => current = child,
+                None => break,
+            }
+            depth += 1;
+        }
+        if depth == code.length {
+            return Err(PrefixError::CodeConflict(value));
         }
-        current.borrow_mut().value = Some(value);
-        current.borrow_mut().code = code;
-        self.current = self.root.clone();
+
+        // Branch nodes are left with an empty code, only the leaf needs one.
+        for depth in depth..code.length {
+            let child = self.nodes.len();
+            self.nodes.push(Node::new());
+            let node = &mut self.nodes[current];
+            if bit(depth) {
+                node.right = Some(child);
+            } else {
+                node.left = Some(child);
+            }
+            current = child;
+        }
+        self.nodes[current].value = Some(value);
+        self.nodes[current].code = code;
+        self.current = Self::ROOT;
+
+        Ok(())
     }
     /// Generates a prefix code tree from the given bit lengths.
     ///
@@ -342,60 +773,32 @@ impl PrefixTree {
     ///
     /// # Returns
     ///
-    /// A new instance of PrefixTree built from the bit lengths provided.
-    pub fn from_lengths(code_lengths: &[u8]) -> Self {
-        // Define an array to hold the amount of times a code length appears.
-        // The index is the code length, and the value at the index is the
-        // number of occurances.
-        let mut occurances = [0u32; 256];
-
-        // Get the higest code length in the array.
-        let max_length = *code_lengths.iter().max().unwrap_or(&0) as usize;
-
-        // Iterates over code_lengths, taking occurances in as acc, and taking
-        // the current iterated value as idx. Then, acc is dereferenced to
-        // directly modify occurances, and it is indexed by idx (the code
-        // length) before being incremented while preventing overflow by
-        // saturating_add. acc is then returned, and the fold operation repeats
-        // until all members of code_lengths have been iterated over.
-        code_lengths.iter().fold(&mut occurances, |acc, &idx| {
-            (*acc)[idx as usize] = (*acc)[idx as usize].saturating_add(1);
-            acc
-        });
-
-        // Intialize next_code and code as zeroes.
-        let mut next_code = vec![0; max_length + 1];
-        let mut code = 0;
-        occurances[0] = 0;
-
-        for i in 1..=max_length {
-            code = (code + occurances[i - 1]) << 1;
-            next_code[i] = code;
+    /// A new instance of PrefixTree built from the bit lengths provided, or
+    /// a PrefixError if a length is too long or the lengths are
+    /// over-subscribed. Incomplete codes are allowed.
+    pub fn from_lengths(code_lengths: &[u8]) -> Result<Self, PrefixError> {
+        if let Some(&length) = code_lengths.iter().find(|&&len| len > MAX_CODE_LENGTH) {
+            return Err(PrefixError::InvalidLength(length));
         }
-
-        let mut codes = vec![None; code_lengths.len()];
-
-        for j in 0..code_lengths.len() {
-            let len = code_lengths[j] as usize;
-            if len != 0 {
-                codes[j] = Some(next_code[len]);
-                next_code[len] += 1;
-            }
+        if kraft(code_lengths) == Ordering::Greater {
+            return Err(PrefixError::OverSubscribed);
         }
 
         let mut tree = PrefixTree::new();
+        // A complete code of n symbols takes 2n - 1 nodes.
+        let used = code_lengths.iter().filter(|&&len| len > 0).count();
+        tree.nodes.reserve((2 * used).saturating_sub(1));
 
-        for (index, code) in codes.iter().enumerate() {
-            if let Some(c) = code {
-                let code_struct = Code::from(c.to_owned(), code_lengths[index]);
-                tree.insert_code(code_struct, index);
+        for (index, code) in canonical_codes(code_lengths).into_iter().enumerate() {
+            if let Some(code) = code {
+                tree.insert_code(code, index)?;
             }
         }
 
-        tree
+        Ok(tree)
     }
     /// Accepts a u8 representing a binary value and walks that direction on
-    /// the tree. Will panic if a non-binary value is given.
+    /// the tree.
     ///
     /// # Arguments
     ///
@@ -405,52 +808,36 @@ impl PrefixTree {
     /// # Returns
     ///
     /// If the node is a branch and does not hold a value, None will be
-    /// returned, otherwise, the value at that leaf will be returned.
+    /// returned, otherwise, the value at that leaf will be returned. Or, a
+    /// PrefixError::InvalidBit if direction isn't 0 or 1.
     ///
     /// # Examples
     ///
     /// '''
     /// let mut tree = PrefixTree::new();
     ///
-    /// tree.insert_code(Code::from(0b111, 3), 255);
+    /// tree.insert_code(Code::from(0b111, 3), 255)?;
     ///
-    /// assert_eq!(tree.walk(1), None);
-    /// assert_eq!(tree.walk(1), None);
-    /// assert_eq!(tree.walk(1), Some(255));
+    /// assert_eq!(tree.walk(1)?, None);
+    /// assert_eq!(tree.walk(1)?, None);
+    /// assert_eq!(tree.walk(1)?, Some(255));
     /// '''
-    pub fn walk(&mut self, direction: u8) -> Option<usize> {
-        assert!(direction < 2);
-
-        match direction {
-            0 => {
-                let tmp = self.current.clone();
-                let tmp_borrow = tmp.borrow();
-                if let Some(left) = tmp_borrow.left.clone() {
-                    self.current = left.clone();
-                    if let Some(value) = self.current.clone().borrow().value {
-                        self.current = self.root.clone();
-                        return Some(value);
-                    } else {
-                        return None;
-                    }
-                }
-            }
-            1 => {
-                let tmp = self.current.clone();
-                let tmp_borrow = tmp.borrow();
-                if let Some(right) = tmp_borrow.right.clone() {
-                    self.current = right.clone();
-                    if let Some(value) = self.current.clone().borrow().value {
-                        self.current = self.root.clone();
-                        return Some(value);
-                    } else {
-                        return None;
-                    }
-                }
+    pub fn walk(&mut self, direction: u8) -> Result<Option<usize>, PrefixError> {
+        let node = &self.nodes[self.current];
+        let next = match direction {
+            0 => node.left,
+            1 => node.right,
+            _ => return Err(PrefixError::InvalidBit(direction)),
+        };
+
+        if let Some(idx) = next {
+            self.current = idx;
+            if let Some(value) = self.nodes[idx].value {
+                self.current = Self::ROOT;
+                return Ok(Some(value));
             }
-            _ => {}
         }
-        None
+        Ok(None)
     }
 }
 
@@ -462,34 +849,321 @@ impl Default for PrefixTree {
 
 impl fmt::Display for PrefixTree {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Branches don't store their codes, so each is built up from the
+        // path taken to reach it.
         fn format_node(
-            node: &Option<Rc<RefCell<Node>>>,
+            tree: &PrefixTree,
+            node: Option<usize>,
+            code: Code,
             prefix: String,
             is_right: bool,
             f: &mut fmt::Formatter<'_>,
         ) -> fmt::Result {
-            if let Some(node) = node {
+            if let Some(idx) = node {
+                let node = &tree.nodes[idx];
                 writeln!(
                     f,
                     "{}{}({}{})",
                     prefix,
                     if is_right { "├── " } else { "└── " },
-                    node.borrow().code,
-                    if let Some(value) = node.borrow().value {
+                    code,
+                    if let Some(value) = node.value {
                         format!(": {}", value)
                     } else {
                         String::new()
                     }
                 )?;
                 let new_prefix = format!("{}{}", prefix, if is_right { "│   " } else { "    " });
-                format_node(&node.borrow().right, new_prefix.clone(), true, f)?;
-                format_node(&node.borrow().left, new_prefix, false, f)?;
+                let (mut right, mut left) = (code, code);
+                right.push(1, 1);
+                left.push(0, 1);
+                format_node(tree, node.right, right, new_prefix.clone(), true, f)?;
+                format_node(tree, node.left, left, new_prefix, false, f)?;
             }
             Ok(())
         }
 
-        writeln!(f, "{}", self.root.borrow())?;
-        format_node(&self.root.borrow().right, String::new(), true, f)?;
-        format_node(&self.root.borrow().left, String::new(), false, f)
+        let root = &self.nodes[Self::ROOT];
+        writeln!(f, "{}", root)?;
+        let (right, left) = (Code::from(1, 1), Code::from(0, 1));
+        format_node(self, root.right, right, String::new(), true, f)?;
+        format_node(self, root.left, left, String::new(), false, f)
+    }
+}
+
+/// A single entry of a DecodeTable.
+///
+/// # Variants
+///
+/// * 'Invalid' - No code maps to this index, only possible when the code
+///         lengths do not form a complete prefix code.
+/// * 'Symbol' - The decoded symbol, along with the length of the code that
+///         produced it, which is how many bits should be consumed.
+/// * 'Link' - Points to a sub-table starting at 'offset', which is indexed by
+///         the 'bits' bits following the root index.
+#[cfg(feature = "inflate")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableEntry {
+    Invalid,
+    Symbol { symbol: u16, length: u8 },
+    Link { offset: u16, bits: u8 },
+}
+
+/// A two level lookup table for decoding prefix codes a whole symbol at a
+/// time instead of walking a tree bit by bit. Codes no longer than
+/// 'root_bits' are resolved by the root table, and longer codes are resolved
+/// by a second lookup into a sub-table shared by every code with the same
+/// root prefix. Both are indexed by upcoming bits in stream order, the first
+/// bit as the least significant, so bits can be looked up as DEFLATE packs
+/// them without reversing every code on the way in.
+///
+/// # Fields
+///
+/// * 'root_bits' - The number of bits used to index the root table.
+/// * 'entries' - The root table followed by all of the sub-tables.
+#[cfg(feature = "inflate")]
+#[derive(Debug, Clone)]
+pub struct DecodeTable {
+    pub root_bits: u8,
+    pub entries: Vec<TableEntry>,
+}
+
+/// Calls f with the symbol, code, and length of every symbol that has a
+/// code, assigning codes in canonical order from first_code. Codes which
+/// don't fit in their length only show up when the code lengths are
+/// over-subscribed, those are skipped instead of overflowing the table, along
+/// with any code longer than DEFLATE allows.
+#[cfg(feature = "inflate")]
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+fn for_each_code<F: FnMut(usize, u32, u8)>(
+    code_lengths: &[u8],
+    mut first_code: [u32; MAX_CODE_LENGTH as usize + 1],
+    mut f: F,
+) {
+    for (symbol, &length) in code_lengths.iter().enumerate() {
+        if !(1..=MAX_CODE_LENGTH).contains(&length) {
+            continue;
+        }
+
+        let Some(next) = first_code.get_mut(length as usize) else {
+            continue;
+        };
+        let code = *next;
+        *next += 1;
+
+        if code >> length == 0 {
+            f(symbol, code, length);
+        }
+    }
+}
+
+/// Returns the length least significant bits of code in reverse order, which
+/// turns a prefix code into the order it appears in the stream.
+#[cfg(feature = "inflate")]
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+fn reverse(code: u32, length: u8) -> usize {
+    code.reverse_bits()
+        .checked_shr(32 - length as u32)
+        .unwrap_or(0) as usize
+}
+
+#[cfg(feature = "inflate")]
+#[deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+impl DecodeTable {
+    /// Generates a lookup table from the given code lengths.
+    ///
+    /// # Arguments
+    ///
+    /// * 'code_lengths' - An array of u8 values representing the number of
+    ///         bits in the code for each symbol, the symbol is the index of
+    ///         the value.
+    /// * 'root_bits' - The maximum number of bits to index the root table
+    ///         with. Will be lowered to the longest code length if possible.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of DecodeTable built from the lengths provided.
+    pub fn from_lengths(code_lengths: &[u8], root_bits: u8) -> Self {
+        let mut table = Self {
+            root_bits: 0,
+            entries: Vec::new(),
+        };
+        table.rebuild(code_lengths, root_bits);
+        table
+    }
+    /// Replaces the table with one built from the given code lengths, reusing
+    /// the memory already allocated for its entries.
+    ///
+    /// # Arguments
+    ///
+    /// * 'code_lengths' - An array of u8 values representing the number of
+    ///         bits in the code for each symbol, the symbol is the index of
+    ///         the value.
+    /// * 'root_bits' - The maximum number of bits to index the root table
+    ///         with. Will be lowered to the longest code length if possible.
+    pub fn rebuild(&mut self, code_lengths: &[u8], root_bits: u8) {
+        let max_length = code_lengths.iter().max().copied().unwrap_or(0);
+        let root_bits = root_bits.min(max_length).clamp(1, MAX_CODE_LENGTH);
+
+        // The first code of each length, found the same way as in
+        // canonical_codes but without allocating.
+        let mut occurances = [0u32; MAX_CODE_LENGTH as usize + 1];
+        for &length in code_lengths {
+            if let Some(count) = occurances.get_mut(length as usize).filter(|_| length > 0) {
+                *count += 1;
+            }
+        }
+
+        let mut first_code = [0u32; MAX_CODE_LENGTH as usize + 1];
+        let mut code = 0;
+        for (first, &count) in first_code.iter_mut().skip(1).zip(&occurances) {
+            code = (code + count) << 1;
+            *first = code;
+        }
+
+        self.root_bits = root_bits;
+        self.entries.clear();
+        self.entries.resize(1 << root_bits, TableEntry::Invalid);
+
+        // Size the sub-table for each root prefix by the longest code
+        // sharing that prefix, keeping the size in the root entry until the
+        // sub-tables are laid out.
+        for_each_code(code_lengths, first_code, |_, code, length| {
+            if length > root_bits {
+                let prefix = reverse(code >> (length - root_bits), root_bits);
+                if let Some(entry) = self.entries.get_mut(prefix) {
+                    let bits = match *entry {
+                        TableEntry::Link { bits, .. } => bits,
+                        _ => 0,
+                    };
+                    *entry = TableEntry::Link {
+                        offset: 0,
+                        bits: bits.max(length - root_bits),
+                    };
+                }
+            }
+        });
+
+        let mut offset = self.entries.len();
+        for entry in self.entries.iter_mut() {
+            if let TableEntry::Link { bits, .. } = *entry {
+                *entry = TableEntry::Link {
+                    offset: offset as u16,
+                    bits,
+                };
+                offset += 1 << bits;
+            }
+        }
+        self.entries.resize(offset, TableEntry::Invalid);
+
+        // Every code fills each index that starts with it, which with the
+        // first bit lowest are those a multiple of 1 << length apart.
+        for_each_code(code_lengths, first_code, |symbol, code, length| {
+            let entry = TableEntry::Symbol {
+                symbol: symbol as u16,
+                length,
+            };
+
+            if length <= root_bits {
+                let table = self.entries.get_mut(..1 << root_bits).unwrap_or_default();
+                for index in table
+                    .iter_mut()
+                    .skip(reverse(code, length))
+                    .step_by(1 << length)
+                {
+                    *index = entry;
+                }
+            } else {
+                let extra = length - root_bits;
+                let prefix = reverse(code >> extra, root_bits);
+                if let Some(&TableEntry::Link { offset, bits }) = self.entries.get(prefix) {
+                    let start = offset as usize;
+                    let table = self
+                        .entries
+                        .get_mut(start..start + (1 << bits))
+                        .unwrap_or_default();
+                    let low = reverse(code & ((1 << extra) - 1), extra);
+                    for index in table.iter_mut().skip(low).step_by(1 << extra) {
+                        *index = entry;
+                    }
+                }
+            }
+        });
+    }
+    /// Finds the entry for the code at the start of the given bits.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bits' - At least the next MAX_CODE_LENGTH bits of the stream, with
+    ///         the first bit as the least significant. Any bits above those
+    ///         are ignored.
+    ///
+    /// # Returns
+    ///
+    /// Either TableEntry::Symbol holding the decoded symbol and the length of
+    /// its code, or TableEntry::Invalid if no code matches the bits.
+    #[inline]
+    pub fn lookup(&self, bits: u64) -> TableEntry {
+        let root = bits & ((1 << self.root_bits) - 1);
+
+        // Both indices are masked to within the tables rebuild laid out, so
+        // the fallbacks are never taken.
+        match self.entry(root as usize) {
+            TableEntry::Link {
+                offset,
+                bits: sub_bits,
+            } => {
+                let index = (bits >> self.root_bits) & ((1 << sub_bits) - 1);
+                self.entry(offset as usize + index as usize)
+            }
+            entry => entry,
+        }
+    }
+    /// Returns the entry at index, or TableEntry::Invalid past the end.
+    #[inline]
+    fn entry(&self, index: usize) -> TableEntry {
+        self.entries
+            .get(index)
+            .copied()
+            .unwrap_or(TableEntry::Invalid)
+    }
+    /// Decodes the next symbol from the bitstream, consuming only the bits
+    /// that made up its code.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bitstream' - The BitVector64 to read the code from.
+    ///
+    /// # Returns
+    ///
+    /// The decoded symbol, or None if the upcoming bits are not a valid code
+    /// or the bitstream ends partway through one.
+    pub fn decode(&self, bitstream: &mut BitVector64) -> Option<usize> {
+        let bits = bitstream.peek_bits(MAX_CODE_LENGTH).reverse_bits() >> (64 - MAX_CODE_LENGTH);
+        match self.lookup(bits) {
+            TableEntry::Symbol { symbol, length } if length as usize <= bitstream.remaining() => {
+                bitstream.consume(length as usize);
+                Some(symbol as usize)
+            }
+            _ => None,
+        }
     }
 }
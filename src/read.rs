@@ -0,0 +1,233 @@
+//! Decompressing readers.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::{
+    bufread::{self, Progress, ProgressHook},
+    gzip::{GzipError, GzipHeader, HeaderOptions},
+    inflate::{BlockInfo, MemoryUsage},
+};
+
+/// A reader that decompresses a gzip file as it is read from, pulling
+/// compressed bytes from the inner reader only as they're needed. The inner
+/// reader is buffered, a reader that already is can use
+/// bufread::GzDecoder directly.
+pub struct GzDecoder<R: Read> {
+    inner: bufread::GzDecoder<BufReader<R>>,
+}
+
+impl<R: Read> GzDecoder<R> {
+    /// Wraps a reader holding a gzip file. Nothing is read until the first
+    /// call to read.
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: bufread::GzDecoder::new(BufReader::new(reader)),
+        }
+    }
+    /// Returns the file's header, or None if it hasn't been read yet.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.inner.header()
+    }
+    /// Sets the HeaderOptions the header is read with, see
+    /// bufread::GzDecoder::set_header_options.
+    ///
+    /// # Arguments
+    ///
+    /// * 'options' - The HeaderOptions to read the header with.
+    pub fn set_header_options(&mut self, options: HeaderOptions) {
+        self.inner.set_header_options(options);
+    }
+    /// Limits how large the file can decompress to, see
+    /// bufread::GzDecoder::set_max_output_size.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to decompress, or None for no limit.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.inner.set_max_output_size(limit);
+    }
+    /// Limits how many times larger than the compressed data the output can
+    /// be, see bufread::GzDecoder::set_max_expansion_ratio.
+    ///
+    /// # Arguments
+    ///
+    /// * 'ratio' - The most bytes of output per byte of input, or None for
+    ///         no limit.
+    pub fn set_max_expansion_ratio(&mut self, ratio: Option<u64>) {
+        self.inner.set_max_expansion_ratio(ratio);
+    }
+    /// Limits how much memory the inflater can allocate, see
+    /// bufread::GzDecoder::set_memory_limit.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to allocate, or None for no limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.inner.set_memory_limit(limit);
+    }
+    /// Keeps the last few events decoded and attaches them to any error,
+    /// see bufread::GzDecoder::set_history.
+    ///
+    /// # Arguments
+    ///
+    /// * 'len' - How many events to keep, or None to keep none.
+    pub fn set_history(&mut self, len: Option<usize>) {
+        self.inner.set_history(len);
+    }
+    /// Sets whether a BlockInfo is kept for every block, see
+    /// bufread::GzDecoder::set_record_blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * 'record' - Whether to record blocks.
+    pub fn set_record_blocks(&mut self, record: bool) {
+        self.inner.set_record_blocks(record);
+    }
+    /// Returns every block that has been read so far.
+    pub fn blocks(&self) -> &[BlockInfo] {
+        self.inner.blocks()
+    }
+    /// Returns how much heap memory the decoder has allocated, see
+    /// bufread::GzDecoder::memory_usage, along with the buffer between it
+    /// and the inner reader.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.inner.memory_usage();
+        usage.buffers += self.inner.get_ref().capacity();
+        usage
+    }
+    /// Sets a flag that cancels decompressing, see
+    /// bufread::GzDecoder::set_cancel.
+    ///
+    /// # Arguments
+    ///
+    /// * 'cancel' - The flag to check, or None to never be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.inner.set_cancel(cancel);
+    }
+    /// Sets a hook to be called with the decoder's Progress at the end of
+    /// every read, see bufread::GzDecoder::set_progress.
+    ///
+    /// # Arguments
+    ///
+    /// * 'hook' - The hook to call, or None to stop reporting progress.
+    pub fn set_progress(&mut self, hook: Option<ProgressHook>) {
+        self.inner.set_progress(hook);
+    }
+    /// Returns how far through the file decompression is.
+    pub fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+    /// Saves where the decoder is in the file, see
+    /// bufread::GzDecoder::save_state. The inner reader may have been read
+    /// past that point, it's progress().total_in that says where to carry on
+    /// from.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.inner.save_state()
+    }
+    /// Creates a decoder from a state saved by save_state, see
+    /// bufread::GzDecoder::restore_state.
+    ///
+    /// # Arguments
+    ///
+    /// * 'reader' - The file, positioned progress().total_in bytes in, as it
+    ///         was when the state was saved.
+    /// * 'state' - The saved state.
+    ///
+    /// # Returns
+    ///
+    /// The restored decoder, or a GzipError if state wasn't written by
+    /// save_state.
+    pub fn restore_state(reader: R, state: &[u8]) -> Result<Self, GzipError> {
+        Ok(Self {
+            inner: bufread::GzDecoder::restore_state(BufReader::new(reader), state)?,
+        })
+    }
+    /// Decompresses the rest of the member into a writer in large batches,
+    /// see bufread::GzDecoder::copy_to.
+    ///
+    /// # Arguments
+    ///
+    /// * 'writer' - The writer to write the decompressed data to.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written, or the io::Error from reading or
+    /// writing.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<u64> {
+        self.inner.copy_to(writer)
+    }
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// A reader that decompresses every member of a gzip file one after the
+/// other, buffering the inner reader, see bufread::MultiGzDecoder. Wrapped
+/// around a .tar.gz, it's what a tar reader should read from.
+pub struct MultiGzDecoder<R: Read> {
+    inner: bufread::MultiGzDecoder<BufReader<R>>,
+}
+
+impl<R: Read> MultiGzDecoder<R> {
+    /// Wraps a reader holding a gzip file of any number of members.
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: bufread::MultiGzDecoder::new(BufReader::new(reader)),
+        }
+    }
+    /// Returns the header of the member being decompressed, or None if it
+    /// hasn't been read yet.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.inner.header()
+    }
+    /// Returns how far through the file decompression is.
+    pub fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+    /// Returns how much heap memory the decoder has allocated, see
+    /// bufread::MultiGzDecoder::memory_usage, and the inner reader's
+    /// buffer.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.inner.memory_usage();
+        usage.buffers += self.inner.get_ref().capacity();
+        usage
+    }
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+}
+
+impl<R: Read> Read for MultiGzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Read> BufRead for MultiGzDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
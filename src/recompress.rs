@@ -0,0 +1,377 @@
+//! Decoding a gzip or zlib stream and encoding its data again, in one call,
+//! or moving a DEFLATE stream to a different container as it is.
+//!
+//! recompress never holds the data all at once: it's decoded a chunk at a
+//! time and each chunk handed straight to the encoder, so only the output
+//! and the encoder's window are kept, making it fit for squeezing down
+//! large files in storage. convert doesn't encode at all, it copies the
+//! stream between headers and trailers, decoding it once to find where it
+//! ends and to compute the checksum the new trailer needs.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    adler,
+    bufread::GzDecoder,
+    crc,
+    deflate::{CompressOptions, StreamCompressor},
+    gzip::{verify_trailer, GzipError, GzipHeader},
+    inflate::{try_zeroed, DeflateError, Inflater, Status},
+    zlib::{self, ZlibError, ZlibHeader},
+};
+
+/// The size of the buffer convert inflates into, which is thrown away.
+const SCRATCH_SIZE: usize = 32768;
+
+/// The framing around a DEFLATE stream.
+///
+/// # Variants
+///
+/// * 'Gzip' - A gzip member, RFC 1952, ending with a CRC-32 and the size.
+/// * 'Zlib' - A zlib stream, RFC 1950, ending with an Adler-32.
+/// * 'Raw' - The DEFLATE stream alone, with no header or checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Container {
+    #[default]
+    Gzip,
+    Zlib,
+    Raw,
+}
+
+/// How to encode the data again.
+///
+/// # Fields
+///
+/// * 'container' - The framing to write. A gzip output is a single member
+///         with a default header, whatever the input's members were.
+/// * 'compress' - How to find matches and split blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecompressOptions {
+    pub container: Container,
+    pub compress: CompressOptions,
+}
+
+/// An error from recompress or convert.
+///
+/// # Members
+///
+/// * 'UnknownContainer' - Used when the input starts with neither the gzip
+///             magic bytes nor a valid zlib header. Contains the first
+///             two bytes there were.
+/// * 'GzipError' - Wrapper for a GzipError raised decoding a gzip input.
+/// * 'ZlibError' - Wrapper for a ZlibError raised decoding a zlib input.
+/// * 'DeflateError' - Wrapper for a DeflateError raised decoding a raw
+///             DEFLATE input.
+/// * 'TrailingData' - Used when convert finds bytes after the input's
+///             trailer, such as a second gzip member, which a single stream
+///             can't carry without re-encoding. Contains how many there are.
+#[derive(Debug)]
+pub enum RecompressError {
+    UnknownContainer(Vec<u8>),
+    GzipError(GzipError),
+    ZlibError(ZlibError),
+    DeflateError(DeflateError),
+    TrailingData(usize),
+}
+
+impl Display for RecompressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecompressError::UnknownContainer(bytes) => {
+                write!(
+                    f,
+                    "Error: Bytes {:?} start neither a gzip file nor a zlib stream",
+                    bytes
+                )
+            }
+            RecompressError::GzipError(err) => {
+                write!(f, "Error: Decoding the gzip input raised the {}", err)
+            }
+            RecompressError::ZlibError(err) => {
+                write!(f, "Error: Decoding the zlib input raised the {}", err)
+            }
+            RecompressError::DeflateError(err) => {
+                write!(f, "Error: Decoding the DEFLATE input raised the {}", err)
+            }
+            RecompressError::TrailingData(len) => {
+                write!(f, "Error: {} bytes follow the end of the input", len)
+            }
+        }
+    }
+}
+
+impl Error for RecompressError {}
+
+impl From<GzipError> for RecompressError {
+    fn from(err: GzipError) -> Self {
+        RecompressError::GzipError(err)
+    }
+}
+
+impl From<ZlibError> for RecompressError {
+    fn from(err: ZlibError) -> Self {
+        RecompressError::ZlibError(err)
+    }
+}
+
+/// Decodes a gzip file or zlib stream and compresses its data again.
+///
+/// # Arguments
+///
+/// * 'input' - A gzip file, any number of members, or a zlib stream with
+///         no preset dictionary. Which it is comes from its first bytes.
+/// * 'options' - The container and compression to encode with.
+///
+/// # Returns
+///
+/// The re-encoded data, or a RecompressError if the input couldn't be
+/// decoded.
+pub fn recompress(input: &[u8], options: &RecompressOptions) -> Result<Vec<u8>, RecompressError> {
+    let mut encoder = Encoder::new(options);
+
+    if input.starts_with(&[0x1f, 0x8b]) {
+        decompress_members(input, &mut encoder)?;
+    } else if ZlibHeader::build(input).is_ok() {
+        zlib::decompress_to_sink(input, |chunk| encoder.push(chunk))?;
+    } else {
+        let start = input.get(..2).unwrap_or(input);
+        return Err(RecompressError::UnknownContainer(start.to_vec()));
+    }
+
+    Ok(encoder.finish())
+}
+
+/// Moves a DEFLATE stream from one container to another without
+/// re-encoding it. The stream is decoded once, into a buffer that's thrown
+/// away, to find its end, check the input's own checksum, and compute the
+/// one the output needs. A gzip input's header is kept if the output is
+/// gzip too, and likewise for zlib.
+///
+/// # Arguments
+///
+/// * 'input' - A single gzip member, zlib stream with no preset dictionary,
+///         or raw DEFLATE stream.
+/// * 'from' - Which of those input is.
+/// * 'to' - The container to move the stream to.
+///
+/// # Returns
+///
+/// The stream in its new container, or a RecompressError, the wrapped
+/// error matching from.
+pub fn convert(input: &[u8], from: Container, to: Container) -> Result<Vec<u8>, RecompressError> {
+    let (header, trailer_len) = match from {
+        Container::Gzip => (input.get(..GzipHeader::build(input)?.end_idx), 8),
+        Container::Zlib => {
+            let header = ZlibHeader::build(input)?;
+            if let Some(id) = header.dictionary_id {
+                return Err(ZlibError::PresetDictionary(id).into());
+            }
+            (input.get(..header.end_idx), 4)
+        }
+        Container::Raw => (Some(&[][..]), 0),
+    };
+    let header = header.unwrap_or_default();
+    let body = input.get(header.len()..).unwrap_or_default();
+
+    let checksums = inflate_checksums(body).map_err(|err| from.error(err))?;
+    let stream = body.get(..checksums.stream_len).unwrap_or_default();
+    let rest = body.get(checksums.stream_len..).unwrap_or_default();
+
+    let Some(trailer) = rest.get(..trailer_len) else {
+        return Err(from.error(DeflateError::UnexpectedEofError { bit_offset: 0 }));
+    };
+    match *trailer {
+        [c0, c1, c2, c3, i0, i1, i2, i3] => {
+            let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+            let isize = u32::from_le_bytes([i0, i1, i2, i3]);
+            verify_trailer(crc32, isize, checksums.crc, checksums.len)
+                .map_err(|err| from.error(err))?;
+        }
+        [a0, a1, a2, a3] => {
+            let expected = u32::from_be_bytes([a0, a1, a2, a3]);
+            if expected != checksums.adler {
+                return Err(ZlibError::ChecksumMismatch {
+                    expected,
+                    actual: checksums.adler,
+                }
+                .into());
+            }
+        }
+        _ => {}
+    }
+    if rest.len() > trailer_len {
+        return Err(RecompressError::TrailingData(rest.len() - trailer_len));
+    }
+
+    let mut output = match (to, from) {
+        (Container::Gzip, Container::Gzip) | (Container::Zlib, Container::Zlib) => header.to_vec(),
+        (Container::Gzip, _) => GzipHeader::default().to_bytes(),
+        (Container::Zlib, _) => zlib::HEADER.to_vec(),
+        (Container::Raw, _) => Vec::new(),
+    };
+    output.extend_from_slice(stream);
+    match to {
+        Container::Gzip => {
+            output.extend(checksums.crc.to_le_bytes());
+            output.extend((checksums.len as u32).to_le_bytes());
+        }
+        Container::Zlib => output.extend(checksums.adler.to_be_bytes()),
+        Container::Raw => {}
+    }
+
+    Ok(output)
+}
+
+impl Container {
+    /// Wraps a DeflateError in the error for this container's input.
+    fn error(self, err: DeflateError) -> RecompressError {
+        match self {
+            Container::Gzip => GzipError::from(err).into(),
+            Container::Zlib => ZlibError::from(err).into(),
+            Container::Raw => RecompressError::DeflateError(err),
+        }
+    }
+}
+
+/// What inflate_checksums found decoding a stream.
+///
+/// # Fields
+///
+/// * 'stream_len' - How many bytes long the stream is.
+/// * 'crc' - The CRC-32 of the data.
+/// * 'adler' - The Adler-32 of the data.
+/// * 'len' - The size of the data.
+struct Checksums {
+    stream_len: usize,
+    crc: u32,
+    adler: u32,
+    len: u64,
+}
+
+/// Inflates a DEFLATE stream, anything after its end ignored, computing
+/// both checksums of the data.
+fn inflate_checksums(stream: &[u8]) -> Result<Checksums, DeflateError> {
+    let mut inflater = Inflater::new();
+    let mut scratch = try_zeroed(SCRATCH_SIZE)?;
+    let mut checksums = Checksums {
+        stream_len: 0,
+        crc: 0,
+        adler: adler::hash(&[]),
+        len: 0,
+    };
+
+    loop {
+        let input = stream.get(checksums.stream_len..).unwrap_or_default();
+        let (consumed, produced, status) = inflater.inflate(input, &mut scratch)?;
+        checksums.stream_len += consumed;
+
+        let data = scratch.get(..produced).unwrap_or_default();
+        checksums.crc = crc::update(checksums.crc, data);
+        checksums.adler = adler::update(checksums.adler, data);
+        checksums.len += produced as u64;
+
+        match status {
+            Status::StreamEnd => return Ok(checksums),
+            Status::NeedsInput => return Err(inflater.unexpected_eof()),
+            Status::NeedsOutput | Status::Pending => {}
+        }
+    }
+}
+
+/// Decompresses every member of a gzip file into encoder.
+fn decompress_members(bytes: &[u8], encoder: &mut Encoder) -> Result<(), GzipError> {
+    let mut decoder = GzDecoder::new(bytes);
+
+    loop {
+        decoder.copy_to(encoder)?;
+
+        if decoder.get_mut().fill_buf()?.is_empty() {
+            return Ok(());
+        }
+        decoder.reset();
+    }
+}
+
+/// Compresses data into a container as it's pushed.
+///
+/// # Fields
+///
+/// * 'container' - The framing being written.
+/// * 'compressor' - The DEFLATE stream.
+/// * 'output' - Everything encoded so far, starting with the header.
+/// * 'checksum' - The container's checksum of the data so far, a CRC-32
+///         or Adler-32, or nothing for a raw stream.
+/// * 'len' - The number of bytes pushed.
+struct Encoder {
+    container: Container,
+    compressor: StreamCompressor,
+    output: Vec<u8>,
+    checksum: u32,
+    len: u64,
+}
+
+impl Encoder {
+    fn new(options: &RecompressOptions) -> Self {
+        let (output, checksum) = match options.container {
+            Container::Gzip => (GzipHeader::default().to_bytes(), 0),
+            Container::Zlib => (zlib::HEADER.to_vec(), adler::hash(&[])),
+            Container::Raw => (Vec::new(), 0),
+        };
+
+        Self {
+            container: options.container,
+            compressor: StreamCompressor::new(options.compress),
+            output,
+            checksum,
+            len: 0,
+        }
+    }
+    fn push(&mut self, data: &[u8]) {
+        self.checksum = match self.container {
+            Container::Gzip => crc::update(self.checksum, data),
+            Container::Zlib => adler::update(self.checksum, data),
+            Container::Raw => 0,
+        };
+        self.len += data.len() as u64;
+        let stream = self.compressor.write(data);
+        self.output.extend(stream);
+    }
+    /// Ends the stream and writes the trailer.
+    fn finish(mut self) -> Vec<u8> {
+        let stream = self.compressor.compress(true);
+        self.output.extend(stream);
+
+        match self.container {
+            Container::Gzip => {
+                self.output.extend(self.checksum.to_le_bytes());
+                self.output.extend((self.len as u32).to_le_bytes());
+            }
+            Container::Zlib => self.output.extend(self.checksum.to_be_bytes()),
+            Container::Raw => {}
+        }
+        self.output
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! A slow inflater meant to be obviously correct rather than fast, to test
+//! Inflater against.
+//!
+//! It follows RFC 1951 step by step the way this crate first did: the
+//! stream is read into a BitVector64, and each prefix code is decoded a bit
+//! at a time by walking a PrefixTree, where Inflater looks several bits up
+//! at once in a DecodeTable and works on input that arrives in pieces. It
+//! checks streams by the same rules, so the two should agree on every
+//! input, the data they decode it to and whether it's valid at all.
+//!
+//! Only built with the test-utils feature.
+use std::cmp::Ordering;
+
+use crate::{
+    bits::BitVector64,
+    prefix::{
+        kraft, PrefixTree, CL_CODES, CL_CODE_ORDER, DISTANCE_BASE, DISTANCE_EXTRA_BITS,
+        FIXED_CODE_LENGTHS, HCLEN_BASE, HDIST_BASE, HLIT_BASE, LENGTH_BASE, LENGTH_EXTRA_BITS,
+        MAX_CODE_LENGTH, MAX_HDIST, MAX_HLIT,
+    },
+    stream::DeflateError,
+};
+
+/// Decompresses a raw DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its final block is
+///         ignored.
+///
+/// # Returns
+///
+/// The decompressed data, or a DeflateError if the stream is invalid.
+pub fn inflate(stream: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut bits = BitVector64::from_be_bytes(stream);
+    let mut output = Vec::new();
+
+    loop {
+        let bfinal = read(&mut bits, 1)?;
+
+        match read(&mut bits, 2)? {
+            0 => stored(&mut bits, &mut output)?,
+            1 => {
+                let mut literals = tree(&FIXED_CODE_LENGTHS)?;
+                // 30 and 31 are in the fixed code but aren't distances.
+                let mut distances = tree(&[5; 32])?;
+                codes(&mut bits, &mut literals, &mut distances, &mut output)?;
+            }
+            2 => {
+                let (mut literals, mut distances) = dynamic_trees(&mut bits)?;
+                codes(&mut bits, &mut literals, &mut distances, &mut output)?;
+            }
+            _ => return Err(DeflateError::InvalidBlockError("Invalid BTYPE.")),
+        }
+
+        if bfinal == 1 {
+            return Ok(output);
+        }
+    }
+}
+
+/// Reads an n bit integer, or fails if the stream ends first.
+fn read(bits: &mut BitVector64, n: u8) -> Result<u64, DeflateError> {
+    bits.read_bits(n).ok_or(DeflateError::UnexpectedEofError {
+        bit_offset: bits.idx as u64,
+    })
+}
+
+/// Builds the tree for a set of code lengths.
+fn tree(lengths: &[u8]) -> Result<PrefixTree, DeflateError> {
+    PrefixTree::from_lengths(lengths).map_err(|_| {
+        DeflateError::InvalidBlockError("Dynamic block's code lengths can't be built into a tree.")
+    })
+}
+
+/// Decodes a symbol by walking tree one bit at a time from its root.
+fn decode(bits: &mut BitVector64, tree: &mut PrefixTree) -> Result<usize, DeflateError> {
+    tree.current = PrefixTree::ROOT;
+
+    for _ in 0..MAX_CODE_LENGTH {
+        let bit = bits.next().ok_or(DeflateError::UnexpectedEofError {
+            bit_offset: bits.idx as u64,
+        })?;
+        // The bits come from a BitVector64, so are always 0 or 1.
+        if let Ok(Some(symbol)) = tree.walk(bit) {
+            return Ok(symbol);
+        }
+    }
+
+    Err(DeflateError::DecompressionError(
+        "Bitstream held an invalid prefix code.",
+    ))
+}
+
+/// Copies out a stored block, which starts at the next byte boundary.
+fn stored(bits: &mut BitVector64, output: &mut Vec<u8>) -> Result<(), DeflateError> {
+    bits.consume((8 - bits.idx % 8) % 8);
+
+    let len = read(bits, 16)? as u16;
+    let nlen = read(bits, 16)? as u16;
+    if len != !nlen {
+        return Err(DeflateError::InvalidBlockError(
+            "BTYPE is 0, but NLEN is not the bitwise complement to LEN.",
+        ));
+    }
+
+    for _ in 0..len {
+        output.push(read(bits, 8)? as u8);
+    }
+    Ok(())
+}
+
+/// Decodes literals and matches until the end of block symbol.
+fn codes(
+    bits: &mut BitVector64,
+    literals: &mut PrefixTree,
+    distances: &mut PrefixTree,
+    output: &mut Vec<u8>,
+) -> Result<(), DeflateError> {
+    loop {
+        let symbol = decode(bits, literals)?;
+
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let (Some(&base), Some(&extra)) = (
+            LENGTH_BASE.get(symbol - 257),
+            LENGTH_EXTRA_BITS.get(symbol - 257),
+        ) else {
+            return Err(DeflateError::InvalidSymbolError(
+                symbol,
+                "Literal/length codes 286 and 287 don't represent a length.",
+            ));
+        };
+        let length = base as usize + read(bits, extra)? as usize;
+
+        let symbol = decode(bits, distances)?;
+        let (Some(&base), Some(&extra)) =
+            (DISTANCE_BASE.get(symbol), DISTANCE_EXTRA_BITS.get(symbol))
+        else {
+            return Err(DeflateError::InvalidSymbolError(
+                symbol,
+                "Distance codes 30 and 31 don't represent a distance.",
+            ));
+        };
+        let distance = base as usize + read(bits, extra)? as usize;
+
+        if distance > output.len() {
+            return Err(DeflateError::InvalidDistanceError {
+                distance,
+                output_len: output.len() as u64,
+            });
+        }
+        // A byte at a time, so a match can overlap the bytes it's copying.
+        for _ in 0..length {
+            output.push(output[output.len() - distance]);
+        }
+    }
+}
+
+/// Reads a dynamic block's code lengths and builds its literal/length and
+/// distance trees.
+fn dynamic_trees(bits: &mut BitVector64) -> Result<(PrefixTree, PrefixTree), DeflateError> {
+    let hlit = read(bits, 5)? as usize + HLIT_BASE;
+    let hdist = read(bits, 5)? as usize + HDIST_BASE;
+    let hclen = read(bits, 4)? as usize + HCLEN_BASE;
+    if hlit > MAX_HLIT || hdist > MAX_HDIST {
+        return Err(DeflateError::InvalidBlockError(
+            "HLIT or HDIST is larger than the number of codes in the alphabet.",
+        ));
+    }
+
+    let mut code_length_lengths = [0; CL_CODES];
+    for &symbol in &CL_CODE_ORDER[..hclen] {
+        code_length_lengths[symbol] = read(bits, 3)? as u8;
+    }
+    check(&code_length_lengths, false)?;
+    let mut code_length_tree = tree(&code_length_lengths)?;
+
+    let mut lengths: Vec<u8> = Vec::new();
+    while lengths.len() < hlit + hdist {
+        let (repeat, length) = match decode(bits, &mut code_length_tree)? {
+            symbol @ 0..16 => (1, symbol as u8),
+            16 => {
+                let Some(&previous) = lengths.last() else {
+                    return Err(DeflateError::InvalidBlockError(
+                        "Code length 16 repeats the previous length, but there isn't one.",
+                    ));
+                };
+                (3 + read(bits, 2)? as usize, previous)
+            }
+            17 => (3 + read(bits, 3)? as usize, 0),
+            _ => (11 + read(bits, 7)? as usize, 0),
+        };
+
+        if lengths.len() + repeat > hlit + hdist {
+            return Err(DeflateError::InvalidBlockError(
+                "Code length repeats past the number of codes.",
+            ));
+        }
+        lengths.resize(lengths.len() + repeat, length);
+    }
+
+    let (literal_lengths, distance_lengths) = lengths.split_at(hlit);
+    if literal_lengths[256] == 0 {
+        return Err(DeflateError::InvalidBlockError(
+            "Dynamic block has no code for the end of block symbol.",
+        ));
+    }
+    check(literal_lengths, true)?;
+    check(distance_lengths, true)?;
+
+    Ok((tree(literal_lengths)?, tree(distance_lengths)?))
+}
+
+/// Checks code lengths make a complete prefix code, or, if allow_single is
+/// set, have no code longer than one bit, which is how a block with only
+/// one symbol, or no distances, is coded.
+fn check(lengths: &[u8], allow_single: bool) -> Result<(), DeflateError> {
+    match kraft(lengths) {
+        Ordering::Greater => Err(DeflateError::InvalidBlockError(
+            "Dynamic block's code is over-subscribed.",
+        )),
+        Ordering::Less if !(allow_single && lengths.iter().all(|&length| length <= 1)) => Err(
+            DeflateError::InvalidBlockError("Dynamic block's code is incomplete."),
+        ),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,185 @@
+//! Machine readable metadata for gzip files, for archive inventories and
+//! other tools that would rather not parse our text output.
+//!
+//! Needs the json feature. The report describes every member: its header
+//! fields, trailer, sizes, and where each of its blocks sits in the
+//! stream.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use serde_json::{json, Value};
+
+use crate::{
+    crc,
+    gzip::{GzipError, GzipHeader},
+    inflate::{BlockInfo, BlockType, DeflateError, Inflater, Status},
+};
+
+/// The size of the scratch buffer decompressed bytes are written to.
+const OUTPUT_CHUNK: usize = 32768;
+
+/// Builds a report of every member of a gzip file.
+///
+/// It takes the form:
+///
+/// ```text
+/// {
+///   "size": 1234,
+///   "members": [{
+///     "offset": 0,
+///     "header": { "mtime": 0, "os": 3, "fname": "a.txt", ... },
+///     "compressed_size": 1200,
+///     "uncompressed_size": 4096,
+///     "crc32": 3735928559,
+///     "crc32_ok": true,
+///     "isize": 4096,
+///     "blocks": [{ "type": "dynamic", "final": true, ... }]
+///   }]
+/// }
+/// ```
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+///
+/// # Returns
+///
+/// The report, or a GzipError if the file is invalid.
+pub fn report(bytes: &[u8]) -> Result<Value, GzipError> {
+    let mut members = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let rest = bytes.get(offset..).unwrap_or_default();
+        let header = GzipHeader::build(rest)?;
+        let (used, len, crc, blocks) =
+            inflate_member(rest.get(header.end_idx..).unwrap_or_default())?;
+
+        let trailer_start = header.end_idx + used;
+        let Some(&[c0, c1, c2, c3, i0, i1, i2, i3]) = rest
+            .get(trailer_start..)
+            .and_then(|trailer| trailer.first_chunk::<8>())
+        else {
+            return Err(DeflateError::UnexpectedEofError {
+                bit_offset: used as u64 * 8,
+            }
+            .into());
+        };
+        let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+        let isize = u32::from_le_bytes([i0, i1, i2, i3]);
+
+        members.push(json!({
+            "offset": offset,
+            "header": header_json(&header),
+            "compressed_size": trailer_start + 8,
+            "uncompressed_size": len,
+            "crc32": crc32,
+            "crc32_ok": crc32 == crc && isize == len as u32,
+            "isize": isize,
+            "blocks": blocks.iter().map(block_json).collect::<Vec<_>>(),
+        }));
+
+        offset += trailer_start + 8;
+    }
+
+    Ok(json!({
+        "size": bytes.len(),
+        "members": members,
+    }))
+}
+
+/// Like report, but returns the report as pretty printed JSON.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+///
+/// # Returns
+///
+/// The JSON text, or a GzipError if the file is invalid.
+pub fn to_json(bytes: &[u8]) -> Result<String, GzipError> {
+    let report = report(bytes)?;
+    // A Value only holds what JSON can represent, so this can't fail.
+    Ok(serde_json::to_string_pretty(&report).unwrap_or_default())
+}
+
+/// Returns the fields of a header as JSON.
+fn header_json(header: &GzipHeader) -> Value {
+    let extra = header.extra_fields().map(|(si1, si2, data)| {
+        json!({
+            "id": String::from_utf8_lossy(&[si1, si2]),
+            "len": data.len(),
+        })
+    });
+
+    json!({
+        "cm": header.cm,
+        "ftext": header.flg[0],
+        "mtime": header.mtime,
+        "xfl": header.xfl,
+        "os": header.os,
+        "crc16": header.crc,
+        "extra": header.fextra.as_ref().map(|_| extra.collect::<Vec<_>>()),
+        "fname": header.fname,
+        "fcomment": header.fcomment,
+        "header_size": header.end_idx,
+    })
+}
+
+/// Returns where a block sits as JSON.
+fn block_json(block: &BlockInfo) -> Value {
+    let block_type = match block.block_type {
+        BlockType::Stored => "stored",
+        BlockType::Fixed => "fixed",
+        BlockType::Dynamic => "dynamic",
+    };
+
+    json!({
+        "type": block_type,
+        "final": block.final_block,
+        "bit_start": block.bits.start,
+        "bit_end": block.bits.end,
+        "output_start": block.bytes.start,
+        "output_end": block.bytes.end,
+    })
+}
+
+/// Decompresses a member's DEFLATE stream, keeping only what the report
+/// needs.
+///
+/// # Returns
+///
+/// A tuple containing the bytes of stream used, the decompressed size, the
+/// CRC-32 of the decompressed data, and the member's blocks. Or, a
+/// DeflateError if the stream is invalid.
+fn inflate_member(stream: &[u8]) -> Result<(usize, u64, u32, Vec<BlockInfo>), DeflateError> {
+    let mut inflater = Inflater::new();
+    inflater.set_record_blocks(true);
+
+    let mut output = vec![0; OUTPUT_CHUNK];
+    let mut input = stream;
+    let mut len = 0;
+    let mut crc = 0;
+    loop {
+        let (consumed, produced, status) = inflater.inflate(input, &mut output)?;
+        input = input.get(consumed..).unwrap_or_default();
+        len += produced as u64;
+        crc = crc::update(crc, output.get(..produced).unwrap_or_default());
+
+        match status {
+            Status::StreamEnd => {
+                let used = stream.len() - input.len();
+                return Ok((used, len, crc, inflater.blocks().to_vec()));
+            }
+            Status::NeedsInput => return Err(inflater.unexpected_eof()),
+            Status::NeedsOutput | Status::Pending => {}
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! What the decoder and the encoder both need to know about a DEFLATE
+//! stream: the window size, the ways a block can be coded, and the errors
+//! and trace records the Inflater reports, and the memory both sides hold.
+//! Kept out of inflate so builds without the inflate feature still have
+//! them, inflate re-exports all of it.
+use std::{error::Error, fmt::Display};
+
+/// The size of the sliding window back-references can reach into.
+pub const WINDOW_SIZE: usize = 32768;
+
+#[derive(Debug)]
+pub enum DeflateError {
+    InvalidBlockError(&'static str),
+    InvalidSymbolError(usize, &'static str),
+    DecompressionError(&'static str),
+    OutputTooSmallError(usize),
+    ChecksumMismatchError {
+        expected: u32,
+        actual: u32,
+    },
+    SizeMismatchError {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidDistanceError {
+        distance: usize,
+        output_len: u64,
+    },
+    OutputLimitError(u64),
+    ExpansionRatioError(u64),
+    MemoryLimitError(usize),
+    OutOfMemoryError(usize),
+    CancelledError,
+    InvalidStateError(&'static str),
+    UnexpectedEofError {
+        bit_offset: u64,
+    },
+    PositionedError {
+        error: Box<DeflateError>,
+        position: ErrorPosition,
+    },
+    TracedError {
+        error: Box<DeflateError>,
+        history: Vec<TraceRecord>,
+    },
+}
+
+impl DeflateError {
+    /// Returns where in the stream the error was found, if it's known.
+    pub fn position(&self) -> Option<ErrorPosition> {
+        match self {
+            DeflateError::PositionedError { position, .. } => Some(*position),
+            DeflateError::TracedError { error, .. } => error.position(),
+            _ => None,
+        }
+    }
+    /// Returns what was decoded just before the error, oldest first, if
+    /// the Inflater was keeping a history, see Inflater::set_history.
+    pub fn history(&self) -> Option<&[TraceRecord]> {
+        match self {
+            DeflateError::TracedError { history, .. } => Some(history),
+            _ => None,
+        }
+    }
+    /// Returns the error without its position or history, for matching on
+    /// what went wrong.
+    pub fn inner(&self) -> &DeflateError {
+        match self {
+            DeflateError::PositionedError { error, .. } => error.inner(),
+            DeflateError::TracedError { error, .. } => error.inner(),
+            error => error,
+        }
+    }
+}
+
+impl Display for DeflateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeflateError::InvalidBlockError(s) => {
+                write!(f, "InvalidBlock Error: {}", s)
+            }
+            DeflateError::InvalidSymbolError(v, r) => {
+                write!(f, "InvalidSymbolError cause by symbol: {}, {}", v, r)
+            }
+            DeflateError::DecompressionError(s) => {
+                write!(f, "DecompressionError: {}", s)
+            }
+            DeflateError::OutputTooSmallError(len) => {
+                write!(
+                    f,
+                    "OutputTooSmallError: Decompressed data does not fit in {} bytes",
+                    len
+                )
+            }
+            DeflateError::ChecksumMismatchError { expected, actual } => {
+                write!(
+                    f,
+                    "ChecksumMismatchError: Expected CRC-32 {:#010x}, but the data hashed to {:#010x}",
+                    expected, actual
+                )
+            }
+            DeflateError::SizeMismatchError { expected, actual } => {
+                write!(
+                    f,
+                    "SizeMismatchError: Expected {} bytes mod 2^32, but decompressed {}",
+                    expected, actual
+                )
+            }
+            DeflateError::OutputLimitError(limit) => {
+                write!(
+                    f,
+                    "OutputLimitError: Decompressed data is larger than the {} byte limit",
+                    limit
+                )
+            }
+            DeflateError::ExpansionRatioError(ratio) => {
+                write!(
+                    f,
+                    "ExpansionRatioError: Decompressed data is more than {} times larger than the compressed data",
+                    ratio
+                )
+            }
+            DeflateError::MemoryLimitError(limit) => {
+                write!(
+                    f,
+                    "MemoryLimitError: Decompressing needs more than the {} byte memory limit",
+                    limit
+                )
+            }
+            DeflateError::OutOfMemoryError(size) => {
+                write!(f, "OutOfMemoryError: Failed to allocate {} bytes", size)
+            }
+            DeflateError::CancelledError => {
+                write!(f, "CancelledError: Decompression was cancelled")
+            }
+            DeflateError::InvalidStateError(s) => {
+                write!(f, "InvalidStateError: {}", s)
+            }
+            DeflateError::UnexpectedEofError { bit_offset } => {
+                write!(
+                    f,
+                    "UnexpectedEofError: Compressed data ended before the final block, at bit {}",
+                    bit_offset
+                )
+            }
+            DeflateError::PositionedError { error, position } => {
+                write!(
+                    f,
+                    "{}, at bit {} in block {} while reading the {}",
+                    error, position.bit_offset, position.block, position.phase
+                )
+            }
+            DeflateError::TracedError { error, history } => {
+                write!(f, "{}, after:", error)?;
+                for record in history {
+                    write!(f, "\n  {}", record)?;
+                }
+                Ok(())
+            }
+            DeflateError::InvalidDistanceError {
+                distance,
+                output_len,
+            } => {
+                write!(
+                    f,
+                    "InvalidDistanceError: Distance {} reaches further back than the {} bytes of output or the {} byte window",
+                    distance, output_len, WINDOW_SIZE
+                )
+            }
+        }
+    }
+}
+
+impl Error for DeflateError {}
+
+/// Where in a DEFLATE stream an error was found.
+///
+/// # Fields
+///
+/// * 'bit_offset' - The number of bits of the stream read before the error.
+/// * 'block' - The index of the block the error is in, counting from 0.
+/// * 'phase' - The part of the block that was being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorPosition {
+    pub bit_offset: u64,
+    pub block: u64,
+    pub phase: Phase,
+}
+
+/// The parts of a block the Inflater reads through.
+///
+/// # Variants
+///
+/// * 'Header' - The block header, including a stored block's LEN and NLEN
+///         and a dynamic block's HLIT, HDIST, and HCLEN.
+/// * 'CodeLengths' - A dynamic block's code length code and code lengths.
+/// * 'Data' - The block's literals and matches, or stored bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Header,
+    CodeLengths,
+    Data,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Header => write!(f, "header"),
+            Phase::CodeLengths => write!(f, "code lengths"),
+            Phase::Data => write!(f, "data"),
+        }
+    }
+}
+
+/// The ways a DEFLATE block can be coded, from its BTYPE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Stored,
+    Fixed,
+    Dynamic,
+}
+
+/// How much heap memory a decoder or encoder has allocated, in bytes, see
+/// Inflater::memory_usage and GzEncoder::memory_usage.
+///
+/// # Fields
+///
+/// * 'window' - The sliding window, 32KB once the first call to inflate has
+///         allocated it. An encoder's window also holds the bytes waiting
+///         to be compressed.
+/// * 'tables' - The decoding tables for dynamic blocks. An encoder builds
+///         its codes again for each block, so holds none.
+/// * 'buffers' - Everything else, like recorded blocks and history, the
+///         input, output, and header buffers of decoders built on the
+///         Inflater, and the compressed bytes an encoder is holding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub window: usize,
+    pub tables: usize,
+    pub buffers: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the total bytes allocated.
+    pub fn total(&self) -> usize {
+        self.window + self.tables + self.buffers
+    }
+}
+
+/// A TraceEvent kept in the Inflater's history, see Inflater::set_history.
+/// The same as the event, but owning everything so it can outlive the
+/// call that decoded it, and with only the sizes of a dynamic block's
+/// tables.
+///
+/// # Variants
+///
+/// * 'Block' - A block header, at the offset of its BFINAL bit.
+/// * 'Stored' - The LEN of a stored block, at the offset of its first byte
+///         of data.
+/// * 'DynamicTables' - The number of literal/length, distance, and code
+///         length code lengths of a dynamic block, at the offset just past
+///         them.
+/// * 'Literal' - A literal byte, at the offset of its code.
+/// * 'Match' - A match, at the offset of its length code.
+/// * 'BlockEnd' - The end of a block, at the offset just past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceRecord {
+    Block {
+        bit_offset: u64,
+        final_block: bool,
+        block_type: BlockType,
+    },
+    Stored {
+        bit_offset: u64,
+        len: u16,
+    },
+    DynamicTables {
+        bit_offset: u64,
+        hlit: usize,
+        hdist: usize,
+        hclen: usize,
+    },
+    Literal {
+        bit_offset: u64,
+        byte: u8,
+    },
+    Match {
+        bit_offset: u64,
+        length: usize,
+        distance: usize,
+    },
+    BlockEnd {
+        bit_offset: u64,
+    },
+}
+
+impl TraceRecord {
+    /// Returns the bit offset the record was found at.
+    pub fn bit_offset(&self) -> u64 {
+        match *self {
+            TraceRecord::Block { bit_offset, .. }
+            | TraceRecord::Stored { bit_offset, .. }
+            | TraceRecord::DynamicTables { bit_offset, .. }
+            | TraceRecord::Literal { bit_offset, .. }
+            | TraceRecord::Match { bit_offset, .. }
+            | TraceRecord::BlockEnd { bit_offset } => bit_offset,
+        }
+    }
+}
+
+// Records are shown like lines of disasm, with the bit offset first.
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bit {}: ", self.bit_offset())?;
+        match *self {
+            TraceRecord::Block {
+                final_block,
+                block_type,
+                ..
+            } => {
+                let last = if final_block { "last " } else { "" };
+                write!(f, "{}{:?} block", last, block_type)
+            }
+            TraceRecord::Stored { len, .. } => write!(f, "stored len {}", len),
+            TraceRecord::DynamicTables {
+                hlit, hdist, hclen, ..
+            } => write!(f, "count {} {} {}", hlit, hdist, hclen),
+            TraceRecord::Literal { byte, .. } if byte.is_ascii_graphic() || byte == b' ' => {
+                write!(f, "literal '{}", byte as char)
+            }
+            TraceRecord::Literal { byte, .. } => write!(f, "literal {}", byte),
+            TraceRecord::Match {
+                length, distance, ..
+            } => write!(f, "match {} {}", length, distance),
+            TraceRecord::BlockEnd { .. } => write!(f, "end"),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Decoding a DEFLATE stream to its LZ77 tokens rather than its bytes.
+//!
+//! Tools that work on how a stream was compressed, like transcoders and
+//! analyzers, want the literals and matches themselves. The events here are
+//! that structure with the entropy coding taken off, while the bit level
+//! detail stays with the Inflater's trace hook.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::inflate::{BlockType, DeflateError, Inflater, Status, TraceEvent};
+
+/// The size of the scratch buffer decompressed bytes are written to.
+const OUTPUT_CHUNK: usize = 32768;
+
+/// A single part of a DEFLATE stream's LZ77 structure.
+///
+/// # Variants
+///
+/// * 'BlockStart' - The start of a block.
+/// * 'Literal' - A literal byte. The bytes of a stored block are handed on
+///         as literals too, so every byte of output comes from a literal or
+///         a match.
+/// * 'Match' - A copy of length bytes from distance bytes back.
+/// * 'EndOfBlock' - The end of block symbol of a fixed or dynamic block.
+/// * 'BlockEnd' - The end of a block of any type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    BlockStart {
+        block_type: BlockType,
+        final_block: bool,
+    },
+    Literal(u8),
+    Match {
+        length: usize,
+        distance: usize,
+    },
+    EndOfBlock,
+    BlockEnd,
+}
+
+/// Something that takes the events of a stream in order. Any closure
+/// taking an Event is a Visitor that never fails.
+pub trait Visitor {
+    /// Takes the next event.
+    ///
+    /// # Returns
+    ///
+    /// Nothing, or a DeflateError to stop decoding with, such as for a
+    /// match the visitor finds reaching back past the start of the output.
+    fn visit(&mut self, event: Event) -> Result<(), DeflateError>;
+}
+
+impl<F: FnMut(Event)> Visitor for F {
+    fn visit(&mut self, event: Event) -> Result<(), DeflateError> {
+        self(event);
+        Ok(())
+    }
+}
+
+/// An event waiting for its turn, a stored block's bytes can't be handed
+/// on until they've been written to the output.
+///
+/// # Variants
+///
+/// * 'Event' - An event that's ready as it is.
+/// * 'Stored' - The number of bytes of a stored block still to hand on.
+enum Pending {
+    Event(Event),
+    Stored(usize),
+}
+
+/// Decodes a raw DEFLATE stream, handing each of its events to visitor.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+/// * 'visitor' - What to hand the events to.
+///
+/// # Returns
+///
+/// The number of bytes of stream the DEFLATE data took up, or a
+/// DeflateError if the stream is invalid or the visitor gives one. Events
+/// before the error are still handed on.
+pub fn decode_events<V: Visitor>(stream: &[u8], visitor: &mut V) -> Result<usize, DeflateError> {
+    let pending = Arc::new(Mutex::new(VecDeque::new()));
+    let sink = Arc::clone(&pending);
+
+    let mut inflater = Inflater::new();
+    let mut stored = false;
+    inflater.set_trace(Some(Box::new(move |event| {
+        let mut pending = sink.lock().unwrap_or_else(PoisonError::into_inner);
+        let next = match *event {
+            TraceEvent::Block {
+                final_block,
+                block_type,
+                ..
+            } => {
+                stored = block_type == BlockType::Stored;
+                Pending::Event(Event::BlockStart {
+                    block_type,
+                    final_block,
+                })
+            }
+            TraceEvent::Stored { len, .. } => Pending::Stored(len as usize),
+            TraceEvent::Literal { byte, .. } => Pending::Event(Event::Literal(byte)),
+            TraceEvent::Match {
+                length, distance, ..
+            } => Pending::Event(Event::Match { length, distance }),
+            // A stored block ends straight after its bytes, the others
+            // with an end of block symbol first.
+            TraceEvent::BlockEnd { .. } if !stored => {
+                pending.push_back(Pending::Event(Event::EndOfBlock));
+                Pending::Event(Event::BlockEnd)
+            }
+            TraceEvent::BlockEnd { .. } => Pending::Event(Event::BlockEnd),
+            TraceEvent::DynamicTables { .. } => return,
+        };
+        pending.push_back(next);
+    })));
+
+    let mut output = vec![0; OUTPUT_CHUNK];
+    let mut input = stream;
+    // Where the next event's output starts and where this call's output
+    // starts, counting from the start of the stream.
+    let mut position = 0;
+    let mut output_start = 0;
+    loop {
+        let result = inflater.inflate(input, &mut output);
+        let produced = result.as_ref().map_or(0, |(_, produced, _)| *produced);
+
+        let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+        while let Some(next) = pending.front_mut() {
+            match next {
+                Pending::Event(event) => {
+                    position += match *event {
+                        Event::Literal(_) => 1,
+                        Event::Match { length, .. } => length as u64,
+                        _ => 0,
+                    };
+                    visitor.visit(*event)?;
+                }
+                Pending::Stored(remaining) => {
+                    // A stored block's header is only read once everything
+                    // before it has been written, so its bytes start within
+                    // this call's output, unless the call failed.
+                    let start = (position - output_start) as usize;
+                    if start > produced {
+                        break;
+                    }
+                    let end = produced.min(start + *remaining);
+                    for &byte in output.get(start..end).unwrap_or_default() {
+                        visitor.visit(Event::Literal(byte))?;
+                    }
+
+                    position += (end - start) as u64;
+                    *remaining -= end - start;
+                    if *remaining > 0 {
+                        break;
+                    }
+                }
+            }
+            pending.pop_front();
+        }
+        output_start += produced as u64;
+
+        let (consumed, _, status) = result?;
+        input = input.get(consumed..).unwrap_or_default();
+
+        match status {
+            Status::StreamEnd => return Ok(stream.len() - input.len()),
+            Status::NeedsInput => return Err(inflater.unexpected_eof()),
+            Status::NeedsOutput | Status::Pending => {}
+        }
+    }
+}
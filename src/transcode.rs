@@ -0,0 +1,201 @@
+//! Re-encoding a DEFLATE stream from its own tokens.
+//!
+//! The literals and matches of a stream are kept as they are and only the
+//! entropy coding is redone, so there's no match finding to pay for. Each
+//! block can be rewritten as whichever type is smallest, forced to one type,
+//! or the tokens can be split into blocks of a different size.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use crate::{
+    bits::BitWriter,
+    deflate::{write_block, Token},
+    gzip::{GzipError, GzipHeader},
+    inflate::{BlockType, DeflateError, WINDOW_SIZE},
+    tokens::{decode_events, Event, Visitor},
+};
+
+/// How to re-encode a stream.
+///
+/// # Fields
+///
+/// * 'block_type' - The type every block is written as, or None for
+///         whichever is smallest for each block.
+/// * 'block_size' - The number of tokens to put in each block, or None to
+///         keep the stream's own blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TranscodeOptions {
+    pub block_type: Option<BlockType>,
+    pub block_size: Option<usize>,
+}
+
+/// Re-encodes a raw DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'stream' - The DEFLATE stream, anything after its end is ignored.
+/// * 'options' - How to re-encode it.
+///
+/// # Returns
+///
+/// The new stream, or a DeflateError if the stream is invalid.
+pub fn transcode(stream: &[u8], options: &TranscodeOptions) -> Result<Vec<u8>, DeflateError> {
+    transcode_stream(stream, options).map(|(transcoded, _)| transcoded)
+}
+
+/// Re-encodes the DEFLATE stream of each member of a gzip file, keeping
+/// the headers and trailers as they are. The decompressed data doesn't
+/// change, so neither do the CRC-32s.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file.
+/// * 'options' - How to re-encode each stream.
+///
+/// # Returns
+///
+/// The new gzip file, or a GzipError if the file is invalid.
+pub fn transcode_gzip(bytes: &[u8], options: &TranscodeOptions) -> Result<Vec<u8>, GzipError> {
+    let mut output = Vec::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let header = GzipHeader::build(rest)?;
+        let (transcoded, used) =
+            transcode_stream(rest.get(header.end_idx..).unwrap_or_default(), options)?;
+
+        let trailer_start = header.end_idx + used;
+        let (member, after) =
+            rest.split_at_checked(trailer_start + 8)
+                .ok_or(DeflateError::UnexpectedEofError {
+                    bit_offset: used as u64 * 8,
+                })?;
+        output.extend_from_slice(member.get(..header.end_idx).unwrap_or_default());
+        output.extend_from_slice(&transcoded);
+        output.extend_from_slice(member.get(trailer_start..).unwrap_or_default());
+
+        rest = after;
+    }
+
+    Ok(output)
+}
+
+/// Re-encodes a stream, returning the new stream and how many bytes the
+/// old one took up.
+fn transcode_stream(
+    stream: &[u8],
+    options: &TranscodeOptions,
+) -> Result<(Vec<u8>, usize), DeflateError> {
+    let mut transcoder = Transcoder {
+        options: *options,
+        writer: BitWriter::new(),
+        tokens: Vec::new(),
+        history: Vec::new(),
+        output_len: 0,
+        block_start: 0,
+        final_block: false,
+    };
+
+    let used = decode_events(stream, &mut transcoder)?;
+    // With blocks of a new size, the last one is only known to be last
+    // once the stream has ended.
+    if options.block_size.is_some() {
+        transcoder.flush(true);
+    }
+
+    Ok((transcoder.writer.finish(), used))
+}
+
+/// Gathers a stream's tokens into blocks and writes them out again.
+///
+/// # Fields
+///
+/// * 'options' - How to re-encode the stream.
+/// * 'writer' - Where the new stream is written.
+/// * 'tokens' - The tokens of the block being gathered.
+/// * 'history' - The decompressed data of the block being gathered, and
+///         at least the window before it for matches to copy from.
+/// * 'output_len' - The number of bytes of decompressed data so far.
+/// * 'block_start' - The index in history the block's data starts at.
+/// * 'final_block' - Whether the block being read has BFINAL set.
+struct Transcoder {
+    options: TranscodeOptions,
+    writer: BitWriter,
+    tokens: Vec<Token>,
+    history: Vec<u8>,
+    output_len: u64,
+    block_start: usize,
+    final_block: bool,
+}
+
+impl Transcoder {
+    /// Writes the tokens gathered so far as a block.
+    fn flush(&mut self, final_block: bool) {
+        write_block(
+            &mut self.writer,
+            &self.tokens,
+            self.history.get(self.block_start..).unwrap_or_default(),
+            self.options.block_type,
+            final_block,
+        );
+        self.tokens.clear();
+
+        // Only the window is needed for matches in the next block.
+        if self.history.len() > WINDOW_SIZE * 2 {
+            self.history.drain(..self.history.len() - WINDOW_SIZE);
+        }
+        self.block_start = self.history.len();
+    }
+    /// Adds a token to the block, starting a new block first if it's full.
+    fn push(&mut self, token: Token) {
+        if self
+            .options
+            .block_size
+            .is_some_and(|size| self.tokens.len() >= size.max(1))
+        {
+            self.flush(false);
+        }
+        self.tokens.push(token);
+    }
+}
+
+impl Visitor for Transcoder {
+    fn visit(&mut self, event: Event) -> Result<(), DeflateError> {
+        match event {
+            Event::BlockStart { final_block, .. } => self.final_block = final_block,
+            Event::Literal(byte) => {
+                self.push(Token::Literal(byte));
+                self.history.push(byte);
+                self.output_len += 1;
+            }
+            Event::Match { length, distance } => {
+                // A broken stream's match is handed on before the Inflater
+                // finds it reaches back too far, so it's checked here too.
+                let invalid = DeflateError::InvalidDistanceError {
+                    distance,
+                    output_len: self.output_len,
+                };
+                let start = (self.history.len().checked_sub(distance))
+                    .filter(|_| distance > 0)
+                    .ok_or(invalid)?;
+                self.push(Token::Match { length, distance });
+                // Copied a byte at a time, the match can overlap itself.
+                for index in start..start + length {
+                    let byte = self.history.get(index).copied().unwrap_or_default();
+                    self.history.push(byte);
+                }
+                self.output_len += length as u64;
+            }
+            Event::BlockEnd if self.options.block_size.is_none() => self.flush(self.final_block),
+            Event::EndOfBlock | Event::BlockEnd => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Round trip checks, for confidence that a file and our own encoding of
+//! it agree.
+//!
+//! verify decompresses a gzip file, compresses what came out again with
+//! the given options, decompresses that, and checks the two are the same.
+//! verify_with_reference also hands the recompressed file to some other
+//! decoder, such as zlib, so our output is known to work beyond our own
+//! inflater.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, BufRead, Read},
+};
+
+use crate::{
+    bufread::GzDecoder,
+    deflate::{compress_gzip, CompressOptions},
+    gzip::GzipError,
+};
+
+/// A decoder to check our output against.
+type Reference<'a> = &'a mut dyn FnMut(&[u8]) -> io::Result<Vec<u8>>;
+
+/// An error from a round trip.
+///
+/// # Members
+///
+/// * 'Original' - Used when the file being checked fails to decompress.
+/// * 'Recompressed' - Used when our recompressed file fails to decompress.
+/// * 'Mismatch' - Used when our recompressed file decompresses to something
+///             different. Contains the offset of the first byte that
+///             differs.
+/// * 'Reference' - Used when the reference decoder fails on our
+///             recompressed file.
+/// * 'ReferenceMismatch' - Used when the reference decoder decompresses our
+///             recompressed file to something different. Contains the offset
+///             of the first byte that differs.
+#[derive(Debug)]
+pub enum VerifyError {
+    Original(GzipError),
+    Recompressed(GzipError),
+    Mismatch { offset: usize },
+    Reference(io::Error),
+    ReferenceMismatch { offset: usize },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Original(err) => {
+                write!(f, "Error: Decompressing the file raised the {}", err)
+            }
+            VerifyError::Recompressed(err) => {
+                write!(
+                    f,
+                    "Error: Decompressing the recompressed file raised the {}",
+                    err
+                )
+            }
+            VerifyError::Mismatch { offset } => {
+                write!(f, "Error: Round trip differs from byte {}", offset)
+            }
+            VerifyError::Reference(err) => {
+                write!(f, "Error: Reference decoder raised the io::Error: {}", err)
+            }
+            VerifyError::ReferenceMismatch { offset } => {
+                write!(
+                    f,
+                    "Error: Reference decoder's output differs from byte {}",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+impl Error for VerifyError {}
+
+/// The sizes seen during a successful round trip.
+///
+/// # Fields
+///
+/// * 'plaintext' - The size of the decompressed data.
+/// * 'compressed' - The size of the file that was checked.
+/// * 'recompressed' - The size of our recompressed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    pub plaintext: usize,
+    pub compressed: usize,
+    pub recompressed: usize,
+}
+
+/// Decompresses a gzip file, recompresses it, and checks the recompressed
+/// file decompresses to the same bytes.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file, any number of members.
+/// * 'options' - The options to recompress with.
+///
+/// # Returns
+///
+/// The sizes at each step, or a VerifyError for the step that failed.
+pub fn verify(bytes: &[u8], options: &CompressOptions) -> Result<Verification, VerifyError> {
+    round_trip(bytes, options, None)
+}
+
+/// Like verify, but also checks that the reference decoder decompresses our
+/// recompressed file to the same bytes.
+///
+/// # Arguments
+///
+/// * 'bytes' - The gzip file, any number of members.
+/// * 'options' - The options to recompress with.
+/// * 'reference' - A closure that decompresses a gzip file with some other
+///         decoder.
+///
+/// # Returns
+///
+/// The sizes at each step, or a VerifyError for the step that failed.
+pub fn verify_with_reference<F>(
+    bytes: &[u8],
+    options: &CompressOptions,
+    mut reference: F,
+) -> Result<Verification, VerifyError>
+where
+    F: FnMut(&[u8]) -> io::Result<Vec<u8>>,
+{
+    round_trip(bytes, options, Some(&mut reference))
+}
+
+/// Does the round trip for verify and verify_with_reference.
+fn round_trip(
+    bytes: &[u8],
+    options: &CompressOptions,
+    reference: Option<Reference<'_>>,
+) -> Result<Verification, VerifyError> {
+    let plaintext = decompress_members(bytes).map_err(VerifyError::Original)?;
+    let recompressed = compress_gzip(&plaintext, options);
+
+    let round_tripped = decompress_members(&recompressed).map_err(VerifyError::Recompressed)?;
+    if let Some(offset) = first_difference(&plaintext, &round_tripped) {
+        return Err(VerifyError::Mismatch { offset });
+    }
+
+    if let Some(reference) = reference {
+        let decoded = reference(&recompressed).map_err(VerifyError::Reference)?;
+        if let Some(offset) = first_difference(&plaintext, &decoded) {
+            return Err(VerifyError::ReferenceMismatch { offset });
+        }
+    }
+
+    Ok(Verification {
+        plaintext: plaintext.len(),
+        compressed: bytes.len(),
+        recompressed: recompressed.len(),
+    })
+}
+
+/// Decompresses every member of a gzip file.
+fn decompress_members(bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let mut output = Vec::new();
+    let mut decoder = GzDecoder::new(bytes);
+
+    loop {
+        decoder.read_to_end(&mut output)?;
+
+        if decoder.get_mut().fill_buf()?.is_empty() {
+            return Ok(output);
+        }
+        decoder.reset();
+    }
+}
+
+/// Returns the offset of the first byte that differs between a and b,
+/// counting the end of the shorter one as a difference.
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(a, b)| a != b)
+        .or((a.len() != b.len()).then(|| a.len().min(b.len())))
+}
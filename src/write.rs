@@ -0,0 +1,504 @@
+//! Compressing and decompressing writers.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+#[cfg(feature = "inflate")]
+use std::io::IoSlice;
+use std::io::{self, Write};
+
+#[cfg(feature = "deflate")]
+use crate::deflate::{CompressOptions, CompressStats, StreamCompressor};
+use crate::{crc, gzip::GzipHeader, stream::MemoryUsage};
+#[cfg(feature = "inflate")]
+use crate::{
+    gzip::{verify_trailer, GzipError},
+    inflate::{Inflater, Status},
+};
+
+/// The size of the buffer decompressed bytes are gathered in before being
+/// written to the inner writer. Many small writes of compressed data are
+/// batched into one write of this size.
+#[cfg(feature = "inflate")]
+const OUTPUT_CHUNK: usize = 32768;
+
+/// Writes every slice to w, using write_vectored so slices can go out in
+/// one call where the writer supports it, and retrying partial writes.
+///
+/// # Arguments
+///
+/// * 'w' - The writer to write to.
+/// * 'slices' - The bytes to write, in order. They're advanced past
+///         whatever has been written, so are left empty on success.
+#[cfg(feature = "inflate")]
+pub(crate) fn write_all_vectored<W: Write + ?Sized>(
+    w: &mut W,
+    mut slices: &mut [IoSlice<'_>],
+) -> io::Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        match w.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes all of buf like write_all, but keeps count of how much has been
+/// written, so a write that fails part way can be picked up where it left
+/// off rather than starting again.
+///
+/// # Arguments
+///
+/// * 'w' - The writer to write to.
+/// * 'buf' - The bytes to write.
+/// * 'written' - How many bytes of buf have been written, updated as they
+///         are.
+fn write_counted<W: Write + ?Sized>(w: &mut W, buf: &[u8], written: &mut usize) -> io::Result<()> {
+    while let Some(rest) = buf.get(*written..).filter(|rest| !rest.is_empty()) {
+        match w.write(rest) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => *written += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// A writer that takes a gzip file's compressed bytes and writes the
+/// decompressed bytes to an inner writer, for when the compressed data is
+/// pushed in pieces rather than pulled from a reader.
+///
+/// # Fields
+///
+/// * 'writer' - The inner writer decompressed bytes go to.
+/// * 'header' - The file's header, once enough bytes have been written.
+/// * 'pending' - Header bytes until the header is complete, then trailer
+///         bytes once the DEFLATE data has ended.
+/// * 'inflater' - The inflater decoding the DEFLATE data.
+/// * 'output' - Decompressed bytes waiting to be written to the inner
+///         writer, held until it's full, flushed, or finished.
+/// * 'filled' - How many bytes at the start of output are waiting.
+/// * 'written' - How many of those the inner writer has already taken, when
+///         it failed part way through them.
+/// * 'crc' - The CRC-32 of the bytes decompressed so far.
+/// * 'finished' - Whether the trailer has been written and checked.
+/// * 'error' - An error hit after some of a write's bytes were taken, held
+///         back to be returned by the next call.
+#[cfg(feature = "inflate")]
+pub struct DecompressWriter<W: Write> {
+    writer: W,
+    header: Option<GzipHeader>,
+    pending: Vec<u8>,
+    inflater: Inflater,
+    output: Vec<u8>,
+    filled: usize,
+    written: usize,
+    crc: u32,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "inflate")]
+impl<W: Write> DecompressWriter<W> {
+    /// Wraps the writer the decompressed bytes should go to.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header: None,
+            pending: Vec::new(),
+            inflater: Inflater::new(),
+            output: vec![0; OUTPUT_CHUNK],
+            filled: 0,
+            written: 0,
+            crc: 0,
+            finished: false,
+            error: None,
+        }
+    }
+    /// Returns the file's header, or None if it hasn't all been written yet.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.header.as_ref()
+    }
+    /// Limits how large the file can decompress to, writing past the limit
+    /// fails with an io::Error wrapping DeflateError::OutputLimitError.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to decompress, or None for no limit.
+    pub fn set_max_output_size(&mut self, limit: Option<u64>) {
+        self.inflater.set_max_output_size(limit);
+    }
+    /// Limits how many times larger than the compressed data the output can
+    /// be, writing past the limit fails with an io::Error wrapping
+    /// DeflateError::ExpansionRatioError.
+    ///
+    /// # Arguments
+    ///
+    /// * 'ratio' - The most bytes of output per byte of input, or None for
+    ///         no limit.
+    pub fn set_max_expansion_ratio(&mut self, ratio: Option<u64>) {
+        self.inflater.set_max_expansion_ratio(ratio);
+    }
+    /// Limits how much memory the inflater can allocate, see
+    /// Inflater::set_memory_limit.
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - The most bytes to allocate, or None for no limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.inflater.set_memory_limit(limit);
+    }
+    /// Keeps the last few events decoded and attaches them to any error,
+    /// see Inflater::set_history.
+    ///
+    /// # Arguments
+    ///
+    /// * 'len' - How many events to keep, or None to keep none.
+    pub fn set_history(&mut self, len: Option<usize>) {
+        self.inflater.set_history(len);
+    }
+    /// Returns how much heap memory the writer has allocated, the
+    /// inflater's, see Inflater::memory_usage, and its header, output, and
+    /// pending bytes.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.inflater.memory_usage();
+        usage.buffers += self.output.capacity()
+            + self.pending.capacity()
+            + self.header.as_ref().map_or(0, GzipHeader::memory_usage);
+        usage
+    }
+    /// Returns a reference to the inner writer. Decompressed bytes are
+    /// batched, so it may not have been given all of them until flush or
+    /// finish is called.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+    /// Writes out any batched bytes and checks the whole file was written,
+    /// then returns the inner writer.
+    ///
+    /// # Returns
+    ///
+    /// The inner writer, or an io::Error if the file was cut short.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_output()?;
+
+        if self.header.is_some() && !self.inflater.is_finished() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                self.inflater.unexpected_eof(),
+            ));
+        }
+        if !self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Compressed data ended before the end of the gzip file.",
+            ));
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+    /// Writes the batched decompressed bytes to the inner writer.
+    fn write_output(&mut self) -> io::Result<()> {
+        let output = self.output.get(..self.filled).unwrap_or_default();
+        write_counted(&mut self.writer, output, &mut self.written)?;
+        (self.filled, self.written) = (0, 0);
+        Ok(())
+    }
+    /// Does the work of write, moving pos past every byte of buf taken.
+    fn decompress(&mut self, buf: &[u8], pos: &mut usize) -> io::Result<()> {
+        if self.header.is_none() {
+            match self.write_header(buf)? {
+                Some(used) => *pos = used,
+                None => {
+                    *pos = buf.len();
+                    return Ok(());
+                }
+            }
+        }
+
+        while !self.inflater.is_finished() {
+            if self.filled == self.output.len() {
+                self.write_output()?;
+            }
+
+            let output = self.output.get_mut(self.filled..).unwrap_or_default();
+            let (consumed, produced, status) = self
+                .inflater
+                .inflate(buf.get(*pos..).unwrap_or_default(), output)
+                .map_err(GzipError::from)?;
+
+            *pos += consumed;
+            self.crc = crc::update(self.crc, output.get(..produced).unwrap_or_default());
+            self.filled += produced;
+
+            if status == Status::NeedsInput {
+                return Ok(());
+            }
+        }
+
+        if !self.finished {
+            *pos += self.write_trailer(buf.get(*pos..).unwrap_or_default())?;
+        }
+
+        Ok(())
+    }
+    /// Collects header bytes, returning how many bytes of buf were part of
+    /// the header, or None if the header still isn't complete.
+    fn write_header(&mut self, buf: &[u8]) -> Result<Option<usize>, GzipError> {
+        let before = self.pending.len();
+        self.pending.extend_from_slice(buf);
+
+        match GzipHeader::from_reader(&mut &self.pending[..]) {
+            Ok(header) => {
+                let used = header.end_idx - before;
+                self.header = Some(header);
+                self.pending.clear();
+                Ok(Some(used))
+            }
+            Err(GzipError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+    /// Collects trailer bytes, checking the trailer once all 8 are in.
+    fn write_trailer(&mut self, buf: &[u8]) -> Result<usize, GzipError> {
+        let used = buf.len().min(8_usize.saturating_sub(self.pending.len()));
+        self.pending
+            .extend_from_slice(buf.get(..used).unwrap_or_default());
+
+        if let &[c0, c1, c2, c3, i0, i1, i2, i3] = &self.pending[..] {
+            let crc32 = u32::from_le_bytes([c0, c1, c2, c3]);
+            let isize = u32::from_le_bytes([i0, i1, i2, i3]);
+
+            verify_trailer(crc32, isize, self.crc, self.inflater.total_out())?;
+
+            self.finished = true;
+        }
+
+        Ok(used)
+    }
+}
+
+#[cfg(feature = "inflate")]
+impl<W: Write> Write for DecompressWriter<W> {
+    /// Takes compressed bytes, writing out whatever can be decompressed from
+    /// them. Bytes after the end of the gzip file aren't taken, so once it
+    /// has all been written this returns 0.
+    ///
+    /// An error after some of buf has gone into the inflater is held back
+    /// and returned by the next call, and the bytes taken are returned
+    /// instead, so a caller retrying the rest doesn't hand them over twice.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        let mut pos = 0;
+        match self.decompress(buf, &mut pos) {
+            Err(err) if pos > 0 => {
+                self.error = Some(err);
+                Ok(pos)
+            }
+            result => result.map(|_| pos),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        self.write_output()?;
+        self.writer.flush()
+    }
+}
+
+/// A writer that gzip compresses what's written to it into an inner writer,
+/// for responses sent as they're produced, such as with HTTP chunked
+/// transfer encoding or server-sent events.
+///
+/// Every flush ends the data so far with a sync flush, an empty stored
+/// block, so the bytes written to the inner writer decompress to everything
+/// written before the flush without waiting for the end of the file. Data
+/// is otherwise compressed 64KB at a time, with matches reaching back into
+/// the last 32KB.
+///
+/// Compressed bytes the inner writer fails to take are kept and written
+/// again on the next call, so once a write has returned Ok its bytes are
+/// part of the file even if the inner writer failed along the way.
+///
+/// # Fields
+///
+/// * 'writer' - The inner writer compressed bytes go to.
+/// * 'compressor' - The DEFLATE stream being written.
+/// * 'header' - The header to start the file with, until it's queued in
+///         pending.
+/// * 'pending' - Compressed bytes the inner writer hasn't taken yet.
+/// * 'crc' - The CRC-32 of everything written.
+/// * 'len' - The number of bytes written.
+/// * 'flushed' - Whether nothing has been written since the last flush.
+/// * 'error' - An error from the inner writer after the bytes of a write
+///         had already been taken, returned from the next call.
+#[cfg(feature = "deflate")]
+pub struct GzEncoder<W: Write> {
+    writer: W,
+    compressor: StreamCompressor,
+    header: Option<GzipHeader>,
+    pending: Vec<u8>,
+    crc: u32,
+    len: u64,
+    flushed: bool,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "deflate")]
+impl<W: Write> GzEncoder<W> {
+    /// Wraps the writer the compressed bytes should go to. Nothing is
+    /// written to it until the first write or flush.
+    ///
+    /// # Arguments
+    ///
+    /// * 'writer' - The inner writer.
+    /// * 'options' - How to find matches and split blocks.
+    pub fn new(writer: W, options: CompressOptions) -> Self {
+        Self::with_header(writer, options, GzipHeader::default())
+    }
+    /// Like new, but starting the file with header rather than an empty
+    /// one, such as to store the name and modification time of the file
+    /// being compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * 'writer' - The inner writer.
+    /// * 'options' - How to find matches and split blocks.
+    /// * 'header' - The header to write, laid out by GzipHeader::to_bytes.
+    pub fn with_header(writer: W, options: CompressOptions, header: GzipHeader) -> Self {
+        Self {
+            writer,
+            compressor: StreamCompressor::new(options),
+            header: Some(header),
+            pending: Vec::new(),
+            crc: 0,
+            len: 0,
+            flushed: false,
+            error: None,
+        }
+    }
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+    /// Returns the blocks, literals and matches written so far. Data still
+    /// waiting for a full chunk or a flush isn't counted, and bytes_out
+    /// doesn't count the header or trailer.
+    pub fn stats(&self) -> &CompressStats {
+        self.compressor.stats()
+    }
+    /// Returns how much heap memory the encoder has allocated, the window
+    /// and the bytes waiting to be compressed, and the header and
+    /// compressed bytes not yet written. The codes built for each block
+    /// are freed once it's written, so aren't counted.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.compressor.memory_usage();
+        usage.buffers +=
+            self.pending.capacity() + self.header.as_ref().map_or(0, GzipHeader::memory_usage);
+        usage
+    }
+    /// Compresses whatever is left, writes the trailer, and returns the
+    /// inner writer.
+    ///
+    /// # Returns
+    ///
+    /// The inner writer, or the io::Error from writing to it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let stream = self.compressor.compress(true);
+        self.queue(&stream);
+        self.pending.extend_from_slice(&self.crc.to_le_bytes());
+        self.pending
+            .extend_from_slice(&(self.len as u32).to_le_bytes());
+
+        self.write_pending()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+    /// Queues bytes of the stream to be written, after the header if it
+    /// hasn't been yet.
+    fn queue(&mut self, stream: &[u8]) {
+        if let Some(header) = self.header.take() {
+            self.pending.extend(header.to_bytes());
+        }
+        self.pending.extend_from_slice(stream);
+    }
+    /// Writes the pending bytes to the inner writer, keeping whatever it
+    /// doesn't take if it fails.
+    fn write_pending(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let result = write_counted(&mut self.writer, &self.pending, &mut written);
+        self.pending.drain(..written);
+        result
+    }
+}
+
+#[cfg(feature = "deflate")]
+impl<W: Write> Write for GzEncoder<W> {
+    /// Takes bytes to compress, writing out whatever has been compressed.
+    /// Bytes the inner writer didn't take from an earlier call are written
+    /// first, and if that fails none of buf is taken.
+    ///
+    /// # Returns
+    ///
+    /// buf's length, or the io::Error from the inner writer. If the inner
+    /// writer fails once buf has been taken, the error is returned from the
+    /// next call instead.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.write_pending()?;
+
+        self.crc = crc::update(self.crc, buf);
+        self.len += buf.len() as u64;
+        self.flushed = false;
+
+        let stream = self.compressor.write(buf);
+        if !stream.is_empty() {
+            self.queue(&stream);
+            if let Err(err) = self.write_pending() {
+                self.error = Some(err);
+            }
+        }
+        Ok(buf.len())
+    }
+    /// Compresses everything written so far and ends it with a sync flush,
+    /// then flushes the inner writer. Flushing again with nothing new
+    /// written adds nothing to the stream, but still writes anything the
+    /// inner writer didn't take before.
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if !self.flushed {
+            let stream = self.compressor.sync_flush();
+            self.queue(&stream);
+            self.flushed = true;
+        }
+        self.write_pending()?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,326 @@
+//! The zlib container, RFC 1950: a 2 byte header, a DEFLATE stream, and the
+//! Adler-32 of the data. It's what PNG, git, and HTTP's deflate encoding
+//! wrap DEFLATE in rather than gzip.
+//!
+//! Runs on untrusted input, so nothing here may be able to panic, see
+//! Panics in the README.
+#![deny(
+    clippy::indexing_slicing,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable
+)]
+use std::{error::Error, fmt::Display, io};
+
+use crate::stream::DeflateError;
+#[cfg(feature = "inflate")]
+use crate::{adler, inflate::DeflateData};
+
+/// The only compression method zlib defines, DEFLATE.
+const CM_DEFLATE: u8 = 8;
+
+/// The largest CINFO, the log2 of the window size minus 8, DEFLATE allows.
+const MAX_CINFO: u8 = 7;
+
+/// The FLG bit saying a preset dictionary's Adler-32 follows the header.
+const FDICT: u8 = 0x20;
+
+/// A custom error type for zlib related errors.
+///
+/// # Members
+///
+/// * 'InvalidHeader' - Used when the header bytes fail their check or are
+///             cut short. Contains the header bytes there were.
+/// * 'UnsupportedCompressionMethod' - Used when CM isn't 8, DEFLATE.
+///             Contains the CM nibble.
+/// * 'InvalidWindowSize' - Used when CINFO names a window larger than
+///             DEFLATE's 32KB. Contains CINFO.
+/// * 'PresetDictionary' - Used when FDICT is set but no dictionary was
+///             given. Contains DICTID, the Adler-32 of the dictionary
+///             wanted.
+/// * 'DictionaryMismatch' - Used when the dictionary given isn't the one
+///             DICTID names.
+/// * 'ChecksumMismatch' - Used when the Adler-32 in the trailer doesn't
+///             match the decompressed data.
+/// * 'DeflateError' - Wrapper for a DeflateError raised while decompressing.
+#[derive(Debug)]
+pub enum ZlibError {
+    InvalidHeader(Vec<u8>),
+    UnsupportedCompressionMethod(u8),
+    InvalidWindowSize(u8),
+    PresetDictionary(u32),
+    DictionaryMismatch { expected: u32, actual: u32 },
+    ChecksumMismatch { expected: u32, actual: u32 },
+    DeflateError(DeflateError),
+}
+
+impl Display for ZlibError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZlibError::InvalidHeader(header) => {
+                write!(f, "Error: Failed to parse zlib header bytes {:?}", header)
+            }
+            ZlibError::UnsupportedCompressionMethod(cm) => {
+                write!(
+                    f,
+                    "Error: Compression method {} is not supported, only 8 (DEFLATE) is",
+                    cm
+                )
+            }
+            ZlibError::InvalidWindowSize(cinfo) => {
+                write!(
+                    f,
+                    "Error: CINFO {} asks for a window larger than 32KB",
+                    cinfo
+                )
+            }
+            ZlibError::PresetDictionary(id) => {
+                write!(
+                    f,
+                    "Error: Stream needs the preset dictionary with Adler-32 {:#010x}",
+                    id
+                )
+            }
+            ZlibError::DictionaryMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Stream needs the dictionary with Adler-32 {:#010x}, but was given {:#010x}",
+                    expected, actual
+                )
+            }
+            ZlibError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Expected Adler-32 {:#010x}, but the data hashed to {:#010x}",
+                    expected, actual
+                )
+            }
+            ZlibError::DeflateError(err) => {
+                write!(f, "Error: Decompression raised the {}", err)
+            }
+        }
+    }
+}
+
+impl Error for ZlibError {}
+
+impl From<DeflateError> for ZlibError {
+    fn from(err: DeflateError) -> Self {
+        ZlibError::DeflateError(err)
+    }
+}
+
+// Lets ZlibErrors be returned from io::Read and io::Write implementations,
+// everything but running out of memory is invalid data.
+impl From<ZlibError> for io::Error {
+    fn from(err: ZlibError) -> Self {
+        match &err {
+            ZlibError::DeflateError(inner)
+                if matches!(inner.inner(), DeflateError::OutOfMemoryError(_)) =>
+            {
+                io::Error::new(io::ErrorKind::OutOfMemory, err)
+            }
+            _ => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// The header compress_zlib writes, a 32KB window at the default level.
+#[cfg(feature = "deflate")]
+pub(crate) const HEADER: [u8; 2] = [0x78, 0x9c];
+
+/// A parsed zlib header.
+///
+/// # Fields
+///
+/// * 'window_size' - The window the stream was compressed with, from CINFO.
+/// * 'level' - FLEVEL, from 0 for the fastest compression to 3 for the
+///         smallest. Only informational.
+/// * 'dictionary_id' - The Adler-32 of the preset dictionary the stream
+///         needs, if FDICT is set.
+/// * 'end_idx' - The index of the first byte after the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZlibHeader {
+    pub window_size: usize,
+    pub level: u8,
+    pub dictionary_id: Option<u32>,
+    pub end_idx: usize,
+}
+
+impl ZlibHeader {
+    /// Parses the header at the start of a zlib stream.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - The zlib stream, starting at its header.
+    ///
+    /// # Returns
+    ///
+    /// The header, or a ZlibError if it isn't valid.
+    pub fn build(bytes: &[u8]) -> Result<Self, ZlibError> {
+        let Some(&[cmf, flg]) = bytes.first_chunk::<2>() else {
+            return Err(ZlibError::InvalidHeader(bytes.to_vec()));
+        };
+
+        if u16::from_be_bytes([cmf, flg]) % 31 != 0 {
+            return Err(ZlibError::InvalidHeader(vec![cmf, flg]));
+        }
+        if cmf & 0x0f != CM_DEFLATE {
+            return Err(ZlibError::UnsupportedCompressionMethod(cmf & 0x0f));
+        }
+        if cmf >> 4 > MAX_CINFO {
+            return Err(ZlibError::InvalidWindowSize(cmf >> 4));
+        }
+
+        let mut header = Self {
+            window_size: 1 << ((cmf >> 4) + 8),
+            level: flg >> 6,
+            dictionary_id: None,
+            end_idx: 2,
+        };
+        if flg & FDICT != 0 {
+            let Some(&[i0, i1, i2, i3]) = bytes.get(2..).and_then(|id| id.first_chunk::<4>())
+            else {
+                return Err(ZlibError::InvalidHeader(bytes.to_vec()));
+            };
+            header.dictionary_id = Some(u32::from_be_bytes([i0, i1, i2, i3]));
+            header.end_idx = 6;
+        }
+
+        Ok(header)
+    }
+}
+
+/// Decompresses a zlib stream and checks its Adler-32.
+///
+/// # Arguments
+///
+/// * 'bytes' - The zlib stream, ending with its trailer.
+///
+/// # Returns
+///
+/// The decompressed data, or a ZlibError. A stream that needs a preset
+/// dictionary fails with ZlibError::PresetDictionary naming it, see
+/// decompress_with_dictionary.
+#[cfg(feature = "inflate")]
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    decompress_with_limit(bytes, None)
+}
+
+/// Like decompress, but for when the decompressed size is known, such as
+/// from an image's dimensions. The output is allocated once, and decoding
+/// fails with DeflateError::OutputLimitError rather than going past it.
+///
+/// # Arguments
+///
+/// * 'bytes' - The zlib stream, ending with its trailer.
+/// * 'limit' - The most bytes to decompress, or None for no limit.
+///
+/// # Returns
+///
+/// The decompressed data, or a ZlibError.
+#[cfg(feature = "inflate")]
+pub fn decompress_with_limit(bytes: &[u8], limit: Option<u64>) -> Result<Vec<u8>, ZlibError> {
+    decompress_stream(bytes, None, limit)
+}
+
+/// Like decompress, for a stream compressed with a preset dictionary, which
+/// primes the window before the first block. The dictionary has to be the
+/// one the header names by its Adler-32, see ZlibHeader::dictionary_id.
+///
+/// # Arguments
+///
+/// * 'bytes' - The zlib stream, ending with its trailer.
+/// * 'dictionary' - The dictionary the stream was compressed with. It's
+///         ignored if the stream doesn't set FDICT.
+///
+/// # Returns
+///
+/// The decompressed data, or a ZlibError. A dictionary that isn't the one
+/// named fails with ZlibError::DictionaryMismatch.
+#[cfg(feature = "inflate")]
+pub fn decompress_with_dictionary(bytes: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    decompress_stream(bytes, Some(dictionary), None)
+}
+
+/// Decompresses a zlib stream a chunk at a time, checking its Adler-32
+/// once it ends, for when the data is too large to hold at once. Chunks
+/// handed to the sink before an error aren't taken back.
+#[cfg(all(feature = "inflate", feature = "deflate"))]
+pub(crate) fn decompress_to_sink<F: FnMut(&[u8])>(
+    bytes: &[u8],
+    mut sink: F,
+) -> Result<u64, ZlibError> {
+    let (_, body, expected) = split(bytes, None)?;
+
+    let mut actual = adler::hash(&[]);
+    let len = DeflateData::build(body).try_decompress_to_sink(|chunk| {
+        actual = adler::update(actual, chunk);
+        sink(chunk);
+        Ok::<_, ZlibError>(())
+    })?;
+
+    if expected != actual {
+        return Err(ZlibError::ChecksumMismatch { expected, actual });
+    }
+    Ok(len)
+}
+
+/// Checks a zlib stream's header, and the dictionary against the one it
+/// names, returning the header, the DEFLATE stream, and the Adler-32 from
+/// the trailer.
+#[cfg(feature = "inflate")]
+fn split<'a>(
+    bytes: &'a [u8],
+    dictionary: Option<&[u8]>,
+) -> Result<(ZlibHeader, &'a [u8], u32), ZlibError> {
+    let header = ZlibHeader::build(bytes)?;
+    match (header.dictionary_id, dictionary) {
+        (None, _) => {}
+        (Some(expected), None) => return Err(ZlibError::PresetDictionary(expected)),
+        (Some(expected), Some(dictionary)) => {
+            let actual = adler::hash(dictionary);
+            if actual != expected {
+                return Err(ZlibError::DictionaryMismatch { expected, actual });
+            }
+        }
+    }
+
+    let Some((body, &[a0, a1, a2, a3])) = bytes
+        .get(header.end_idx..)
+        .and_then(|body| body.split_last_chunk::<4>())
+    else {
+        return Err(DeflateError::UnexpectedEofError { bit_offset: 0 }.into());
+    };
+
+    Ok((header, body, u32::from_be_bytes([a0, a1, a2, a3])))
+}
+
+/// Does the work of decompress and its variants.
+#[cfg(feature = "inflate")]
+fn decompress_stream(
+    bytes: &[u8],
+    dictionary: Option<&[u8]>,
+    limit: Option<u64>,
+) -> Result<Vec<u8>, ZlibError> {
+    let (header, body, expected) = split(bytes, dictionary)?;
+    let dictionary = dictionary.filter(|_| header.dictionary_id.is_some());
+
+    let size_hint = limit.map_or(0, |limit| usize::try_from(limit).unwrap_or(usize::MAX));
+    let mut deflate = DeflateData::build_with_size_hint(body, size_hint);
+    deflate.set_max_output_size(limit);
+    if let Some(dictionary) = dictionary {
+        deflate.set_dictionary(dictionary);
+    }
+
+    let mut output = Vec::new();
+    deflate.decompress_to_vec(&mut output)?;
+
+    let actual = adler::hash(&output);
+    if expected != actual {
+        return Err(ZlibError::ChecksumMismatch { expected, actual });
+    }
+
+    Ok(output)
+}
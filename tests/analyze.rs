@@ -0,0 +1,83 @@
+#![cfg(feature = "inflate")]
+
+use std::fs;
+
+use gzip::{
+    analyze::{analyze, breakdown, breakdown_gzip, Breakdown},
+    gzip::GzipHeader,
+    inflate::BlockType,
+};
+
+#[test]
+fn test_analyze_fixed_block() {
+    // A final fixed block holding a literal 'A' and the end of block.
+    let stream = [0x73, 0x04, 0x00];
+
+    let blocks = analyze(&stream).unwrap();
+
+    assert_eq!(blocks.len(), 1);
+    let block = &blocks[0];
+    assert_eq!(block.block_type, BlockType::Fixed);
+    assert_eq!(block.bits, 0..18);
+    assert_eq!(block.header_bits(), 3);
+    assert_eq!(block.data_bits(), 15);
+    assert_eq!(block.literal_lengths[b'A' as usize], 1);
+    assert_eq!(block.literal_lengths[256], 1);
+    // Two equally likely symbols take a bit each.
+    assert_eq!(block.literal_entropy(), 1.0);
+    assert_eq!(block.ideal_bits(), 2.0);
+}
+
+#[test]
+fn test_analyze_counts_every_byte() {
+    for name in ["block_type_0", "block_type_2_long", "sync_flush"] {
+        let bytes = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+        let header = GzipHeader::build(&bytes).unwrap();
+
+        let blocks = analyze(&bytes[header.end_idx..]).unwrap();
+
+        // Each length symbol covers a range of lengths, so only literals
+        // and the number of matches can be checked against the output.
+        let mut merged = blocks[0].clone();
+        for block in &blocks[1..] {
+            assert_eq!(block.bits.start, merged.bits.end);
+            merged.merge(block);
+        }
+        let literals = merged.literal_lengths[..256].iter().sum::<u64>();
+        let matches = merged.literal_lengths[257..].iter().sum::<u64>();
+
+        assert_eq!(matches, merged.distances.iter().sum::<u64>());
+        assert!(literals + merged.stored_bytes + matches * 3 <= raw.len() as u64);
+        assert!(literals + merged.stored_bytes + matches * 258 >= raw.len() as u64);
+    }
+}
+
+#[test]
+fn test_breakdown() {
+    // A final fixed block holding a literal 'A' and the end of block.
+    let (bits, used) = breakdown(&[0x73, 0x04, 0x00, 0xff]).unwrap();
+
+    assert_eq!(used, 3);
+    assert_eq!(
+        bits,
+        Breakdown {
+            literals: 8,
+            lengths: 0,
+            distances: 0,
+            headers: 16,
+            container: 0,
+        }
+    );
+
+    // Every bit of a file is accounted for, member by member.
+    let bytes = fs::read("./tests/compressed/members.gz").unwrap();
+    let members = breakdown_gzip(&bytes).unwrap();
+    assert!(members.len() > 1);
+    assert_eq!(
+        members.iter().map(Breakdown::total).sum::<u64>(),
+        bytes.len() as u64 * 8
+    );
+    // A gzip header is at least 10 bytes, and a trailer 8.
+    assert!(members.iter().all(|member| member.container >= 18 * 8));
+}
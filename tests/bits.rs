@@ -0,0 +1,253 @@
+use gzip::{
+    bits::{BitBuffer, BitVector64, BitWriter},
+    prefix::Code,
+};
+use proptest::prelude::*;
+
+#[test]
+fn test_read_bits() {
+    let mut bitstream = BitVector64::from_be_bytes(&[0b1010_1100, 0xff]);
+
+    // DEFLATE packs fields starting from the least significant bit of each
+    // byte.
+    assert_eq!(bitstream.read_bits(3), Some(0b100));
+    assert_eq!(bitstream.read_bits(5), Some(0b10101));
+    assert_eq!(bitstream.read_bits(9), None);
+    assert_eq!(bitstream.read_bits(8), Some(0xff));
+    assert_eq!(bitstream.read_bits(1), None);
+}
+
+#[test]
+fn test_peek_and_consume() {
+    let mut bitstream = BitVector64::from_be_bytes(&[0b1010_1100]);
+
+    // Peeking returns bits in stream order without advancing.
+    assert_eq!(bitstream.peek_bits(4), 0b0011);
+    assert_eq!(bitstream.peek_bits(4), 0b0011);
+
+    bitstream.consume(6);
+    assert_eq!(bitstream.remaining(), 2);
+
+    // Bits past the end are padded with zeroes.
+    assert_eq!(bitstream.peek_bits(5), 0b01000);
+
+    bitstream.consume(8);
+    assert_eq!(bitstream.remaining(), 0);
+    assert_eq!(bitstream.next(), None);
+}
+
+#[test]
+fn test_align_to_byte() {
+    let mut bits = BitBuffer::new();
+    bits.refill(&[0b1010_1100, 0x5a]);
+
+    // Aligned already, nothing is skipped.
+    assert_eq!(bits.align_to_byte(), 0);
+
+    // A 3 bit header leaves 5 bits of padding in the first byte.
+    assert_eq!(bits.read_bits(3), Some(0b100));
+    assert_eq!(bits.align_to_byte(), 5);
+    assert_eq!(bits.read_bits(8), Some(0x5a));
+    assert_eq!(bits.align_to_byte(), 0);
+}
+
+#[test]
+fn test_bit_writer() {
+    let mut writer = BitWriter::new();
+    writer.write_bits(0b100, 3);
+    // Codes go most significant bit first, unlike integers.
+    writer.write_code(Code::from(0b0011_0001, 8));
+    writer.align_to_byte();
+    writer.write_bytes(&[0x5a]);
+    assert_eq!(writer.bit_len(), 24);
+
+    let mut bits = BitBuffer::new();
+    bits.refill(&writer.finish());
+    assert_eq!(bits.read_bits(3), Some(0b100));
+    // Peeked in stream order, which leaves the code's first bit lowest.
+    assert_eq!(bits.peek_bits(8), 0b1000_1100);
+    bits.consume(8);
+    assert_eq!(bits.align_to_byte(), 5);
+    assert_eq!(bits.read_bits(8), Some(0x5a));
+}
+
+#[test]
+fn test_refill_word() {
+    let input = (0..40u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+
+    // Loading a word at a time reads the same bits as a byte at a time,
+    // whatever is already buffered.
+    for consumed in [0, 1, 7, 8, 13, 60] {
+        let mut words = BitBuffer::new();
+        let mut bytes = BitBuffer::new();
+        let (mut word_pos, mut byte_pos) = (0, 0);
+
+        for _ in 0..4 {
+            word_pos += words.refill(&input[word_pos..]);
+            while bytes.push_byte(input[byte_pos]) {
+                byte_pos += 1;
+            }
+            assert_eq!((words.buffer, words.len), (bytes.buffer, bytes.len));
+            assert_eq!(word_pos, byte_pos);
+
+            words.consume(consumed);
+            bytes.consume(consumed);
+        }
+    }
+}
+
+// The rest check the bit readers and writer against a plain list of bits,
+// every length at every alignment where that's small enough to go through
+// exhaustively, and random sequences of calls otherwise.
+
+/// Pushes prefix bits one at a time, then len bits of value at once, then
+/// one more bit, returning the vector and the bits it should hold.
+fn pushed(prefix: usize, value: u64, len: usize) -> (BitVector64, Vec<u8>) {
+    let mut vector = BitVector64::new();
+    let mut model = Vec::new();
+
+    for i in 0..prefix {
+        let bit = (i % 3 == 0) as u8;
+        vector.push_bit(bit).unwrap();
+        model.push(bit);
+    }
+    vector.push_buffer(value, len).unwrap();
+    // The most significant of the len bits goes first.
+    model.extend((0..len).rev().map(|i| (value >> i) as u8 & 1));
+    vector.push_bit(1).unwrap();
+    model.push(1);
+
+    (vector, model)
+}
+
+#[test]
+fn test_bit_vector_every_alignment() {
+    let value = 0x9e37_79b9_7f4a_7c15;
+
+    for prefix in 0..=128 {
+        for len in 0..=64 {
+            let (mut vector, model) = pushed(prefix, value, len);
+            assert_eq!(vector.len, model.len());
+            let popped: Vec<u8> = std::iter::from_fn(|| vector.pop_front()).collect();
+            assert_eq!(popped, model, "prefix {} len {}", prefix, len);
+
+            let (vector, model) = pushed(prefix, value, len);
+            assert_eq!(vector.collect::<Vec<u8>>(), model);
+
+            // read_bits gives the bits back first bit lowest.
+            let (mut vector, model) = pushed(prefix, value, len);
+            for chunk in model.chunks(13) {
+                let expected = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, &bit)| acc | (bit as u64) << i);
+                assert_eq!(vector.read_bits(chunk.len() as u8), Some(expected));
+            }
+            assert_eq!(vector.remaining(), 0);
+        }
+    }
+
+    assert!(BitVector64::new().push_buffer(0, 65).is_err());
+    assert!(BitVector64::new().push_bit(2).is_err());
+}
+
+#[test]
+fn test_bit_buffer_every_alignment() {
+    let input = (0..16u8)
+        .map(|i| i.wrapping_mul(73) ^ 0xa5)
+        .collect::<Vec<_>>();
+    let model: Vec<u8> = input
+        .iter()
+        .flat_map(|&byte| (0..8).map(move |i| byte >> i & 1))
+        .collect();
+
+    // Consumed bits leave the buffer at every alignment before each refill,
+    // from the word load and from a byte at a time.
+    for consumed in 0..=64u8 {
+        for take in [8, input.len()] {
+            let mut bits = BitBuffer::new();
+            let mut position = bits.refill(&input[..take.min(input.len())]);
+            bits.consume(consumed);
+            let mut read = consumed.min(position as u8 * 8) as usize;
+
+            position += bits.refill(&input[position..]);
+            while let Some(bit) = bits.read_bits(1) {
+                assert_eq!(bit as u8, model[read], "consumed {}", consumed);
+                read += 1;
+            }
+            assert_eq!(read, position * 8);
+        }
+    }
+}
+
+/// The pieces input arrives in, and how many bits to read between them.
+fn pieces() -> impl Strategy<Value = (Vec<u8>, Vec<(usize, u8)>)> {
+    (
+        prop::collection::vec(any::<u8>(), 0..64),
+        prop::collection::vec((0..12usize, 0..=64u8), 0..32),
+    )
+}
+
+proptest! {
+    #[test]
+    fn test_bit_vector_pushes(pushes in prop::collection::vec((any::<u64>(), 0..=64usize), 0..16)) {
+        let mut vector = BitVector64::new();
+        let mut model = Vec::new();
+        for (value, len) in pushes {
+            if len == 1 {
+                vector.push_bit(value as u8 & 1).unwrap();
+            } else {
+                vector.push_buffer(value, len).unwrap();
+            }
+            model.extend((0..len).rev().map(|i| (value >> i) as u8 & 1));
+        }
+
+        prop_assert_eq!(vector.len, model.len());
+        let popped: Vec<u8> = std::iter::from_fn(|| vector.pop_front()).collect();
+        prop_assert_eq!(popped, model);
+    }
+
+    #[test]
+    fn test_bit_buffer_pieces((input, steps) in pieces()) {
+        let model: Vec<u8> = input
+            .iter()
+            .flat_map(|&byte| (0..8).map(move |i| byte >> i & 1))
+            .collect();
+        let mut bits = BitBuffer::new();
+        let (mut position, mut read) = (0, 0);
+
+        for (piece, n) in steps {
+            let end = input.len().min(position + piece);
+            position += bits.refill(&input[position..end]);
+            prop_assert!(bits.len <= 64);
+
+            match bits.read_bits(n) {
+                Some(value) => {
+                    for i in 0..n as usize {
+                        prop_assert_eq!((value >> i) as u8 & 1, model[read + i]);
+                    }
+                    read += n as usize;
+                }
+                None => prop_assert!(n > bits.len),
+            }
+            prop_assert_eq!(read + bits.len as usize, position * 8);
+        }
+    }
+
+    #[test]
+    fn test_bit_writer_round_trip(fields in prop::collection::vec((any::<u32>(), 0..=32u8), 0..64)) {
+        let mut writer = BitWriter::new();
+        for &(value, n) in &fields {
+            writer.write_bits(value, n);
+        }
+        let bytes = writer.finish();
+
+        let mut bits = BitBuffer::new();
+        let mut position = 0;
+        for (value, n) in fields {
+            position += bits.refill(&bytes[position..]);
+            let mask = u64::MAX.checked_shr(64 - n as u32).unwrap_or(0);
+            prop_assert_eq!(bits.read_bits(n), Some(value as u64 & mask));
+        }
+    }
+}
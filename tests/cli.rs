@@ -0,0 +1,387 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+//! Runs the gzip-rs binary on files copied into a scratch directory.
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use gzip::bufread::GzDecoder;
+
+/// Returns an empty directory for a test to work in, named after it so
+/// tests running at once don't share one.
+fn scratch(name: &str) -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("cli")
+        .join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Copies a file from tests/compressed into directory.
+fn fixture(directory: &Path, name: &str) -> PathBuf {
+    let path = directory.join(name);
+    fs::copy(Path::new("./tests/compressed").join(name), &path).unwrap();
+    path
+}
+
+/// Copies a file from tests/raw into directory, as name.
+fn raw_fixture(directory: &Path, raw: &str, name: &str) -> PathBuf {
+    let path = directory.join(name);
+    fs::copy(Path::new("./tests/raw").join(raw), &path).unwrap();
+    path
+}
+
+/// Decompresses a gzip file, returning the header's FNAME and MTIME along
+/// with the decompressed bytes.
+fn read_gzip(path: &Path) -> (Option<String>, u32, Vec<u8>) {
+    let bytes = fs::read(path).unwrap();
+    let mut decoder = GzDecoder::new(&bytes[..]);
+    let mut data = Vec::new();
+    decoder.read_to_end(&mut data).unwrap();
+
+    let header = decoder.header().unwrap();
+    (header.fname.clone(), header.mtime, data)
+}
+
+/// Runs gzip-rs with args.
+fn gzip_rs(args: &[&Path]) -> Output {
+    run(env!("CARGO_BIN_EXE_gzip-rs").as_ref(), args, b"")
+}
+
+/// Runs program with args, handing it stdin.
+fn run(program: &Path, args: &[&Path], stdin: &[u8]) -> Output {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Fed from another thread, so output filling its pipe can't hold up
+    // input going in.
+    let mut input = child.stdin.take().unwrap();
+    let stdin = stdin.to_vec();
+    let feeder = thread::spawn(move || input.write_all(&stdin));
+
+    let output = child.wait_with_output().unwrap();
+    feeder.join().unwrap().unwrap();
+    output
+}
+
+#[test]
+fn test_decompress_members() {
+    let directory = scratch("decompress_members");
+    let input = fixture(&directory, "members.gz");
+
+    let output = gzip_rs(&["-d".as_ref(), &input]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read("./tests/raw/members").unwrap(),
+        fs::read(directory.join("members")).unwrap()
+    );
+
+    // The output is there now, so it isn't overwritten.
+    let output = gzip_rs(&["-d".as_ref(), &input]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+}
+
+#[test]
+fn test_compress() {
+    let directory = scratch("compress");
+    let input = raw_fixture(&directory, "repeats", "repeats");
+    let raw = fs::read(&input).unwrap();
+
+    // -n is the default, so neither stores a name or timestamp.
+    for args in [&[][..], &["-n".as_ref()][..]] {
+        let output = gzip_rs(&[args, &[input.as_path()]].concat());
+        assert!(output.status.success());
+        assert_eq!(
+            read_gzip(&directory.join("repeats.gz")),
+            (None, 0, raw.clone())
+        );
+        // FILE is left in place.
+        assert_eq!(fs::read(&input).unwrap(), raw);
+        fs::remove_file(directory.join("repeats.gz")).unwrap();
+    }
+
+    // Into a directory, and not over an output that's already there.
+    let created = directory.join("created").join("");
+    let output = gzip_rs(&["-o".as_ref(), &created, &input]);
+    assert!(output.status.success());
+    assert_eq!(read_gzip(&created.join("repeats.gz")).2, raw);
+    let output = gzip_rs(&["-o".as_ref(), &created, &input]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+
+    // A file that's already compressed is left alone.
+    let compressed = fixture(&directory, "members.gz");
+    let output = gzip_rs(&[&compressed]);
+    assert!(!output.status.success());
+    assert!(!directory.join("members.gz.gz").exists());
+}
+
+#[test]
+fn test_name() {
+    let directory = scratch("name");
+    let input = raw_fixture(&directory, "repeats", "original.txt");
+    let raw = fs::read(&input).unwrap();
+    let mtime = 1_600_000_000;
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64);
+    File::options()
+        .write(true)
+        .open(&input)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    // -N stores FILE's name and timestamp.
+    let output = gzip_rs(&["-N".as_ref(), &input]);
+    assert!(output.status.success());
+    let compressed = directory.join("renamed.gz");
+    fs::rename(directory.join("original.txt.gz"), &compressed).unwrap();
+    assert_eq!(
+        read_gzip(&compressed),
+        (Some(String::from("original.txt")), mtime, raw.clone())
+    );
+
+    // -N restores them.
+    let restored = directory.join("restored");
+    fs::create_dir(&restored).unwrap();
+    let output = gzip_rs(&[
+        "-d".as_ref(),
+        "-N".as_ref(),
+        "-o".as_ref(),
+        &restored,
+        &compressed,
+    ]);
+    assert!(output.status.success());
+    let path = restored.join("original.txt");
+    assert_eq!(fs::read(&path).unwrap(), raw);
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+    // -n ignores them, naming the output after FILE and leaving its time
+    // as when it was written.
+    let ignored = directory.join("ignored");
+    fs::create_dir(&ignored).unwrap();
+    let output = gzip_rs(&[
+        "-d".as_ref(),
+        "-n".as_ref(),
+        "-o".as_ref(),
+        &ignored,
+        &compressed,
+    ]);
+    assert!(output.status.success());
+    assert!(!ignored.join("original.txt").exists());
+    let path = ignored.join("renamed");
+    assert_eq!(fs::read(&path).unwrap(), raw);
+    assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+}
+
+#[test]
+fn test_output_file() {
+    let directory = scratch("output_file");
+    let input = fixture(&directory, "repeats.gz");
+    let output_path = directory.join("out.txt");
+
+    let output = gzip_rs(&["-d".as_ref(), "-o".as_ref(), &output_path, &input]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read("./tests/raw/repeats").unwrap(),
+        fs::read(&output_path).unwrap()
+    );
+    assert!(!directory.join("repeats").exists());
+
+    // It's there now, so isn't overwritten.
+    fs::write(&output_path, b"kept").unwrap();
+    let output = gzip_rs(&["-d".as_ref(), "-o".as_ref(), &output_path, &input]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    assert_eq!(fs::read(&output_path).unwrap(), b"kept");
+
+    // A file can only take one output.
+    let other = fixture(&directory, "block_type_0.gz");
+    let output = gzip_rs(&[
+        "-d".as_ref(),
+        "-o".as_ref(),
+        &directory.join("both"),
+        &input,
+        &other,
+    ]);
+    assert!(!output.status.success());
+    assert!(!directory.join("both").exists());
+}
+
+#[test]
+fn test_output_directory() {
+    let directory = scratch("output_directory");
+    let inputs = [
+        fixture(&directory, "repeats.gz"),
+        fixture(&directory, "block_type_0.gz"),
+    ];
+
+    // An existing directory.
+    let existing = directory.join("existing");
+    fs::create_dir(&existing).unwrap();
+    let output = gzip_rs(&[
+        "-d".as_ref(),
+        "-o".as_ref(),
+        &existing,
+        &inputs[0],
+        &inputs[1],
+    ]);
+    assert!(output.status.success());
+    for name in ["repeats", "block_type_0"] {
+        assert_eq!(
+            fs::read(Path::new("./tests/raw").join(name)).unwrap(),
+            fs::read(existing.join(name)).unwrap()
+        );
+    }
+
+    // One that ends in a separator is made if it isn't there.
+    let created = directory.join("created").join("");
+    let output = gzip_rs(&["-d".as_ref(), "-o".as_ref(), &created, &inputs[0]]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read("./tests/raw/repeats").unwrap(),
+        fs::read(created.join("repeats")).unwrap()
+    );
+
+    // An output already in the directory isn't overwritten, the rest are
+    // still written.
+    fs::remove_file(existing.join("block_type_0")).unwrap();
+    fs::write(existing.join("repeats"), b"kept").unwrap();
+    let output = gzip_rs(&[
+        "-d".as_ref(),
+        "-o".as_ref(),
+        &existing,
+        &inputs[0],
+        &inputs[1],
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(fs::read(existing.join("repeats")).unwrap(), b"kept");
+    assert!(existing.join("block_type_0").exists());
+}
+
+#[test]
+fn test_cat() {
+    let directory = scratch("cat");
+    let members = fixture(&directory, "members.gz");
+    let repeats = fixture(&directory, "repeats.gz");
+    let raw = |name: &str| fs::read(Path::new("./tests/raw").join(name)).unwrap();
+
+    let output = gzip_rs(&["cat".as_ref(), &members, &repeats]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [raw("members"), raw("repeats")].concat());
+
+    // - is stdin, as is no FILE at all.
+    let stdin = fs::read(&members).unwrap();
+    let output = run(
+        env!("CARGO_BIN_EXE_gzip-rs").as_ref(),
+        &["cat".as_ref(), &repeats, "-".as_ref()],
+        &stdin,
+    );
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [raw("repeats"), raw("members")].concat());
+
+    let output = run(
+        env!("CARGO_BIN_EXE_gzip-rs").as_ref(),
+        &["cat".as_ref()],
+        &stdin,
+    );
+    assert!(output.status.success());
+    assert_eq!(output.stdout, raw("members"));
+
+    // A bad file is reported, and the rest still written.
+    let broken = directory.join("broken.gz");
+    fs::write(&broken, b"not gzip").unwrap();
+    let output = gzip_rs(&["cat".as_ref(), &broken, &repeats]);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, raw("repeats"));
+}
+
+#[test]
+fn test_zcat() {
+    let directory = scratch("zcat");
+    let members = fixture(&directory, "members.gz");
+
+    // Copied, so it works where links can't be made.
+    let zcat = directory.join(format!("zcat{}", std::env::consts::EXE_SUFFIX));
+    fs::copy(env!("CARGO_BIN_EXE_gzip-rs"), &zcat).unwrap();
+
+    let output = run(&zcat, &[&members], b"");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, fs::read("./tests/raw/members").unwrap());
+
+    let output = run(&zcat, &[], &fs::read(&members).unwrap());
+    assert!(output.status.success());
+    assert_eq!(output.stdout, fs::read("./tests/raw/members").unwrap());
+}
+
+#[test]
+fn test_progress() {
+    let directory = scratch("progress");
+    let members = fixture(&directory, "members.gz");
+
+    // stderr is a pipe here, so nothing is drawn on it.
+    let output = gzip_rs(&["cat".as_ref(), &members]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = gzip_rs(&["-d".as_ref(), &members]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let raw = raw_fixture(&directory, "repeats", "repeats");
+    let output = gzip_rs(&[&raw]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+/// Runs gzip-rs with args under script(1), so its stderr is a terminal,
+/// returning what it drew there, or None if script isn't installed.
+#[cfg(target_os = "linux")]
+fn run_in_terminal(args: &str) -> Option<String> {
+    let command = format!("'{}' {} > /dev/null", env!("CARGO_BIN_EXE_gzip-rs"), args);
+    let output = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    assert!(output.status.success());
+
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_progress_terminal() {
+    let directory = scratch("progress_terminal");
+    let members = fixture(&directory, "members.gz");
+    let raw = raw_fixture(&directory, "repeats", "repeats");
+
+    // Decompressing, then compressing.
+    for (args, path) in [
+        (format!("cat '{}'", members.display()), &members),
+        (format!("'{}'", raw.display()), &raw),
+    ] {
+        // Without script there's no terminal to run under.
+        let Some(drawn) = run_in_terminal(&args) else {
+            return;
+        };
+        assert!(
+            drawn.contains(&format!("{}: ", path.display())),
+            "{:?}",
+            drawn
+        );
+        assert!(drawn.contains("% "));
+        // The line is cleared once the file's done.
+        assert!(drawn.ends_with("\r\x1b[K"));
+    }
+    assert!(directory.join("repeats.gz").exists());
+}
@@ -0,0 +1,228 @@
+//! Fixtures generated in code, so tests don't have to rely on files some
+//! external gzip once wrote. Everything is deterministic, the same build
+//! always makes the same bytes.
+//!
+//! Everything but text needs the deflate feature.
+#[cfg(feature = "deflate")]
+use gzip::{
+    deflate::{compress, CompressOptions},
+    gzip::{ExtraField, GzipHeader},
+    stream::BlockType,
+};
+
+/// A generated gzip file and what it decompresses to.
+pub struct Fixture {
+    pub name: &'static str,
+    pub raw: Vec<u8>,
+    pub compressed: Vec<u8>,
+}
+
+/// A small xorshift generator, for random data and corruption that are the
+/// same between runs. Only some of the crates that include this module use
+/// it.
+#[allow(dead_code)]
+pub struct XorShift(u64);
+
+#[allow(dead_code)]
+impl XorShift {
+    /// Starts the generator from seed, which can be anything but 0.
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+    /// Returns the next number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    /// Returns len random bytes.
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+/// Returns text made of a few words picked by a fixed LCG, long enough
+/// that matches have plenty to refer back to.
+pub fn text(len: usize) -> Vec<u8> {
+    const WORDS: [&[u8]; 8] = [
+        b"deflate ",
+        b"huffman ",
+        b"window ",
+        b"literal ",
+        b"the ",
+        b"a ",
+        b"block\n",
+        b"gzip ",
+    ];
+    let mut state = 0x2545_f491u32;
+    let mut text = Vec::with_capacity(len + 8);
+
+    while text.len() < len {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        text.extend_from_slice(WORDS[(state >> 16) as usize % WORDS.len()]);
+    }
+    text.truncate(len);
+    text
+}
+
+/// Returns a gzip member holding data, with the given header.
+#[cfg(feature = "deflate")]
+pub fn member(header: &GzipHeader, data: &[u8], options: &CompressOptions) -> Vec<u8> {
+    let mut bytes = header.to_bytes();
+    bytes.extend(compress(data, options));
+    bytes.extend(gzip::crc::hash(data).to_le_bytes());
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes
+}
+
+/// The sizes the edge_case fixtures are made in: nothing, a single byte,
+/// and either side of the most a stored block holds.
+pub const EDGE_LENGTHS: [usize; 4] = [0, 1, 65535, 65536];
+
+/// Returns every generated fixture: each block type, several members,
+/// headers with every combination of the optional fields, and each block
+/// type at each of EDGE_LENGTHS.
+#[cfg(feature = "deflate")]
+pub fn fixtures() -> Vec<Fixture> {
+    let short = text(600);
+    let long = text(200_000);
+    let plain = GzipHeader::default();
+    let with = |block_type| CompressOptions {
+        block_type: Some(block_type),
+        ..Default::default()
+    };
+
+    let single = |name, header: &GzipHeader, raw: &[u8], options: &CompressOptions| Fixture {
+        name,
+        raw: raw.to_vec(),
+        compressed: member(header, raw, options),
+    };
+
+    let mut fixtures = vec![
+        single("stored", &plain, &short, &with(BlockType::Stored)),
+        single("fixed", &plain, &short, &with(BlockType::Fixed)),
+        single("dynamic", &plain, &short, &with(BlockType::Dynamic)),
+        single("dynamic_long", &plain, &long, &CompressOptions::default()),
+        single(
+            "literals_only",
+            &plain,
+            &short,
+            &CompressOptions {
+                max_chain: 0,
+                ..Default::default()
+            },
+        ),
+        single("empty", &plain, b"", &CompressOptions::default()),
+        single(
+            "fextra",
+            &GzipHeader {
+                fextra: ExtraField::new(b'A', b'p', b"generated".to_vec()).to_bytes(),
+                ..Default::default()
+            },
+            &short,
+            &CompressOptions::default(),
+        ),
+        single(
+            "fhcrc",
+            &GzipHeader {
+                flg: [false, true, false, false, false],
+                ..Default::default()
+            },
+            &short,
+            &CompressOptions::default(),
+        ),
+        single(
+            "all_fields",
+            &GzipHeader {
+                flg: [true, true, false, false, false],
+                mtime: 1_700_000_000,
+                xfl: 2,
+                os: 3,
+                fextra: ExtraField::new(b'A', b'p', vec![1, 2, 3]).to_bytes(),
+                fname: Some(String::from("fixture.txt")),
+                fcomment: Some(String::from("caf\u{e9}")),
+                ..Default::default()
+            },
+            &short,
+            &CompressOptions::default(),
+        ),
+    ];
+
+    // Members of each block type back to back, the last one empty.
+    let mut raw = Vec::new();
+    let mut compressed = Vec::new();
+    for (index, block_type) in [BlockType::Stored, BlockType::Fixed, BlockType::Dynamic]
+        .into_iter()
+        .enumerate()
+    {
+        let data = text(1000 + index * 500);
+        compressed.extend(member(&plain, &data, &with(block_type)));
+        raw.extend(data);
+    }
+    compressed.extend(member(&plain, b"", &CompressOptions::default()));
+    fixtures.push(Fixture {
+        name: "members",
+        raw,
+        compressed,
+    });
+
+    // FEXTRA, FNAME, FCOMMENT and FHCRC, each on or off.
+    for flags in 0..16u8 {
+        let header = GzipHeader {
+            flg: [false, flags & 8 != 0, false, false, false],
+            fextra: (flags & 1 != 0)
+                .then(|| ExtraField::new(b'A', b'p', vec![flags; 4]).to_bytes())
+                .flatten(),
+            fname: (flags & 2 != 0).then(|| String::from("matrix.txt")),
+            fcomment: (flags & 4 != 0).then(|| String::from("generated")),
+            ..Default::default()
+        };
+        let name = HEADER_NAMES[flags as usize];
+        fixtures.push(single(name, &header, &short, &CompressOptions::default()));
+    }
+
+    let edge = text(EDGE_LENGTHS[3]);
+    for (block_type, names) in [
+        (
+            BlockType::Stored,
+            ["stored_0", "stored_1", "stored_65535", "stored_65536"],
+        ),
+        (
+            BlockType::Fixed,
+            ["fixed_0", "fixed_1", "fixed_65535", "fixed_65536"],
+        ),
+        (
+            BlockType::Dynamic,
+            ["dynamic_0", "dynamic_1", "dynamic_65535", "dynamic_65536"],
+        ),
+    ] {
+        for (len, name) in EDGE_LENGTHS.into_iter().zip(names) {
+            fixtures.push(single(name, &plain, &edge[..len], &with(block_type)));
+        }
+    }
+
+    fixtures
+}
+
+/// The names of the header fixtures, indexed by their flags: FEXTRA is 1,
+/// FNAME 2, FCOMMENT 4 and FHCRC 8.
+#[cfg(feature = "deflate")]
+const HEADER_NAMES: [&str; 16] = [
+    "header_none",
+    "header_extra",
+    "header_name",
+    "header_extra_name",
+    "header_comment",
+    "header_extra_comment",
+    "header_name_comment",
+    "header_extra_name_comment",
+    "header_hcrc",
+    "header_extra_hcrc",
+    "header_name_hcrc",
+    "header_extra_name_hcrc",
+    "header_comment_hcrc",
+    "header_extra_comment_hcrc",
+    "header_name_comment_hcrc",
+    "header_extra_name_comment_hcrc",
+];
@@ -0,0 +1,13 @@
+use std::fs;
+
+use gzip::crc;
+
+#[test]
+fn test_incremental_hash() {
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    let crc = raw.chunks(7).fold(0, crc::update);
+
+    assert_eq!(crc, crc::hash(&raw));
+    assert_eq!(crc::hash(b"123456789"), 0xcbf43926);
+}
@@ -1,6 +1,16 @@
-use std::fs;
+#![cfg(all(feature = "inflate", feature = "deflate"))]
 
-use gzip::gzip::GzipFile;
+use std::{
+    borrow::Cow,
+    fs, io,
+    time::{Duration, SystemTime},
+};
+
+use gzip::{
+    deflate::{compress_gzip, CompressOptions},
+    gzip::{ExtraField, ExtraFields, GzipError, GzipFile, GzipHeader, HeaderOptions},
+    inflate::{BlockType, DeflateError, ErrorPosition, Phase},
+};
 
 #[test]
 fn test_block_type_0() {
@@ -52,3 +62,518 @@ fn test_larger_file() {
 
     assert_eq!(raw, decompressed);
 }
+
+#[test]
+fn test_sync_flush() {
+    // A sync flush emits an empty stored block that starts partway through a
+    // byte, followed by more compressed blocks.
+    let mut compressed = GzipFile::from_path("./tests/compressed/sync_flush.gz").unwrap();
+
+    let raw = fs::read("./tests/raw/sync_flush").unwrap();
+
+    let decompressed = compressed.decompress().unwrap();
+
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_decompress_into() {
+    let mut compressed = GzipFile::from_path("./tests/compressed/block_type_2_long.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    // A buffer of exactly the right size is enough.
+    let mut output = vec![0; raw.len()];
+    assert_eq!(compressed.decompress_into(&mut output).unwrap(), raw.len());
+    assert_eq!(raw, output);
+
+    let mut output = vec![0; raw.len() - 1];
+    assert!(matches!(
+        compressed.decompress_into(&mut output),
+        Err(DeflateError::OutputTooSmallError(_))
+    ));
+}
+
+#[test]
+fn test_from_bytes() {
+    let bytes = fs::read("./tests/compressed/block_type_2.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2").unwrap();
+
+    let mut compressed = GzipFile::from_bytes(&bytes).unwrap();
+
+    assert_eq!(raw, compressed.decompress().unwrap());
+}
+
+#[test]
+fn test_stored_zero_copy() {
+    let bytes = fs::read("./tests/compressed/block_type_0.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_0").unwrap();
+
+    // A single stored block is handed back as part of the file's bytes.
+    let mut compressed = GzipFile::from_bytes(&bytes).unwrap();
+    let decompressed = compressed.decompress_cow().unwrap();
+    assert!(matches!(decompressed, Cow::Borrowed(_)));
+    assert!(bytes.as_ptr_range().contains(&decompressed.as_ptr()));
+    assert_eq!(decompressed, raw);
+
+    // Several are each borrowed by stored_slices.
+    let raw = fs::read("./tests/raw/block_type_2").unwrap();
+    let options = CompressOptions {
+        block_type: Some(BlockType::Stored),
+        block_size: 100,
+        ..Default::default()
+    };
+    let bytes = compress_gzip(&raw, &options);
+    let compressed = GzipFile::from_bytes(&bytes).unwrap();
+    let slices = compressed.stored_slices().unwrap().unwrap();
+    assert_eq!(slices.len(), raw.len().div_ceil(100));
+    assert_eq!(slices.concat(), raw);
+    assert_eq!(
+        GzipFile::from_bytes(&bytes)
+            .unwrap()
+            .decompress_cow()
+            .unwrap(),
+        raw
+    );
+
+    // Blocks that aren't stored are decompressed as usual.
+    let bytes = fs::read("./tests/compressed/block_type_2.gz").unwrap();
+    let mut compressed = GzipFile::from_bytes(&bytes).unwrap();
+    assert!(compressed.stored_slices().unwrap().is_none());
+    assert!(matches!(
+        compressed.decompress_cow().unwrap(),
+        Cow::Owned(_)
+    ));
+
+    // The checksum is still confirmed.
+    let mut bytes = compress_gzip(&raw, &options);
+    let crc_idx = bytes.len() - 8;
+    bytes[crc_idx] ^= 1;
+    let compressed = GzipFile::from_bytes(&bytes).unwrap();
+    assert!(matches!(
+        compressed.stored_slices(),
+        Err(DeflateError::ChecksumMismatchError { .. })
+    ));
+}
+
+#[test]
+fn test_decompress_to_vec() {
+    let mut output = Vec::with_capacity(1 << 20);
+    let buffer = output.as_ptr();
+
+    // The same buffer is cleared and reused for each file.
+    for name in ["block_type_0", "block_type_1", "block_type_2"] {
+        let mut compressed =
+            GzipFile::from_path(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        assert_eq!(
+            compressed.decompress_to_vec(&mut output).unwrap(),
+            raw.len()
+        );
+        assert_eq!(output, raw);
+        assert_eq!(output.as_ptr(), buffer);
+    }
+}
+
+#[test]
+fn test_decompress_to_sink() {
+    let mut compressed = GzipFile::from_path("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decompressed = Vec::new();
+    let mut chunks = 0;
+    let mut sink = |chunk: &[u8]| {
+        decompressed.extend_from_slice(chunk);
+        chunks += 1;
+    };
+    // Trait objects work as sinks too.
+    let sink: &mut dyn FnMut(&[u8]) = &mut sink;
+
+    let len = compressed.decompress_to_sink(sink).unwrap();
+
+    assert_eq!(len, raw.len() as u64);
+    assert!(chunks > 1);
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_decompress_to_writer() {
+    let mut compressed = GzipFile::from_path("./tests/compressed/block_type_2_long.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    let mut decompressed = Vec::new();
+    let len = compressed.decompress_to_writer(&mut decompressed).unwrap();
+
+    assert_eq!(len, raw.len() as u64);
+    assert_eq!(raw, decompressed);
+
+    // Errors from the writer come back as GzipError::IoError.
+    let mut full = [0u8; 16];
+    let err = compressed
+        .decompress_to_writer(&mut &mut full[..])
+        .unwrap_err();
+    assert!(matches!(err, GzipError::IoError(_)));
+}
+
+/// A writer that takes at most 1000 bytes per call, counting the calls.
+#[derive(Default)]
+struct Limited {
+    data: Vec<u8>,
+    writes: usize,
+}
+
+impl io::Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[io::IoSlice::new(buf)])
+    }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.writes += 1;
+        let start = self.data.len();
+        for buf in bufs {
+            let room = 1000 - (self.data.len() - start);
+            self.data.extend_from_slice(&buf[..buf.len().min(room)]);
+        }
+        Ok(self.data.len() - start)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_decompress_to_writer_batched() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    // Stored blocks are written from the file's bytes, several per call, and
+    // partial writes pick up where they stopped.
+    let options = CompressOptions {
+        block_type: Some(BlockType::Stored),
+        ..Default::default()
+    };
+    let compressed = compress_gzip(&raw, &options);
+    let mut writer = Limited::default();
+    GzipFile::from_bytes(&compressed)
+        .unwrap()
+        .decompress_to_writer(&mut writer)
+        .unwrap();
+    assert_eq!(raw, writer.data);
+    assert_eq!(writer.writes, raw.len().div_ceil(1000));
+
+    // Anything else is written in batches far larger than a 32KB chunk.
+    let compressed = compress_gzip(&raw, &CompressOptions::default());
+    let mut writes = Vec::new();
+    let mut writer = CountWrites(&mut writes);
+    GzipFile::from_bytes(&compressed)
+        .unwrap()
+        .decompress_to_writer(&mut writer)
+        .unwrap();
+    assert_eq!(writes, [raw.len()]);
+}
+
+/// A writer that records the size of each write.
+struct CountWrites<'a>(&'a mut Vec<usize>);
+
+impl io::Write for CountWrites<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.push(buf.len());
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "memmap")]
+#[test]
+fn test_from_path_mmap() {
+    let mut compressed = GzipFile::from_path_mmap("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    assert_eq!(raw, compressed.decompress().unwrap());
+}
+
+#[test]
+fn test_checksum_mismatch() {
+    let mut bytes = fs::read("./tests/compressed/block_type_2.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2").unwrap();
+
+    // Flip a bit of the stored CRC-32.
+    let crc_idx = bytes.len() - 8;
+    bytes[crc_idx] ^= 1;
+    let expected = u32::from_le_bytes(bytes[crc_idx..crc_idx + 4].try_into().unwrap());
+
+    let err = GzipFile::from_bytes(&bytes)
+        .unwrap()
+        .decompress()
+        .unwrap_err();
+
+    match err {
+        DeflateError::ChecksumMismatchError {
+            expected: e,
+            actual,
+        } => {
+            assert_eq!(e, expected);
+            assert_eq!(actual, gzip::crc::hash(&raw));
+        }
+        err => panic!("Expected a ChecksumMismatchError, got {}", err),
+    }
+}
+
+#[test]
+fn test_size_mismatch() {
+    let mut bytes = fs::read("./tests/compressed/block_type_2.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2").unwrap();
+
+    // Change the stored size, the size is checked before the checksum.
+    let isize_idx = bytes.len() - 4;
+    bytes[isize_idx] ^= 1;
+    bytes[isize_idx - 4] ^= 1;
+
+    let err = GzipFile::from_bytes(&bytes)
+        .unwrap()
+        .decompress()
+        .unwrap_err();
+
+    match err {
+        DeflateError::SizeMismatchError { expected, actual } => {
+            assert_eq!(expected, raw.len() as u32 ^ 1);
+            assert_eq!(actual, raw.len() as u32);
+        }
+        err => panic!("Expected a SizeMismatchError, got {}", err),
+    }
+}
+
+#[test]
+fn test_header_crc() {
+    let mut bytes = fs::read("./tests/compressed/header_crc.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2").unwrap();
+
+    let mut compressed = GzipFile::from_bytes(&bytes).unwrap();
+    assert!(compressed.header.crc.is_some());
+    assert_eq!(compressed.header.fname.as_deref(), Some("block_type_2"));
+    assert_eq!(raw, compressed.decompress().unwrap());
+
+    // Corrupt the file name, the header CRC no longer matches.
+    bytes[10] ^= 0x20;
+    assert!(matches!(
+        GzipFile::from_bytes(&bytes),
+        Err(GzipError::HeaderChecksumMismatch { .. })
+    ));
+
+    let options = HeaderOptions {
+        verify_crc: false,
+        ..HeaderOptions::default()
+    };
+    let mut compressed = GzipFile::from_bytes_with_options(&bytes, &options).unwrap();
+    assert_eq!(raw, compressed.decompress().unwrap());
+}
+
+#[test]
+fn test_max_output_size() {
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let mut compressed = GzipFile::from_path("./tests/compressed/block_type_2_long.gz").unwrap();
+
+    // Exactly the decompressed size is allowed.
+    compressed
+        .deflate
+        .set_max_output_size(Some(raw.len() as u64));
+    assert_eq!(raw, compressed.decompress().unwrap());
+
+    compressed
+        .deflate
+        .set_max_output_size(Some(raw.len() as u64 - 1));
+    assert!(matches!(
+        compressed.decompress().unwrap_err().inner(),
+        DeflateError::OutputLimitError(_)
+    ));
+}
+
+#[test]
+fn test_max_expansion_ratio() {
+    // repeats decompresses to about 4 times its compressed size.
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+    let mut compressed = GzipFile::from_path("./tests/compressed/repeats.gz").unwrap();
+
+    compressed.deflate.set_max_expansion_ratio(Some(5));
+    assert_eq!(raw, compressed.decompress().unwrap());
+
+    compressed.deflate.set_max_expansion_ratio(Some(3));
+    assert!(matches!(
+        compressed.decompress().unwrap_err().inner(),
+        DeflateError::ExpansionRatioError(3)
+    ));
+}
+
+#[test]
+fn test_memory_limit() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+    let mut compressed = GzipFile::from_path("./tests/compressed/repeats.gz").unwrap();
+
+    compressed.deflate.set_memory_limit(Some(1 << 16));
+    assert!(matches!(
+        compressed.decompress().unwrap_err().inner(),
+        DeflateError::MemoryLimitError(_)
+    ));
+
+    // Streaming to a sink only needs the window and a chunk of output.
+    let mut decompressed = Vec::new();
+    compressed
+        .deflate
+        .decompress_to_sink(|chunk| decompressed.extend_from_slice(chunk))
+        .unwrap();
+    assert_eq!(raw, decompressed);
+
+    compressed.deflate.set_memory_limit(Some(1 << 20));
+    assert_eq!(raw, compressed.decompress().unwrap());
+}
+
+#[test]
+fn test_out_of_memory() {
+    // A failed allocation is reported on its own, wherever it happened.
+    let err = DeflateError::PositionedError {
+        error: Box::new(DeflateError::OutOfMemoryError(1 << 40)),
+        position: ErrorPosition {
+            bit_offset: 0,
+            block: 0,
+            phase: Phase::Data,
+        },
+    };
+    let err = GzipError::from(err);
+    assert!(matches!(err, GzipError::OutOfMemory(size) if size == 1 << 40));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::OutOfMemory);
+
+    let err = GzipError::from(DeflateError::MemoryLimitError(1));
+    assert!(matches!(err, GzipError::DeflateError(_)));
+}
+
+#[test]
+fn test_truncated_header() {
+    let bytes = fs::read("./tests/compressed/header_crc.gz").unwrap();
+    let header = GzipHeader::build(&bytes).unwrap();
+
+    // Every way of cutting the header short is an error, not a panic.
+    for end in 0..header.end_idx {
+        assert!(matches!(
+            GzipHeader::build(&bytes[..end]),
+            Err(GzipError::InvalidHeader(_) | GzipError::NotGzipFile(_))
+        ));
+    }
+}
+
+#[test]
+fn test_latin1_name() {
+    // A header with FNAME and FCOMMENT set, both holding an e with an acute
+    // accent, which is 0xe9 in ISO-8859-1 and isn't valid UTF-8 on its own.
+    let mut bytes = vec![0x1f, 0x8b, 8, 0b11000, 0, 0, 0, 0, 0, 3];
+    bytes.extend_from_slice(b"caf\xe9.txt\0r\xe9sum\xe9\0");
+
+    let header = GzipHeader::build(&bytes).unwrap();
+
+    assert_eq!(header.fname.as_deref(), Some("café.txt"));
+    assert_eq!(header.fcomment.as_deref(), Some("résumé"));
+    assert_eq!(header.end_idx, bytes.len());
+}
+
+#[test]
+fn test_extra_fields() {
+    let fields = [
+        ExtraField::new(b'A', b'p', b"apollo".to_vec()),
+        ExtraField::new(b'B', b'C', vec![0x12, 0x34]),
+        ExtraField::new(b'E', b'm', Vec::new()),
+    ];
+    let extra = fields
+        .iter()
+        .flat_map(|field| field.to_bytes().unwrap())
+        .collect::<Vec<_>>();
+
+    // A header with only FEXTRA set.
+    let mut bytes = vec![0x1f, 0x8b, 8, 0b100, 0, 0, 0, 0, 0, 3];
+    bytes.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&extra);
+
+    let header = GzipHeader::build(&bytes).unwrap();
+    assert_eq!(header.end_idx, bytes.len());
+    assert_eq!(
+        header
+            .extra_fields()
+            .map(ExtraField::from)
+            .collect::<Vec<_>>(),
+        fields
+    );
+
+    // A subfield claiming more data than there is ends the iteration.
+    let mut truncated = ExtraFields::new(&extra[..14]);
+    assert_eq!(truncated.next(), Some((b'A', b'p', &b"apollo"[..])));
+    assert_eq!(truncated.next(), None);
+    assert_eq!(truncated.remainder(), &extra[10..14]);
+
+    assert!(ExtraField::new(b'X', b'X', vec![0; 1 << 16])
+        .to_bytes()
+        .is_none());
+}
+
+#[test]
+fn test_strict_flags() {
+    // FNAME and the reserved bit 6 set.
+    let mut bytes = vec![0x1f, 0x8b, 8, 0b0100_1000, 0, 0, 0, 0, 0, 3];
+    bytes.extend_from_slice(b"name\0");
+
+    // Reserved bits are ignored by default.
+    assert_eq!(
+        GzipHeader::build(&bytes).unwrap().fname.as_deref(),
+        Some("name")
+    );
+
+    let options = HeaderOptions {
+        strict_flags: true,
+        ..HeaderOptions::default()
+    };
+    assert!(matches!(
+        GzipHeader::build_with_options(&bytes, &options),
+        Err(GzipError::ReservedFlag(6))
+    ));
+    assert!(matches!(
+        GzipHeader::from_reader_with_options(&mut &bytes[..], &options),
+        Err(GzipError::ReservedFlag(6))
+    ));
+}
+
+#[test]
+fn test_unsupported_compression_method() {
+    let bytes = [0x1f, 0x8b, 15, 0, 0, 0, 0, 0, 0, 3];
+
+    assert!(matches!(
+        GzipHeader::build(&bytes),
+        Err(GzipError::UnsupportedCompressionMethod(15))
+    ));
+}
+
+#[test]
+fn test_original_name() {
+    let header = |name: &[u8], mtime: u32| {
+        let mut bytes = vec![0x1f, 0x8b, 8, 0b1000];
+        bytes.extend_from_slice(&mtime.to_le_bytes());
+        bytes.extend_from_slice(&[0, 3]);
+        bytes.extend_from_slice(name);
+        bytes.push(0);
+        GzipHeader::build(&bytes).unwrap()
+    };
+
+    let plain = header(b"notes.txt", 1_000_000);
+    assert_eq!(plain.original_name(), Some("notes.txt"));
+    assert_eq!(
+        plain.modified(),
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))
+    );
+
+    // Directories are dropped so the name can't point anywhere else.
+    assert_eq!(
+        header(b"../../etc/passwd", 0).original_name(),
+        Some("passwd")
+    );
+    assert_eq!(header(b"C:\\temp\\a.txt", 0).original_name(), Some("a.txt"));
+    assert_eq!(header(b"..", 0).original_name(), None);
+    assert_eq!(header(b"dir/", 0).original_name(), None);
+
+    assert_eq!(header(b"x", 0).modified(), None);
+}
@@ -0,0 +1,95 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{fs, io::Write};
+
+use gzip::{
+    analyze::analyze,
+    deflate::{compress, compress_with_stats, CompressOptions, CompressStats},
+    inflate::BlockType,
+    write::GzEncoder,
+};
+
+#[test]
+fn test_compress_stats() {
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+    let options = CompressOptions {
+        block_size: 4096,
+        ..CompressOptions::default()
+    };
+
+    let (stream, stats) = compress_with_stats(&raw, &options);
+
+    assert_eq!(stream, compress(&raw, &options));
+    assert_eq!(stats.bytes_in, raw.len() as u64);
+    assert_eq!(stats.bytes_out, stream.len() as u64);
+
+    // Each block counted is the one analyze finds in the stream.
+    let blocks = analyze(&stream).unwrap();
+    let count = |block_type| blocks.iter().filter(|b| b.block_type == block_type).count() as u64;
+    assert_eq!(stats.stored_blocks, count(BlockType::Stored));
+    assert_eq!(stats.fixed_blocks, count(BlockType::Fixed));
+    assert_eq!(stats.dynamic_blocks, count(BlockType::Dynamic));
+    assert_eq!(
+        stats.header_bits,
+        blocks.iter().map(|b| b.header_bits()).sum::<u64>()
+    );
+    assert_eq!(
+        stats.data_bits,
+        blocks.iter().map(|b| b.data_bits()).sum::<u64>()
+    );
+
+    // Literals and matches make up every byte that wasn't stored.
+    let stored = blocks.iter().map(|b| b.stored_bytes).sum::<u64>();
+    assert_eq!(
+        stats.literals + stats.match_length + stored,
+        raw.len() as u64
+    );
+    assert!(stats.average_match_length() >= 3.0);
+    assert!(stats.average_match_distance() >= 1.0);
+}
+
+#[test]
+fn test_compress_stats_stored() {
+    // Too much for one stored block.
+    let raw = vec![7; 100_000];
+    let options = CompressOptions {
+        block_type: Some(BlockType::Stored),
+        ..CompressOptions::default()
+    };
+
+    let (stream, stats) = compress_with_stats(&raw, &options);
+
+    assert_eq!(stats.stored_blocks, analyze(&stream).unwrap().len() as u64);
+    assert!(stats.stored_blocks > 1);
+    assert_eq!((stats.literals, stats.matches), (0, 0));
+    assert_eq!(stats.data_bits, raw.len() as u64 * 8);
+    assert_eq!(stats.average_match_length(), 0.0);
+
+    // Nothing still takes an empty block.
+    let (_, stats) = compress_with_stats(&[], &options);
+    assert_eq!(
+        stats,
+        CompressStats {
+            stored_blocks: 1,
+            header_bits: 40,
+            bytes_out: 5,
+            ..CompressStats::default()
+        }
+    );
+}
+
+#[test]
+fn test_gz_encoder_stats() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    let mut encoder = GzEncoder::new(Vec::new(), CompressOptions::default());
+    encoder.write_all(&raw).unwrap();
+    encoder.flush().unwrap();
+    let stats = *encoder.stats();
+
+    assert_eq!(stats.bytes_in, raw.len() as u64);
+    assert_eq!(stats.literals + stats.match_length, raw.len() as u64);
+    // The sync flush is an empty stored block of its own.
+    assert!(stats.stored_blocks >= 1);
+    assert_eq!(stats.header_bits + stats.data_bits, stats.bytes_out * 8);
+}
@@ -0,0 +1,174 @@
+#![cfg(feature = "inflate")]
+//! Decodes the same inputs with this crate and with flate2, which is backed
+//! by miniz_oxide, and checks the two agree byte for byte. With the deflate
+//! feature it also decodes what this crate compresses with flate2, so
+//! compress is checked against a decoder other than its own.
+// Only XorShift and text are used here.
+#[allow(dead_code)]
+mod common;
+
+use std::{fs, io::Read};
+
+use flate2::{bufread::DeflateDecoder, read::GzDecoder};
+#[cfg(feature = "deflate")]
+use gzip::{
+    deflate::{compress, compress_gzip, CompressOptions},
+    inflate::BlockType,
+};
+use gzip::{gzip::GzipHeader, inflate::DeflateData};
+
+/// Decodes a single gzip member with flate2.
+fn reference(compressed: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(compressed).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decodes a raw DEFLATE stream with flate2.
+fn reference_raw(compressed: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(compressed).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Every gzip file in tests/compressed.
+fn corpus() -> Vec<(String, Vec<u8>)> {
+    let mut files = fs::read_dir("./tests/compressed")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gz"))
+        .map(|path| (path.display().to_string(), fs::read(&path).unwrap()))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_corpus_matches_reference() {
+    for (path, compressed) in corpus() {
+        let expected = reference(&compressed).unwrap();
+
+        // Both decoders stop after the first member of a multi-member file.
+        let mut actual = Vec::new();
+        gzip::read::GzDecoder::new(&compressed[..])
+            .read_to_end(&mut actual)
+            .unwrap();
+
+        assert!(expected == actual, "{} decodes differently", path);
+    }
+}
+
+#[test]
+fn test_corrupt_corpus_matches_reference() {
+    let mut random = common::XorShift::new(0x9e3779b97f4a7c15);
+
+    for (path, compressed) in corpus() {
+        // Only the DEFLATE data is corrupted, past the header and before
+        // the trailer, so the two are compared on decoding alone.
+        let header = GzipHeader::build(&compressed).unwrap();
+        let stream = &compressed[header.end_idx..compressed.len() - 8];
+        if stream.len() > 1 << 16 {
+            continue;
+        }
+
+        for _ in 0..200 {
+            let mut corrupt = stream.to_vec();
+            let bit = random.next_u64() as usize % (corrupt.len() * 8);
+            corrupt[bit / 8] ^= 1 << (bit % 8);
+
+            // Where both accept the stream they have to agree on what it
+            // holds. The reference is more lenient about some malformed
+            // codes, so only one of them rejecting it isn't a mismatch.
+            if let (Ok(expected), Ok(actual)) = (
+                reference_raw(&corrupt),
+                DeflateData::build(&corrupt[..]).decompress(),
+            ) {
+                assert!(
+                    expected == actual,
+                    "{} with bit {} flipped decodes differently",
+                    path,
+                    bit
+                );
+            }
+        }
+    }
+}
+
+/// Every way of choosing blocks, with blocks small enough that there are
+/// many of them and large enough for one to hold a whole input.
+#[cfg(feature = "deflate")]
+fn options() -> Vec<CompressOptions> {
+    let mut options = Vec::new();
+
+    for block_type in [
+        None,
+        Some(BlockType::Stored),
+        Some(BlockType::Fixed),
+        Some(BlockType::Dynamic),
+    ] {
+        for (block_size, max_chain) in [(64, 0), (16383, 128), (1 << 20, 1024)] {
+            options.push(CompressOptions {
+                block_type,
+                block_size,
+                max_chain,
+            });
+        }
+    }
+    options
+}
+
+/// The start of every file in tests/raw, with random bytes that barely
+/// compress, text that compresses well, and nothing at all. Inputs are kept
+/// to 64 KB so going through every option stays quick.
+#[cfg(feature = "deflate")]
+fn inputs() -> Vec<(String, Vec<u8>)> {
+    let mut inputs = fs::read_dir("./tests/raw")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .map(|path| {
+            let mut raw = fs::read(&path).unwrap();
+            raw.truncate(1 << 16);
+            (path.display().to_string(), raw)
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+
+    let mut random = common::XorShift::new(0x9e3779b97f4a7c15);
+    inputs.push(("random".to_string(), random.bytes(1 << 16)));
+    inputs.push(("text".to_string(), common::text(1 << 16)));
+    inputs.push(("empty".to_string(), Vec::new()));
+    inputs
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn test_compress_matches_reference() {
+    for (name, raw) in inputs() {
+        for options in options() {
+            let actual = reference_raw(&compress(&raw, &options)).unwrap();
+            assert!(
+                raw == actual,
+                "{} compressed with {:?} decodes differently",
+                name,
+                options
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn test_compress_gzip_matches_reference() {
+    for (name, raw) in inputs() {
+        for options in options() {
+            // flate2 checks the CRC and length in the trailer too.
+            let actual = reference(&compress_gzip(&raw, &options)).unwrap();
+            assert!(
+                raw == actual,
+                "{} compressed with {:?} decodes differently",
+                name,
+                options
+            );
+        }
+    }
+}
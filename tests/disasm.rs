@@ -0,0 +1,150 @@
+#![cfg(feature = "inflate")]
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use gzip::{
+    disasm,
+    gzip::{GzipFile, GzipHeader},
+    inflate::{Inflater, Status, TraceEvent},
+};
+
+#[test]
+fn test_disassemble_stored() {
+    let compressed = fs::read("./tests/compressed/block_type_0.gz").unwrap();
+
+    let mut output = Vec::new();
+    disasm::disassemble(&compressed, &mut output).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "! gzip
+! name \"block_type_0\"
+! mtime 1732227461
+        0 last
+          stored
+       40 len 11
+      128 end
+! crc 0xf44bfb59
+! length 11
+"
+    );
+}
+
+#[test]
+fn test_trace_events() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let file = GzipFile::from_bytes(&compressed).unwrap();
+    let stream = &compressed[file.header.end_idx..compressed.len() - 8];
+
+    // How many bytes of output each literal and match traced stands for,
+    // and how many blocks were started and ended.
+    let tokens = Arc::new(Mutex::new((Vec::new(), 0, 0)));
+    let sink = Arc::clone(&tokens);
+
+    let mut inflater = Inflater::new();
+    inflater.set_trace(Some(Box::new(move |event: &TraceEvent<'_>| {
+        let (lengths, blocks, ends) = &mut *sink.lock().unwrap();
+        match event {
+            TraceEvent::Block { .. } => *blocks += 1,
+            TraceEvent::BlockEnd { .. } => *ends += 1,
+            TraceEvent::Literal { .. } => lengths.push(1),
+            TraceEvent::Match { length, .. } => lengths.push(*length),
+            _ => {}
+        }
+    })));
+
+    // Decoding a few bytes of output at a time still traces each token once,
+    // so they add up to the whole output.
+    let mut output = [0; 7];
+    let mut decompressed = Vec::new();
+    let mut input = stream;
+    loop {
+        let (consumed, produced, status) = inflater.inflate(input, &mut output).unwrap();
+        input = &input[consumed..];
+        decompressed.extend_from_slice(&output[..produced]);
+        if status == Status::StreamEnd {
+            break;
+        }
+    }
+    assert_eq!(raw, decompressed);
+
+    let (lengths, blocks, ends) = &*tokens.lock().unwrap();
+    assert_eq!(lengths.iter().sum::<usize>(), raw.len());
+    assert_eq!((*blocks, *ends), (1, 1));
+}
+
+#[test]
+fn test_dump_tables() {
+    let compressed = fs::read("./tests/compressed/sync_flush.gz").unwrap();
+    let header = GzipHeader::build(&compressed).unwrap();
+
+    let mut output = Vec::new();
+    disasm::dump_tables(&compressed[header.end_idx..], &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // The stored block in the middle has no tables to print.
+    let blocks = output
+        .lines()
+        .filter(|line| line.starts_with("block"))
+        .collect::<Vec<_>>();
+    assert_eq!(blocks, ["block 0 at bit 0", "block 2 at bit 784"]);
+
+    // Every code length is printed, 16 to a row.
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines[1], "hlit 19 hdist 18 hclen 14");
+    assert_eq!(
+        lines[2],
+        "code     0:  3  5  5  4  4  2  2  0  0  0  0  0  0  0  0  0"
+    );
+    assert_eq!(lines[3], "code    16:  5  5  3");
+    let litlen = lines
+        .iter()
+        .take_while(|line| !line.is_empty())
+        .filter(|line| line.starts_with("litlen"))
+        .flat_map(|line| line.split(':').nth(1).unwrap().split_whitespace())
+        .count();
+    assert_eq!(litlen, 276);
+}
+
+#[test]
+fn test_hexdump_fixed() {
+    // A final fixed block holding a literal 'A' and the end of block.
+    let stream = [0x73, 0x04, 0x00];
+
+    let mut output = Vec::new();
+    let used = disasm::hexdump_deflate(&stream, &mut output).unwrap();
+
+    assert_eq!(used, 3);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "      bit   byte  hex            bits                         meaning
+        0      0  73             1                            BFINAL 1
+        1      0  73             10                           BTYPE 1 (fixed)
+        3      0  73 04          01110001                     literal 'A'
+       11      1  04 00          0000000                      end of block
+"
+    );
+}
+
+#[test]
+fn test_hexdump() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+
+    let mut output = Vec::new();
+    disasm::hexdump(&compressed, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+
+    assert!(lines[1].ends_with(" ID"));
+    assert!(lines.iter().any(|line| line.ends_with("BTYPE 2 (dynamic)")));
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("code length code 16:")));
+    assert!(lines.iter().any(|line| line.ends_with("= match 3 55")));
+    assert!(lines[lines.len() - 3].ends_with("end of block"));
+    assert!(lines[lines.len() - 1].ends_with("ISIZE 539"));
+}
@@ -0,0 +1,57 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::fs;
+
+use gzip::{
+    deflate::{compress_zlib, CompressOptions},
+    git::{inflate_loose_object, ObjectError, ObjectType},
+};
+
+/// Compresses an inflated loose object.
+fn loose_object(inflated: &[u8]) -> Vec<u8> {
+    compress_zlib(inflated, &CompressOptions::default())
+}
+
+#[test]
+fn test_inflate_loose_object() {
+    // Written by git hash-object -w.
+    let compressed = fs::read("./tests/compressed/git_blob").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    let object = inflate_loose_object(&compressed).unwrap();
+    assert_eq!(object.object_type, ObjectType::Blob);
+    assert_eq!(object.size, raw.len() as u64);
+    assert_eq!(object.payload, raw);
+
+    let object = inflate_loose_object(&loose_object(b"tree 0\0")).unwrap();
+    assert_eq!(object.object_type, ObjectType::Tree);
+    assert!(object.payload.is_empty());
+}
+
+#[test]
+fn test_loose_object_errors() {
+    for header in [
+        &b"blob 5hello"[..],
+        b"blob\0hello",
+        b"blob 05\0hello",
+        b"blob +5\0hello",
+    ] {
+        let err = inflate_loose_object(&loose_object(header)).unwrap_err();
+        assert!(matches!(err, ObjectError::InvalidHeader(_)), "{:?}", header);
+    }
+
+    let err = inflate_loose_object(&loose_object(b"note 5\0hello")).unwrap_err();
+    assert!(matches!(err, ObjectError::UnknownType(name) if name == b"note"));
+
+    let err = inflate_loose_object(&loose_object(b"blob 6\0hello")).unwrap_err();
+    assert!(matches!(
+        err,
+        ObjectError::SizeMismatch {
+            expected: 6,
+            actual: 5
+        }
+    ));
+
+    let err = inflate_loose_object(b"blob 5\0hello").unwrap_err();
+    assert!(matches!(err, ObjectError::ZlibError(_)));
+}
@@ -0,0 +1,79 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+mod common;
+
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+use gzip::gzip::{GzipFile, GzipHeader};
+
+#[test]
+fn test_generated_fixtures() {
+    for fixture in common::fixtures() {
+        let mut reference = Vec::new();
+        MultiGzDecoder::new(&fixture.compressed[..])
+            .read_to_end(&mut reference)
+            .unwrap();
+        assert_eq!(reference, fixture.raw, "{}", fixture.name);
+
+        // GzipFile only reads the first member.
+        if fixture.name != "members" {
+            let mut file = GzipFile::from_bytes(&fixture.compressed).unwrap();
+            assert_eq!(file.decompress().unwrap(), fixture.raw, "{}", fixture.name);
+        }
+    }
+}
+
+#[test]
+fn test_generated_fixtures_are_deterministic() {
+    for (a, b) in common::fixtures().into_iter().zip(common::fixtures()) {
+        assert_eq!(a.compressed, b.compressed, "{}", a.name);
+    }
+}
+
+#[test]
+fn test_header_to_bytes() {
+    let fixture = common::fixtures()
+        .into_iter()
+        .find(|fixture| fixture.name == "all_fields")
+        .unwrap();
+
+    let header = GzipHeader::build(&fixture.compressed).unwrap();
+    assert_eq!(header.flg, [true, true, true, true, true]);
+    assert_eq!(header.mtime, 1_700_000_000);
+    assert_eq!(header.fname.as_deref(), Some("fixture.txt"));
+    assert_eq!(header.fcomment.as_deref(), Some("caf\u{e9}"));
+    assert!(header.crc.is_some());
+
+    // Writing a parsed header out again gives back the same bytes.
+    assert_eq!(
+        header.to_bytes(),
+        fixture.compressed[..header.end_idx].to_vec()
+    );
+}
+
+#[test]
+fn test_fixture_matrix() {
+    let fixtures = common::fixtures();
+
+    let headers: Vec<_> = fixtures
+        .iter()
+        .filter(|fixture| fixture.name.starts_with("header_"))
+        .collect();
+    assert_eq!(headers.len(), 16);
+    for fixture in headers {
+        let header = GzipHeader::build(&fixture.compressed).unwrap();
+        assert_eq!(header.fextra.is_some(), fixture.name.contains("extra"));
+        assert_eq!(header.fname.is_some(), fixture.name.contains("name"));
+        assert_eq!(header.fcomment.is_some(), fixture.name.contains("comment"));
+        assert_eq!(header.crc.is_some(), fixture.name.contains("hcrc"));
+    }
+
+    for block_type in ["stored", "fixed", "dynamic"] {
+        for len in common::EDGE_LENGTHS {
+            let name = format!("{}_{}", block_type, len);
+            let fixture = fixtures.iter().find(|fixture| fixture.name == name);
+            assert_eq!(fixture.unwrap().raw.len(), len, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::fs;
+
+use gzip::{
+    bits::BitWriter,
+    deflate::{write_block, Token},
+    identify::{identify, Producer},
+    inflate::BlockType,
+};
+
+#[test]
+fn test_identify_fixtures() {
+    let cases = [
+        ("members", Producer::Bgzf),
+        ("repeats", Producer::Zlib),
+        ("sync_flush", Producer::Pigz),
+        ("block_type_1", Producer::Gzip),
+    ];
+
+    for (name, producer) in cases {
+        let bytes = fs::read(format!("tests/compressed/{}.gz", name)).unwrap();
+        let identification = identify(&bytes).unwrap();
+
+        assert_eq!(identification.best(), Some(producer), "{}", name);
+        assert!(!identification.evidence.is_empty(), "{}", name);
+    }
+}
+
+#[test]
+fn test_identify_far_short_matches() {
+    // 5000 literals, then a match of length 3 from all the way back that
+    // zlib's lazy matching would never take.
+    let data = (0..5000u32)
+        .map(|i| (i * 7 % 251) as u8)
+        .chain([0, 7, 14])
+        .collect::<Vec<_>>();
+    let mut tokens = data[..5000]
+        .iter()
+        .map(|&byte| Token::Literal(byte))
+        .collect::<Vec<_>>();
+    tokens.push(Token::Match {
+        length: 3,
+        distance: 5000,
+    });
+
+    let mut writer = BitWriter::new();
+    write_block(&mut writer, &tokens, &data, Some(BlockType::Dynamic), true);
+
+    // A bare header with no name, timestamp, or XFL, and OS 3.
+    let mut bytes = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+    bytes.extend(writer.finish());
+    bytes.extend([0; 8]);
+
+    let identification = identify(&bytes).unwrap();
+    let score = |producer| {
+        identification
+            .scores
+            .iter()
+            .find(|&&(candidate, _)| candidate == producer)
+            .map_or(0, |&(_, score)| score)
+    };
+
+    assert!(score(Producer::Zlib) < score(Producer::Zopfli));
+    assert!(identification
+        .evidence
+        .iter()
+        .any(|evidence| evidence.contains("length 3")));
+}
@@ -0,0 +1,529 @@
+#![cfg(feature = "inflate")]
+
+use std::fs;
+
+use gzip::{
+    bits::BitWriter,
+    gzip::{GzipFile, GzipHeader},
+    inflate::{
+        BlockInfo, BlockType, DeflateData, DeflateError, ErrorPosition, Inflater, Phase, Status,
+        TraceRecord, FIXED_MEMORY_USAGE,
+    },
+    prefix::{canonical_codes, Code, CL_CODE_ORDER},
+};
+
+/// Packs a string of '0's and '1's, in the order they appear in the stream,
+/// into DEFLATE bytes. Spaces are ignored so fields can be kept apart.
+fn pack_bits(bits: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    for (i, bit) in bits.chars().filter(|c| *c != ' ').enumerate() {
+        if i % 8 == 0 {
+            bytes.push(0);
+        }
+        if bit == '1' {
+            *bytes.last_mut().unwrap() |= 1 << (i % 8);
+        }
+    }
+
+    bytes
+}
+
+/// Inflates the DEFLATE stream inside a gzip file, feeding the input and
+/// output through the inflater in pieces of the given sizes.
+fn inflate_in_pieces(path: &str, input_size: usize, output_size: usize) -> (Vec<u8>, usize) {
+    inflate_with(&mut Inflater::new(), path, input_size, output_size)
+}
+
+fn inflate_with(
+    inflater: &mut Inflater,
+    path: &str,
+    input_size: usize,
+    output_size: usize,
+) -> (Vec<u8>, usize) {
+    let bytes = fs::read(path).unwrap();
+    let header = GzipHeader::build(&bytes).unwrap();
+
+    let mut input = &bytes[header.end_idx..];
+    let mut output = vec![0; output_size];
+    let mut decompressed = Vec::new();
+
+    loop {
+        let piece = &input[..input_size.min(input.len())];
+        let (consumed, produced, status) = inflater.inflate(piece, &mut output).unwrap();
+
+        input = &input[consumed..];
+        decompressed.extend_from_slice(&output[..produced]);
+
+        if status == Status::StreamEnd {
+            return (decompressed, input.len());
+        }
+    }
+}
+
+#[test]
+fn test_streaming_inflate() {
+    for name in [
+        "block_type_0",
+        "block_type_1_lzss",
+        "block_type_2_long",
+        "sync_flush",
+    ] {
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        for (input_size, output_size) in [(1, 1), (3, 7), (64, 2)] {
+            let (decompressed, left) = inflate_in_pieces(
+                &format!("./tests/compressed/{}.gz", name),
+                input_size,
+                output_size,
+            );
+
+            assert_eq!(raw, decompressed);
+            // Only the 8 byte trailer should be left over.
+            assert_eq!(left, 8);
+        }
+    }
+}
+
+#[test]
+fn test_streaming_larger_file() {
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let (decompressed, left) = inflate_in_pieces("./tests/compressed/picture.png.gz", 4096, 1000);
+
+    assert_eq!(raw, decompressed);
+    assert_eq!(left, 8);
+}
+
+#[test]
+fn test_reset() {
+    let mut inflater = Inflater::new();
+
+    for name in ["block_type_2_long", "block_type_1_lzss", "block_type_0"] {
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        inflater.reset();
+        let (decompressed, _) = inflate_with(
+            &mut inflater,
+            &format!("./tests/compressed/{}.gz", name),
+            16,
+            16,
+        );
+
+        assert_eq!(raw, decompressed);
+        assert_eq!(inflater.total_out(), raw.len() as u64);
+    }
+}
+
+#[test]
+fn test_decompress_to_sink() {
+    let mut compressed = GzipFile::from_path("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decompressed = Vec::new();
+    let total = compressed
+        .deflate
+        .decompress_to_sink(|chunk| {
+            assert!(chunk.len() <= 32768);
+            decompressed.extend_from_slice(chunk);
+        })
+        .unwrap();
+
+    assert_eq!(total, raw.len() as u64);
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_overlapping_matches() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    let mut compressed = GzipFile::from_path("./tests/compressed/repeats.gz").unwrap();
+    assert_eq!(raw, compressed.decompress().unwrap());
+
+    // Small outputs split matches across calls.
+    for output_size in [1, 7, 300] {
+        let (decompressed, _) =
+            inflate_in_pieces("./tests/compressed/repeats.gz", 4096, output_size);
+        assert_eq!(raw, decompressed);
+    }
+}
+
+#[test]
+fn test_invalid_distance() {
+    // A final fixed block starting with a length 3 match at distance 1,
+    // before anything has been written.
+    let stream = pack_bits("1 10 0000001 00000 0000000");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+
+    assert!(matches!(
+        err.inner(),
+        DeflateError::InvalidDistanceError {
+            distance: 1,
+            output_len: 0
+        }
+    ));
+}
+
+#[test]
+fn test_distance_past_window() {
+    // A literal, then a match using distance code 30, which would reach
+    // past the 32KB window.
+    let stream = pack_bits("1 10 10010001 0000001 11110 00000000000000");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidSymbolError(30, _)),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_invalid_code_lengths() {
+    // A final dynamic block with HLIT 257, HDIST 1, HCLEN 4, giving code
+    // lengths 16, 17, 18 and 0 each a 1 bit code, more than 1 bit can hold.
+    let stream = pack_bits("1 01 00000 00000 0000 100 100 100 100");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidBlockError(_)),
+        "{}",
+        err
+    );
+
+    // Only one of them given a 1 bit code leaves the code incomplete.
+    let stream = pack_bits("1 01 00000 00000 0000 100 000 000 000");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidBlockError(_)),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_repeat_without_previous_length() {
+    // A final dynamic block whose code length code gives 16 and 17 1 bit
+    // codes, then starts the code lengths with 16, repeating nothing.
+    let stream = pack_bits("1 01 00000 00000 0000 100 100 000 000 0 00 00000000");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidBlockError(_)),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_unexpected_eof() {
+    // A final fixed block holding a literal 'A', cut off before the end of
+    // block code. The 5 bits of padding aren't enough for another code.
+    let stream = pack_bits("1 10 01110001");
+
+    let err = DeflateData::build(&stream[..]).decompress().unwrap_err();
+
+    assert!(
+        matches!(
+            err.inner(),
+            DeflateError::UnexpectedEofError { bit_offset: 11 }
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_error_position() {
+    // A stored block, then a block with the reserved BTYPE 3.
+    let stream = pack_bits("0 00 00000 0000000000000000 1111111111111111 1 11");
+
+    let err = Inflater::new().inflate(&stream, &mut [0; 16]).unwrap_err();
+
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidBlockError(_)),
+        "{}",
+        err
+    );
+    assert_eq!(
+        err.position(),
+        Some(ErrorPosition {
+            bit_offset: 43,
+            block: 1,
+            phase: Phase::Header
+        })
+    );
+}
+
+#[test]
+fn test_error_history() {
+    // A fixed block holding 'A' and 'B', then a match reaching back 5 bytes.
+    let stream = pack_bits("1 10 01110001 01110010 0000001 00100 0");
+
+    let mut inflater = Inflater::new();
+    inflater.set_history(Some(2));
+    let err = inflater.inflate(&stream, &mut [0; 16]).unwrap_err();
+
+    assert!(
+        matches!(err.inner(), DeflateError::InvalidDistanceError { .. }),
+        "{}",
+        err
+    );
+    assert_eq!(err.position().map(|position| position.block), Some(0));
+    // The match that failed is the last record.
+    assert_eq!(
+        err.history().unwrap(),
+        [
+            TraceRecord::Literal {
+                bit_offset: 11,
+                byte: b'B'
+            },
+            TraceRecord::Match {
+                bit_offset: 19,
+                length: 3,
+                distance: 5
+            },
+        ]
+    );
+
+    // Without a history errors are only positioned, and the history is
+    // cleared on reset.
+    assert_eq!(inflater.history().count(), 2);
+    inflater.reset();
+    assert_eq!(inflater.history().count(), 0);
+    inflater.set_history(None);
+    let err = inflater.inflate(&stream, &mut [0; 16]).unwrap_err();
+    assert!(err.history().is_none());
+    assert!(err.position().is_some());
+}
+
+#[test]
+fn test_block_info() {
+    // An empty stored block, then a final fixed block holding a literal 'A'.
+    let stream = pack_bits("0 00 00000 0000000000000000 1111111111111111 1 10 01110001 0000000");
+
+    let mut deflate = DeflateData::build(&stream[..]);
+    deflate.set_record_blocks(true);
+    assert_eq!(deflate.decompress().unwrap(), b"A");
+
+    assert_eq!(
+        deflate.blocks(),
+        [
+            BlockInfo {
+                block_type: BlockType::Stored,
+                final_block: false,
+                bits: 0..40,
+                bytes: 0..0,
+            },
+            BlockInfo {
+                block_type: BlockType::Fixed,
+                final_block: true,
+                bits: 40..58,
+                bytes: 0..1,
+            },
+        ]
+    );
+
+    // Blocks are recorded the same when the stream is fed in pieces, and
+    // they cover the whole stream between them.
+    let mut inflater = Inflater::new();
+    inflater.set_record_blocks(true);
+    let (raw, _) = inflate_with(&mut inflater, "./tests/compressed/sync_flush.gz", 3, 7);
+
+    let blocks = inflater.blocks();
+    assert!(blocks.len() > 1);
+    assert!(blocks
+        .windows(2)
+        .all(|pair| pair[0].bits.end == pair[1].bits.start
+            && pair[0].bytes.end == pair[1].bytes.start));
+    assert_eq!(blocks[0].bits.start, 0);
+    assert_eq!(blocks.last().unwrap().bytes.end, raw.len() as u64);
+    assert!(blocks.last().unwrap().final_block);
+}
+
+#[test]
+fn test_fixed_memory() {
+    // Complete codes made of chains of long codes, so the tables need
+    // plenty of sub-tables.
+    let chain = |from: u8, n: usize| (from..=15).chain([15]).cycle().take(n);
+    let mut literals = vec![1, 2, 3, 5, 6];
+    literals.extend(chain(10, 7 * 40));
+    literals.push(0);
+    let mut distances = vec![1, 2, 3, 4, 6];
+    distances.extend(chain(7, 10 * 2));
+    distances.extend([7, 8, 9, 10, 10]);
+
+    // Code lengths 0 through 15 each get a 4 bit code length code.
+    let mut writer = BitWriter::new();
+    writer.write_bits(0b101, 3);
+    writer.write_bits(literals.len() as u32 - 257, 5);
+    writer.write_bits(distances.len() as u32 - 1, 5);
+    writer.write_bits(19 - 4, 4);
+    for symbol in CL_CODE_ORDER {
+        writer.write_bits(if symbol < 16 { 4 } else { 0 }, 3);
+    }
+    for &length in literals.iter().chain(&distances) {
+        writer.write_code(Code::from(length as u32, 4));
+    }
+    writer.write_code(canonical_codes(&literals)[256].unwrap());
+    let stream = writer.finish();
+
+    let mut inflater = Inflater::with_fixed_memory();
+    assert_eq!(inflater.memory_usage().total(), FIXED_MEMORY_USAGE);
+    assert_eq!(
+        inflater.inflate(&stream, &mut [0; 16]).unwrap(),
+        (stream.len(), 0, Status::StreamEnd)
+    );
+    assert_eq!(inflater.memory_usage().total(), FIXED_MEMORY_USAGE);
+
+    // Ordinary streams never need more either.
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = GzipFile::from_bytes(&compressed)
+        .unwrap()
+        .decompress()
+        .unwrap();
+    let mut inflater = Inflater::with_fixed_memory();
+    let mut input = &compressed[GzipHeader::build(&compressed).unwrap().end_idx..];
+    let mut output = [0; 4096];
+    let mut len = 0;
+    loop {
+        let (consumed, produced, status) = inflater.inflate(input, &mut output).unwrap();
+        input = &input[consumed..];
+        len += produced;
+        if status == Status::StreamEnd {
+            break;
+        }
+    }
+    assert_eq!(len, raw.len());
+    assert_eq!(inflater.memory_usage().total(), FIXED_MEMORY_USAGE);
+
+    // Anything that would allocate more goes over the limit.
+    inflater.reset();
+    inflater.set_history(Some(16));
+    let err = inflater.inflate(&stream, &mut [0; 16]).unwrap_err();
+    assert!(matches!(
+        err.inner(),
+        DeflateError::MemoryLimitError(FIXED_MEMORY_USAGE)
+    ));
+}
+
+#[test]
+fn test_inflate_step() {
+    for name in ["block_type_0", "block_type_1_lzss", "block_type_2_long"] {
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+        let bytes = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let header = GzipHeader::build(&bytes).unwrap();
+
+        let mut inflater = Inflater::new();
+        let mut input = &bytes[header.end_idx..];
+        let mut output = vec![0; 1 << 16];
+        let mut decompressed = Vec::new();
+        let mut steps = 0;
+
+        loop {
+            let (consumed, produced, status) =
+                inflater.inflate_step(input, &mut output, 1).unwrap();
+            input = &input[consumed..];
+            decompressed.extend_from_slice(&output[..produced]);
+            steps += 1;
+
+            // A single symbol is at most one literal, match, or stored byte.
+            assert!(produced <= 258, "{}", name);
+            match status {
+                Status::Pending => {}
+                Status::StreamEnd => break,
+                status => panic!("{}: {:?}", name, status),
+            }
+        }
+
+        assert_eq!(decompressed, raw, "{}", name);
+        assert!(steps > raw.len() / 258, "{}", name);
+    }
+}
+
+#[test]
+fn test_save_state() {
+    for name in [
+        "block_type_0",
+        "block_type_1_lzss",
+        "block_type_2_long",
+        "sync_flush",
+    ] {
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+        let bytes = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let stream = &bytes[GzipHeader::build(&bytes).unwrap().end_idx..];
+
+        // Every call goes through a fresh inflater restored from the last
+        // one's state, small pieces stop it everywhere it can.
+        let mut state = Inflater::new().save_state();
+        let mut output = [0; 97];
+        let mut decompressed = Vec::new();
+        loop {
+            let mut inflater = Inflater::restore_state(&state).unwrap();
+            let start = inflater.total_in() as usize;
+            let piece = &stream[start..stream.len().min(start + 13)];
+
+            let (_, produced, status) = inflater.inflate(piece, &mut output).unwrap();
+            decompressed.extend_from_slice(&output[..produced]);
+            state = inflater.save_state();
+
+            if status == Status::StreamEnd {
+                break;
+            }
+        }
+
+        assert_eq!(decompressed, raw, "{}", name);
+    }
+}
+
+#[test]
+fn test_restore_invalid_state() {
+    let mut inflater = Inflater::new();
+    inflater.inflate(&[0b011], &mut [0; 8]).unwrap();
+    let state = inflater.save_state();
+
+    for bad in [
+        &state[..state.len() - 1],
+        &[state.as_slice(), &[0]].concat(),
+        &[&[0], &state[1..]].concat(),
+        &[],
+    ] {
+        assert!(matches!(
+            Inflater::restore_state(bad),
+            Err(DeflateError::InvalidStateError(_))
+        ));
+    }
+}
+
+#[test]
+fn test_set_dictionary() {
+    // A zlib stream with block_type_2_long as its preset dictionary, the
+    // DEFLATE data is between the 6 byte header and 4 byte trailer.
+    let compressed = fs::read("./tests/compressed/fdict.zlib").unwrap();
+    let dictionary = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let stream = &compressed[6..compressed.len() - 4];
+    let mut output = vec![0; 2 * dictionary.len()];
+
+    let mut inflater = Inflater::new();
+    assert!(matches!(
+        inflater.inflate(stream, &mut output).unwrap_err().inner(),
+        DeflateError::InvalidDistanceError { .. }
+    ));
+
+    // The dictionary is forgotten by reset, but kept by save_state.
+    inflater.reset();
+    inflater.set_dictionary(&dictionary).unwrap();
+    let mut inflater = Inflater::restore_state(&inflater.save_state()).unwrap();
+    let (_, produced, status) = inflater.inflate(stream, &mut output).unwrap();
+    assert_eq!(status, Status::StreamEnd);
+    assert_eq!(produced, output.len());
+    assert_eq!(inflater.total_out(), output.len() as u64);
+    assert_eq!(output[dictionary.len()..], dictionary);
+
+    inflater.reset();
+    assert!(inflater.inflate(stream, &mut output).is_err());
+}
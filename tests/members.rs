@@ -0,0 +1,155 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+// Only member and text are used here.
+#[allow(dead_code)]
+mod common;
+
+use std::{fs, io::Read};
+
+use gzip::{
+    deflate::CompressOptions,
+    gzip::{ExtraField, GzipError, GzipHeader},
+    members::{concatenate, members, named, nth},
+    read::MultiGzDecoder,
+};
+
+/// Returns a BGZF member holding data, its 'BC' subfield giving its size.
+fn bgzf_member(data: &[u8]) -> Vec<u8> {
+    let header = |size: u16| GzipHeader {
+        fextra: ExtraField::new(b'B', b'C', size.to_le_bytes().to_vec()).to_bytes(),
+        ..Default::default()
+    };
+    // BSIZE doesn't change the member's length, so build it once to find it.
+    let len = common::member(&header(0), data, &CompressOptions::default()).len();
+    common::member(&header(len as u16 - 1), data, &CompressOptions::default())
+}
+
+#[test]
+fn test_members() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+    let raw = fs::read("./tests/raw/members").unwrap();
+
+    let found = members(&compressed).unwrap();
+    assert!(found.len() > 1);
+    assert_eq!(
+        compressed.len(),
+        found.iter().map(|member| member.bytes.len()).sum::<usize>()
+    );
+
+    let mut decompressed = Vec::new();
+    for member in &found {
+        let data = member.decompress().unwrap();
+        assert_eq!(member.isize, data.len() as u32);
+        assert_eq!(member.crc32, gzip::crc::hash(&data));
+        decompressed.extend(data);
+    }
+    assert_eq!(raw, decompressed);
+
+    let text = common::text(5000);
+    let bgzf = [bgzf_member(&text[..3000]), bgzf_member(&text[3000..])].concat();
+    let found = members(&bgzf).unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(text[3000..], found[1].decompress().unwrap());
+}
+
+#[test]
+fn test_members_errors() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+
+    let truncated = &compressed[..compressed.len() - 3];
+    assert!(members(truncated).is_err());
+
+    let mut trailing = compressed.clone();
+    trailing.extend(b"garbage");
+    assert!(matches!(
+        members(&trailing),
+        Err(GzipError::InvalidHeader(_))
+    ));
+
+    assert!(members(&[]).unwrap().is_empty());
+
+    // A BGZF member is only taken at its BSIZE when its stream and trailer
+    // really end there.
+    let bgzf = bgzf_member(&common::text(3000));
+    let mut wrong_size = bgzf.clone();
+    wrong_size[16] -= 1;
+    assert!(matches!(
+        members(&wrong_size),
+        Err(GzipError::InvalidHeader(_))
+    ));
+
+    let mut reserved_block = bgzf.clone();
+    reserved_block[18] |= 0b110;
+    assert!(members(&reserved_block).is_err());
+
+    let mut wrong_isize = bgzf.clone();
+    let last = wrong_isize.len() - 1;
+    wrong_isize[last] ^= 1;
+    assert!(members(&wrong_isize).is_err());
+}
+
+#[test]
+fn test_concatenate() {
+    let names = ["block_type_0", "repeats", "members", "block_type_2_long"];
+    let files: Vec<_> = names
+        .iter()
+        .map(|name| fs::read(format!("./tests/compressed/{}.gz", name)).unwrap())
+        .collect();
+    let raw: Vec<u8> = names
+        .iter()
+        .flat_map(|name| fs::read(format!("./tests/raw/{}", name)).unwrap())
+        .collect();
+
+    let files: Vec<&[u8]> = files.iter().map(|file| &file[..]).collect();
+    let joined = concatenate(&files).unwrap();
+    assert_eq!(joined, files.concat());
+
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(&joined[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(raw, decompressed);
+
+    let broken = &files[2][..files[2].len() - 1];
+    assert!(concatenate(&[files[0], broken]).is_err());
+}
+
+#[test]
+fn test_find_member() {
+    let text = common::text(6000);
+    let header = |name: &str| GzipHeader {
+        fname: Some(String::from(name)),
+        ..Default::default()
+    };
+    let options = CompressOptions::default();
+    let parts = [&text[..1000], &text[1000..4000], &text[4000..]];
+
+    let mut compressed = Vec::new();
+    for (part, name) in parts.iter().zip(["a.txt", "b.txt", "c.txt"]) {
+        compressed.extend(common::member(&header(name), part, &options));
+    }
+
+    for (index, part) in parts.iter().enumerate() {
+        let member = nth(&compressed, index).unwrap().unwrap();
+        assert_eq!(*part, member.decompress().unwrap());
+    }
+    assert!(nth(&compressed, 3).unwrap().is_none());
+
+    let member = named(&compressed, "b.txt").unwrap().unwrap();
+    assert_eq!(member.header.fname.as_deref(), Some("b.txt"));
+    assert_eq!(parts[1], member.decompress().unwrap());
+    assert!(named(&compressed, "d.txt").unwrap().is_none());
+
+    // Members after the one found aren't looked at.
+    let mut damaged = compressed.clone();
+    let last = damaged.len() - 20;
+    damaged.truncate(last);
+    assert!(nth(&damaged, 1).unwrap().is_some());
+    assert!(named(&damaged, "c.txt").is_err());
+
+    let bgzf = [bgzf_member(&text[..3000]), bgzf_member(&text[3000..])].concat();
+    assert_eq!(
+        text[3000..],
+        nth(&bgzf, 1).unwrap().unwrap().decompress().unwrap()
+    );
+}
@@ -0,0 +1,116 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+//! Checks the memory_usage figures against what's really allocated, counted
+//! by the global allocator. Everything is in one test so no other test's
+//! allocations get counted.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    io::{self, Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use gzip::{
+    bufread,
+    deflate::{self, CompressOptions},
+    inflate::BlockType,
+    read::GzDecoder,
+    write::GzEncoder,
+};
+
+/// The bytes allocated right now, and the most there have been since the
+/// last call to reset_peak.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, keeping track of ALLOCATED and PEAK.
+struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        grow(layout.size());
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        grow(layout.size());
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Both the old and new allocation exist while the bytes are moved.
+        grow(new_size);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: TrackingAllocator = TrackingAllocator;
+
+fn grow(size: usize) {
+    let now = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+/// Returns how far the peak has gone past what was allocated at the start,
+/// running f.
+fn peak_during<F: FnOnce()>(f: F) -> usize {
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    f();
+    PEAK.load(Ordering::Relaxed) - start
+}
+
+#[test]
+fn test_memory_usage() {
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+
+    // Whatever the block type, compressing stays under the bound.
+    for block_type in [None, Some(BlockType::Stored), Some(BlockType::Fixed)] {
+        let options = CompressOptions {
+            block_type,
+            ..Default::default()
+        };
+        let peak = peak_during(|| drop(deflate::compress(&raw, &options)));
+        assert!(peak <= deflate::memory_usage(raw.len(), &options));
+    }
+
+    // A decoder's figure is what it's holding on to once it's been built
+    // and used.
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut decoder = GzDecoder::new(&compressed[..]);
+    let mut chunk = vec![0; 1000];
+    decoder.read_exact(&mut chunk).unwrap();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before - chunk.capacity();
+
+    let usage = decoder.memory_usage();
+    assert_eq!(usage.window, 32768);
+    assert!(usage.tables > 0);
+    assert_eq!(usage.total(), held);
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+    decoder.read_exact(&mut chunk).unwrap();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+    assert_eq!(decoder.memory_usage().total(), held);
+
+    // So is an encoder's, both with bytes waiting to be compressed and once
+    // they've been written out.
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut encoder = GzEncoder::new(io::sink(), CompressOptions::default());
+    for piece in raw[..200_000].chunks(30_000) {
+        encoder.write_all(piece).unwrap();
+        let held = ALLOCATED.load(Ordering::Relaxed) - before;
+        assert_eq!(encoder.memory_usage().total(), held);
+    }
+    encoder.flush().unwrap();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    let usage = encoder.memory_usage();
+    assert!(usage.window >= 32768);
+    assert_eq!(usage.tables, 0);
+    assert_eq!(usage.total(), held);
+}
@@ -0,0 +1,169 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+//! Every decoding entry point is fed broken input, any of them panicking
+//! fails the test. Errors are fine, see Panics in the README.
+use std::io::{self, BufReader, Read, Write};
+
+use gzip::{
+    analyze::{analyze, breakdown, breakdown_gzip},
+    bufread,
+    deflate::{compress_gzip, compress_zlib, CompressOptions},
+    disasm::{disassemble, dump_tables, hexdump},
+    git,
+    gzip::{GzipFile, GzipHeader},
+    identify::identify,
+    inflate::{Inflater, Status},
+    members, png, read,
+    recompress::{convert, recompress, Container, RecompressOptions},
+    tokens::decode_events,
+    transcode::{transcode, transcode_gzip, TranscodeOptions},
+    verify::verify,
+    write::DecompressWriter,
+    zlib,
+};
+use proptest::prelude::*;
+
+/// Arbitrary bytes, or a valid gzip file with some bytes overwritten and
+/// maybe cut short, which gets much further into the decoder.
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    damaged(compress_gzip)
+}
+
+/// Like input, but damaging what compress makes.
+fn damaged(compress: fn(&[u8], &CompressOptions) -> Vec<u8>) -> impl Strategy<Value = Vec<u8>> {
+    let damaged = (
+        "(deflate |huffman |window |a|\n){0,300}",
+        prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..8),
+        any::<prop::sample::Index>(),
+    )
+        .prop_map(move |(text, changes, cut)| {
+            let mut bytes = compress(text.as_bytes(), &CompressOptions::default());
+            for (index, byte) in changes {
+                let index = index.index(bytes.len());
+                bytes[index] = byte;
+            }
+            bytes.truncate(cut.index(bytes.len() + 1));
+            bytes
+        });
+
+    prop_oneof![prop::collection::vec(any::<u8>(), 0..2048), damaged]
+}
+
+/// Inflates stream handing the inflater a byte at a time and small pieces
+/// of output, so it has to stop and resume everywhere it can.
+fn inflate_in_pieces(stream: &[u8]) {
+    let mut inflater = Inflater::new();
+    let mut output = [0; 7];
+    let mut input = stream;
+
+    for _ in 0..stream.len() * 1100 + 16 {
+        let piece = &input[..input.len().min(1)];
+        let Ok((consumed, _, status)) = inflater.inflate(piece, &mut output) else {
+            return;
+        };
+        input = &input[consumed..];
+
+        if status == Status::StreamEnd || (status == Status::NeedsInput && input.is_empty()) {
+            return;
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_gzip_file(bytes in input()) {
+        let _ = GzipHeader::build(&bytes);
+        let _ = GzipHeader::from_reader(&mut &bytes[..]);
+        let _ = members::members(&bytes);
+        let _ = recompress(&bytes, &RecompressOptions::default());
+        let _ = convert(&bytes, Container::Gzip, Container::Zlib);
+        let _ = convert(&bytes, Container::Raw, Container::Gzip);
+        let _ = transcode_gzip(&bytes, &TranscodeOptions::default());
+        let _ = transcode(bytes.get(10..).unwrap_or_default(), &TranscodeOptions {
+            block_type: None,
+            block_size: Some(5),
+        });
+
+        if let Ok(mut file) = GzipFile::from_bytes(&bytes) {
+            let _ = file.stored_slices();
+            let _ = file.decompress_cow();
+            let _ = file.decompress();
+            let _ = file.decompress_into(&mut [0; 64]);
+        }
+    }
+
+    #[test]
+    fn test_streaming(bytes in input()) {
+        let mut decoder = bufread::GzDecoder::new(BufReader::with_capacity(3, &bytes[..]));
+        let mut output = [0; 5];
+        while let Ok(1..) = decoder.read(&mut output) {}
+
+        let _ = io::copy(&mut bufread::GzDecoder::new(&bytes[..]), &mut io::sink());
+        let _ = io::copy(&mut bufread::MultiGzDecoder::new(&bytes[..]), &mut io::sink());
+        let _ = io::copy(&mut read::GzDecoder::new(&bytes[..]), &mut io::sink());
+        let _ = io::copy(&mut read::MultiGzDecoder::new(&bytes[..]), &mut io::sink());
+
+        // Written a few bytes at a time, so the header and trailer are split
+        // across calls.
+        let mut writer = DecompressWriter::new(io::sink());
+        if bytes.chunks(3).all(|piece| writer.write_all(piece).is_ok()) {
+            let _ = writer.finish();
+        }
+    }
+
+    #[test]
+    fn test_tools(bytes in input()) {
+        let stream = bytes.get(10..).unwrap_or_default();
+        let _ = decode_events(stream, &mut |_| {});
+        let _ = analyze(stream);
+        let _ = breakdown(stream);
+        let _ = breakdown_gzip(&bytes);
+        let _ = identify(&bytes);
+        let _ = disassemble(&bytes, &mut io::sink());
+        let _ = dump_tables(stream, &mut io::sink());
+        let _ = hexdump(&bytes, &mut io::sink());
+        let _ = verify(&bytes, &CompressOptions::default());
+
+        #[cfg(feature = "json")]
+        let _ = gzip::report::to_json(&bytes);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel(bytes in input()) {
+        use gzip::parallel;
+
+        let _ = parallel::decompress_with_threads(&bytes, 2);
+        let _ = parallel::decompress_pipelined(&bytes, &mut io::sink());
+        let _ = io::copy(&mut parallel::ReadAheadDecoder::new(io::Cursor::new(bytes)), &mut io::sink());
+    }
+
+    #[test]
+    fn test_zlib(bytes in damaged(compress_zlib)) {
+        let _ = zlib::decompress(&bytes);
+        let _ = png::decode_idat(&bytes, Some(100));
+        let _ = git::inflate_loose_object(&bytes);
+        let _ = recompress(&bytes, &RecompressOptions::default());
+        let _ = convert(&bytes, Container::Zlib, Container::Raw);
+    }
+
+    #[test]
+    fn test_inflater(bytes in input()) {
+        // Past the fixed 10 byte header, most of what's left is DEFLATE data.
+        inflate_in_pieces(&bytes);
+        inflate_in_pieces(bytes.get(10..).unwrap_or_default());
+    }
+
+    #[test]
+    fn test_restore_state(bytes in input(), cut in any::<prop::sample::Index>()) {
+        // A real state with its end replaced, so the checks get past the
+        // first few fields.
+        let mut state = Inflater::new().save_state();
+        state.truncate(cut.index(state.len()));
+        state.extend(&bytes);
+
+        if let Ok(mut inflater) = Inflater::restore_state(&state) {
+            let _ = inflater.inflate(&bytes, &mut [0; 300]);
+        }
+        let _ = bufread::GzDecoder::restore_state(&bytes[..], &bytes);
+    }
+}
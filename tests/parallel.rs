@@ -0,0 +1,123 @@
+#![cfg(feature = "parallel")]
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use gzip::{gzip::GzipError, inflate::DeflateError, parallel};
+
+#[test]
+fn test_parallel_members() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+    let raw = fs::read("./tests/raw/members").unwrap();
+
+    for threads in [1, 3, 8] {
+        let decompressed = parallel::decompress_with_threads(&compressed, threads).unwrap();
+        assert_eq!(raw, decompressed);
+    }
+
+    assert_eq!(raw, parallel::decompress(&compressed).unwrap());
+}
+
+#[test]
+fn test_parallel_single_member() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    assert_eq!(raw, parallel::decompress(&compressed).unwrap());
+}
+
+#[test]
+fn test_pipelined() {
+    for name in ["members", "block_type_2_long", "picture.png", "sync_flush"] {
+        let compressed = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        let mut decompressed = Vec::new();
+        let len = parallel::decompress_pipelined(&compressed, &mut decompressed).unwrap();
+        assert_eq!(len, raw.len() as u64);
+        assert_eq!(raw, decompressed, "{}", name);
+    }
+}
+
+#[test]
+fn test_pipelined_errors() {
+    let mut compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+
+    // A writer that fails has its error reported, not a decoding one.
+    struct Failing;
+    impl Write for Failing {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("full"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    assert!(matches!(
+        parallel::decompress_pipelined(&compressed, &mut Failing),
+        Err(GzipError::IoError(_))
+    ));
+
+    // The trailer is still checked on the writing thread.
+    let crc_idx = compressed.len() - 8;
+    compressed[crc_idx] ^= 1;
+    assert!(matches!(
+        parallel::decompress_pipelined(&compressed, &mut io::sink()),
+        Err(GzipError::DeflateError(
+            DeflateError::ChecksumMismatchError { .. }
+        ))
+    ));
+
+    // So is the end of the file.
+    compressed.truncate(crc_idx + 4);
+    assert!(matches!(
+        parallel::decompress_pipelined(&compressed, &mut io::sink()),
+        Err(GzipError::DeflateError(
+            DeflateError::UnexpectedEofError { .. }
+        ))
+    ));
+}
+
+#[test]
+fn test_read_ahead() {
+    for name in [
+        "block_type_0",
+        "block_type_2_long",
+        "picture.png",
+        "sync_flush",
+    ] {
+        let compressed = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        let mut decoder = parallel::ReadAheadDecoder::new(io::Cursor::new(compressed));
+        let mut decompressed = Vec::new();
+        let mut buf = [0; 1000];
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => break,
+                n => decompressed.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(raw, decompressed, "{}", name);
+        assert_eq!(decoder.read(&mut buf).unwrap(), 0);
+    }
+}
+
+#[test]
+fn test_read_ahead_errors() {
+    let mut compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+    let crc_idx = compressed.len() - 8;
+    compressed[crc_idx] ^= 1;
+
+    // What was decoded before the error comes out first, and reading on
+    // afterwards fails too.
+    let mut decoder = parallel::ReadAheadDecoder::new(io::Cursor::new(compressed));
+    let mut decompressed = Vec::new();
+    assert!(decoder.read_to_end(&mut decompressed).is_err());
+    assert!(decompressed.len() > raw.len() / 2);
+    assert!(raw.starts_with(&decompressed));
+    assert!(decoder.read(&mut [0; 10]).is_err());
+}
@@ -0,0 +1,65 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::fs;
+
+use gzip::{
+    deflate::CompressOptions,
+    png::{decode_idat, encode_idat, scanlines_len},
+};
+
+/// Returns the IHDR fields and the joined IDAT payloads of a PNG file.
+fn parse_png(png: &[u8]) -> ([u8; 13], Vec<u8>) {
+    let mut ihdr = [0; 13];
+    let mut idat = Vec::new();
+    let mut rest = &png[8..];
+
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let data = &rest[8..8 + len];
+        match &rest[4..8] {
+            b"IHDR" => ihdr.copy_from_slice(data),
+            b"IDAT" => idat.extend_from_slice(data),
+            _ => {}
+        }
+        rest = &rest[12 + len..];
+    }
+    (ihdr, idat)
+}
+
+#[test]
+fn test_decode_idat() {
+    let png = fs::read("./tests/raw/picture.png").unwrap();
+    let (ihdr, idat) = parse_png(&png);
+
+    let width = u32::from_be_bytes(ihdr[..4].try_into().unwrap());
+    let height = u32::from_be_bytes(ihdr[4..8].try_into().unwrap());
+    // 8 bit RGBA.
+    assert_eq!(ihdr[8..10], [8, 6]);
+    let len = scanlines_len(width, height, 32, false);
+
+    let scanlines = decode_idat(&idat, Some(len)).unwrap();
+    assert_eq!(scanlines.len() as u64, len);
+    // Every row starts with one of the 5 filter types.
+    let row = width as usize * 4 + 1;
+    assert!(scanlines.chunks(row).all(|row| row[0] <= 4));
+
+    // An image that claims to be smaller can't decompress past its size.
+    assert!(decode_idat(&idat, Some(len - 1)).is_err());
+
+    let rows = &scanlines[..row * 50];
+    let encoded = encode_idat(rows, &CompressOptions::default());
+    assert_eq!(rows, decode_idat(&encoded, None).unwrap());
+}
+
+#[test]
+fn test_scanlines_len() {
+    // 1 bit pixels are packed into bytes, rounding each row up.
+    assert_eq!(scanlines_len(10, 3, 1, false), 3 * (2 + 1));
+    assert_eq!(scanlines_len(0, 3, 8, false), 0);
+
+    // A 1x1 image only has pixels in the first Adam7 pass.
+    assert_eq!(scanlines_len(1, 1, 24, true), 4);
+    // An 8x8 block has 1, 1, 2, 4, 8, 16, and 32 pixels in its passes, in
+    // 1, 1, 1, 2, 2, 4, and 4 rows.
+    assert_eq!(scanlines_len(8, 8, 8, true), 64 + 15);
+}
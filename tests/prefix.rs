@@ -0,0 +1,171 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::cmp::Ordering;
+
+use gzip::{
+    bits::BitVector64,
+    prefix::{
+        canonical_codes, cl_code_count, cl_code_symbol, huffman_lengths, kraft, Code, DecodeTable,
+        PrefixError, PrefixTree, CL_CODES, CL_CODE_ORDER, FIXED_CODE_LENGTHS, FIXED_DISTANCE_CODES,
+        FIXED_DISTANCE_LENGTHS, FIXED_LITERAL_CODES, HCLEN_BASE,
+    },
+};
+
+#[test]
+fn test_fixed_code_tables() {
+    let mut literal_tree = PrefixTree::from_lengths(&FIXED_CODE_LENGTHS).unwrap();
+    let mut distance_tree = PrefixTree::from_lengths(&FIXED_DISTANCE_LENGTHS).unwrap();
+
+    for (symbol, code) in FIXED_LITERAL_CODES.iter().enumerate() {
+        assert_eq!(code.length, FIXED_CODE_LENGTHS[symbol]);
+        assert_eq!(
+            code.map(|bit| literal_tree.walk(bit).unwrap()).last(),
+            Some(Some(symbol))
+        );
+    }
+
+    for (symbol, code) in FIXED_DISTANCE_CODES.iter().enumerate() {
+        assert_eq!(
+            code.map(|bit| distance_tree.walk(bit).unwrap()).last(),
+            Some(Some(symbol))
+        );
+    }
+}
+
+#[test]
+fn test_decode_table() {
+    // Lengths 1 through 15 plus one more 15 bit code make a complete code
+    // long enough to need sub-tables.
+    let mut lengths = (1..=15).collect::<Vec<u8>>();
+    lengths.push(15);
+
+    let table = DecodeTable::from_lengths(&lengths, 9);
+    let mut bitstream = BitVector64::new();
+
+    let codes = canonical_codes(&lengths);
+    for code in codes.iter().rev().flatten() {
+        bitstream
+            .push_buffer(code.buffer as u64, code.length as usize)
+            .unwrap();
+    }
+
+    for symbol in (0..lengths.len()).rev() {
+        assert_eq!(table.decode(&mut bitstream), Some(symbol));
+    }
+    assert_eq!(table.decode(&mut bitstream), None);
+}
+
+#[test]
+fn test_rebuild_decode_table() {
+    let mut lengths = (1..=15).collect::<Vec<u8>>();
+    lengths.push(15);
+
+    // Rebuilding a table with sub-tables as one without, and back, gives
+    // the same tables as building them fresh.
+    let mut table = DecodeTable::from_lengths(&lengths, 9);
+    table.rebuild(&FIXED_DISTANCE_LENGTHS, 9);
+    let fixed = DecodeTable::from_lengths(&FIXED_DISTANCE_LENGTHS, 9);
+    assert_eq!(table.root_bits, fixed.root_bits);
+    assert_eq!(table.entries, fixed.entries);
+
+    table.rebuild(&lengths, 9);
+    assert_eq!(
+        table.entries,
+        DecodeTable::from_lengths(&lengths, 9).entries
+    );
+}
+
+#[test]
+fn test_kraft() {
+    assert_eq!(kraft(&FIXED_CODE_LENGTHS), Ordering::Equal);
+    assert_eq!(kraft(&[1, 2, 3, 3]), Ordering::Equal);
+    assert_eq!(kraft(&[1, 2, 3, 0]), Ordering::Less);
+    assert_eq!(kraft(&[1, 1, 2]), Ordering::Greater);
+    assert_eq!(kraft(&[0, 0]), Ordering::Less);
+}
+
+#[test]
+fn test_prefix_tree_errors() {
+    assert!(matches!(
+        PrefixTree::from_lengths(&[1, 1, 1]),
+        Err(PrefixError::OverSubscribed)
+    ));
+    assert!(matches!(
+        PrefixTree::from_lengths(&[16, 1]),
+        Err(PrefixError::InvalidLength(16))
+    ));
+
+    let mut tree = PrefixTree::from_lengths(&[1, 2]).unwrap();
+    assert!(matches!(tree.walk(2), Err(PrefixError::InvalidBit(2))));
+
+    // 0 is already a leaf, and 10 already holds a value.
+    assert!(matches!(
+        tree.insert_code(Code::from(0b01, 2), 5),
+        Err(PrefixError::CodeConflict(5))
+    ));
+    assert!(matches!(
+        tree.insert_code(Code::from(0b10, 2), 6),
+        Err(PrefixError::CodeConflict(6))
+    ));
+    // The free code 11 can still be added.
+    tree.insert_code(Code::from(0b11, 2), 7).unwrap();
+    assert_eq!(tree.walk(1).unwrap(), None);
+    assert_eq!(tree.walk(1).unwrap(), Some(7));
+}
+
+#[test]
+fn test_prefix_tree_display() {
+    let tree = PrefixTree::from_lengths(&[1, 2, 2]).unwrap();
+
+    // Only the leaves store codes, branches are shown by their path.
+    assert_eq!(
+        tree.nodes
+            .iter()
+            .filter(|node| node.code.length > 0)
+            .count(),
+        3
+    );
+    assert_eq!(
+        tree.to_string(),
+        "(None, 0)\n├── (1)\n│   ├── (11: 2)\n│   └── (10: 1)\n└── (0: 0)\n"
+    );
+}
+
+#[test]
+fn test_huffman_lengths() {
+    // Frequencies that halve give a code one longer for each symbol.
+    let lengths = huffman_lengths(&[16, 8, 4, 2, 1, 1], 15);
+    assert_eq!(lengths, [1, 2, 3, 4, 5, 5]);
+
+    // Fibonacci frequencies make the deepest possible tree, which has to be
+    // cut down to the limit while staying complete.
+    let mut fibonacci = vec![1u64, 1];
+    while fibonacci.len() < 30 {
+        fibonacci.push(fibonacci[fibonacci.len() - 1] + fibonacci[fibonacci.len() - 2]);
+    }
+    let lengths = huffman_lengths(&fibonacci, 7);
+    assert!(lengths.iter().all(|&length| (1..=7).contains(&length)));
+    assert_eq!(kraft(&lengths), Ordering::Equal);
+
+    // Unused symbols get no code, but there are always two codes.
+    assert_eq!(huffman_lengths(&[0, 5, 0, 5], 15), [0, 1, 0, 1]);
+    assert_eq!(huffman_lengths(&[0, 5, 0], 15), [1, 1, 0]);
+    assert_eq!(huffman_lengths(&[0, 0], 15), [1, 1]);
+}
+
+#[test]
+fn test_cl_code_order() {
+    // Every symbol is stored exactly once.
+    let mut sorted = CL_CODE_ORDER;
+    sorted.sort_unstable();
+    assert_eq!(sorted, std::array::from_fn::<usize, CL_CODES, _>(|i| i));
+    assert_eq!(cl_code_symbol(3), 0);
+
+    // Trailing unused lengths are left off, but never below HCLEN_BASE.
+    let mut lengths = [0; CL_CODES];
+    assert_eq!(cl_code_count(&lengths), HCLEN_BASE);
+    lengths[8] = 2;
+    assert_eq!(cl_code_count(&lengths), 5);
+    lengths[15] = 2;
+    assert_eq!(cl_code_count(&lengths), CL_CODES);
+}
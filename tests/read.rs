@@ -0,0 +1,345 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{
+    fs,
+    io::{self, BufRead, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use gzip::{
+    bufread::{self, Progress, FIXED_HEADER_SIZE},
+    deflate::{compress_gzip, CompressOptions},
+    gzip::{GzipError, GzipFile, GzipHeader},
+    inflate::{BlockType, DeflateError},
+    read::{GzDecoder, MultiGzDecoder},
+};
+
+/// A reader that hands out at most size bytes per read.
+struct Trickle<R: Read> {
+    reader: R,
+    size: usize,
+}
+
+impl<R: Read> Read for Trickle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.size);
+        self.reader.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn test_gz_decoder() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decoder = GzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert!(decoder.header().is_some());
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_gz_decoder_small_reads() {
+    let compressed = fs::read("./tests/compressed/repeats.gz").unwrap();
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    let mut decoder = GzDecoder::new(Trickle {
+        reader: &compressed[..],
+        size: 3,
+    });
+    let mut decompressed = Vec::new();
+    let mut buf = [0u8; 5];
+
+    loop {
+        match decoder.read(&mut buf).unwrap() {
+            0 => break,
+            n => decompressed.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_gz_decoder_truncated() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+
+    let mut decoder = GzDecoder::new(&compressed[..compressed.len() / 2]);
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_buf_read_decoder() {
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decoder = bufread::GzDecoder::from_path("./tests/compressed/picture.png.gz").unwrap();
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(raw, decompressed);
+
+    // The inner reader is left right after the file.
+    let compressed = fs::read("./tests/compressed/block_type_2.gz").unwrap();
+    let mut concatenated = compressed.clone();
+    concatenated.extend_from_slice(b"after");
+
+    let mut decoder = bufread::GzDecoder::new(&concatenated[..]);
+    io::copy(&mut decoder, &mut io::sink()).unwrap();
+    assert_eq!(decoder.into_inner(), b"after");
+}
+
+#[test]
+fn test_gz_decoder_reset() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+    let raw = fs::read("./tests/raw/members").unwrap();
+
+    // One decoder reads every member, keeping its settings between them.
+    let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+    decoder.set_max_output_size(Some(raw.len() as u64));
+    let mut decompressed = Vec::new();
+    let mut members = 0;
+    loop {
+        decoder.read_to_end(&mut decompressed).unwrap();
+        members += 1;
+        if decoder.get_mut().is_empty() {
+            break;
+        }
+        decoder.reset();
+    }
+
+    assert!(members > 1);
+    assert_eq!(raw, decompressed);
+}
+
+#[test]
+fn test_gz_decoder_max_output_size() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+
+    let mut decoder = GzDecoder::new(&compressed[..]);
+    decoder.set_max_output_size(Some(1 << 20));
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_gz_decoder_fixed_memory() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decoder = bufread::GzDecoder::with_fixed_memory(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(raw, decompressed);
+
+    // A header too long to hold is rejected before it's all read.
+    let header = GzipHeader {
+        fname: Some("a".repeat(FIXED_HEADER_SIZE)),
+        ..Default::default()
+    }
+    .to_bytes();
+    let mut decoder = bufread::GzDecoder::with_fixed_memory(&header[..]);
+    assert!(matches!(
+        decoder.read_header(),
+        Err(GzipError::InvalidHeader(bytes)) if bytes.len() == FIXED_HEADER_SIZE
+    ));
+}
+
+#[test]
+fn test_gz_decoder_progress() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+    let raw = fs::read("./tests/raw/members").unwrap();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+    decoder.set_progress(Some(Box::new({
+        let seen = Arc::clone(&seen);
+        move |progress: &Progress| seen.lock().unwrap().push(*progress)
+    })));
+
+    let mut buf = [0; 100];
+    loop {
+        while decoder.read(&mut buf).unwrap() > 0 {}
+        if decoder.get_mut().is_empty() {
+            break;
+        }
+        decoder.reset();
+    }
+
+    let seen = seen.lock().unwrap();
+    assert!(seen
+        .windows(2)
+        .all(|pair| pair[0].total_in <= pair[1].total_in
+            && pair[0].total_out <= pair[1].total_out
+            && pair[0].member <= pair[1].member));
+
+    let last = seen.last().unwrap();
+    assert!(last.member > 0);
+    assert_eq!(last.total_in, compressed.len() as u64);
+    assert_eq!(last.total_out, raw.len() as u64);
+    assert_eq!(decoder.progress(), *last);
+}
+
+#[test]
+fn test_gz_decoder_cancel() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut decoder = GzDecoder::new(&compressed[..]);
+    decoder.set_cancel(Some(Arc::clone(&cancel)));
+
+    let mut buf = vec![0; 100];
+    assert_eq!(decoder.read(&mut buf).unwrap(), 100);
+    assert_eq!(buf, raw[..100]);
+
+    cancel.store(true, Ordering::Relaxed);
+    let err = decoder.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    let err = err.into_inner().unwrap().downcast::<GzipError>().unwrap();
+    assert!(matches!(*err, GzipError::Cancelled));
+
+    // Decoding everything in one go is cancelled at the first block.
+    let mut file = GzipFile::from_bytes(&compressed).unwrap();
+    file.deflate.set_cancel(Some(cancel));
+    assert!(matches!(
+        file.decompress().unwrap_err().inner(),
+        DeflateError::CancelledError
+    ));
+}
+
+#[test]
+fn test_gz_decoder_save_state() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+    let mut decompressed = vec![0; 5000];
+    decoder.read_exact(&mut decompressed).unwrap();
+    let state = decoder.save_state();
+    let start = decoder.progress().total_in as usize;
+    drop(decoder);
+
+    // Picked up again from where the first decoder left the file.
+    let mut decoder = GzDecoder::restore_state(&compressed[start..], &state).unwrap();
+    assert!(decoder.header().is_some());
+    decoder.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, raw);
+    assert_eq!(decoder.progress().total_in, compressed.len() as u64);
+}
+
+/// A writer that counts how many times it's written to.
+#[derive(Default)]
+struct CountWrites {
+    data: Vec<u8>,
+    writes: usize,
+}
+
+impl io::Write for CountWrites {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_gz_decoder_copy_to() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut copied = CountWrites::default();
+    io::copy(&mut GzDecoder::new(&compressed[..]), &mut copied).unwrap();
+
+    let mut batched = CountWrites::default();
+    let len = GzDecoder::new(&compressed[..])
+        .copy_to(&mut batched)
+        .unwrap();
+
+    assert_eq!(len, raw.len() as u64);
+    assert_eq!(batched.data, raw);
+    assert_eq!(batched.writes, raw.len().div_ceil(1 << 18));
+    assert!(batched.writes * 10 < copied.writes);
+}
+
+#[test]
+fn test_multi_gz_decoder() {
+    let compressed = fs::read("./tests/compressed/members.gz").unwrap();
+    let raw = fs::read("./tests/raw/members").unwrap();
+
+    let mut decoder = MultiGzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(raw, decompressed);
+    assert!(decoder.progress().member > 0);
+
+    // Reading lines through BufRead crosses members too.
+    let lines = MultiGzDecoder::new(&compressed[..]).lines().count();
+    assert_eq!(
+        lines,
+        raw.split(|&b| b == b'\n').count() - raw.ends_with(b"\n") as usize
+    );
+
+    // Empty members are skipped over.
+    let options = CompressOptions::default();
+    let mut concatenated = compress_gzip(b"", &options);
+    concatenated.extend(compress_gzip(b"one", &options));
+    concatenated.extend(compress_gzip(b"", &options));
+    concatenated.extend(compress_gzip(b"two", &options));
+    let mut decompressed = String::new();
+    MultiGzDecoder::new(&concatenated[..])
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, "onetwo");
+}
+
+#[test]
+fn test_multi_gz_decoder_tar() {
+    let files = [
+        ("picture.png", fs::read("./tests/raw/picture.png").unwrap()),
+        ("repeats", fs::read("./tests/raw/repeats").unwrap()),
+    ];
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, data) in &files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, &data[..]).unwrap();
+    }
+    let archive = builder.into_inner().unwrap();
+
+    // Compressed as members of 1MB, as parallel compressors write them.
+    let options = CompressOptions {
+        block_type: Some(BlockType::Fixed),
+        max_chain: 0,
+        ..Default::default()
+    };
+    let compressed = archive
+        .chunks(1 << 20)
+        .flat_map(|chunk| compress_gzip(chunk, &options))
+        .collect::<Vec<_>>();
+
+    let mut archive = tar::Archive::new(MultiGzDecoder::new(&compressed[..]));
+    let mut entries = 0;
+    for (entry, (name, data)) in archive.entries().unwrap().zip(&files) {
+        let mut entry = entry.unwrap();
+        assert_eq!(entry.path().unwrap().to_str(), Some(*name));
+
+        let mut extracted = Vec::new();
+        entry.read_to_end(&mut extracted).unwrap();
+        assert!(extracted == *data, "{} differs", name);
+        entries += 1;
+    }
+    assert_eq!(entries, files.len());
+}
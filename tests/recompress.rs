@@ -0,0 +1,142 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{fs, io::Read};
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use gzip::{
+    deflate::{compress, compress_zlib, CompressOptions},
+    gzip::GzipError,
+    inflate::BlockType,
+    recompress::{convert, recompress, Container, RecompressError, RecompressOptions},
+};
+
+/// Decodes output in container with flate2.
+fn reference(output: &[u8], container: Container) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    match container {
+        Container::Gzip => MultiGzDecoder::new(output).read_to_end(&mut decoded),
+        Container::Zlib => ZlibDecoder::new(output).read_to_end(&mut decoded),
+        Container::Raw => DeflateDecoder::new(output).read_to_end(&mut decoded),
+    }
+    .unwrap();
+    decoded
+}
+
+#[test]
+fn test_recompress() {
+    for name in ["members", "block_type_2_long", "sync_flush"] {
+        let compressed = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        for container in [Container::Gzip, Container::Zlib, Container::Raw] {
+            let options = RecompressOptions {
+                container,
+                ..Default::default()
+            };
+            let output = recompress(&compressed, &options).unwrap();
+            assert_eq!(raw, reference(&output, container), "{}", name);
+        }
+    }
+
+    // From zlib, and to a different block type.
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let options = RecompressOptions {
+        container: Container::Gzip,
+        compress: CompressOptions {
+            block_type: Some(BlockType::Fixed),
+            ..Default::default()
+        },
+    };
+    let output = recompress(&compress_zlib(&raw, &CompressOptions::default()), &options).unwrap();
+    assert_eq!(raw, reference(&output, Container::Gzip));
+}
+
+#[test]
+fn test_recompress_errors() {
+    let options = RecompressOptions::default();
+    assert!(matches!(
+        recompress(b"plain text", &options),
+        Err(RecompressError::UnknownContainer(_))
+    ));
+    assert!(matches!(
+        recompress(&[], &options),
+        Err(RecompressError::UnknownContainer(_))
+    ));
+
+    let mut compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let last = compressed.len() - 5;
+    compressed[last] ^= 1;
+    assert!(matches!(
+        recompress(&compressed, &options),
+        Err(RecompressError::GzipError(_))
+    ));
+
+    let mut zlib = compress_zlib(b"recompress", &CompressOptions::default());
+    let last = zlib.len() - 1;
+    zlib[last] ^= 1;
+    assert!(matches!(
+        recompress(&zlib, &options),
+        Err(RecompressError::ZlibError(_))
+    ));
+}
+
+#[test]
+fn test_convert() {
+    let raw = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let gzip = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let options = CompressOptions::default();
+    let zlib = compress_zlib(&raw, &options);
+    let stream = compress(&raw, &options);
+
+    for (from, input) in [
+        (Container::Gzip, &gzip),
+        (Container::Zlib, &zlib),
+        (Container::Raw, &stream),
+    ] {
+        for to in [Container::Gzip, Container::Zlib, Container::Raw] {
+            let output = convert(input, from, to).unwrap();
+            assert_eq!(raw, reference(&output, to), "{:?} to {:?}", from, to);
+        }
+        assert_eq!(*input, convert(input, from, from).unwrap());
+    }
+
+    // The stream itself is copied as it is.
+    assert_eq!(
+        stream,
+        convert(&zlib, Container::Zlib, Container::Raw).unwrap()
+    );
+    let converted = convert(&stream, Container::Raw, Container::Gzip).unwrap();
+    assert_eq!(stream, converted[10..converted.len() - 8]);
+}
+
+#[test]
+fn test_convert_errors() {
+    let members = fs::read("./tests/compressed/members.gz").unwrap();
+    assert!(matches!(
+        convert(&members, Container::Gzip, Container::Zlib),
+        Err(RecompressError::TrailingData(_))
+    ));
+
+    let mut gzip = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+    let last = gzip.len() - 5;
+    gzip[last] ^= 1;
+    assert!(matches!(
+        convert(&gzip, Container::Gzip, Container::Raw),
+        Err(RecompressError::GzipError(GzipError::DeflateError(_)))
+    ));
+    assert!(matches!(
+        convert(&gzip[..gzip.len() - 4], Container::Gzip, Container::Raw),
+        Err(RecompressError::GzipError(_))
+    ));
+
+    let fdict = fs::read("./tests/compressed/fdict.zlib").unwrap();
+    assert!(matches!(
+        convert(&fdict, Container::Zlib, Container::Gzip),
+        Err(RecompressError::ZlibError(_))
+    ));
+
+    assert!(matches!(
+        convert(&[0xff; 16], Container::Raw, Container::Gzip),
+        Err(RecompressError::DeflateError(_))
+    ));
+}
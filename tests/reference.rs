@@ -0,0 +1,80 @@
+#![cfg(all(feature = "test-utils", feature = "inflate", feature = "deflate"))]
+//! Checks Inflater against the slow reference inflater on every fixture and
+//! on random and damaged streams. They have to agree on what each stream
+//! decodes to, and on whether it's valid at all.
+mod common;
+
+use std::fs;
+
+use gzip::{
+    deflate::{compress, CompressOptions},
+    gzip::GzipHeader,
+    inflate::DeflateData,
+    reference,
+};
+use proptest::prelude::*;
+
+/// Decodes stream both ways, failing if they disagree.
+fn cross_check(stream: &[u8]) -> Result<(), TestCaseError> {
+    let fast = DeflateData::build(stream).decompress();
+    let slow = reference::inflate(stream);
+
+    match (fast, slow) {
+        (Ok(fast), Ok(slow)) => prop_assert_eq!(fast, slow),
+        (Err(_), Err(_)) => {}
+        (fast, slow) => prop_assert!(
+            false,
+            "Inflater gave {:?}, the reference {:?}",
+            fast.map(|data| data.len()),
+            slow.map(|data| data.len())
+        ),
+    }
+    Ok(())
+}
+
+/// Returns the DEFLATE stream of a gzip file's first member, with whatever
+/// follows it.
+fn stream(gzip: &[u8]) -> &[u8] {
+    &gzip[GzipHeader::build(gzip).unwrap().end_idx..]
+}
+
+#[test]
+fn test_reference_corpus() {
+    for entry in fs::read_dir("./tests/compressed").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "gz") {
+            let bytes = fs::read(&path).unwrap();
+            cross_check(stream(&bytes)).unwrap();
+        }
+    }
+
+    for fixture in common::fixtures() {
+        cross_check(stream(&fixture.compressed)).unwrap();
+
+        // Only the first member is decoded.
+        if fixture.name != "members" {
+            let decoded = reference::inflate(stream(&fixture.compressed)).unwrap();
+            assert_eq!(decoded, fixture.raw, "{}", fixture.name);
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_reference_random(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+        cross_check(&bytes)?;
+    }
+
+    #[test]
+    fn test_reference_damaged(
+        text in "(deflate |huffman |window |a|\n){0,300}",
+        changes in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..8),
+    ) {
+        let mut stream = compress(text.as_bytes(), &CompressOptions::default());
+        for (index, byte) in changes {
+            let index = index.index(stream.len());
+            stream[index] = byte;
+        }
+        cross_check(&stream)?;
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "json")]
+
+use std::fs;
+
+use gzip::report::{report, to_json};
+
+#[test]
+fn test_report() {
+    let bytes = fs::read("tests/compressed/members.gz").unwrap();
+    let raw = fs::read("tests/raw/members").unwrap();
+
+    let report = report(&bytes).unwrap();
+    assert_eq!(report["size"], bytes.len());
+
+    let members = report["members"].as_array().unwrap();
+    assert!(members.len() > 1);
+    assert_eq!(members[0]["header"]["extra"][0]["id"], "BC");
+    let mut offset = 0;
+    let mut uncompressed = 0;
+    for member in members {
+        assert_eq!(member["offset"], offset);
+        assert_eq!(member["crc32_ok"], true);
+        assert!(!member["blocks"].as_array().unwrap().is_empty());
+
+        offset += member["compressed_size"].as_u64().unwrap();
+        uncompressed += member["uncompressed_size"].as_u64().unwrap();
+    }
+    assert_eq!(offset, bytes.len() as u64);
+    assert_eq!(uncompressed, raw.len() as u64);
+}
+
+#[test]
+fn test_to_json() {
+    let bytes = fs::read("tests/compressed/block_type_2.gz").unwrap();
+
+    let json = to_json(&bytes).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(value, report(&bytes).unwrap());
+    assert_eq!(value["members"][0]["blocks"][0]["type"], "dynamic");
+    assert_eq!(value["members"][0]["blocks"][0]["final"], true);
+}
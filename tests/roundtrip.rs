@@ -0,0 +1,135 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::io::{Read, Write};
+
+use flate2::{write::GzEncoder, Compression};
+use gzip::{
+    deflate::{compress_gzip, CompressOptions},
+    gzip::GzipFile,
+    inflate::BlockType,
+    read::GzDecoder,
+    write,
+};
+use proptest::prelude::*;
+
+/// Compresses data with flate2 at the given level, writing it in pieces of
+/// the given sizes and sync flushing after the pieces marked to, so the
+/// stream has empty stored blocks and blocks ending mid-file.
+fn compress(data: &[u8], level: u32, pieces: &[(usize, bool)]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+    let mut rest = data;
+
+    for &(size, flush) in pieces {
+        let (piece, after) = rest.split_at(size.min(rest.len()));
+        encoder.write_all(piece).unwrap();
+        if flush {
+            encoder.flush().unwrap();
+        }
+        rest = after;
+    }
+    encoder.write_all(rest).unwrap();
+
+    encoder.finish().unwrap()
+}
+
+/// Compresses data with our own GzEncoder, the same way compress does with
+/// flate2's.
+fn compress_ours(data: &[u8], options: CompressOptions, pieces: &[(usize, bool)]) -> Vec<u8> {
+    let mut encoder = write::GzEncoder::new(Vec::new(), options);
+    let mut rest = data;
+
+    for &(size, flush) in pieces {
+        let (piece, after) = rest.split_at(size.min(rest.len()));
+        encoder.write_all(piece).unwrap();
+        if flush {
+            encoder.flush().unwrap();
+        }
+        rest = after;
+    }
+    encoder.write_all(rest).unwrap();
+
+    encoder.finish().unwrap()
+}
+
+/// Every block type or the smallest, blocks from a token each to bigger
+/// than the default, and match finding from off to a long search.
+fn options() -> impl Strategy<Value = CompressOptions> {
+    let block_type = prop_oneof![
+        Just(None),
+        Just(Some(BlockType::Stored)),
+        Just(Some(BlockType::Fixed)),
+        Just(Some(BlockType::Dynamic)),
+    ];
+    let block_size = prop_oneof![1usize..64, 64usize..40000];
+
+    (block_type, block_size, 0usize..256).prop_map(|(block_type, block_size, max_chain)| {
+        CompressOptions {
+            block_type,
+            block_size,
+            max_chain,
+        }
+    })
+}
+
+/// Random bytes, or text made of a few words so there's plenty for matches
+/// to refer back to.
+fn data() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..4096),
+        "(deflate |huffman |window |a|\n){0,800}".prop_map(String::into_bytes),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_round_trip(
+        data in data(),
+        level in 0u32..=9,
+        pieces in prop::collection::vec((0usize..512, any::<bool>()), 0..8),
+    ) {
+        let compressed = compress(&data, level, &pieces);
+
+        let decompressed = GzipFile::from_bytes(&compressed).unwrap().decompress().unwrap();
+        prop_assert_eq!(&decompressed, &data);
+    }
+
+    #[test]
+    fn test_round_trip_ours(data in data(), options in options()) {
+        let compressed = compress_gzip(&data, &options);
+
+        let decompressed = GzipFile::from_bytes(&compressed).unwrap().decompress().unwrap();
+        prop_assert_eq!(&decompressed, &data);
+    }
+
+    #[test]
+    fn test_round_trip_gz_encoder(
+        data in data(),
+        options in options(),
+        pieces in prop::collection::vec((0usize..512, any::<bool>()), 0..8),
+    ) {
+        let compressed = compress_ours(&data, options, &pieces);
+
+        let decompressed = GzipFile::from_bytes(&compressed).unwrap().decompress().unwrap();
+        prop_assert_eq!(&decompressed, &data);
+    }
+
+    #[test]
+    fn test_round_trip_streaming(
+        data in data(),
+        level in 0u32..=9,
+        read_size in 1usize..300,
+    ) {
+        let compressed = compress(&data, level, &[]);
+
+        let mut decoder = GzDecoder::new(&compressed[..]);
+        let mut decompressed = Vec::new();
+        let mut buf = vec![0; read_size];
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => break,
+                n => decompressed.extend_from_slice(&buf[..n]),
+            }
+        }
+        prop_assert_eq!(&decompressed, &data);
+    }
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "inflate")]
+
+use std::{fs, io::Write};
+
+use flate2::{write::DeflateEncoder, Compression};
+
+use gzip::{
+    gzip::GzipHeader,
+    inflate::BlockType,
+    tokens::{decode_events, Event},
+};
+
+#[test]
+fn test_decode_events() {
+    // An empty stored block, then a final fixed block holding a literal 'A'.
+    let stream = [0x00, 0x00, 0x00, 0xff, 0xff, 0x73, 0x04, 0x00];
+
+    let mut events = Vec::new();
+    let used = decode_events(&stream, &mut |event| events.push(event)).unwrap();
+
+    assert_eq!(used, stream.len());
+    assert_eq!(
+        events,
+        [
+            Event::BlockStart {
+                block_type: BlockType::Stored,
+                final_block: false
+            },
+            Event::BlockEnd,
+            Event::BlockStart {
+                block_type: BlockType::Fixed,
+                final_block: true
+            },
+            Event::Literal(b'A'),
+            Event::EndOfBlock,
+            Event::BlockEnd,
+        ]
+    );
+}
+
+#[test]
+fn test_events_rebuild_output() {
+    for name in [
+        "block_type_0",
+        "block_type_1_lzss",
+        "block_type_2_long",
+        "sync_flush",
+        "picture.png",
+    ] {
+        let bytes = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+        let header = GzipHeader::build(&bytes).unwrap();
+
+        // Replaying the literals and matches should give back the output.
+        let mut rebuilt = Vec::new();
+        decode_events(&bytes[header.end_idx..], &mut |event| match event {
+            Event::Literal(byte) => rebuilt.push(byte),
+            Event::Match { length, distance } => {
+                for _ in 0..length {
+                    rebuilt.push(rebuilt[rebuilt.len() - distance]);
+                }
+            }
+            _ => {}
+        })
+        .unwrap();
+
+        assert!(rebuilt == raw, "{}", name);
+    }
+}
+
+#[test]
+fn test_stored_events_span_output_chunks() {
+    // Stored blocks of 64KB go past the 32KB the output is decoded in.
+    let raw = (0..200_000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect::<Vec<_>>();
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::none());
+    encoder.write_all(&raw).unwrap();
+    let stream = encoder.finish().unwrap();
+
+    let mut literals = Vec::new();
+    decode_events(&stream, &mut |event| {
+        if let Event::Literal(byte) = event {
+            literals.push(byte);
+        }
+    })
+    .unwrap();
+
+    assert!(literals == raw);
+}
@@ -0,0 +1,116 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{fs, io::Read};
+
+use flate2::read::MultiGzDecoder;
+use gzip::{
+    analyze::analyze,
+    bits::BitWriter,
+    deflate::{write_block, Token},
+    gzip::{GzipFile, GzipHeader},
+    inflate::{BlockType, DeflateError},
+    transcode::{transcode, transcode_gzip, TranscodeOptions},
+};
+
+const NAMES: [&str; 8] = [
+    "block_type_0",
+    "block_type_1",
+    "block_type_1_lzss",
+    "block_type_2",
+    "block_type_2_long",
+    "members",
+    "repeats",
+    "sync_flush",
+];
+
+#[test]
+fn test_transcode_gzip() {
+    let options = [
+        TranscodeOptions::default(),
+        TranscodeOptions {
+            block_type: Some(BlockType::Stored),
+            block_size: None,
+        },
+        TranscodeOptions {
+            block_type: Some(BlockType::Fixed),
+            block_size: None,
+        },
+        TranscodeOptions {
+            block_type: Some(BlockType::Dynamic),
+            block_size: Some(100),
+        },
+        TranscodeOptions {
+            block_type: None,
+            block_size: Some(5000),
+        },
+    ];
+
+    for name in NAMES {
+        let compressed = fs::read(format!("./tests/compressed/{}.gz", name)).unwrap();
+        let raw = fs::read(format!("./tests/raw/{}", name)).unwrap();
+
+        for options in &options {
+            let transcoded = transcode_gzip(&compressed, options).unwrap();
+
+            // The output has to decode the same here and with zlib.
+            let mut decompressed = Vec::new();
+            MultiGzDecoder::new(&transcoded[..])
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert!(decompressed == raw, "{} {:?}", name, options);
+
+            if name != "members" {
+                let decompressed = GzipFile::from_bytes(&transcoded)
+                    .unwrap()
+                    .decompress()
+                    .unwrap();
+                assert!(decompressed == raw, "{} {:?}", name, options);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_transcode_block_types() {
+    let compressed = fs::read("./tests/compressed/block_type_1_lzss.gz").unwrap();
+    let header = GzipHeader::build(&compressed).unwrap();
+    let stream = &compressed[header.end_idx..];
+
+    let options = TranscodeOptions {
+        block_type: Some(BlockType::Dynamic),
+        block_size: Some(10),
+    };
+    let blocks = analyze(&transcode(stream, &options).unwrap()).unwrap();
+    let tokens = analyze(stream).unwrap()[0].literal_lengths[..]
+        .iter()
+        .sum::<u64>()
+        - 1;
+
+    assert_eq!(blocks.len() as u64, tokens.div_ceil(10));
+    assert!(blocks
+        .iter()
+        .all(|block| block.block_type == BlockType::Dynamic));
+
+    // Picking the smallest type never does worse than the original.
+    let smallest = transcode(stream, &TranscodeOptions::default()).unwrap();
+    assert!(smallest.len() <= compressed.len() - header.end_idx - 8);
+}
+
+#[test]
+fn test_transcode_distance_too_far() {
+    // A match as the first token has nothing behind it to copy from.
+    let mut writer = BitWriter::new();
+    let tokens = [Token::Match {
+        length: 3,
+        distance: 3,
+    }];
+    write_block(&mut writer, &tokens, b"abc", Some(BlockType::Fixed), true);
+    let stream = writer.finish();
+
+    let err = transcode(&stream, &TranscodeOptions::default()).unwrap_err();
+    assert!(
+        matches!(err, DeflateError::InvalidDistanceError { distance: 3, .. }),
+        "{}",
+        err
+    );
+}
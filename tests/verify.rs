@@ -0,0 +1,85 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{fs, io::Read};
+
+use flate2::read::MultiGzDecoder;
+use gzip::{
+    deflate::{compress, compress_gzip, CompressOptions},
+    inflate::{BlockType, DeflateData},
+    verify::{verify, verify_with_reference, VerifyError},
+};
+
+/// Decompresses a gzip file with flate2.
+fn reference(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    MultiGzDecoder::new(bytes).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+#[test]
+fn test_verify_fixtures() {
+    let fixtures = [
+        "block_type_0",
+        "block_type_1",
+        "block_type_2_long",
+        "header_crc",
+        "members",
+        "repeats",
+        "sync_flush",
+    ];
+    let options = [
+        CompressOptions::default(),
+        CompressOptions {
+            max_chain: 0,
+            ..Default::default()
+        },
+        CompressOptions {
+            block_type: Some(BlockType::Fixed),
+            block_size: 100,
+            ..Default::default()
+        },
+        CompressOptions {
+            block_type: Some(BlockType::Stored),
+            ..Default::default()
+        },
+    ];
+
+    for name in fixtures {
+        let bytes = fs::read(format!("tests/compressed/{}.gz", name)).unwrap();
+        for options in &options {
+            let verification = verify_with_reference(&bytes, options, reference)
+                .unwrap_or_else(|err| panic!("{} {:?}: {}", name, options, err));
+
+            assert_eq!(verification.compressed, bytes.len());
+        }
+    }
+}
+
+#[test]
+fn test_compress() {
+    let raw = fs::read("tests/raw/repeats").unwrap();
+
+    let stream = compress(&raw, &CompressOptions::default());
+    assert!(stream.len() < raw.len() / 2);
+    assert_eq!(DeflateData::build(&stream).decompress().unwrap(), raw);
+
+    // Empty data is still a complete stream.
+    let stream = compress(&[], &CompressOptions::default());
+    assert_eq!(DeflateData::build(&stream).decompress().unwrap(), b"");
+    assert_eq!(
+        reference(&compress_gzip(&[], &CompressOptions::default())).unwrap(),
+        b""
+    );
+}
+
+#[test]
+fn test_verify_invalid_file() {
+    let mut bytes = fs::read("tests/compressed/block_type_2.gz").unwrap();
+    let len = bytes.len();
+    bytes[len - 8] ^= 0xff;
+
+    assert!(matches!(
+        verify(&bytes, &CompressOptions::default()),
+        Err(VerifyError::Original(_))
+    ));
+}
@@ -0,0 +1,267 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use flate2::read::GzDecoder;
+use gzip::{
+    bufread,
+    deflate::{compress_gzip, CompressOptions},
+    gzip::GzipHeader,
+    write::{DecompressWriter, GzEncoder},
+};
+
+#[test]
+fn test_decompress_writer() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    let mut writer = DecompressWriter::new(Vec::new());
+    writer.write_all(&compressed).unwrap();
+
+    assert!(writer.header().is_some());
+    assert_eq!(raw, writer.finish().unwrap());
+}
+
+#[test]
+fn test_decompress_writer_small_writes() {
+    let compressed = fs::read("./tests/compressed/repeats.gz").unwrap();
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    // Writes smaller than the header and trailer split them across calls.
+    let mut writer = DecompressWriter::new(Vec::new());
+    for piece in compressed.chunks(3) {
+        writer.write_all(piece).unwrap();
+    }
+
+    assert_eq!(raw, writer.finish().unwrap());
+}
+
+#[test]
+fn test_decompress_writer_truncated() {
+    let compressed = fs::read("./tests/compressed/block_type_2_long.gz").unwrap();
+
+    let mut writer = DecompressWriter::new(Vec::new());
+    writer
+        .write_all(&compressed[..compressed.len() - 4])
+        .unwrap();
+
+    assert!(writer.finish().is_err());
+
+    // Bytes past the end of the file aren't taken.
+    let mut writer = DecompressWriter::new(Vec::new());
+    writer.write_all(&compressed).unwrap();
+    assert_eq!(writer.write(&[0, 1, 2]).unwrap(), 0);
+}
+
+/// A writer that counts how many times it's written to.
+#[derive(Default)]
+struct CountWrites {
+    data: Vec<u8>,
+    writes: usize,
+}
+
+impl Write for CountWrites {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that fails the first time it's written to, or once it's been
+/// written to fail_after times.
+#[derive(Default)]
+struct FailOnce {
+    data: Vec<u8>,
+    fail_after: usize,
+    failed: bool,
+}
+
+impl Write for FailOnce {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.failed && self.fail_after == 0 {
+            self.failed = true;
+            return Err(io::Error::other("full"));
+        }
+        self.fail_after = self.fail_after.saturating_sub(1);
+        // Only ever half taken, so a write can fail part way through.
+        let n = buf.len().div_ceil(2);
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_decompress_writer_retry() {
+    let compressed = fs::read("./tests/compressed/picture.png.gz").unwrap();
+    let raw = fs::read("./tests/raw/picture.png").unwrap();
+
+    for fail_after in [0, 1, 3] {
+        // The inner writer fails once a batch of output is full, by which
+        // point the inflater has taken some of the write, so those bytes
+        // are reported as taken and the error comes on the next call. Past
+        // the first write, it fails part way through a batch.
+        let inner = FailOnce {
+            fail_after,
+            ..FailOnce::default()
+        };
+        let mut writer = DecompressWriter::new(inner);
+        let mut input = &compressed[..];
+        let mut errors = 0;
+        while !input.is_empty() {
+            match writer.write(input) {
+                Ok(0) => break,
+                Ok(written) => input = &input[written..],
+                Err(err) => {
+                    errors += 1;
+                    assert_eq!(errors, 1, "{}", err);
+                }
+            }
+        }
+
+        assert_eq!(errors, 1);
+        assert!(
+            raw == writer.finish().unwrap().data,
+            "failing after {} writes",
+            fail_after
+        );
+    }
+}
+
+#[test]
+fn test_decompress_writer_batches_output() {
+    let compressed = fs::read("./tests/compressed/repeats.gz").unwrap();
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    // Output from many small writes goes to the inner writer 32KB at a time.
+    let mut writer = DecompressWriter::new(CountWrites::default());
+    for piece in compressed.chunks(100) {
+        writer.write_all(piece).unwrap();
+    }
+    let inner = writer.finish().unwrap();
+
+    assert_eq!(raw, inner.data);
+    assert_eq!(inner.writes, raw.len().div_ceil(32768));
+}
+
+#[test]
+fn test_gz_encoder() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    // Pieces of an odd size cross the encoder's chunks anywhere.
+    let mut encoder = GzEncoder::new(Vec::new(), CompressOptions::default());
+    for piece in raw.chunks(7777) {
+        encoder.write_all(piece).unwrap();
+    }
+    let compressed = encoder.finish().unwrap();
+
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(raw, decompressed);
+    // Matches reaching across chunks keep it about as small as compressing
+    // everything at once.
+    let whole = compress_gzip(&raw, &CompressOptions::default());
+    assert!(compressed.len() < whole.len() + whole.len() / 100);
+}
+
+#[test]
+fn test_gz_encoder_sync_flush() {
+    let events = [&b"data: one\n\n"[..], b"data: two\n\n", b"data: one\n\n"];
+    let mut encoder = GzEncoder::new(Vec::new(), CompressOptions::default());
+    let mut sent = Vec::new();
+
+    for (index, event) in events.iter().enumerate() {
+        encoder.write_all(event).unwrap();
+        encoder.flush().unwrap();
+        let len = encoder.get_ref().len();
+        assert!(encoder.get_ref().ends_with(&[0, 0, 0xff, 0xff]));
+        // Flushing again without writing adds nothing.
+        encoder.flush().unwrap();
+        assert_eq!(encoder.get_ref().len(), len);
+
+        // What's been sent so far decodes to every event so far.
+        sent.extend_from_slice(event);
+        let mut decoder = bufread::GzDecoder::new(&encoder.get_ref()[..]);
+        let mut decoded = vec![0; sent.len()];
+        decoder.read_exact(&mut decoded).unwrap();
+        assert_eq!(decoded, sent, "after event {}", index);
+    }
+
+    let compressed = encoder.finish().unwrap();
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, sent);
+}
+
+#[test]
+fn test_gz_encoder_retry() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    for fail_after in [0, 1, 5] {
+        // Whichever write the inner writer fails on, nothing of the stream is
+        // lost or written twice.
+        let inner = FailOnce {
+            fail_after,
+            ..FailOnce::default()
+        };
+        let mut encoder = GzEncoder::new(inner, CompressOptions::default());
+        let mut errors = 0;
+        for piece in raw.chunks(50_000) {
+            let mut piece = piece;
+            while !piece.is_empty() {
+                match encoder.write(piece) {
+                    Ok(written) => piece = &piece[written..],
+                    Err(err) => {
+                        errors += 1;
+                        assert_eq!(errors, 1, "{}", err);
+                    }
+                }
+            }
+            while let Err(err) = encoder.flush() {
+                errors += 1;
+                assert_eq!(errors, 1, "{}", err);
+            }
+        }
+        let compressed = encoder.finish().unwrap().data;
+
+        assert_eq!(errors, 1);
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert!(decompressed == raw, "failing after {} writes", fail_after);
+    }
+}
+
+#[test]
+fn test_gz_encoder_header() {
+    let header = GzipHeader {
+        mtime: 1_700_000_000,
+        fname: Some(String::from("notes.txt")),
+        ..GzipHeader::default()
+    };
+    let mut encoder = GzEncoder::with_header(Vec::new(), CompressOptions::default(), header);
+    encoder.write_all(b"some notes\n").unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut decoder = bufread::GzDecoder::new(&compressed[..]);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, b"some notes\n");
+    let header = decoder.header().unwrap();
+    assert_eq!(header.fname.as_deref(), Some("notes.txt"));
+    assert_eq!(header.mtime, 1_700_000_000);
+}
@@ -0,0 +1,123 @@
+#![cfg(all(feature = "inflate", feature = "deflate"))]
+
+use std::{fs, io::Read};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use gzip::{
+    adler,
+    deflate::{compress_zlib, CompressOptions},
+    inflate::{BlockType, DeflateError},
+    zlib::{self, ZlibError, ZlibHeader},
+};
+
+/// Compresses data with flate2.
+fn zlib_encode(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn test_adler32() {
+    assert_eq!(adler::hash(b""), 1);
+    assert_eq!(adler::hash(b"Wikipedia"), 0x11e60398);
+
+    // Long enough to need several reductions, and split anywhere.
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+    let (a, b) = raw.split_at(12345);
+    assert_eq!(adler::update(adler::hash(a), b), adler::hash(&raw));
+}
+
+#[test]
+fn test_zlib_round_trip() {
+    let raw = fs::read("./tests/raw/repeats").unwrap();
+
+    assert_eq!(raw, zlib::decompress(&zlib_encode(&raw)).unwrap());
+
+    for block_type in [None, Some(BlockType::Stored), Some(BlockType::Fixed)] {
+        let options = CompressOptions {
+            block_type,
+            ..Default::default()
+        };
+        let compressed = compress_zlib(&raw, &options);
+
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(raw, decompressed);
+        assert_eq!(raw, zlib::decompress(&compressed).unwrap());
+    }
+}
+
+#[test]
+fn test_zlib_errors() {
+    let mut compressed = compress_zlib(b"zlib", &CompressOptions::default());
+
+    assert!(matches!(
+        zlib::decompress(&[0x78, 0x9d]),
+        Err(ZlibError::InvalidHeader(_))
+    ));
+    assert!(matches!(
+        zlib::decompress(&[0x79, 0x18]),
+        Err(ZlibError::UnsupportedCompressionMethod(9))
+    ));
+    assert!(matches!(
+        zlib::decompress(&[0x88, 0x98]),
+        Err(ZlibError::InvalidWindowSize(8))
+    ));
+    assert!(matches!(
+        zlib::decompress(&[0x78, 0xbb, 0, 0, 0, 1]),
+        Err(ZlibError::PresetDictionary(1))
+    ));
+    assert!(matches!(
+        zlib::decompress(&compressed[..4]),
+        Err(ZlibError::DeflateError(_))
+    ));
+
+    *compressed.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        zlib::decompress(&compressed),
+        Err(ZlibError::ChecksumMismatch { .. })
+    ));
+
+    let compressed = compress_zlib(&[0; 1000], &CompressOptions::default());
+    let err = zlib::decompress_with_limit(&compressed, Some(999)).unwrap_err();
+    assert!(matches!(
+        err,
+        ZlibError::DeflateError(err) if matches!(err.inner(), DeflateError::OutputLimitError(999))
+    ));
+}
+
+#[test]
+fn test_zlib_dictionary() {
+    // Written by Python's zlib, with block_type_2_long as the dictionary,
+    // compressing it reversed and then as it is.
+    let compressed = fs::read("./tests/compressed/fdict.zlib").unwrap();
+    let dictionary = fs::read("./tests/raw/block_type_2_long").unwrap();
+    let mut raw: Vec<u8> = dictionary.iter().rev().copied().collect();
+    raw.extend(&dictionary);
+
+    let header = ZlibHeader::build(&compressed).unwrap();
+    assert_eq!(header.dictionary_id, Some(adler::hash(&dictionary)));
+
+    assert!(matches!(
+        zlib::decompress(&compressed),
+        Err(ZlibError::PresetDictionary(id)) if id == adler::hash(&dictionary)
+    ));
+    assert!(matches!(
+        zlib::decompress_with_dictionary(&compressed, b"wrong"),
+        Err(ZlibError::DictionaryMismatch { .. })
+    ));
+    let decompressed = zlib::decompress_with_dictionary(&compressed, &dictionary).unwrap();
+    assert_eq!(raw, decompressed);
+
+    // Without FDICT the dictionary isn't used.
+    let plain = compress_zlib(&raw, &CompressOptions::default());
+    assert_eq!(
+        raw,
+        zlib::decompress_with_dictionary(&plain, b"unused").unwrap()
+    );
+}